@@ -4,7 +4,7 @@
 
 //! Architectural processor code.
 
-use crate::{bsp, cpu};
+use crate::{bsp, cpu, exception, memory};
 use cortex_a::{asm, regs::*};
 
 //--------------------------------------------------------------------------------------------------
@@ -77,12 +77,36 @@ unsafe fn el2_to_el1_transition() -> ! {
     asm::eret()
 }
 
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// The exception level that corresponds to a privilege level from
+/// `exception::current_privilege_level()`.
+fn exception_level_of(level: exception::PrivilegeLevel) -> cpu::ExceptionLevel {
+    use exception::PrivilegeLevel;
+
+    match level {
+        PrivilegeLevel::User => cpu::ExceptionLevel::EL0,
+        PrivilegeLevel::Kernel => cpu::ExceptionLevel::EL1,
+        PrivilegeLevel::Hypervisor => cpu::ExceptionLevel::EL2,
+        PrivilegeLevel::SecureMonitor => cpu::ExceptionLevel::EL3,
+        // `CurrentEL.EL` has two bits, and each of their values is a level.
+        PrivilegeLevel::Unknown => unreachable!(),
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Public Code
 //--------------------------------------------------------------------------------------------------
 
 pub use asm::nop;
 
+/// The exception level the executing core runs at.
+pub fn current_exception_level() -> cpu::ExceptionLevel {
+    exception_level_of(exception::current_privilege_level().0)
+}
+
 /// Pause execution on the core.
 #[inline(always)]
 pub fn wait_forever() -> ! {
@@ -91,6 +115,54 @@ pub fn wait_forever() -> ! {
     }
 }
 
+/// Returns true if IRQs are masked on the executing core.
+///
+/// Reads the live I bit of DAIF, e.g. for asserting that code runs inside an IRQ-safe critical
+/// section.
+#[inline(always)]
+pub fn irqs_masked() -> bool {
+    DAIF.is_set(DAIF::I)
+}
+
+/// Leave the running kernel behind and branch to a binary image in memory.
+///
+/// The data cache is cleaned for the image, then the MMU and caches are switched off, so that the
+/// image starts in the same state as if it was loaded by the firmware, except that it runs in EL1.
+///
+/// # Safety
+///
+/// - The image must be identity mapped and contain AArch64 code linked to run from its start
+///   address.
+/// - The currently executing code must be identity mapped, too, because it continues to run for a
+///   few instructions after the MMU is off.
+pub unsafe fn jump_to_image(image: &[u8]) -> ! {
+    exception::asynchronous::local_irq_mask();
+
+    let start = image.as_ptr() as usize;
+    memory::cache::clean_range(start, image.len());
+
+    // Must be a single asm block, because nothing that touches the stack may run after the caches
+    // are off.
+    #[rustfmt::skip]
+    asm!(
+        "mrs {tmp}, sctlr_el1",
+        "bic {tmp}, {tmp}, #(1 << 12)", // I
+        "bic {tmp}, {tmp}, #(1 << 2)",  // C
+        "bic {tmp}, {tmp}, #(1 << 0)",  // M
+        "msr sctlr_el1, {tmp}",
+        "isb",
+        "ic iallu",
+        "dsb sy",
+        "isb",
+        "br {entry}",
+        entry = in(reg) start,
+        tmp = out(reg) _,
+        options(nostack)
+    );
+
+    unreachable!()
+}
+
 //--------------------------------------------------------------------------------------------------
 // Testing
 //--------------------------------------------------------------------------------------------------
@@ -107,3 +179,33 @@ pub fn qemu_exit_failure() -> ! {
 pub fn qemu_exit_success() -> ! {
     QEMU_EXIT_HANDLE.exit_success()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exception::PrivilegeLevel;
+    use test_macros::kernel_test;
+
+    /// Every privilege level maps to its exception level, and the kernel runs at EL1.
+    #[kernel_test]
+    fn privilege_level_maps_to_exception_level() {
+        assert_eq!(
+            exception_level_of(PrivilegeLevel::User),
+            cpu::ExceptionLevel::EL0
+        );
+        assert_eq!(
+            exception_level_of(PrivilegeLevel::Kernel),
+            cpu::ExceptionLevel::EL1
+        );
+        assert_eq!(
+            exception_level_of(PrivilegeLevel::Hypervisor),
+            cpu::ExceptionLevel::EL2
+        );
+        assert_eq!(
+            exception_level_of(PrivilegeLevel::SecureMonitor),
+            cpu::ExceptionLevel::EL3
+        );
+
+        assert_eq!(current_exception_level(), cpu::ExceptionLevel::EL1);
+    }
+}
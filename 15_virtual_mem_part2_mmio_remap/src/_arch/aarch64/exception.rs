@@ -7,7 +7,7 @@
 use crate::{bsp, exception};
 use core::{cell::UnsafeCell, fmt};
 use cortex_a::{barrier, regs::*};
-use register::InMemoryRegister;
+use register::{InMemoryRegister, LocalRegisterCopy};
 
 // Assembly counterpart to this file.
 global_asm!(include_str!("exception.S"));
@@ -39,6 +39,56 @@ struct ExceptionContext {
 /// Wrapper struct for pretty printing ESR_EL1.
 struct EsrEL1;
 
+/// VBAR_EL1 must be aligned to 2 KiB. Its low bits are RES0.
+const VECTOR_TABLE_ALIGN: u64 = 2048;
+
+/// Each vector has room for 32 instructions.
+const VECTOR_SIZE: u64 = 0x80;
+
+const NUM_VECTORS: usize = 16;
+
+/// The vectors in the order of the table.
+const VECTOR_NAMES: [&str; NUM_VECTORS] = [
+    "Current EL, SP_EL0, Synchronous",
+    "Current EL, SP_EL0, IRQ",
+    "Current EL, SP_EL0, FIQ",
+    "Current EL, SP_EL0, SError",
+    "Current EL, SP_ELx, Synchronous",
+    "Current EL, SP_ELx, IRQ",
+    "Current EL, SP_ELx, FIQ",
+    "Current EL, SP_ELx, SError",
+    "Lower EL, AArch64, Synchronous",
+    "Lower EL, AArch64, IRQ",
+    "Lower EL, AArch64, FIQ",
+    "Lower EL, AArch64, SError",
+    "Lower EL, AArch32, Synchronous",
+    "Lower EL, AArch32, IRQ",
+    "Lower EL, AArch32, FIQ",
+    "Lower EL, AArch32, SError",
+];
+
+/// What the first instruction of a vector looks like.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum VectorEntry {
+    /// All zeros, which is `udf #0`. A vector that was never populated.
+    Empty,
+
+    /// An unconditional branch.
+    Branch,
+
+    /// Handler code that starts right in the vector.
+    Inline,
+}
+
+/// The decoded vector table.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct VectorTableReport {
+    vbar: u64,
+
+    /// `None` if `vbar` is no valid table address, in which case the table was not read.
+    entries: Option<[VectorEntry; NUM_VECTORS]>,
+}
+
 //--------------------------------------------------------------------------------------------------
 // Private Code
 //--------------------------------------------------------------------------------------------------
@@ -56,6 +106,53 @@ fn default_exception_handler(e: &ExceptionContext) {
     );
 }
 
+impl VectorEntry {
+    fn decode(instruction: u32) -> Self {
+        // B <label> is 0b000101 followed by a 26 bit offset.
+        const B_MASK: u32 = 0xfc00_0000;
+        const B_OPCODE: u32 = 0x1400_0000;
+
+        match instruction {
+            0 => Self::Empty,
+            x if x & B_MASK == B_OPCODE => Self::Branch,
+            _ => Self::Inline,
+        }
+    }
+}
+
+impl VectorTableReport {
+    /// Returns true if the table is aligned and every vector is populated.
+    fn is_valid(&self) -> bool {
+        match self.entries {
+            Some(entries) => entries.iter().all(|x| *x != VectorEntry::Empty),
+            None => false,
+        }
+    }
+}
+
+/// Decode the vector table at `vbar`, reading the first instruction of the vector at an address
+/// with `read`.
+///
+/// A table at address zero, or one that is not 2 KiB aligned, is invalid and not read.
+fn check_vector_table(vbar: u64, read: impl Fn(u64) -> u32) -> VectorTableReport {
+    if vbar == 0 || vbar % VECTOR_TABLE_ALIGN != 0 {
+        return VectorTableReport {
+            vbar,
+            entries: None,
+        };
+    }
+
+    let mut entries = [VectorEntry::Empty; NUM_VECTORS];
+    for (i, entry) in entries.iter_mut().enumerate() {
+        *entry = VectorEntry::decode(read(vbar + i as u64 * VECTOR_SIZE));
+    }
+
+    VectorTableReport {
+        vbar,
+        entries: Some(entries),
+    }
+}
+
 //------------------------------------------------------------------------------
 // Current, EL0
 //------------------------------------------------------------------------------
@@ -228,10 +325,13 @@ impl fmt::Display for ExceptionContext {
 //--------------------------------------------------------------------------------------------------
 use crate::exception::PrivilegeLevel;
 
-/// The processing element's current privilege level.
-pub fn current_privilege_level() -> (PrivilegeLevel, &'static str) {
-    let el = CurrentEL.read_as_enum(CurrentEL::EL);
+/// The privilege level in a copy of `CurrentEL`.
+fn privilege_level_of(
+    current_el: LocalRegisterCopy<u64, CurrentEL::Register>,
+) -> (PrivilegeLevel, &'static str) {
+    let el = current_el.read_as_enum(CurrentEL::EL);
     match el {
+        Some(CurrentEL::EL::Value::EL3) => (PrivilegeLevel::SecureMonitor, "EL3"),
         Some(CurrentEL::EL::Value::EL2) => (PrivilegeLevel::Hypervisor, "EL2"),
         Some(CurrentEL::EL::Value::EL1) => (PrivilegeLevel::Kernel, "EL1"),
         Some(CurrentEL::EL::Value::EL0) => (PrivilegeLevel::User, "EL0"),
@@ -239,6 +339,11 @@ pub fn current_privilege_level() -> (PrivilegeLevel, &'static str) {
     }
 }
 
+/// The processing element's current privilege level.
+pub fn current_privilege_level() -> (PrivilegeLevel, &'static str) {
+    privilege_level_of(CurrentEL.extract())
+}
+
 /// Init exception handling by setting the exception vector base address register.
 ///
 /// # Safety
@@ -258,3 +363,80 @@ pub unsafe fn handling_init() {
     // Force VBAR update to complete before next instruction.
     barrier::isb(barrier::SY);
 }
+
+/// Print which exception vector table is installed and whether all of its vectors are populated.
+///
+/// A broken table does not show until the first exception, which then ends up in garbage and
+/// usually takes the core down without a word.
+pub fn print_vector_report() {
+    use crate::{info, warn};
+
+    let report = check_vector_table(VBAR_EL1.get(), |addr| unsafe {
+        core::ptr::read_volatile(addr as *const u32)
+    });
+
+    info!("      VBAR_EL1: {:#018x}", report.vbar);
+
+    let entries = match report.entries {
+        Some(x) => x,
+        None => {
+            warn!("      Invalid, must be non-zero and 2 KiB aligned");
+            return;
+        }
+    };
+
+    for (name, entry) in VECTOR_NAMES.iter().zip(entries.iter()) {
+        if *entry == VectorEntry::Empty {
+            warn!("      {:<32} Empty", name);
+        }
+    }
+
+    if report.is_valid() {
+        info!("      All {} vectors populated", NUM_VECTORS);
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_macros::kernel_test;
+
+    /// A misaligned VBAR is invalid and the table behind it is not read. Empty vectors in an
+    /// aligned table are found.
+    #[kernel_test]
+    fn misaligned_vbar_is_invalid() {
+        let report = check_vector_table(0x8_0400, |_| panic!("Misaligned table was read"));
+        assert_eq!(report.entries, None);
+        assert!(!report.is_valid());
+
+        assert!(!check_vector_table(0, |_| 0xd503_201f).is_valid());
+
+        // The first vector branches, the IRQ vector of the current EL with SP_ELx is missing, all
+        // others start with a nop.
+        let report = check_vector_table(0x8_0800, |addr| match addr {
+            0x8_0800 => 0x1400_0010,
+            0x8_0a80 => 0,
+            _ => 0xd503_201f,
+        });
+        let entries = report.entries.unwrap();
+        assert_eq!(entries[0], VectorEntry::Branch);
+        assert_eq!(entries[1], VectorEntry::Inline);
+        assert_eq!(entries[5], VectorEntry::Empty);
+        assert!(!report.is_valid());
+    }
+
+    /// The level comes from bits 3:2 of `CurrentEL`, and all four are told apart.
+    #[kernel_test]
+    fn current_el_decodes_to_privilege_level() {
+        let level = |raw| privilege_level_of(LocalRegisterCopy::new(raw));
+
+        assert!(level(0b0000) == (PrivilegeLevel::User, "EL0"));
+        assert!(level(0b0100) == (PrivilegeLevel::Kernel, "EL1"));
+        assert!(level(0b1000) == (PrivilegeLevel::Hypervisor, "EL2"));
+        assert!(level(0b1100) == (PrivilegeLevel::SecureMonitor, "EL3"));
+    }
+}
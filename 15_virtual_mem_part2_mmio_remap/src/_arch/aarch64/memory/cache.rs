@@ -0,0 +1,166 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Architectural data cache maintenance.
+//!
+//! # Orientation
+//!
+//! Since arch modules are imported into generic modules using the path attribute, the path of this
+//! file is:
+//!
+//! crate::memory::cache::arch_cache
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A maintenance operation on a single cache line.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum LineOp {
+    Clean,
+    Invalidate,
+    CleanAndInvalidate,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Call `emit` with the operation and address for every cache line that overlaps the range.
+///
+/// Maintenance works on whole lines. If a line is only partially covered by the range, an
+/// invalidation would also discard dirty data around it, so such lines are cleaned and invalidated
+/// instead.
+fn for_each_line(
+    start: usize,
+    size: usize,
+    line_size: usize,
+    op: LineOp,
+    mut emit: impl FnMut(LineOp, usize),
+) {
+    if size == 0 {
+        return;
+    }
+
+    let end = start + size;
+    let mut addr = start & !(line_size - 1);
+    while addr < end {
+        let partial = addr < start || addr + line_size > end;
+
+        if op == LineOp::Invalidate && partial {
+            emit(LineOp::CleanAndInvalidate, addr);
+        } else {
+            emit(op, addr);
+        }
+        addr += line_size;
+    }
+}
+
+fn execute(op: LineOp, addr: usize) {
+    unsafe {
+        match op {
+            LineOp::Clean => asm!("dc cvac, {}", in(reg) addr, options(nostack, preserves_flags)),
+            LineOp::Invalidate => {
+                asm!("dc ivac, {}", in(reg) addr, options(nostack, preserves_flags))
+            }
+            LineOp::CleanAndInvalidate => {
+                asm!("dc civac, {}", in(reg) addr, options(nostack, preserves_flags))
+            }
+        }
+    }
+}
+
+/// Apply `op` to the range, then wait until the maintenance has completed.
+fn maintain(start: usize, size: usize, op: LineOp) {
+    for_each_line(start, size, dcache_min_line_size(), op, execute);
+
+    unsafe { asm!("dsb sy", options(nostack, preserves_flags)) };
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// The smallest data cache line size in bytes.
+pub fn dcache_min_line_size() -> usize {
+    // Encoded as log2 of the number of words.
+    let ctr: u64;
+    unsafe { asm!("mrs {}, ctr_el0", out(reg) ctr, options(nomem, nostack, preserves_flags)) };
+
+    4 << ((ctr >> 16) & 0xf)
+}
+
+/// Write dirty cache lines in the range back to memory.
+pub fn clean_range(start: usize, size: usize) {
+    maintain(start, size, LineOp::Clean);
+}
+
+/// Discard the cache lines in the range, without writing them back.
+///
+/// Lines at the edges that the range covers only partially are written back before they are
+/// discarded, so that data which shares them is not lost. For those lines, stale data of the range
+/// itself might be written back, too. Buffers that are shared with other bus masters should
+/// therefore start and end on a cache line boundary.
+pub fn invalidate_range(start: usize, size: usize) {
+    maintain(start, size, LineOp::Invalidate);
+}
+
+/// Write dirty cache lines in the range back to memory and discard them.
+pub fn clean_and_invalidate_range(start: usize, size: usize) {
+    maintain(start, size, LineOp::CleanAndInvalidate);
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_macros::kernel_test;
+
+    /// Collects the operations instead of executing them.
+    fn record(start: usize, size: usize, op: LineOp) -> ([(LineOp, usize); 4], usize) {
+        let mut ops = [(LineOp::Clean, 0); 4];
+        let mut num_ops = 0;
+
+        for_each_line(start, size, 64, op, |op, addr| {
+            ops[num_ops] = (op, addr);
+            num_ops += 1;
+        });
+
+        (ops, num_ops)
+    }
+
+    /// Every line that overlaps the range gets exactly one operation. Partially covered lines are
+    /// never just invalidated.
+    #[kernel_test]
+    fn cache_range_is_covered_line_by_line() {
+        let (ops, num_ops) = record(0x1010, 0x80, LineOp::Invalidate);
+        assert_eq!(
+            ops[..num_ops],
+            [
+                (LineOp::CleanAndInvalidate, 0x1000),
+                (LineOp::Invalidate, 0x1040),
+                (LineOp::CleanAndInvalidate, 0x1080),
+            ]
+        );
+
+        let (ops, num_ops) = record(0x1010, 0x80, LineOp::Clean);
+        assert_eq!(
+            ops[..num_ops],
+            [
+                (LineOp::Clean, 0x1000),
+                (LineOp::Clean, 0x1040),
+                (LineOp::Clean, 0x1080),
+            ]
+        );
+
+        let (_, num_ops) = record(0x1000, 0x40, LineOp::Invalidate);
+        assert_eq!(num_ops, 1);
+
+        let (_, num_ops) = record(0x1010, 0, LineOp::Invalidate);
+        assert_eq!(num_ops, 0);
+    }
+}
@@ -12,7 +12,7 @@ use crate::{
         mmu,
         mmu::{
             AccessPermissions, Address, AddressType, AttributeFields, MemAttributes, Page,
-            PageSliceDescriptor, Physical, Virtual,
+            PageSliceDescriptor, Physical, Translation, Virtual,
         },
     },
     synchronization::InitStateLock,
@@ -210,6 +210,14 @@ impl convert::From<usize> for TableDescriptor {
     }
 }
 
+impl TableDescriptor {
+    /// Returns the valid bit.
+    fn is_valid(&self) -> bool {
+        InMemoryRegister::<u64, STAGE1_TABLE_DESCRIPTOR::Register>::new(self.0)
+            .is_set(STAGE1_TABLE_DESCRIPTOR::VALID)
+    }
+}
+
 /// Convert the kernel's generic memory attributes to HW-specific attributes of the MMU.
 impl convert::From<AttributeFields>
     for register::FieldValue<u64, STAGE1_PAGE_DESCRIPTOR::Register>
@@ -266,6 +274,37 @@ impl PageDescriptor {
         InMemoryRegister::<u64, STAGE1_PAGE_DESCRIPTOR::Register>::new(self.0)
             .is_set(STAGE1_PAGE_DESCRIPTOR::VALID)
     }
+
+    /// The physical page address.
+    fn output_addr(&self) -> usize {
+        let val = InMemoryRegister::<u64, STAGE1_PAGE_DESCRIPTOR::Register>::new(self.0);
+
+        (val.read(STAGE1_PAGE_DESCRIPTOR::OUTPUT_ADDR_64KiB) as usize) << Granule64KiB::SHIFT
+    }
+
+    /// Convert the HW-specific attributes back to the kernel's generic ones.
+    fn attribute_fields(&self) -> AttributeFields {
+        let val = InMemoryRegister::<u64, STAGE1_PAGE_DESCRIPTOR::Register>::new(self.0);
+
+        let mem_attributes = if val.read(STAGE1_PAGE_DESCRIPTOR::AttrIndx) == mair::DEVICE {
+            MemAttributes::Device
+        } else {
+            MemAttributes::CacheableDRAM
+        };
+
+        // The upper bit of AP makes the page read-only, regardless of EL0 access.
+        let acc_perms = if val.read(STAGE1_PAGE_DESCRIPTOR::AP) & 0b10 != 0 {
+            AccessPermissions::ReadOnly
+        } else {
+            AccessPermissions::ReadWrite
+        };
+
+        AttributeFields {
+            mem_attributes,
+            acc_perms,
+            execute_never: val.is_set(STAGE1_PAGE_DESCRIPTOR::PXN),
+        }
+    }
 }
 
 impl<const NUM_TABLES: usize> FixedSizeTranslationTable<{ NUM_TABLES }> {
@@ -429,13 +468,16 @@ impl<const NUM_TABLES: usize> mmu::interface::TranslationTable
             return Err("Tried to map outside of physical address space");
         }
 
-        let iter = p.iter().zip(v.iter());
-        for (phys_page, virt_page) in iter {
-            let page_descriptor = self.page_descriptor_from(virt_page.as_ptr())?;
-            if page_descriptor.is_valid() {
+        // Check all pages first, so that a conflict does not leave a partial mapping behind.
+        for virt_page in v.iter() {
+            if self.page_descriptor_from(virt_page.as_ptr())?.is_valid() {
                 return Err("Virtual page is already mapped");
             }
+        }
 
+        let iter = p.iter().zip(v.iter());
+        for (phys_page, virt_page) in iter {
+            let page_descriptor = self.page_descriptor_from(virt_page.as_ptr())?;
             *page_descriptor = PageDescriptor::new(phys_page.as_ptr(), &attr);
         }
 
@@ -478,6 +520,28 @@ impl<const NUM_TABLES: usize> mmu::interface::TranslationTable
 
         false
     }
+
+    fn translate(&self, virt_addr: Address<Virtual>) -> Option<Translation> {
+        let (lvl2_index, lvl3_index) = self
+            .lvl2_lvl3_index_from(virt_addr.into_usize() as *const Page<Virtual>)
+            .ok()?;
+
+        if !self.lvl2[lvl2_index].is_valid() {
+            return None;
+        }
+
+        let page_descriptor = &self.lvl3[lvl2_index][lvl3_index];
+        if !page_descriptor.is_valid() {
+            return None;
+        }
+
+        let offset = virt_addr.into_usize() & Granule64KiB::MASK;
+
+        Some(Translation {
+            phys_addr: Address::new(page_descriptor.output_addr() + offset),
+            attributes: page_descriptor.attribute_fields(),
+        })
+    }
 }
 
 impl mmu::interface::MMU for MemoryManagementUnit {
@@ -511,6 +575,10 @@ impl mmu::interface::MMU for MemoryManagementUnit {
 
         Ok(())
     }
+
+    fn is_enabled(&self) -> bool {
+        SCTLR_EL1.matches_all(SCTLR_EL1::M::Enable)
+    }
 }
 
 //--------------------------------------------------------------------------------------------------
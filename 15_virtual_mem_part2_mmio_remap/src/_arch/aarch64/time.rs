@@ -41,11 +41,21 @@ impl time::interface::TimeManager for GenericTimer {
         Duration::from_nanos(NS_PER_S / (CNTFRQ_EL0.get() as u64))
     }
 
+    /// The counter frequency is set up by the firmware. Without it, nothing can be converted.
+    fn is_running(&self) -> bool {
+        CNTFRQ_EL0.get() != 0
+    }
+
+    /// The 64 bit counter itself does not wrap in the lifetime of a device. Scaling it to
+    /// nanoseconds must not wrap either, so whole seconds and the remainder are converted apart.
     fn uptime(&self) -> Duration {
         let frq: u64 = CNTFRQ_EL0.get() as u64;
-        let current_count: u64 = CNTPCT_EL0.get() * NS_PER_S;
+        let current_count: u64 = CNTPCT_EL0.get();
+
+        let secs = current_count / frq;
+        let nanos = (current_count % frq) * NS_PER_S / frq;
 
-        Duration::from_nanos(current_count / frq)
+        Duration::new(secs, nanos as u32)
     }
 
     fn spin_for(&self, duration: Duration) {
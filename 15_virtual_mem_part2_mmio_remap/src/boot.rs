@@ -0,0 +1,127 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Boot progress indicator and timing.
+//!
+//! Long stretches of boot, e.g. setting up the translation tables or initializing drivers, print
+//! nothing and look just like a hang. Progress updates overwrite each other on a single line and
+//! go out through the early console, so they work before the console driver is up.
+//!
+//! The uptime at which the console became usable is recorded, as a metric for boot time
+//! optimizations.
+
+use crate::{bsp, synchronization, synchronization::IRQSafeNullLock, time};
+use core::{
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Advances by one with every update, so that a stall is visible even without a new stage.
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static STEP: AtomicUsize = AtomicUsize::new(0);
+
+static CONSOLE_READY_TIME: IRQSafeNullLock<Option<Duration>> = IRQSafeNullLock::new(None);
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn progress_to(out: &mut impl fmt::Write, step: usize, stage: &str) -> fmt::Result {
+    // Return to the line start, then erase what is left of the previous update.
+    write!(out, "\r[{}] {}\x1b[K", SPINNER[step % SPINNER.len()], stage)
+}
+
+/// `record_console_ready()` with the timer passed in.
+fn record_console_ready_with(timer: &impl time::interface::TimeManager) {
+    if !timer.is_running() {
+        return;
+    }
+
+    CONSOLE_READY_TIME.lock(|x| *x = Some(timer.uptime()));
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+use synchronization::interface::Mutex;
+
+/// Show that boot reached `stage`.
+///
+/// Writes a handful of bytes to the UART and returns. Nothing is shown while the UART is not
+/// reachable, i.e. after the MMU is on and before the console driver mapped it.
+pub fn progress(stage: &str) {
+    let step = STEP.fetch_add(1, Ordering::Relaxed);
+
+    if let Some(mut out) = unsafe { bsp::console::early_console_out() } {
+        let _ = progress_to(&mut out, step, stage);
+    }
+}
+
+/// Record that the console is usable from now on. Called by the console driver once its init
+/// completed.
+///
+/// Nothing is recorded while the timer is not running yet.
+pub fn record_console_ready() {
+    record_console_ready_with(time::time_manager());
+}
+
+/// The uptime at which the console became usable, or `None` if that was not recorded.
+pub fn console_ready_time() -> Option<Duration> {
+    CONSOLE_READY_TIME.lock(|x| *x)
+}
+
+/// Erase the progress line, so that regular output starts on a clean line.
+pub fn progress_done() {
+    if let Some(mut out) = unsafe { bsp::console::early_console_out() } {
+        let _ = fmt::Write::write_str(&mut out, "\r\x1b[K");
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{console::FmtBuf, time::mock::MockTimer};
+    use test_macros::kernel_test;
+
+    /// An update rewrites the current line with the spinner and the stage label.
+    #[kernel_test]
+    fn progress_overwrites_line_with_stage() {
+        let mut out: FmtBuf<32> = FmtBuf::new();
+
+        progress_to(&mut out, 5, "MMU").unwrap();
+        assert_eq!(out.as_str(), "\r[/] MMU\x1b[K");
+    }
+
+    /// The console ready time is the uptime when it was recorded, and only recorded once the
+    /// timer runs.
+    #[kernel_test]
+    fn console_ready_time_is_captured_uptime() {
+        let timer = MockTimer::new(1);
+        timer.set_running(false);
+        timer.set_uptime(Duration::from_micros(1500));
+        CONSOLE_READY_TIME.lock(|x| *x = None);
+
+        record_console_ready_with(&timer);
+        assert_eq!(console_ready_time(), None);
+
+        timer.set_running(true);
+        record_console_ready_with(&timer);
+        timer.set_uptime(Duration::from_millis(9));
+        assert_eq!(console_ready_time(), Some(Duration::from_micros(1500)));
+    }
+}
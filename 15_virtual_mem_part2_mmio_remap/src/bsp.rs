@@ -4,7 +4,13 @@
 
 //! Conditional re-exporting of Board Support Packages.
 
-mod device_driver;
+#[cfg(not(any(feature = "bsp_rpi3", feature = "bsp_rpi4")))]
+compile_error!("No board selected. Enable either the `bsp_rpi3` or the `bsp_rpi4` feature.");
+
+#[cfg(all(feature = "bsp_rpi3", feature = "bsp_rpi4"))]
+compile_error!("Multiple boards selected. Enable only one of `bsp_rpi3` and `bsp_rpi4`.");
+
+pub(crate) mod device_driver;
 
 #[cfg(any(feature = "bsp_rpi3", feature = "bsp_rpi4"))]
 mod raspberrypi;
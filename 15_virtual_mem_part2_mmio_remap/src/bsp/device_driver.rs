@@ -8,9 +8,11 @@
 mod arm;
 #[cfg(any(feature = "bsp_rpi3", feature = "bsp_rpi4"))]
 mod bcm;
-mod common;
+pub(crate) mod common;
+mod soft_uart;
 
 #[cfg(feature = "bsp_rpi4")]
 pub use arm::*;
 #[cfg(any(feature = "bsp_rpi3", feature = "bsp_rpi4"))]
 pub use bcm::*;
+pub use soft_uart::*;
@@ -89,6 +89,8 @@ use core::sync::atomic::{AtomicBool, Ordering};
 // Private Definitions
 //--------------------------------------------------------------------------------------------------
 
+/// Registered IRQ handlers, indexed by IRQ number. Numbers beyond [`GICv2::NUM_IRQS`] are
+/// rejected instead of being used as an index.
 type HandlerTable = [Option<exception::asynchronous::IRQDescriptor>; GICv2::NUM_IRQS];
 
 //--------------------------------------------------------------------------------------------------
@@ -114,6 +116,9 @@ pub struct GICv2 {
 
     /// Stores registered IRQ handlers. Writable only during kernel init. RO afterwards.
     handler_table: InitStateLock<HandlerTable>,
+
+    /// Called for IRQs without a handler. Writable only during kernel init. RO afterwards.
+    default_handler: InitStateLock<Option<exception::asynchronous::DefaultIRQHandler>>,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -122,8 +127,21 @@ pub struct GICv2 {
 
 impl GICv2 {
     const MAX_IRQ_NUMBER: usize = 300; // Normally 1019, but keep it lower to save some space.
+
+    /// Size of the IRQ handler table, one entry per IRQ number.
     const NUM_IRQS: usize = Self::MAX_IRQ_NUMBER + 1;
 
+    /// Translate to the GIC's priority values, where lower values mean higher priority.
+    fn hw_priority(priority: exception::asynchronous::IRQPriority) -> u8 {
+        use exception::asynchronous::IRQPriority;
+
+        match priority {
+            IRQPriority::High => 0x40,
+            IRQPriority::Normal => 0x80,
+            IRQPriority::Low => 0xc0,
+        }
+    }
+
     /// Create an instance.
     ///
     /// # Safety
@@ -140,6 +158,7 @@ impl GICv2 {
             gicc: gicc::GICC::new(gicc_phys_mmio_descriptor.start_addr().into_usize()),
             is_mmio_remapped: AtomicBool::new(false),
             handler_table: InitStateLock::new([None; Self::NUM_IRQS]),
+            default_handler: InitStateLock::new(None),
         }
     }
 }
@@ -154,7 +173,12 @@ impl driver::interface::DeviceDriver for GICv2 {
         "GICv2 (ARM Generic Interrupt Controller v2)"
     }
 
-    unsafe fn init(&self) -> Result<(), &'static str> {
+    #[cfg(feature = "mmio_counters")]
+    fn mmio_accesses(&self) -> usize {
+        self.gicd.mmio_accesses() + self.gicc.mmio_accesses()
+    }
+
+    unsafe fn init(&self) -> Result<(), driver::DriverError> {
         let remapped = self.is_mmio_remapped.load(Ordering::Relaxed);
         if !remapped {
             let mut virt_addr;
@@ -191,22 +215,58 @@ impl exception::asynchronous::interface::IRQManager for GICv2 {
         descriptor: exception::asynchronous::IRQDescriptor,
     ) -> Result<(), &'static str> {
         self.handler_table.write(|table| {
-            let irq_number = irq_number.get();
+            let slot = table
+                .get_mut(irq_number.get())
+                .ok_or("IRQ number exceeds the handler table")?;
 
-            if table[irq_number].is_some() {
+            if slot.is_some() {
                 return Err("IRQ handler already registered");
             }
 
-            table[irq_number] = Some(descriptor);
+            *slot = Some(descriptor);
 
             Ok(())
-        })
+        })?;
+
+        self.gicd
+            .set_priority(irq_number, Self::hw_priority(descriptor.priority));
+
+        Ok(())
+    }
+
+    fn register_default_handler(&self, handler: exception::asynchronous::DefaultIRQHandler) {
+        self.default_handler.write(|x| *x = Some(handler));
     }
 
     fn enable(&self, irq_number: Self::IRQNumberType) {
         self.gicd.enable(irq_number);
     }
 
+    fn disable(&self, irq_number: Self::IRQNumberType) {
+        self.gicd.disable(irq_number);
+    }
+
+    fn is_enabled(&self, irq_number: Self::IRQNumberType) -> bool {
+        self.gicd.is_enabled(irq_number)
+    }
+
+    fn set_priority(
+        &self,
+        irq_number: Self::IRQNumberType,
+        priority: exception::asynchronous::IRQPriority,
+    ) {
+        self.handler_table.write(|table| {
+            if let Some(Some(descriptor)) = table.get_mut(irq_number.get()) {
+                descriptor.priority = priority;
+            }
+        });
+
+        // The GIC itself signals the highest priority pending IRQ first. Among equal priorities,
+        // the lowest IRQ number wins.
+        self.gicd
+            .set_priority(irq_number, Self::hw_priority(priority));
+    }
+
     fn handle_pending_irqs<'irq_context>(
         &'irq_context self,
         ic: &exception::asynchronous::IRQContext<'irq_context>,
@@ -220,10 +280,18 @@ impl exception::asynchronous::interface::IRQManager for GICv2 {
             return;
         }
 
-        // Call the IRQ handler. Panic if there is none.
+        // Call the IRQ handler. Fall back to the default handler if there is none.
         self.handler_table.read(|table| {
-            match table[irq_number] {
-                None => panic!("No handler registered for IRQ {}", irq_number),
+            match table.get(irq_number).and_then(Option::as_ref) {
+                None => {
+                    let default_handler = self
+                        .default_handler
+                        .read(|x| x.unwrap_or(exception::asynchronous::mask_unhandled_irq));
+
+                    default_handler(irq_number, &|| {
+                        self.gicd.disable(IRQNumber::new(irq_number))
+                    });
+                }
                 Some(descriptor) => {
                     // Call the IRQ handler. Panics on failure.
                     descriptor.handler.handle().expect("Error handling IRQ");
@@ -83,6 +83,12 @@ impl GICC {
             .write(|regs| *regs = Registers::new(new_mmio_start_addr));
     }
 
+    /// The number of register accesses, see `DeviceDriver::mmio_accesses()`.
+    #[cfg(feature = "mmio_counters")]
+    pub fn mmio_accesses(&self) -> usize {
+        self.registers.read(|regs| regs.accesses())
+    }
+
     /// Accept interrupts of any priority.
     ///
     /// Quoting the GICv2 Architecture Specification:
@@ -47,7 +47,11 @@ register_structs! {
         (0x004 => TYPER: ReadOnly<u32, TYPER::Register>),
         (0x008 => _reserved1),
         (0x104 => ISENABLER: [ReadWrite<u32>; 31]),
-        (0x108 => _reserved2),
+        (0x180 => _reserved2),
+        (0x184 => ICENABLER: [WriteOnly<u32>; 31]),
+        (0x200 => _reserved3),
+        (0x420 => IPRIORITYR: [ReadWrite<u32>; 248]),
+        (0x800 => _reserved4),
         (0x820 => ITARGETSR: [ReadWrite<u32, ITARGETSR::Register>; 248]),
         (0x824 => @END),
     }
@@ -59,6 +63,10 @@ register_structs! {
         (0x000 => _reserved1),
         (0x100 => ISENABLER: ReadWrite<u32>),
         (0x104 => _reserved2),
+        (0x180 => ICENABLER: WriteOnly<u32>),
+        (0x184 => _reserved3),
+        (0x400 => IPRIORITYR: [ReadWrite<u32>; 8]),
+        (0x420 => _reserved4),
         (0x800 => ITARGETSR: [ReadOnly<u32, ITARGETSR::Register>; 8]),
         (0x804 => @END),
     }
@@ -140,6 +148,13 @@ impl GICD {
             .write(|regs| *regs = BankedRegisters::new(new_mmio_start_addr));
     }
 
+    /// The number of register accesses, see `DeviceDriver::mmio_accesses()`.
+    #[cfg(feature = "mmio_counters")]
+    pub fn mmio_accesses(&self) -> usize {
+        self.shared_registers.lock(|regs| regs.accesses())
+            + self.banked_registers.read(|regs| regs.accesses())
+    }
+
     /// Use a banked ITARGETSR to retrieve the executing core's GIC target mask.
     ///
     /// Quoting the GICv2 Architecture Specification:
@@ -202,4 +217,78 @@ impl GICD {
             }
         }
     }
+
+    pub fn disable(&self, irq_num: super::IRQNumber) {
+        let irq_num = irq_num.get();
+
+        let disable_reg_index = irq_num >> 5;
+        let disable_bit: u32 = 1u32 << (irq_num % 32);
+
+        // Writing a 1 disables the IRQ, zeros have no effect. So no read-modify-write needed.
+        match irq_num {
+            // Private.
+            0..=31 => self
+                .banked_registers
+                .read(|regs| regs.ICENABLER.set(disable_bit)),
+            // Shared.
+            _ => {
+                let disable_reg_index_shared = disable_reg_index - 1;
+
+                self.shared_registers
+                    .lock(|regs| regs.ICENABLER[disable_reg_index_shared].set(disable_bit));
+            }
+        }
+    }
+
+    /// Returns true if the IRQ is enabled.
+    pub fn is_enabled(&self, irq_num: super::IRQNumber) -> bool {
+        let irq_num = irq_num.get();
+
+        let enable_reg_index = irq_num >> 5;
+        let enable_bit: u32 = 1u32 << (irq_num % 32);
+
+        // Reading ISENABLER returns the enable state, regardless of the set/clear register used
+        // to change it.
+        let enable_reg_val = match irq_num {
+            // Private.
+            0..=31 => self.banked_registers.read(|regs| regs.ISENABLER.get()),
+            // Shared.
+            _ => {
+                let enable_reg_index_shared = enable_reg_index - 1;
+
+                self.shared_registers
+                    .lock(|regs| regs.ISENABLER[enable_reg_index_shared].get())
+            }
+        };
+
+        enable_reg_val & enable_bit != 0
+    }
+
+    /// Set the priority of an IRQ. Lower values mean higher priority.
+    pub fn set_priority(&self, irq_num: super::IRQNumber, priority: u8) {
+        let irq_num = irq_num.get();
+
+        // Each u32 priority register holds the 8 bit priority fields of four IRQs.
+        let priority_reg_index = irq_num >> 2;
+        let shift = (irq_num % 4) * 8;
+        let mask: u32 = 0xff << shift;
+        let value: u32 = u32::from(priority) << shift;
+
+        let update = |reg: &ReadWrite<u32>| reg.set((reg.get() & !mask) | value);
+
+        // Check if we are handling a private or shared IRQ.
+        match irq_num {
+            // Private.
+            0..=31 => self
+                .banked_registers
+                .read(|regs| update(&regs.IPRIORITYR[priority_reg_index])),
+            // Shared.
+            _ => {
+                let priority_reg_index_shared = priority_reg_index - 8;
+
+                self.shared_registers
+                    .lock(|regs| update(&regs.IPRIORITYR[priority_reg_index_shared]));
+            }
+        }
+    }
 }
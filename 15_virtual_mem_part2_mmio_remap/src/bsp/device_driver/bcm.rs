@@ -4,12 +4,16 @@
 
 //! BCM driver top level.
 
+mod bcm2xxx_clock;
 mod bcm2xxx_gpio;
 #[cfg(feature = "bsp_rpi3")]
 mod bcm2xxx_interrupt_controller;
 mod bcm2xxx_pl011_uart;
+mod bcm2xxx_power;
 
+pub use bcm2xxx_clock::*;
 pub use bcm2xxx_gpio::*;
 #[cfg(feature = "bsp_rpi3")]
 pub use bcm2xxx_interrupt_controller::*;
 pub use bcm2xxx_pl011_uart::*;
+pub use bcm2xxx_power::*;
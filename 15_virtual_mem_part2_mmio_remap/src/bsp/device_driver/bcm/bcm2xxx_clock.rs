@@ -0,0 +1,298 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Firmware clock control.
+//!
+//! Peripheral clocks are owned by the VideoCore firmware. It is asked to switch them on or off
+//! through the property channel of the mailbox. A request is a message in memory, the mailbox
+//! only transports its address.
+
+use crate::{
+    bsp::device_driver::common::MMIODerefWrapper, driver, memory, memory::mmu::Physical,
+    synchronization, synchronization::IRQSafeNullLock, time,
+};
+use core::time::Duration;
+use register::{mmio::*, register_bitfields, register_structs};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+// Mailbox registers.
+//
+// Descriptions taken from
+// https://github.com/raspberrypi/firmware/wiki/Mailboxes
+register_bitfields! {
+    u32,
+
+    /// Mailbox Status
+    STATUS [
+        /// The mailbox cannot take another message.
+        FULL OFFSET(31) NUMBITS(1) [],
+
+        /// The mailbox does not hold any message.
+        EMPTY OFFSET(30) NUMBITS(1) []
+    ]
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    RegisterBlock {
+        (0x00 => READ: ReadOnly<u32>),
+        (0x04 => _reserved1),
+        (0x18 => READ_STATUS: ReadOnly<u32, STATUS::Register>),
+        (0x1c => _reserved2),
+        (0x20 => WRITE: WriteOnly<u32>),
+        (0x24 => _reserved3),
+        (0x38 => WRITE_STATUS: ReadOnly<u32, STATUS::Register>),
+        (0x3c => @END),
+    }
+}
+
+/// Abstraction for the associated MMIO registers.
+type Registers = MMIODerefWrapper<RegisterBlock>;
+
+/// The mailbox channel for requests to the firmware's property interface.
+const PROPERTY_CHANNEL: u32 = 8;
+
+const TAG_GET_CLOCK_STATE: u32 = 0x0003_0001;
+const TAG_SET_CLOCK_STATE: u32 = 0x0003_8001;
+
+/// Set in the message header if the firmware processed the request.
+const RESPONSE_SUCCESS: u32 = 0x8000_0000;
+
+/// Set in a tag's size field if the firmware filled in a response.
+const TAG_RESPONSE: u32 = 0x8000_0000;
+
+/// Clock state bits.
+const CLOCK_ON: u32 = 1 << 0;
+const CLOCK_MISSING: u32 = 1 << 1;
+
+/// How long the firmware may take to answer.
+const RESPONSE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// A property message with a single clock state tag.
+///
+/// The mailbox only transports the upper 28 bits of the address, hence the alignment.
+#[repr(C, align(16))]
+#[derive(Copy, Clone)]
+struct ClockStateMessage([u32; 8]);
+
+struct FirmwareClocksInner {
+    registers: Registers,
+    message: ClockStateMessage,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Firmware clock IDs. Only the clocks used by the kernel are listed.
+#[allow(missing_docs)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ClockId {
+    Uart = 2,
+}
+
+/// Clock interfaces.
+pub mod interface {
+    use super::ClockId;
+    use crate::driver::DriverError;
+
+    /// Clock gating functions.
+    pub trait ClockControl {
+        /// Switch a clock on or off. Switching a clock to the state it is in already does nothing.
+        fn set_clock_enabled(&self, clock: ClockId, enable: bool) -> Result<(), DriverError>;
+    }
+}
+
+/// Representation of the firmware's clock control.
+pub struct FirmwareClocks {
+    phys_mmio_descriptor: memory::mmu::MMIODescriptor<Physical>,
+    inner: IRQSafeNullLock<FirmwareClocksInner>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl ClockStateMessage {
+    fn new(tag: u32, clock: ClockId, state: u32) -> Self {
+        Self([
+            core::mem::size_of::<Self>() as u32,
+            0, // Request.
+            tag,
+            8, // Size of the value buffer.
+            0, // Request.
+            clock as u32,
+            state,
+            0, // End tag.
+        ])
+    }
+
+    /// Return the clock state from the firmware's response.
+    fn response_state(&self) -> Result<u32, driver::DriverError> {
+        if self.0[1] != RESPONSE_SUCCESS || self.0[4] & TAG_RESPONSE == 0 {
+            return Err(driver::DriverError::InvalidConfig(
+                "Firmware rejected the clock request",
+            ));
+        }
+
+        let state = self.0[6];
+        if state & CLOCK_MISSING != 0 {
+            return Err(driver::DriverError::InvalidConfig("Clock does not exist"));
+        }
+
+        Ok(state)
+    }
+}
+
+impl FirmwareClocksInner {
+    const unsafe fn new(mmio_start_addr: usize) -> Self {
+        Self {
+            registers: Registers::new(mmio_start_addr),
+            message: ClockStateMessage([0; 8]),
+        }
+    }
+
+    /// Hand `message` to the firmware and wait for its response.
+    ///
+    /// The message buffer is part of the kernel image, which is identity mapped, so its virtual
+    /// address is the physical one the firmware needs.
+    fn call(&mut self, message: ClockStateMessage) -> Result<u32, driver::DriverError> {
+        use time::interface::TimeManager;
+
+        self.message = message;
+
+        let start = &self.message as *const _ as usize;
+        let size = core::mem::size_of::<ClockStateMessage>();
+        let request = start as u32 | PROPERTY_CHANNEL;
+
+        // The firmware reads the message from memory, past the caches.
+        memory::cache::clean_and_invalidate_range(start, size);
+
+        let deadline = time::time_manager().deadline_in(RESPONSE_TIMEOUT);
+        let registers = &self.registers;
+
+        synchronization::spin_until(
+            || !registers.WRITE_STATUS.matches_all(STATUS::FULL::SET),
+            deadline,
+        )
+        .map_err(|_| driver::DriverError::Transient("Mailbox is full"))?;
+        registers.WRITE.set(request);
+
+        synchronization::spin_until(
+            || {
+                // Responses to the requests of others are discarded.
+                !registers.READ_STATUS.matches_all(STATUS::EMPTY::SET)
+                    && registers.READ.get() == request
+            },
+            deadline,
+        )
+        .map_err(|_| driver::DriverError::Transient("Mailbox response timed out"))?;
+
+        // Drop stale cache lines, so that the response is read from memory.
+        memory::cache::clean_and_invalidate_range(start, size);
+
+        self.message.response_state()
+    }
+
+    fn set_clock_enabled(
+        &mut self,
+        clock: ClockId,
+        enable: bool,
+    ) -> Result<(), driver::DriverError> {
+        let state = self.call(ClockStateMessage::new(TAG_GET_CLOCK_STATE, clock, 0))?;
+        if (state & CLOCK_ON != 0) == enable {
+            return Ok(());
+        }
+
+        let state = if enable { CLOCK_ON } else { 0 };
+        self.call(ClockStateMessage::new(TAG_SET_CLOCK_STATE, clock, state))?;
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl FirmwareClocks {
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide correct MMIO descriptors.
+    pub const unsafe fn new(phys_mmio_descriptor: memory::mmu::MMIODescriptor<Physical>) -> Self {
+        Self {
+            phys_mmio_descriptor,
+            inner: IRQSafeNullLock::new(FirmwareClocksInner::new(
+                phys_mmio_descriptor.start_addr().into_usize(),
+            )),
+        }
+    }
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+use synchronization::interface::Mutex;
+
+impl driver::interface::DeviceDriver for FirmwareClocks {
+    fn compatible(&self) -> &'static str {
+        "BCM Firmware Clocks"
+    }
+
+    #[cfg(feature = "mmio_counters")]
+    fn mmio_accesses(&self) -> usize {
+        self.inner.lock(|inner| inner.registers.accesses())
+    }
+
+    unsafe fn init(&self) -> Result<(), driver::DriverError> {
+        let registers =
+            memory::mmu::map_mmio_registers(self.compatible(), &self.phys_mmio_descriptor)?;
+
+        self.inner.lock(|inner| inner.registers = registers);
+
+        Ok(())
+    }
+}
+
+impl interface::ClockControl for FirmwareClocks {
+    fn set_clock_enabled(&self, clock: ClockId, enable: bool) -> Result<(), driver::DriverError> {
+        self.inner
+            .lock(|inner| inner.set_clock_enabled(clock, enable))
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_macros::kernel_test;
+
+    /// A response is only accepted if both the message and the tag were answered, and the clock
+    /// exists.
+    #[kernel_test]
+    fn clock_state_response_is_checked() {
+        let mut message = ClockStateMessage::new(TAG_GET_CLOCK_STATE, ClockId::Uart, 0);
+        assert_eq!(message.0[0], 32);
+        assert_eq!(message.0[5], 2);
+        assert!(message.response_state().is_err());
+
+        message.0[1] = RESPONSE_SUCCESS;
+        assert!(message.response_state().is_err());
+
+        message.0[4] = TAG_RESPONSE | 8;
+        message.0[6] = CLOCK_ON;
+        assert_eq!(message.response_state(), Ok(CLOCK_ON));
+
+        message.0[6] = CLOCK_MISSING;
+        assert!(message.response_state().is_err());
+    }
+}
@@ -5,8 +5,11 @@
 //! GPIO Driver.
 
 use crate::{
-    bsp::device_driver::common::MMIODerefWrapper, driver, memory, memory::mmu::Physical,
-    synchronization, synchronization::IRQSafeNullLock,
+    bsp::device_driver::{common::MMIODerefWrapper, OutputPin},
+    driver, memory,
+    memory::mmu::Physical,
+    synchronization,
+    synchronization::IRQSafeNullLock,
 };
 use core::sync::atomic::{AtomicUsize, Ordering};
 use register::{mmio::*, register_bitfields, register_structs};
@@ -91,12 +94,22 @@ register_bitfields! {
 register_structs! {
     #[allow(non_snake_case)]
     RegisterBlock {
-        (0x00 => _reserved1),
+        (0x00 => GPFSEL0: ReadWrite<u32>),
         (0x04 => GPFSEL1: ReadWrite<u32, GPFSEL1::Register>),
-        (0x08 => _reserved2),
+        (0x08 => GPFSEL2: ReadWrite<u32>),
+        (0x0C => GPFSEL3: ReadWrite<u32>),
+        (0x10 => GPFSEL4: ReadWrite<u32>),
+        (0x14 => GPFSEL5: ReadWrite<u32>),
+        (0x18 => _reserved1),
+        (0x1C => GPSET0: WriteOnly<u32>),
+        (0x20 => GPSET1: WriteOnly<u32>),
+        (0x24 => _reserved2),
+        (0x28 => GPCLR0: WriteOnly<u32>),
+        (0x2C => GPCLR1: WriteOnly<u32>),
+        (0x30 => _reserved3),
         (0x94 => GPPUD: ReadWrite<u32, GPPUD::Register>),
         (0x98 => GPPUDCLK0: ReadWrite<u32, GPPUDCLK0::Register>),
-        (0x9C => _reserved3),
+        (0x9C => _reserved4),
         (0xE4 => GPIO_PUP_PDN_CNTRL_REG0: ReadWrite<u32, GPIO_PUP_PDN_CNTRL_REG0::Register>),
         (0xE8 => @END),
     }
@@ -105,6 +118,12 @@ register_structs! {
 /// Abstraction for the associated MMIO registers.
 type Registers = MMIODerefWrapper<RegisterBlock>;
 
+/// Number of GPIO pins of the SoC.
+const NUM_PINS: usize = 54;
+
+/// Function select value for a general purpose output.
+const FSEL_OUTPUT: u32 = 0b001;
+
 //--------------------------------------------------------------------------------------------------
 // Public Definitions
 //--------------------------------------------------------------------------------------------------
@@ -123,6 +142,12 @@ pub struct GPIO {
     inner: IRQSafeNullLock<GPIOInner>,
 }
 
+/// A pin that has been configured as an output. Created by [`GPIO::output_pin()`].
+pub struct GPIOOutputPin {
+    gpio: &'static GPIO,
+    pin: usize,
+}
+
 //--------------------------------------------------------------------------------------------------
 // Public Code
 //--------------------------------------------------------------------------------------------------
@@ -182,6 +207,33 @@ impl GPIOInner {
         );
     }
 
+    /// Configure `pin` as a general purpose output.
+    fn set_output(&mut self, pin: usize) {
+        let shift = (pin % 10) * 3;
+        let fsel = |old: u32| (old & !(0b111 << shift)) | (FSEL_OUTPUT << shift);
+
+        let r = &self.registers;
+        match pin / 10 {
+            0 => r.GPFSEL0.set(fsel(r.GPFSEL0.get())),
+            1 => r.GPFSEL1.set(fsel(r.GPFSEL1.get())),
+            2 => r.GPFSEL2.set(fsel(r.GPFSEL2.get())),
+            3 => r.GPFSEL3.set(fsel(r.GPFSEL3.get())),
+            4 => r.GPFSEL4.set(fsel(r.GPFSEL4.get())),
+            _ => r.GPFSEL5.set(fsel(r.GPFSEL5.get())),
+        }
+    }
+
+    /// Drive an output `pin` high or low.
+    fn set_level(&mut self, pin: usize, high: bool) {
+        let r = &self.registers;
+        match (pin < 32, high) {
+            (true, true) => r.GPSET0.set(1 << pin),
+            (true, false) => r.GPCLR0.set(1 << pin),
+            (false, true) => r.GPSET1.set(1 << (pin - 32)),
+            (false, false) => r.GPCLR1.set(1 << (pin - 32)),
+        }
+    }
+
     /// Map PL011 UART as standard output.
     ///
     /// TX to pin 14
@@ -221,6 +273,19 @@ impl GPIO {
     pub fn map_pl011_uart(&self) {
         self.inner.lock(|inner| inner.map_pl011_uart())
     }
+
+    /// Configure `pin` as an output and return a handle for driving it.
+    ///
+    /// Nothing stops two users from taking the same pin, or a pin another driver uses.
+    pub fn output_pin(&'static self, pin: usize) -> Result<GPIOOutputPin, &'static str> {
+        if pin >= NUM_PINS {
+            return Err("GPIO pin does not exist");
+        }
+
+        self.inner.lock(|inner| inner.set_output(pin));
+
+        Ok(GPIOOutputPin { gpio: self, pin })
+    }
 }
 
 //------------------------------------------------------------------------------
@@ -233,21 +298,28 @@ impl driver::interface::DeviceDriver for GPIO {
         "BCM GPIO"
     }
 
-    unsafe fn init(&self) -> Result<(), &'static str> {
+    #[cfg(feature = "mmio_counters")]
+    fn mmio_accesses(&self) -> usize {
+        self.inner.lock(|inner| inner.registers.accesses())
+    }
+
+    unsafe fn init(&self) -> Result<(), driver::DriverError> {
         let virt_addr =
             memory::mmu::kernel_map_mmio(self.compatible(), &self.phys_mmio_descriptor)?;
 
         self.inner
             .lock(|inner| inner.init(Some(virt_addr.into_usize())))?;
 
+        // Release, so that whoever sees the address also sees the mapping and the register setup.
         self.virt_mmio_start_addr
-            .store(virt_addr.into_usize(), Ordering::Relaxed);
+            .store(virt_addr.into_usize(), Ordering::Release);
 
         Ok(())
     }
 
     fn virt_mmio_start_addr(&self) -> Option<usize> {
-        let addr = self.virt_mmio_start_addr.load(Ordering::Relaxed);
+        // Pairs with the Release store in `init()`.
+        let addr = self.virt_mmio_start_addr.load(Ordering::Acquire);
 
         if addr == 0 {
             return None;
@@ -256,3 +328,11 @@ impl driver::interface::DeviceDriver for GPIO {
         Some(addr)
     }
 }
+
+impl OutputPin for GPIOOutputPin {
+    fn set_level(&self, high: bool) {
+        self.gpio
+            .inner
+            .lock(|inner| inner.set_level(self.pin, high))
+    }
+}
@@ -72,6 +72,8 @@ impl Iterator for PendingIRQs {
 impl InterruptController {
     const MAX_LOCAL_IRQ_NUMBER: usize = 11;
     const MAX_PERIPHERAL_IRQ_NUMBER: usize = 63;
+
+    /// Size of the peripheral IRQ handler table, one entry per IRQ number.
     const NUM_PERIPHERAL_IRQS: usize = Self::MAX_PERIPHERAL_IRQ_NUMBER + 1;
 
     /// Create an instance.
@@ -98,9 +100,14 @@ impl driver::interface::DeviceDriver for InterruptController {
         "BCM Interrupt Controller"
     }
 
-    unsafe fn init(&self) -> Result<(), &'static str> {
+    unsafe fn init(&self) -> Result<(), driver::DriverError> {
         self.periph.init()
     }
+
+    #[cfg(feature = "mmio_counters")]
+    fn mmio_accesses(&self) -> usize {
+        self.periph.mmio_accesses()
+    }
 }
 
 impl exception::asynchronous::interface::IRQManager for InterruptController {
@@ -117,6 +124,10 @@ impl exception::asynchronous::interface::IRQManager for InterruptController {
         }
     }
 
+    fn register_default_handler(&self, handler: exception::asynchronous::DefaultIRQHandler) {
+        self.periph.register_default_handler(handler)
+    }
+
     fn enable(&self, irq: Self::IRQNumberType) {
         match irq {
             IRQNumber::Local(_) => unimplemented!("Local IRQ controller not implemented."),
@@ -124,6 +135,34 @@ impl exception::asynchronous::interface::IRQManager for InterruptController {
         }
     }
 
+    fn disable(&self, irq: Self::IRQNumberType) {
+        match irq {
+            // Local IRQs cannot be enabled yet, so they are disabled already.
+            IRQNumber::Local(_) => (),
+            IRQNumber::Peripheral(pirq) => self.periph.disable(pirq),
+        }
+    }
+
+    fn is_enabled(&self, irq: Self::IRQNumberType) -> bool {
+        match irq {
+            // Local IRQs cannot be enabled yet.
+            IRQNumber::Local(_) => false,
+            IRQNumber::Peripheral(pirq) => self.periph.is_enabled(pirq),
+        }
+    }
+
+    fn set_priority(
+        &self,
+        irq: Self::IRQNumberType,
+        priority: exception::asynchronous::IRQPriority,
+    ) {
+        match irq {
+            // Local IRQs cannot have a handler yet, so there is nothing to order.
+            IRQNumber::Local(_) => (),
+            IRQNumber::Peripheral(pirq) => self.periph.set_priority(pirq, priority),
+        }
+    }
+
     fn handle_pending_irqs<'irq_context>(
         &'irq_context self,
         ic: &exception::asynchronous::IRQContext<'irq_context>,
@@ -136,3 +175,25 @@ impl exception::asynchronous::interface::IRQManager for InterruptController {
         self.periph.print_handler();
     }
 }
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_macros::kernel_test;
+
+    /// All simultaneously pending IRQs are decoded, in ascending order.
+    #[kernel_test]
+    fn pending_irqs_decode_all_bits() {
+        let mut pending = PendingIRQs::new((1 << 40) | (1 << 3));
+
+        assert_eq!(pending.next(), Some(3));
+        assert_eq!(pending.next(), Some(40));
+        assert_eq!(pending.next(), None);
+
+        assert_eq!(PendingIRQs::new(0).next(), None);
+    }
+}
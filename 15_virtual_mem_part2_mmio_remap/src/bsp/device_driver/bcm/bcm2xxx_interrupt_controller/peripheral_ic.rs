@@ -24,6 +24,9 @@ register_structs! {
         (0x00 => _reserved1),
         (0x10 => ENABLE_1: WriteOnly<u32>),
         (0x14 => ENABLE_2: WriteOnly<u32>),
+        (0x18 => _reserved2),
+        (0x1c => DISABLE_1: WriteOnly<u32>),
+        (0x20 => DISABLE_2: WriteOnly<u32>),
         (0x24 => @END),
     }
 }
@@ -34,7 +37,10 @@ register_structs! {
         (0x00 => _reserved1),
         (0x04 => PENDING_1: ReadOnly<u32>),
         (0x08 => PENDING_2: ReadOnly<u32>),
-        (0x0c => @END),
+        (0x0c => _reserved2),
+        (0x10 => ENABLE_1: ReadOnly<u32>),
+        (0x14 => ENABLE_2: ReadOnly<u32>),
+        (0x18 => @END),
     }
 }
 
@@ -44,8 +50,24 @@ type WriteOnlyRegisters = MMIODerefWrapper<WORegisterBlock>;
 /// Abstraction for the ReadOnly parts of the associated MMIO registers.
 type ReadOnlyRegisters = MMIODerefWrapper<RORegisterBlock>;
 
-type HandlerTable =
-    [Option<exception::asynchronous::IRQDescriptor>; InterruptController::NUM_PERIPHERAL_IRQS];
+/// Registered IRQ handlers.
+///
+/// The controller has no notion of priorities, so pending IRQs are ordered in software. Equal
+/// priorities are handled in registration order.
+///
+/// There are [`InterruptController::NUM_PERIPHERAL_IRQS`] entries. Numbers beyond are rejected
+/// instead of being used as an index.
+struct HandlerTable {
+    descriptors:
+        [Option<exception::asynchronous::IRQDescriptor>; InterruptController::NUM_PERIPHERAL_IRQS],
+
+    /// IRQ numbers in the order their handlers were registered.
+    registration_order: [usize; InterruptController::NUM_PERIPHERAL_IRQS],
+    num_registered: usize,
+
+    /// Called for pending IRQs without a handler.
+    default_handler: Option<exception::asynchronous::DefaultIRQHandler>,
+}
 
 //--------------------------------------------------------------------------------------------------
 // Public Definitions
@@ -65,6 +87,73 @@ pub struct PeripheralIC {
     handler_table: InitStateLock<HandlerTable>,
 }
 
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl HandlerTable {
+    const fn new() -> Self {
+        Self {
+            descriptors: [None; InterruptController::NUM_PERIPHERAL_IRQS],
+            registration_order: [0; InterruptController::NUM_PERIPHERAL_IRQS],
+            num_registered: 0,
+            default_handler: None,
+        }
+    }
+
+    fn register(
+        &mut self,
+        irq_number: usize,
+        descriptor: exception::asynchronous::IRQDescriptor,
+    ) -> Result<(), &'static str> {
+        let slot = self
+            .descriptors
+            .get_mut(irq_number)
+            .ok_or("IRQ number exceeds the handler table")?;
+
+        if slot.is_some() {
+            return Err("IRQ handler already registered");
+        }
+
+        *slot = Some(descriptor);
+        self.registration_order[self.num_registered] = irq_number;
+        self.num_registered += 1;
+
+        Ok(())
+    }
+
+    /// Call `f` for each pending IRQ that has a handler, highest priority first.
+    ///
+    /// Returns the bitmask of pending IRQs without a handler.
+    fn for_each_pending(
+        &self,
+        mut pending_mask: u64,
+        mut f: impl FnMut(&exception::asynchronous::IRQDescriptor),
+    ) -> u64 {
+        use exception::asynchronous::IRQPriority;
+
+        let registered = &self.registration_order[..self.num_registered];
+
+        for priority in [IRQPriority::High, IRQPriority::Normal, IRQPriority::Low].iter() {
+            for irq_number in registered.iter() {
+                let bit = 1 << irq_number;
+                if pending_mask & bit == 0 {
+                    continue;
+                }
+
+                if let Some(Some(descriptor)) = self.descriptors.get(*irq_number) {
+                    if descriptor.priority == *priority {
+                        f(descriptor);
+                        pending_mask &= !bit;
+                    }
+                }
+            }
+        }
+
+        pending_mask
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Public Code
 //--------------------------------------------------------------------------------------------------
@@ -82,18 +171,14 @@ impl PeripheralIC {
             phys_mmio_descriptor,
             wo_registers: IRQSafeNullLock::new(WriteOnlyRegisters::new(addr)),
             ro_registers: InitStateLock::new(ReadOnlyRegisters::new(addr)),
-            handler_table: InitStateLock::new([None; InterruptController::NUM_PERIPHERAL_IRQS]),
+            handler_table: InitStateLock::new(HandlerTable::new()),
         }
     }
 
-    /// Query the list of pending IRQs.
-    fn pending_irqs(&self) -> PendingIRQs {
-        self.ro_registers.read(|regs| {
-            let pending_mask: u64 =
-                (u64::from(regs.PENDING_2.get()) << 32) | u64::from(regs.PENDING_1.get());
-
-            PendingIRQs::new(pending_mask)
-        })
+    /// Query the bitmask of pending IRQs.
+    fn pending_irqs_mask(&self) -> u64 {
+        self.ro_registers
+            .read(|regs| (u64::from(regs.PENDING_2.get()) << 32) | u64::from(regs.PENDING_1.get()))
     }
 }
 
@@ -107,7 +192,13 @@ impl driver::interface::DeviceDriver for PeripheralIC {
         "BCM Peripheral Interrupt Controller"
     }
 
-    unsafe fn init(&self) -> Result<(), &'static str> {
+    #[cfg(feature = "mmio_counters")]
+    fn mmio_accesses(&self) -> usize {
+        self.wo_registers.lock(|regs| regs.accesses())
+            + self.ro_registers.read(|regs| regs.accesses())
+    }
+
+    unsafe fn init(&self) -> Result<(), driver::DriverError> {
         let virt_addr =
             memory::mmu::kernel_map_mmio(self.compatible(), &self.phys_mmio_descriptor)?
                 .into_usize();
@@ -129,17 +220,13 @@ impl exception::asynchronous::interface::IRQManager for PeripheralIC {
         irq: Self::IRQNumberType,
         descriptor: exception::asynchronous::IRQDescriptor,
     ) -> Result<(), &'static str> {
-        self.handler_table.write(|table| {
-            let irq_number = irq.get();
-
-            if table[irq_number].is_some() {
-                return Err("IRQ handler already registered");
-            }
-
-            table[irq_number] = Some(descriptor);
+        self.handler_table
+            .write(|table| table.register(irq.get(), descriptor))
+    }
 
-            Ok(())
-        })
+    fn register_default_handler(&self, handler: exception::asynchronous::DefaultIRQHandler) {
+        self.handler_table
+            .write(|table| table.default_handler = Some(handler));
     }
 
     fn enable(&self, irq: Self::IRQNumberType) {
@@ -158,30 +245,72 @@ impl exception::asynchronous::interface::IRQManager for PeripheralIC {
         });
     }
 
+    fn disable(&self, irq: Self::IRQNumberType) {
+        self.wo_registers.lock(|regs| {
+            let disable_reg = if irq.get() <= 31 {
+                &regs.DISABLE_1
+            } else {
+                &regs.DISABLE_2
+            };
+
+            let disable_bit: u32 = 1 << (irq.get() % 32);
+
+            // Same as for enabling, only set bits have an effect.
+            disable_reg.set(disable_bit);
+        });
+    }
+
+    fn is_enabled(&self, irq: Self::IRQNumberType) -> bool {
+        // Reading the enable registers returns the set of currently enabled IRQs.
+        self.ro_registers.read(|regs| {
+            let enable_reg = if irq.get() <= 31 {
+                &regs.ENABLE_1
+            } else {
+                &regs.ENABLE_2
+            };
+
+            enable_reg.get() & (1 << (irq.get() % 32)) != 0
+        })
+    }
+
     fn handle_pending_irqs<'irq_context>(
         &'irq_context self,
         _ic: &exception::asynchronous::IRQContext<'irq_context>,
     ) {
         self.handler_table.read(|table| {
-            for irq_number in self.pending_irqs() {
-                match table[irq_number] {
-                    None => panic!("No handler registered for IRQ {}", irq_number),
-                    Some(descriptor) => {
-                        // Call the IRQ handler. Panics on failure.
-                        descriptor.handler.handle().expect("Error handling IRQ");
-                    }
-                }
+            let unhandled = table.for_each_pending(self.pending_irqs_mask(), |descriptor| {
+                // Call the IRQ handler. Panics on failure.
+                descriptor.handler.handle().expect("Error handling IRQ");
+            });
+
+            let default_handler = table
+                .default_handler
+                .unwrap_or(exception::asynchronous::mask_unhandled_irq);
+            for irq_number in PendingIRQs::new(unhandled) {
+                default_handler(irq_number, &|| self.disable(PeripheralIRQ::new(irq_number)));
             }
         })
     }
 
+    fn set_priority(
+        &self,
+        irq: Self::IRQNumberType,
+        priority: exception::asynchronous::IRQPriority,
+    ) {
+        self.handler_table.write(|table| {
+            if let Some(Some(descriptor)) = table.descriptors.get_mut(irq.get()) {
+                descriptor.priority = priority;
+            }
+        });
+    }
+
     fn print_handler(&self) {
         use crate::info;
 
         info!("      Peripheral handler:");
 
         self.handler_table.read(|table| {
-            for (i, opt) in table.iter().enumerate() {
+            for (i, opt) in table.descriptors.iter().enumerate() {
                 if let Some(handler) = opt {
                     info!("            {: >3}. {}", i, handler.name);
                 }
@@ -189,3 +318,223 @@ impl exception::asynchronous::interface::IRQManager for PeripheralIC {
         });
     }
 }
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use exception::asynchronous::{
+        interface::{IRQHandler, IRQManager},
+        IRQChainMode, IRQContext, IRQDescriptor, IRQHandlerChain, IRQPriority,
+    };
+    use test_macros::kernel_test;
+
+    /// Appends the handler's ID as a decimal digit to `CALL_LOG`.
+    struct RecordingHandler(usize);
+
+    static CALL_LOG: AtomicUsize = AtomicUsize::new(0);
+
+    impl IRQHandler for RecordingHandler {
+        fn handle(&self) -> Result<(), &'static str> {
+            let log = CALL_LOG.load(Ordering::Relaxed);
+            CALL_LOG.store(log * 10 + self.0, Ordering::Relaxed);
+
+            Ok(())
+        }
+    }
+
+    static HANDLER_1: RecordingHandler = RecordingHandler(1);
+    static HANDLER_2: RecordingHandler = RecordingHandler(2);
+    static HANDLER_3: RecordingHandler = RecordingHandler(3);
+
+    fn descriptor(handler: &'static RecordingHandler, priority: IRQPriority) -> IRQDescriptor {
+        IRQDescriptor {
+            name: "Test",
+            handler,
+            priority,
+        }
+    }
+
+    /// Higher priorities are dispatched first, equal priorities in registration order.
+    #[kernel_test]
+    fn pending_irqs_dispatch_by_priority() {
+        let mut table = HandlerTable::new();
+
+        table
+            .register(5, descriptor(&HANDLER_1, IRQPriority::Low))
+            .unwrap();
+        table
+            .register(40, descriptor(&HANDLER_2, IRQPriority::High))
+            .unwrap();
+        table
+            .register(3, descriptor(&HANDLER_3, IRQPriority::Low))
+            .unwrap();
+        assert!(table
+            .register(3, descriptor(&HANDLER_1, IRQPriority::Low))
+            .is_err());
+        assert_eq!(
+            table.register(
+                InterruptController::NUM_PERIPHERAL_IRQS,
+                descriptor(&HANDLER_1, IRQPriority::Low)
+            ),
+            Err("IRQ number exceeds the handler table")
+        );
+
+        let pending = (1 << 3) | (1 << 5) | (1 << 40) | (1 << 60);
+        let unhandled = table.for_each_pending(pending, |d| d.handler.handle().unwrap());
+
+        assert_eq!(CALL_LOG.load(Ordering::Relaxed), 213);
+        assert_eq!(unhandled, 1 << 60);
+    }
+
+    static CHAIN_ALL: IRQHandlerChain<2> = IRQHandlerChain::new(IRQChainMode::All);
+    static CHAIN_FIRST_CLAIM: IRQHandlerChain<2> = IRQHandlerChain::new(IRQChainMode::FirstClaim);
+
+    /// Two handlers chained on the UART's IRQ both run, or only the first one if it claims the
+    /// IRQ and the chain stops there. The chain length is bounded.
+    #[kernel_test]
+    fn chained_handlers_run_in_registration_order() {
+        const UART_IRQ: usize = 57;
+
+        // Both register blocks overlap, so one array backs them.
+        let mut mock = [0u32; 0x24 / 4];
+        mock[0x08 / 4] = 1 << (UART_IRQ - 32);
+
+        let ic = unsafe {
+            PeripheralIC::new(memory::mmu::MMIODescriptor::new(
+                memory::mmu::Address::new(mock.as_mut_ptr() as usize),
+                0x24,
+            ))
+        };
+
+        for chain in [&CHAIN_ALL, &CHAIN_FIRST_CLAIM].iter() {
+            chain.add(&HANDLER_1).unwrap();
+            chain.add(&HANDLER_2).unwrap();
+            assert_eq!(chain.add(&HANDLER_3), Err("IRQ handler chain is full"));
+        }
+
+        ic.register_handler(
+            PeripheralIRQ::new(UART_IRQ),
+            IRQDescriptor {
+                name: "Test chain",
+                handler: &CHAIN_ALL,
+                priority: IRQPriority::Low,
+            },
+        )
+        .unwrap();
+
+        CALL_LOG.store(0, Ordering::Relaxed);
+        unsafe { ic.handle_pending_irqs(&IRQContext::new()) };
+        assert_eq!(CALL_LOG.load(Ordering::Relaxed), 12);
+
+        CALL_LOG.store(0, Ordering::Relaxed);
+        CHAIN_FIRST_CLAIM.handle().unwrap();
+        assert_eq!(CALL_LOG.load(Ordering::Relaxed), 1);
+    }
+
+    /// Counts its calls.
+    struct CountingHandler(AtomicUsize);
+
+    impl IRQHandler for CountingHandler {
+        fn handle(&self) -> Result<(), &'static str> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+
+            Ok(())
+        }
+    }
+
+    static LOW_HANDLER: CountingHandler = CountingHandler(AtomicUsize::new(0));
+    static HIGH_HANDLER: CountingHandler = CountingHandler(AtomicUsize::new(0));
+
+    /// Two IRQs pending at once, one per pending register, both get their handler called. After
+    /// disabling them, neither is pending anymore, and neither handler runs.
+    #[kernel_test]
+    fn disabled_irqs_run_no_handler() {
+        const LOW_IRQ: usize = 5;
+        const HIGH_IRQ: usize = 40;
+
+        // Both register blocks overlap, so one array backs them.
+        let mut mock = [0u32; 0x24 / 4];
+        let regs = mock.as_mut_ptr();
+        let ic = unsafe {
+            PeripheralIC::new(memory::mmu::MMIODescriptor::new(
+                memory::mmu::Address::new(regs as usize),
+                0x24,
+            ))
+        };
+        let reg = |offset: usize| unsafe { core::ptr::read_volatile(regs.add(offset / 4)) };
+        let set_reg = |offset: usize, value| unsafe {
+            core::ptr::write_volatile(regs.add(offset / 4), value)
+        };
+
+        for (irq, handler) in [(LOW_IRQ, &LOW_HANDLER), (HIGH_IRQ, &HIGH_HANDLER)].iter() {
+            ic.register_handler(
+                PeripheralIRQ::new(*irq),
+                IRQDescriptor {
+                    name: "Test",
+                    handler: *handler,
+                    priority: IRQPriority::Low,
+                },
+            )
+            .unwrap();
+            ic.enable(PeripheralIRQ::new(*irq));
+        }
+        assert_eq!(reg(0x10), 1 << LOW_IRQ);
+        assert_eq!(reg(0x14), 1 << (HIGH_IRQ - 32));
+
+        set_reg(0x04, 1 << LOW_IRQ);
+        set_reg(0x08, 1 << (HIGH_IRQ - 32));
+        unsafe { ic.handle_pending_irqs(&IRQContext::new()) };
+        assert_eq!(LOW_HANDLER.0.load(Ordering::Relaxed), 1);
+        assert_eq!(HIGH_HANDLER.0.load(Ordering::Relaxed), 1);
+
+        ic.disable(PeripheralIRQ::new(LOW_IRQ));
+        ic.disable(PeripheralIRQ::new(HIGH_IRQ));
+        assert_eq!(reg(0x1c), 1 << LOW_IRQ);
+        assert_eq!(reg(0x20), 1 << (HIGH_IRQ - 32));
+
+        // The controller clears the enable and pending bits of disabled IRQs.
+        for offset in [0x04, 0x08, 0x10, 0x14].iter() {
+            set_reg(*offset, 0);
+        }
+        assert!(!ic.is_enabled(PeripheralIRQ::new(LOW_IRQ)));
+        assert!(!ic.is_enabled(PeripheralIRQ::new(HIGH_IRQ)));
+
+        unsafe { ic.handle_pending_irqs(&IRQContext::new()) };
+        assert_eq!(LOW_HANDLER.0.load(Ordering::Relaxed), 1);
+        assert_eq!(HIGH_HANDLER.0.load(Ordering::Relaxed), 1);
+    }
+
+    static DEFAULT_HANDLER_IRQ: AtomicUsize = AtomicUsize::new(0);
+
+    fn record_and_mask(irq_number: usize, mask: &dyn Fn()) {
+        DEFAULT_HANDLER_IRQ.store(irq_number, Ordering::Relaxed);
+        mask();
+    }
+
+    /// A pending IRQ without a handler goes to the default handler, which masks it in the
+    /// controller.
+    #[kernel_test]
+    fn unhandled_irq_is_masked_by_default_handler() {
+        // Both register blocks overlap, so one array backs them.
+        let mut mock = [0u32; 0x24 / 4];
+        mock[0x04 / 4] = 1 << 5;
+
+        let ic = unsafe {
+            PeripheralIC::new(memory::mmu::MMIODescriptor::new(
+                memory::mmu::Address::new(mock.as_mut_ptr() as usize),
+                0x24,
+            ))
+        };
+        ic.register_default_handler(record_and_mask);
+
+        unsafe { ic.handle_pending_irqs(&IRQContext::new()) };
+
+        assert_eq!(DEFAULT_HANDLER_IRQ.load(Ordering::Relaxed), 5);
+        assert_eq!(unsafe { core::ptr::read_volatile(&mock[0x1c / 4]) }, 1 << 5);
+    }
+}
@@ -4,15 +4,24 @@
 
 //! PL011 UART driver.
 
+mod uart16550;
+
+pub use uart16550::Uart16550Shim;
+
 use crate::{
-    bsp, bsp::device_driver::common::MMIODerefWrapper, console, cpu, driver, exception, memory,
-    memory::mmu::Physical, synchronization, synchronization::IRQSafeNullLock,
+    boot, bsp, bsp::device_driver::common::MMIODerefWrapper, checksum, collections::RingBuffer,
+    console, cpu, driver, exception, framing::slip, memory, memory::mmu::Physical, print,
+    synchronization, synchronization::IRQSafeNullLock, telemetry, time, xmodem,
 };
 use core::{
-    fmt,
-    sync::atomic::{AtomicUsize, Ordering},
+    cmp, fmt,
+    num::NonZeroUsize,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::Duration,
 };
-use register::{mmio::*, register_bitfields, register_structs};
+use register::{mmio::*, register_bitfields, register_structs, LocalRegisterCopy};
+
+use bsp::device_driver::{interface::ClockControl, ClockId, OutputPin};
 
 //--------------------------------------------------------------------------------------------------
 // Private Definitions
@@ -35,6 +44,13 @@ register_bitfields! {
         /// not indicate if there is data in the transmit shift register.
         TXFE OFFSET(7) NUMBITS(1) [],
 
+        /// Receive FIFO full. The meaning of this bit depends on the state of the FEN bit in the
+        /// UARTLCR_ LCRH Register.
+        ///
+        /// If the FIFO is disabled, this bit is set when the receive holding register is full. If
+        /// the FIFO is enabled, the RXFF bit is set when the receive FIFO is full.
+        RXFF OFFSET(6) NUMBITS(1) [],
+
         /// Transmit FIFO full. The meaning of this bit depends on the state of the FEN bit in the
         /// UARTLCR_ LCRH Register.
         ///
@@ -47,7 +63,19 @@ register_bitfields! {
         ///
         /// If the FIFO is disabled, this bit is set when the receive holding register is empty. If
         /// the FIFO is enabled, the RXFE bit is set when the receive FIFO is empty.
-        RXFE OFFSET(4) NUMBITS(1) []
+        RXFE OFFSET(4) NUMBITS(1) [],
+
+        /// UART busy. If this bit is set to 1, the UART is busy transmitting data. This bit remains
+        /// set until the complete byte, including all the stop bits, has been sent from the shift
+        /// register.
+        ///
+        /// This bit is set as soon as the transmit FIFO becomes non-empty, regardless of whether
+        /// the UART is enabled or not.
+        BUSY OFFSET(3) NUMBITS(1) [],
+
+        /// Clear to send. This bit is the complement of the UART clear to send, nUARTCTS, modem
+        /// status input. That is, the bit is 1 when nUARTCTS is LOW.
+        CTS OFFSET(0) NUMBITS(1) []
     ],
 
     /// Integer Baud rate divisor
@@ -64,6 +92,18 @@ register_bitfields! {
 
     /// Line Control register
     LCRH [
+        /// Stick parity select.
+        ///
+        /// 0 = stick parity is disabled
+        ///
+        /// 1 = either:
+        /// - if the EPS bit is 0 then the parity bit is transmitted and checked as a 1
+        /// - if the EPS bit is 1 then the parity bit is transmitted and checked as a 0.
+        SPS OFFSET(7) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ],
+
         /// Word length. These bits indicate the number of data bits transmitted or received in a
         /// frame.
         WLEN OFFSET(5) NUMBITS(2) [
@@ -82,7 +122,43 @@ register_bitfields! {
         FEN  OFFSET(4) NUMBITS(1) [
             FifosDisabled = 0,
             FifosEnabled = 1
-        ]
+        ],
+
+        /// Two stop bits select. If this bit is set to 1, two stop bits are transmitted at the end
+        /// of the frame. The receive logic does not check for two stop bits being received.
+        STP2 OFFSET(3) NUMBITS(1) [
+            One = 0,
+            Two = 1
+        ],
+
+        /// Even parity select. Controls the type of parity the UART uses during transmission and
+        /// reception:
+        ///
+        /// 0 = odd parity. The UART generates or checks for an odd number of 1s in the data and
+        /// parity bits.
+        ///
+        /// 1 = even parity. The UART generates or checks for an even number of 1s in the data and
+        /// parity bits.
+        ///
+        /// This bit has no effect when the PEN bit disables parity checking and generation.
+        EPS OFFSET(2) NUMBITS(1) [
+            Odd = 0,
+            Even = 1
+        ],
+
+        /// Parity enable:
+        ///
+        /// 0 = parity is disabled and no parity bit added to the data frame
+        ///
+        /// 1 = parity checking and generation is enabled.
+        PEN OFFSET(1) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ],
+
+        /// Send break. If this bit is set to 1, a low-level is continually output on the TXD
+        /// output, after completing transmission of the current character.
+        BRK OFFSET(0) NUMBITS(1) []
     ],
 
     /// Control Register
@@ -103,6 +179,13 @@ register_bitfields! {
             Enabled = 1
         ],
 
+        /// Loopback enable. If this bit is set to 1, the UARTTXD path is fed through to the
+        /// UARTRXD path.
+        LBE    OFFSET(7) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ],
+
         /// UART enable
         UARTEN OFFSET(0) NUMBITS(1) [
             /// If the UART is disabled in the middle of transmission or reception, it completes the
@@ -116,7 +199,17 @@ register_bitfields! {
     IFLS [
         /// Receive interrupt FIFO level select. The trigger points for the receive interrupt are as
         /// follows.
-        RXIFLSEL OFFSET(3) NUMBITS(5) [
+        RXIFLSEL OFFSET(3) NUMBITS(3) [
+            OneEigth = 0b000,
+            OneQuarter = 0b001,
+            OneHalf = 0b010,
+            ThreeQuarters = 0b011,
+            SevenEights = 0b100
+        ],
+
+        /// Transmit interrupt FIFO level select. The trigger points for the transmit interrupt are
+        /// as follows.
+        TXIFLSEL OFFSET(0) NUMBITS(3) [
             OneEigth = 0b000,
             OneQuarter = 0b001,
             OneHalf = 0b010,
@@ -135,11 +228,26 @@ register_bitfields! {
             Enabled = 1
         ],
 
+        /// Transmit interrupt mask. A read returns the current mask for the UARTTXINTR interrupt.
+        /// On a write of 1, the mask of the interrupt is set. A write of 0 clears the mask.
+        TXIM OFFSET(5) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ],
+
         /// Receive interrupt mask. A read returns the current mask for the UARTRXINTR interrupt. On
         /// a write of 1, the mask of the interrupt is set. A write of 0 clears the mask.
         RXIM OFFSET(4) NUMBITS(1) [
             Disabled = 0,
             Enabled = 1
+        ],
+
+        /// nUARTCTS modem interrupt mask. A read returns the current mask for the UARTCTSINTR
+        /// interrupt. On a write of 1, the mask of the interrupt is set. A write of 0 clears the
+        /// mask.
+        CTSMIM OFFSET(1) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
         ]
     ],
 
@@ -149,322 +257,7478 @@ register_bitfields! {
         /// UARTRTINTR interrupt.
         RTMIS OFFSET(6) NUMBITS(1) [],
 
+        /// Transmit masked interrupt status. Returns the masked interrupt state of the UARTTXINTR
+        /// interrupt.
+        TXMIS OFFSET(5) NUMBITS(1) [],
+
         /// Receive masked interrupt status. Returns the masked interrupt state of the UARTRXINTR
         /// interrupt.
-        RXMIS OFFSET(4) NUMBITS(1) []
+        RXMIS OFFSET(4) NUMBITS(1) [],
+
+        /// nUARTCTS modem masked interrupt status. Returns the masked interrupt state of the
+        /// UARTCTSINTR interrupt.
+        CTSMMIS OFFSET(1) NUMBITS(1) []
     ],
 
     /// Interrupt Clear Register
     ICR [
+        /// Receive timeout interrupt clear. Clears the UARTRTINTR interrupt.
+        RTIC OFFSET(6) NUMBITS(1) [],
+
+        /// Transmit interrupt clear. Clears the UARTTXINTR interrupt.
+        TXIC OFFSET(5) NUMBITS(1) [],
+
+        /// Receive interrupt clear. Clears the UARTRXINTR interrupt.
+        RXIC OFFSET(4) NUMBITS(1) [],
+
+        /// nUARTCTS modem interrupt clear. Clears the UARTCTSINTR interrupt.
+        CTSMIC OFFSET(1) NUMBITS(1) [],
+
         /// Meta field for all pending interrupts
         ALL OFFSET(0) NUMBITS(11) []
+    ],
+
+    /// DMA Control Register
+    DMACR [
+        /// DMA on error. If set, the RX DMA request is disabled when a receive error occurs.
+        DMAONERR OFFSET(2) NUMBITS(1) [],
+
+        /// Transmit DMA enable.
+        TXDMAE OFFSET(1) NUMBITS(1) [],
+
+        /// Receive DMA enable.
+        RXDMAE OFFSET(0) NUMBITS(1) []
+    ],
+
+    /// Test Control Register
+    ITCR [
+        /// Test FIFO enable. When this bit is 1, a write to the Test Data Register, UART_TDR
+        /// writes data into the receive FIFO, and reads from the UART_TDR register reads data out
+        /// of the transmit FIFO.
+        ///
+        /// When this bit is 0, data cannot be read directly from the transmit FIFO or written
+        /// directly to the receive FIFO (normal operation).
+        ITCR1 OFFSET(1) NUMBITS(1) [],
+
+        /// Integration test enable. When this bit is 1, the UART is placed in integration test
+        /// mode, otherwise it is in normal mode.
+        ITCR0 OFFSET(0) NUMBITS(1) []
     ]
 }
 
-register_structs! {
+/// `register_structs!`, which also generates [`REGISTER_MAP`] from the same definitions, so that
+/// the two cannot drift apart.
+macro_rules! register_structs_with_map {
+    (@map [$($descs:tt)*]) => {
+        /// The layout of the registers, in the order of their offsets. Reserved gaps are left out.
+        #[allow(dead_code)] // For tooling and tests, the driver itself does not need it.
+        pub const REGISTER_MAP: &[RegisterDesc] = &[$($descs)*];
+    };
+    (
+        @map [$($descs:tt)*]
+        ($offset:literal => $field:ident: $access:ident<$width:ty $(, $bits:path)?>),
+        $($rest:tt)*
+    ) => {
+        register_structs_with_map!(@map [
+            $($descs)*
+            RegisterDesc {
+                name: stringify!($field),
+                offset: $offset,
+                access: RegisterAccess::$access,
+                width: core::mem::size_of::<$width>() * 8,
+            },
+        ] $($rest)*);
+    };
+    // Reserved gaps and the end marker.
+    (@map [$($descs:tt)*] ($offset:literal => $($skipped:tt)*), $($rest:tt)*) => {
+        register_structs_with_map!(@map [$($descs)*] $($rest)*);
+    };
+    ($(#[$attr:meta])* pub $name:ident { $($entries:tt)* }) => {
+        register_structs! {
+            $(#[$attr])*
+            pub $name {
+                $($entries)*
+            }
+        }
+
+        register_structs_with_map!(@map [] $($entries)*);
+    };
+}
+
+register_structs_with_map! {
     #[allow(non_snake_case)]
     pub RegisterBlock {
         (0x00 => DR: ReadWrite<u32>),
-        (0x04 => _reserved1),
+        (0x04 => RSRECR: ReadWrite<u32>),
+        (0x08 => _reserved1),
         (0x18 => FR: ReadOnly<u32, FR::Register>),
         (0x1c => _reserved2),
-        (0x24 => IBRD: WriteOnly<u32, IBRD::Register>),
-        (0x28 => FBRD: WriteOnly<u32, FBRD::Register>),
-        (0x2c => LCRH: WriteOnly<u32, LCRH::Register>),
-        (0x30 => CR: WriteOnly<u32, CR::Register>),
+        (0x24 => IBRD: ReadWrite<u32, IBRD::Register>),
+        (0x28 => FBRD: ReadWrite<u32, FBRD::Register>),
+        (0x2c => LCRH: ReadWrite<u32, LCRH::Register>),
+        (0x30 => CR: ReadWrite<u32, CR::Register>),
         (0x34 => IFLS: ReadWrite<u32, IFLS::Register>),
         (0x38 => IMSC: ReadWrite<u32, IMSC::Register>),
         (0x3C => _reserved3),
         (0x40 => MIS: ReadOnly<u32, MIS::Register>),
         (0x44 => ICR: WriteOnly<u32, ICR::Register>),
-        (0x48 => @END),
+        (0x48 => DMACR: ReadWrite<u32, DMACR::Register>),
+        (0x4c => _reserved4),
+        (0x80 => ITCR: ReadWrite<u32, ITCR::Register>),
+        (0x84 => ITIP: ReadWrite<u32>),
+        (0x88 => ITOP: ReadWrite<u32>),
+        (0x8c => TDR: ReadWrite<u32>),
+        (0x90 => @END),
     }
 }
 
 /// Abstraction for the associated MMIO registers.
 type Registers = MMIODerefWrapper<RegisterBlock>;
 
-#[derive(PartialEq)]
-enum BlockingMode {
-    Blocking,
-    NonBlocking,
-}
+/// Number of entries in the TX FIFO.
+const TX_FIFO_DEPTH: usize = 16;
 
-//--------------------------------------------------------------------------------------------------
-// Public Definitions
-//--------------------------------------------------------------------------------------------------
+/// Number of bytes taken from the RX FIFO in one go before they are processed. Smaller than the
+/// FIFO, to keep the stack footprint of the interrupt handler small.
+const RX_BATCH_SIZE: usize = 8;
 
-pub struct PL011UartInner {
-    registers: Registers,
-    chars_written: usize,
-    chars_read: usize,
-}
+/// Number of received bytes that can be buffered in software.
+const RX_BUFFER_SIZE: usize = 256;
 
-// Export the inner struct so that BSPs can use it for the panic handler.
-pub use PL011UartInner as PanicUart;
+/// Number of characters that can be accumulated in line-buffered mode.
+const LINE_BUFFER_SIZE: usize = 128;
 
-/// Representation of the UART.
-pub struct PL011Uart {
-    phys_mmio_descriptor: memory::mmu::MMIODescriptor<Physical>,
-    virt_mmio_start_addr: AtomicUsize,
-    inner: IRQSafeNullLock<PL011UartInner>,
-    irq_number: bsp::device_driver::IRQNumber,
-}
+/// Number of bytes of a received line that are handed to the line callback at once.
+const RX_LINE_CHUNK_SIZE: usize = 64;
 
-//--------------------------------------------------------------------------------------------------
-// Public Code
-//--------------------------------------------------------------------------------------------------
+/// The UART reference clock, as set in config.txt.
+const UART_CLOCK_HZ: u32 = 48_000_000;
 
-impl PL011UartInner {
-    /// Create an instance.
-    ///
-    /// # Safety
-    ///
-    /// - The user must ensure to provide a correct MMIO start address.
-    pub const unsafe fn new(mmio_start_addr: usize) -> Self {
-        Self {
-            registers: Registers::new(mmio_start_addr),
-            chars_written: 0,
-            chars_read: 0,
-        }
-    }
+/// The lowest UART reference clock that is taken as plausible. It still produces 57600 baud.
+const MIN_REF_CLOCK_HZ: u32 = 1_000_000;
 
-    /// Set up baud rate and characteristics.
-    ///
-    /// The calculation for the BRD given a target rate of 2300400 and a clock set to 48 MHz is:
-    /// `(48_000_000/16)/230400 = 13,02083`. `13` goes to the `IBRD` (integer field). The `FBRD`
-    /// (fractional field) is only 6 bits so `0,0208*64 = 1,3312 rounded to 1` will give the best
-    /// approximation we can get. A 5 % error margin is acceptable for UART and we're now at 0,01 %.
-    ///
-    /// This results in 8N1 and 230400 baud (we set the clock to 48 MHz in config.txt).
-    ///
-    /// # Safety
-    ///
-    /// - The user must ensure to provide a correct MMIO start address.
-    pub unsafe fn init(&mut self, new_mmio_start_addr: Option<usize>) -> Result<(), &'static str> {
-        if let Some(addr) = new_mmio_start_addr {
-            self.registers = Registers::new(addr);
-        }
+/// The baud rate that `init()` programs.
+const INIT_BAUD_RATE: u32 = 230_400;
 
-        // Turn it off temporarily.
-        self.registers.CR.set(0);
+/// How far the programmed baud rate may deviate from the target, in percent. A UART link
+/// tolerates about 5 % in total, of which the other end needs its share.
+const DEFAULT_BAUD_TOLERANCE: u32 = 2;
 
-        self.registers.ICR.write(ICR::ALL::CLEAR);
-        self.registers.IBRD.write(IBRD::IBRD.val(13));
-        self.registers.FBRD.write(FBRD::FBRD.val(1));
-        self.registers
-            .LCRH
-            .write(LCRH::WLEN::EightBit + LCRH::FEN::FifosEnabled); // 8N1 + Fifo on
-        self.registers.IFLS.write(IFLS::RXIFLSEL::OneEigth); // RX FIFO fill level at 1/8
-        self.registers
-            .IMSC
-            .write(IMSC::RXIM::Enabled + IMSC::RTIM::Enabled); // RX IRQ + RX timeout IRQ
-        self.registers
-            .CR
-            .write(CR::UARTEN::Enabled + CR::TXE::Enabled + CR::RXE::Enabled);
+/// Framing error flag in the data register.
+const DR_FE: u16 = 1 << 8;
 
-        Ok(())
-    }
+/// Parity error flag in the data register.
+const DR_PE: u16 = 1 << 9;
 
-    /// Send a character.
-    fn write_char(&mut self, c: char) {
-        // Spin while TX FIFO full is set, waiting for an empty slot.
-        while self.registers.FR.matches_all(FR::TXFF::SET) {
-            cpu::nop();
-        }
+/// Break error flag in the data register.
+const DR_BE: u16 = 1 << 10;
 
-        // Write the character to the buffer.
-        self.registers.DR.set(c as u32);
+/// Overrun error flag in the data register.
+const DR_OE: u16 = 1 << 11;
 
-        self.chars_written += 1;
-    }
+/// Number of received bytes over which framing errors are counted.
+const FRAMING_ERROR_WINDOW: u32 = 64;
 
-    /// Retrieve a character.
-    fn read_char_converting(&mut self, blocking_mode: BlockingMode) -> Option<char> {
-        // If RX FIFO is empty,
-        if self.registers.FR.matches_all(FR::RXFE::SET) {
-            // immediately return in non-blocking mode.
-            if blocking_mode == BlockingMode::NonBlocking {
-                return None;
-            }
+/// Framing errors per window above which a baud rate mismatch is suspected.
+const FRAMING_ERROR_THRESHOLD: u32 = FRAMING_ERROR_WINDOW / 4;
 
-            // Otherwise, wait until a char was received.
-            while self.registers.FR.matches_all(FR::RXFE::SET) {
-                cpu::nop();
-            }
-        }
+/// Number of received bytes over which the RX line state is judged.
+const RX_LINE_WINDOW: u32 = 32;
 
-        // Read one character.
-        let mut ret = self.registers.DR.get() as u8 as char;
+/// Breaks in a row after which the RX line is considered stuck low.
+const RX_LINE_STUCK_LOW_BREAKS: u32 = 4;
 
-        // Convert carrige return to newline.
-        if ret == '\r' {
-            ret = '\n'
-        }
+/// How long a verdict that needs ongoing traffic holds after the last received byte.
+const RX_LINE_STATE_MAX_AGE: Duration = Duration::from_secs(1);
 
-        // Update statistics.
-        self.chars_read += 1;
+/// How often a single `write_bytes()` steps aside for pending RX interrupts. Bounded, so that
+/// continuous RX cannot stall the write.
+const MAX_RX_YIELDS_PER_WRITE: usize = 16;
 
-        Some(ret)
-    }
-}
+/// How long `read_scatter()` waits for the next byte before it returns early.
+const SCATTER_IDLE_TIMEOUT: Duration = Duration::from_millis(100);
 
-/// Implementing `core::fmt::Write` enables usage of the `format_args!` macros, which in turn are
-/// used to implement the `kernel`'s `print!` and `println!` macros. By implementing `write_str()`,
-/// we get `write_fmt()` automatically.
-///
-/// The function takes an `&mut self`, so it must be implemented for the inner struct.
-///
-/// See [`src/print.rs`].
-///
-/// [`src/print.rs`]: ../../print/index.html
-impl fmt::Write for PL011UartInner {
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        for c in s.chars() {
-            self.write_char(c);
-        }
+/// How long a UTF-8 read waits for each continuation byte before it gives up on the sequence.
+const UTF8_CONTINUATION_TIMEOUT: Duration = Duration::from_millis(10);
 
-        Ok(())
-    }
-}
+/// Number of received bytes an [`RxRecording`] holds.
+const RX_RECORDING_SIZE: usize = 256;
 
-impl PL011Uart {
-    /// Create an instance.
-    ///
-    /// # Safety
-    ///
-    /// - The user must ensure to provide correct MMIO descriptors.
-    /// - The user must ensure to provide correct IRQ numbers.
-    pub const unsafe fn new(
-        phys_mmio_descriptor: memory::mmu::MMIODescriptor<Physical>,
-        irq_number: bsp::device_driver::IRQNumber,
-    ) -> Self {
-        Self {
-            phys_mmio_descriptor,
-            virt_mmio_start_addr: AtomicUsize::new(0),
-            inner: IRQSafeNullLock::new(PL011UartInner::new(
-                phys_mmio_descriptor.start_addr().into_usize(),
-            )),
-            irq_number,
-        }
-    }
-}
+/// How long `record_rx()` waits for another byte before it considers the session over.
+const RX_RECORDING_IDLE_TIMEOUT: Duration = Duration::from_secs(1);
 
-//------------------------------------------------------------------------------
-// OS Interface Code
-//------------------------------------------------------------------------------
-use synchronization::interface::Mutex;
+/// How much later than recorded a replayed byte may be fed before it counts as late.
+const RX_REPLAY_TOLERANCE: Duration = Duration::from_micros(100);
 
-impl driver::interface::DeviceDriver for PL011Uart {
-    fn compatible(&self) -> &'static str {
-        "BCM PL011 UART"
-    }
+/// Sent by `physical_loopback_test()`. Alternating bits, and both extremes.
+const LOOPBACK_PATTERN: [u8; 4] = [0x55, 0xaa, 0x00, 0xff];
 
-    unsafe fn init(&self) -> Result<(), &'static str> {
-        let virt_addr =
-            memory::mmu::kernel_map_mmio(self.compatible(), &self.phys_mmio_descriptor)?;
+/// How long `physical_loopback_test()` waits for each byte to come back.
+const LOOPBACK_TIMEOUT: Duration = Duration::from_millis(10);
 
-        self.inner
-            .lock(|inner| inner.init(Some(virt_addr.into_usize())))?;
+/// How many bytes `PL011Uart::verify_baud()` times. The time of all but the first one is
+/// measured, so 16 leave the timer resolution and the polling delay at well below a percent.
+const BAUD_CHECK_BYTES: usize = 16;
 
-        self.virt_mmio_start_addr
-            .store(virt_addr.into_usize(), Ordering::Relaxed);
+/// Tags the serial settings in the persistent area, see `PL011Uart::persist_config()`.
+const PERSIST_MAGIC: u32 = 0x504c_3131; // "PL11"
 
-        Ok(())
-    }
+/// Magic, baud rate, reference clock, stop bits, a reserved byte and a CRC-16 over the rest, all
+/// little endian.
+const PERSISTED_CONFIG_LEN: usize = 16;
 
-    fn register_and_enable_irq_handler(&'static self) -> Result<(), &'static str> {
-        use bsp::exception::asynchronous::irq_manager;
-        use exception::asynchronous::{interface::IRQManager, IRQDescriptor};
+/// Number of received bytes kept by the initial capture.
+const INITIAL_CAPTURE_SIZE: usize = 16;
 
-        let descriptor = IRQDescriptor {
-            name: "BCM PL011 UART",
-            handler: self,
-        };
+/// Returned by blocking reads while the receiver is disabled, since nothing can arrive to wait for.
+const RX_DISABLED_BYTE: u8 = 0;
 
-        irq_manager().register_handler(self.irq_number, descriptor)?;
-        irq_manager().enable(self.irq_number);
+/// [`RX_DISABLED_BYTE`] as a character.
+const RX_DISABLED_CHAR: char = RX_DISABLED_BYTE as char;
 
-        Ok(())
-    }
+/// A received byte in the software buffer, with its error flags and the uptime at which the
+/// driver picked it up.
+///
+/// Kept in one slot, so that a byte can never be paired with the timestamp of another.
+#[derive(Copy, Clone)]
+struct RxSlot {
+    byte: u8,
 
-    fn virt_mmio_start_addr(&self) -> Option<usize> {
-        let addr = self.virt_mmio_start_addr.load(Ordering::Relaxed);
+    /// Bits 11:8 of the data register, shifted down.
+    flags: u8,
+    timestamp: Duration,
+}
 
-        if addr == 0 {
-            return None;
-        }
+/// A copy of the software RX buffer in logical order, printed as a hexdump.
+struct RxRingDump {
+    bytes: [u8; RX_BUFFER_SIZE],
+    len: usize,
+}
 
-        Some(addr)
-    }
+/// Decides which writes fail, see [`WriteFaults`].
+#[cfg(test)]
+struct FaultInjector {
+    percent: u32,
+    state: u32,
 }
 
-impl console::interface::Write for PL011Uart {
-    /// Passthrough of `args` to the `core::fmt::Write` implementation, but guarded by a Mutex to
-    /// serialize access.
-    fn write_char(&self, c: char) {
-        self.inner.lock(|inner| inner.write_char(c));
-    }
+#[derive(Copy, Clone, PartialEq)]
+enum BlockingMode {
+    Blocking,
+    NonBlocking,
+}
 
-    fn write_fmt(&self, args: core::fmt::Arguments) -> fmt::Result {
-        // Fully qualified syntax for the call to `core::fmt::Write::write:fmt()` to increase
-        // readability.
-        self.inner.lock(|inner| fmt::Write::write_fmt(inner, args))
-    }
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
 
-    fn flush(&self) {
-        // Spin until TX FIFO empty is set.
-        self.inner.lock(|inner| {
-            while !inner.registers.FR.matches_all(FR::TXFE::SET) {
-                cpu::nop();
-            }
-        });
-    }
+/// How a register can be accessed, see [`RegisterDesc`].
+#[allow(missing_docs)]
+#[allow(dead_code)] // Only constructed in REGISTER_MAP.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RegisterAccess {
+    ReadOnly,
+    WriteOnly,
+    ReadWrite,
 }
 
-impl console::interface::Read for PL011Uart {
-    fn read_char(&self) -> char {
-        self.inner
-            .lock(|inner| inner.read_char_converting(BlockingMode::Blocking).unwrap())
-    }
+/// Describes a register of the UART, for tooling. See [`REGISTER_MAP`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RegisterDesc {
+    /// The name as in the datasheet, without the `UART_` prefix.
+    pub name: &'static str,
 
-    fn clear(&self) {
-        self.inner.lock(|inner| {
-            // Read from the RX FIFO until it is indicating empty.
-            while !inner.registers.FR.matches_all(FR::RXFE::SET) {
-                inner.registers.DR.get();
-            }
-        })
-    }
+    /// The offset from the start of the register block in bytes.
+    pub offset: usize,
+
+    /// How the register can be accessed.
+    pub access: RegisterAccess,
+
+    /// The width in bits.
+    pub width: usize,
 }
 
-impl console::interface::Statistics for PL011Uart {
-    fn chars_written(&self) -> usize {
+/// Tracks when the TX side went idle, for sending keep-alive bytes.
+struct KeepAliveState {
+    config: KeepAlive,
+    chars_written: usize,
+    idle_since: Option<Duration>,
+}
+
+/// Progress of the baud rate search, see [`PL011Uart::set_auto_baud()`].
+struct AutoBaudState {
+    config: AutoBaud,
+
+    /// Framing error windows in a row in which a baud rate mismatch was suspected.
+    bad_windows: u32,
+
+    /// The rate the current search started from, and how many switches it made since.
+    search: Option<(u32, usize)>,
+
+    /// The rate to switch to before the next read.
+    pending: Option<u32>,
+}
+
+/// Watches the framing error rate of received bytes.
+///
+/// The verdict is renewed after every window, so a single burst of errors does not stick.
+struct FramingErrorMonitor {
+    window_bytes: u32,
+    window_errors: u32,
+    baud_mismatch_suspected: bool,
+}
+
+/// Judges the RX line from the pattern of received bytes and their error flags.
+struct RxLineMonitor {
+    window_bytes: u32,
+    window_errors: u32,
+    window_all_ones: u32,
+    breaks_in_a_row: u32,
+    state: RxLineState,
+}
+
+/// What is left of the grace window after init, see [`PL011Uart::set_connect_grace()`].
+#[derive(Copy, Clone)]
+enum GraceWindow {
+    /// Open until this time since boot.
+    Until(Duration),
+
+    /// Open for this many more received bytes.
+    Bytes(usize),
+}
+
+/// A hook called from the UART's IRQ handler.
+///
+/// Hooks run with the UART locked, so they must not use the UART themselves.
+pub type RxIRQHook = fn();
+
+/// A callback that sees every received byte before it is echoed and buffered, see
+/// [`PL011Uart::set_rx_callback()`].
+///
+/// Returns true if it consumed the byte, which is then not buffered. Runs with the UART locked,
+/// like [`RxIRQHook`].
+pub type RxByteCallback = &'static (dyn Fn(u8) -> bool + Sync);
+
+/// A callback that is handed every received line, see [`PL011Uart::set_line_callback()`].
+///
+/// Gets the line without the newline, and true if the line continues in the next call. Runs
+/// without the UART locked, so it can use the UART itself.
+pub type LineCallback = &'static (dyn Fn(&[u8], bool) + Sync);
+
+/// A hook called when the TX FIFO drained to its watermark, see [`PL011Uart::arm_tx_ready()`].
+///
+/// Runs with the UART locked, like [`RxIRQHook`]. So it cannot write the data itself, but must
+/// signal the producer to do so.
+pub type TxReadyHook = fn();
+
+/// A hook called when the remote changed CTS, see [`PL011Uart::set_cts_change_hook()`].
+///
+/// Gets the new level, true if the remote is ready to receive. Runs with the UART locked, like
+/// [`RxIRQHook`].
+pub type CtsChangeHook = fn(bool);
+
+/// An LED that blinks on TX activity, see [`PL011Uart::set_tx_activity_led()`].
+///
+/// Driven with the UART locked, like [`RxIRQHook`].
+pub type ActivityLed = &'static (dyn OutputPin + Sync);
+
+/// A hook called when a baud rate mismatch starts being suspected.
+///
+/// Runs with the UART locked, like [`RxIRQHook`].
+pub type BaudMismatchHook = fn();
+
+/// The first bytes received after [`PL011Uart::start_initial_capture()`], e.g. for guessing what
+/// is connected.
+#[derive(Copy, Clone)]
+pub struct InitialCapture {
+    bytes: [u8; INITIAL_CAPTURE_SIZE],
+    len: usize,
+}
+
+/// How long received NUL bytes are dropped after init, see [`PL011Uart::set_connect_grace()`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ConnectGrace {
+    /// For this long after init.
+    Duration(Duration),
+
+    /// For this many received bytes after init, NUL or not.
+    Bytes(usize),
+}
+
+/// What happens to console output while the UART is locked already, see
+/// [`PL011Uart::set_reentrant_write()`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ReentrantWrite {
+    /// Written to the registers directly, bypassing the driver.
+    Raw,
+
+    /// Dropped.
+    Drop,
+}
+
+/// What the read path does with bytes that were received with a framing, parity or break error,
+/// see [`PL011Uart::set_rx_error_retry()`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RxErrorRetry {
+    /// Sent to the peer for every dropped byte, to request it again.
+    pub nak: Option<u8>,
+
+    /// How many errored bytes in a row are dropped. The next one is delivered with its error
+    /// flags, so that a broken line cannot keep a read going forever.
+    pub max_retries: usize,
+}
+
+/// Received bytes with their relative timing, see [`PL011Uart::record_rx()`].
+pub struct RxRecording {
+    /// Timestamps are relative to the first byte.
+    slots: [RxSlot; RX_RECORDING_SIZE],
+    len: usize,
+}
+
+/// How closely [`PL011Uart::replay_rx()`] kept to the recorded timing.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RxReplayTiming {
+    /// Bytes that were fed more than 100 µs later than recorded.
+    pub late: usize,
+
+    /// The largest delay of a byte against its recorded time.
+    pub max_lag: Duration,
+}
+
+/// The outcome of [`PL011Uart::timed_loopback_test()`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LoopbackTiming {
+    /// True if every byte came back unchanged.
+    pub matched: bool,
+
+    /// The mean time from sending a byte to receiving it back.
+    pub round_trip: Duration,
+
+    /// The longest of those times.
+    pub max_round_trip: Duration,
+}
+
+/// A byte that is sent whenever TX was idle for a while, see [`PL011Uart::poll_keep_alive()`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct KeepAlive {
+    /// The byte to send.
+    pub byte: u8,
+
+    /// How long TX must be idle before the byte is sent.
+    pub idle_time: Duration,
+}
+
+/// Switching to other baud rates under sustained framing errors, see
+/// [`PL011Uart::set_auto_baud()`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AutoBaud {
+    /// The rates to try, in order, e.g. the common ones from 921600 down to 9600.
+    pub rates: &'static [u32],
+
+    /// Framing error windows in a row after which the next rate is tried. A window lasts 64
+    /// received bytes.
+    pub sustained_windows: u32,
+}
+
+/// What the received data says about the RX line, see [`PL011Uart::rx_line_state()`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RxLineState {
+    /// Nothing suspicious. Also the verdict for a line that is idle.
+    Normal,
+
+    /// Break after break, as from a shorted line or a TX that is not powered.
+    StuckLow,
+
+    /// Nothing but 0xff, as from short glitches on a line that is otherwise idle, e.g. because it
+    /// is not connected.
+    StuckHigh,
+
+    /// Framing, parity or break errors in many of the bytes, e.g. from a wrong baud rate or bad
+    /// cabling.
+    Noisy,
+}
+
+/// The DMA requests the UART is set up to raise, see [`PL011Uart::dma_status()`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DmaStatus {
+    /// TX FIFO requests are enabled.
+    pub tx_enabled: bool,
+
+    /// RX FIFO requests are enabled.
+    pub rx_enabled: bool,
+
+    /// RX requests are disabled on a receive error.
+    pub rx_stops_on_error: bool,
+}
+
+/// The UART interrupts that are unmasked, see [`PL011Uart::enabled_interrupts()`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EnabledIrqs {
+    /// RX FIFO level interrupt.
+    pub rx: bool,
+
+    /// RX timeout interrupt.
+    pub rx_timeout: bool,
+
+    /// TX FIFO level interrupt.
+    pub tx: bool,
+
+    /// CTS change interrupt.
+    pub cts: bool,
+}
+
+/// RX interrupt counts next to what was read, for spotting lost or misrouted interrupts, see
+/// [`PL011Uart::rx_health()`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RxHealth {
+    /// RX interrupts handled, level and timeout.
+    pub rx_irqs: usize,
+
+    /// RX interrupts that found the RX FIFO empty.
+    pub empty_rx_irqs: usize,
+
+    /// Bytes read from the RX FIFO, see `chars_read()`.
+    pub chars_read: usize,
+
+    /// Of `chars_read`, those read by the IRQ handler.
+    pub chars_read_in_irq: usize,
+}
+
+/// The RX FIFO fill level at which the RX interrupt fires, see
+/// [`PL011Uart::set_rx_trigger_level()`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RxTriggerLevel {
+    /// 1/8 full.
+    OneEighth,
+
+    /// 1/4 full.
+    OneQuarter,
+
+    /// 1/2 full.
+    OneHalf,
+
+    /// 3/4 full.
+    ThreeQuarters,
+
+    /// 7/8 full.
+    SevenEighths,
+}
+
+/// Makes writes fail at random, to exercise the error paths of upper layers, see
+/// [`PL011Uart::set_write_faults()`]. Only available in test builds.
+#[cfg(test)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct WriteFaults {
+    /// Start of the pseudo-random sequence. The same seed gives the same faults.
+    pub seed: u32,
+
+    /// Chance of a fault per write, in percent.
+    pub percent: u32,
+}
+
+/// The number of stop bits at the end of a frame.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum StopBits {
+    /// One stop bit.
+    One,
+
+    /// Two stop bits.
+    Two,
+}
+
+/// Framing for single characters, see [`PL011Uart::write_char_with_framing()`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct UartFraming {
+    /// The number of stop bits.
+    pub stop_bits: StopBits,
+}
+
+/// Line settings that can be changed at runtime, see [`PL011Uart::reconfigure()`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct UartConfig {
+    /// The baud rate.
+    pub baud_rate: u32,
+
+    /// The frequency of the UART reference clock, for boards that do not clock the UART with the
+    /// 48 MHz of config.txt. `None` keeps the one in use. See [`UartConfig::with_ref_clock_hz()`].
+    pub ref_clock_hz: Option<u32>,
+}
+
+/// A snapshot of the UART's configuration, state and statistics, see [`PL011Uart::status()`].
+///
+/// Prints as a multi-line report.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct UartStatus {
+    /// The line settings.
+    pub config: UartConfig,
+
+    /// Bits per character, 5 to 8.
+    pub data_bits: u32,
+
+    /// A parity bit is sent and checked.
+    pub parity_enabled: bool,
+
+    /// The parity is even, if enabled.
+    pub even_parity: bool,
+
+    /// The number of stop bits.
+    pub stop_bits: StopBits,
+
+    /// The FIFOs are on. Otherwise, each direction holds a single character.
+    pub fifos_enabled: bool,
+
+    /// The transmitter is on. Off while powered down, see [`PL011Uart::set_tx_power_down()`].
+    pub tx_enabled: bool,
+
+    /// Reception is paused, see [`PL011Uart::pause_rx()`].
+    pub rx_paused: bool,
+
+    /// The TX FIFO is empty. The UART does not report FIFO levels in between.
+    pub tx_fifo_empty: bool,
+
+    /// The TX FIFO is full.
+    pub tx_fifo_full: bool,
+
+    /// The RX FIFO is empty.
+    pub rx_fifo_empty: bool,
+
+    /// The RX FIFO is full.
+    pub rx_fifo_full: bool,
+
+    /// A character is being sent.
+    pub tx_busy: bool,
+
+    /// The RX FIFO fill level at which the RX interrupt fires.
+    pub rx_trigger_level: RxTriggerLevel,
+
+    /// The unmasked interrupts, as in IMSC.
+    pub irqs_enabled: u32,
+
+    /// The pending unmasked interrupts, as in MIS.
+    pub irqs_pending: u32,
+
+    /// The sticky receive error flags, as in RSRECR.
+    pub rx_error_flags: u32,
+
+    /// See [`PL011Uart::overruns()`].
+    pub overruns: usize,
+
+    /// See [`PL011Uart::baud_mismatch_suspected()`].
+    pub baud_mismatch_suspected: bool,
+
+    /// See [`PL011Uart::rx_line_state()`].
+    pub rx_line_state: RxLineState,
+
+    /// Received bytes waiting in the software buffer.
+    pub rx_buffered: usize,
+
+    /// Characters written to the TX FIFO, not counting echoed input.
+    pub chars_written: usize,
+
+    /// See [`PL011Uart::rx_health()`].
+    pub rx_health: RxHealth,
+}
+
+/// The UART logic, on top of the registers `T`.
+pub struct PL011UartInner<T = Registers> {
+    registers: T,
+    lcrh: LocalRegisterCopy<u32, LCRH::Register>,
+    /// The last value written to CR, from which the enable bits of single sections are changed
+    /// without reading it back first.
+    cr: LocalRegisterCopy<u32, CR::Register>,
+    /// The last values written to IBRD and FBRD.
+    divisors: (u32, u32),
+    tx_only: bool,
+    fifos_enabled: bool,
+    verify_writes: bool,
+    rx_buffer: RingBuffer<RxSlot, RX_BUFFER_SIZE>,
+    initial_capture: Option<InitialCapture>,
+    connect_grace: Option<ConnectGrace>,
+    grace_window: Option<GraceWindow>,
+    rx_error_retry: Option<RxErrorRetry>,
+    /// Errored bytes dropped in a row.
+    rx_error_retries: usize,
+    last_rx: Option<Duration>,
+    line_buffered: bool,
+    line_buffer: RingBuffer<char, LINE_BUFFER_SIZE>,
+    timestamp_prefix: bool,
+    at_line_start: bool,
+    echo_mode: console::EchoMode,
+    translate_cr: bool,
+    input_codec: console::InputCodec,
+    utf8_decoder: console::Utf8Decoder,
+    on_rx_level: Option<RxIRQHook>,
+    on_rx_timeout: Option<RxIRQHook>,
+    on_rx_byte: Option<RxByteCallback>,
+    on_line: Option<LineCallback>,
+    /// The last line taken for the line callback ended with a carriage return, so a newline
+    /// right after it is part of the same line end.
+    line_ended_cr: bool,
+    on_tx_ready: Option<TxReadyHook>,
+    on_cts_change: Option<CtsChangeHook>,
+    tx_activity_led: Option<ActivityLed>,
+    tx_activity_level: bool,
+    #[cfg(test)]
+    write_faults: Option<FaultInjector>,
+    framing_errors: FramingErrorMonitor,
+    rx_line: RxLineMonitor,
+    on_baud_mismatch: Option<BaudMismatchHook>,
+    auto_baud: Option<AutoBaudState>,
+    write_yield_interval: Option<NonZeroUsize>,
+    tx_inter_frame_idle: Duration,
+    tx_power_down: bool,
+    tx_powered_down: bool,
+    keep_alive: Option<KeepAliveState>,
+    baud_rate: u32,
+    baud_tolerance: u32,
+    ref_clock_hz: u32,
+    rx_trigger_level: RxTriggerLevel,
+    rx_irqs_enabled: bool,
+    rx_paused: bool,
+    overruns: usize,
+    rx_irq_stats: RxHealth,
+    chars_written: usize,
+    chars_read: usize,
+}
+
+/// The register accesses that [`PL011UartInner`] is built on.
+///
+/// Implemented by the MMIO registers, and by test code with a stand-in, so that the UART logic
+/// can be exercised off-target. The inner struct is generic over it instead of using a trait
+/// object, so for the MMIO registers every call compiles down to the plain register access.
+///
+/// Registers with fields are read as a local copy. Writes take the raw value, e.g.
+/// `FieldValue::value`.
+#[allow(missing_docs)]
+pub trait UartRegisters {
+    fn dr(&self) -> u32;
+    fn set_dr(&self, value: u32);
+    fn rsrecr(&self) -> u32;
+    fn set_rsrecr(&self, value: u32);
+    fn fr(&self) -> LocalRegisterCopy<u32, FR::Register>;
+    fn ibrd(&self) -> u32;
+    fn set_ibrd(&self, value: u32);
+    fn fbrd(&self) -> u32;
+    fn set_fbrd(&self, value: u32);
+    fn lcrh(&self) -> LocalRegisterCopy<u32, LCRH::Register>;
+    fn set_lcrh(&self, value: u32);
+    fn cr(&self) -> LocalRegisterCopy<u32, CR::Register>;
+    fn set_cr(&self, value: u32);
+    fn ifls(&self) -> LocalRegisterCopy<u32, IFLS::Register>;
+    fn set_ifls(&self, value: u32);
+    fn imsc(&self) -> LocalRegisterCopy<u32, IMSC::Register>;
+    fn set_imsc(&self, value: u32);
+    fn mis(&self) -> LocalRegisterCopy<u32, MIS::Register>;
+    fn set_icr(&self, value: u32);
+    fn dmacr(&self) -> LocalRegisterCopy<u32, DMACR::Register>;
+    fn set_itcr(&self, value: u32);
+    fn tdr(&self) -> u32;
+    fn set_tdr(&self, value: u32);
+}
+
+/// A minimal, lock-free writer for a UART that was already set up by someone else, e.g. the
+/// firmware.
+///
+/// For the earliest boot messages, before the driver is initialized.
+pub struct EarlyUart {
+    registers: Registers,
+}
+
+// Export the inner struct so that BSPs can use it for the panic handler.
+pub use PL011UartInner as PanicUart;
+
+/// Representation of the UART.
+pub struct PL011Uart {
+    phys_mmio_descriptor: memory::mmu::MMIODescriptor<Physical>,
+    virt_mmio_start_addr: AtomicUsize,
+    inner: IRQSafeNullLock<PL011UartInner>,
+    irq_number: bsp::device_driver::IRQNumber,
+    clocks: &'static (dyn ClockControl + Sync),
+
+    /// Outside of `inner`, because it is needed exactly when `inner` is locked.
+    drop_reentrant_writes: AtomicBool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Convert a received byte to a character, turning carriage returns into newlines.
+fn convert_char(byte: u8) -> char {
+    match byte as char {
+        '\r' => '\n',
+        c => c,
+    }
+}
+
+/// Format `uptime` as a line prefix, e.g. `[   12.345678] `.
+fn format_timestamp(uptime: Duration) -> console::FmtBuf<32> {
+    let mut buf = console::FmtBuf::new();
+
+    // Cannot be truncated, the buffer has room for the largest possible value.
+    let _ = fmt::Write::write_fmt(
+        &mut buf,
+        format_args!("[{:>5}.{:06}] ", uptime.as_secs(), uptime.subsec_micros()),
+    );
+
+    buf
+}
+
+/// Fill `segments` in order with bytes from `link`, giving up once it has been idle for
+/// `idle_timeout`.
+fn read_scatter_from(
+    link: &impl xmodem::interface::Link,
+    segments: &mut [&mut [u8]],
+    idle_timeout: Duration,
+) -> usize {
+    let mut count = 0;
+
+    for byte in segments.iter_mut().flat_map(|x| x.iter_mut()) {
+        match link.recv(idle_timeout) {
+            Some(x) => *byte = x,
+            None => break,
+        }
+        count += 1;
+    }
+
+    count
+}
+
+/// Record bytes from `read()` into `into`, replacing its contents, until it is full or until
+/// nothing was read for `idle_timeout`.
+fn record_rx_from(
+    into: &mut RxRecording,
+    mut read: impl FnMut() -> Option<(u8, Duration)>,
+    mut now: impl FnMut() -> Duration,
+    idle_timeout: Duration,
+) {
+    into.len = 0;
+    let mut first = Duration::from_secs(0);
+    let mut idle_since = now();
+
+    while into.len < RX_RECORDING_SIZE {
+        match read() {
+            Some((byte, timestamp)) => {
+                if into.len == 0 {
+                    first = timestamp;
+                }
+
+                into.slots[into.len] = RxSlot {
+                    byte,
+                    flags: 0,
+                    timestamp: timestamp.checked_sub(first).unwrap_or_default(),
+                };
+                into.len += 1;
+                idle_since = now();
+            }
+            None if now() >= time::deadline_after(idle_since, idle_timeout) => break,
+            None => (),
+        }
+    }
+}
+
+/// Hand the bytes of `recording` to `feed()`, each once `now()` reached its recorded time
+/// relative to the start of the replay.
+fn replay_rx_to(
+    recording: &RxRecording,
+    mut now: impl FnMut() -> Duration,
+    mut feed: impl FnMut(u8, Duration),
+) -> RxReplayTiming {
+    let start = now();
+    let mut timing = RxReplayTiming {
+        late: 0,
+        max_lag: Duration::from_secs(0),
+    };
+
+    for slot in recording.slots[..recording.len].iter() {
+        let due = time::deadline_after(start, slot.timestamp);
+        let mut t = now();
+        while t < due {
+            t = now();
+        }
+
+        let lag = t - due;
+        if lag > RX_REPLAY_TOLERANCE {
+            timing.late += 1;
+        }
+        timing.max_lag = timing.max_lag.max(lag);
+
+        feed(slot.byte, t);
+    }
+
+    timing
+}
+
+/// Send `LOOPBACK_PATTERN` byte by byte over `link` and check that each byte comes back.
+fn loopback_test_over(
+    link: &impl xmodem::interface::Link,
+    timeout: Duration,
+) -> Result<(), &'static str> {
+    for (i, byte) in LOOPBACK_PATTERN.iter().enumerate() {
+        link.send(*byte);
+
+        match link.recv(timeout) {
+            Some(x) if x == *byte => (),
+            Some(_) => return Err("Physical loopback: received data differs from sent data"),
+            None if i == 0 => {
+                return Err(
+                    "Physical loopback: nothing received, check TX-RX jumper and GPIO setup",
+                )
+            }
+            None => return Err("Physical loopback: lost part of the pattern"),
+        }
+    }
+
+    Ok(())
+}
+
+/// The record that `PL011Uart::persist_config()` leaves in the persistent area.
+fn encode_persisted_config(config: UartConfig, framing: UartFraming) -> [u8; PERSISTED_CONFIG_LEN] {
+    let mut record = [0; PERSISTED_CONFIG_LEN];
+
+    record[0..4].copy_from_slice(&PERSIST_MAGIC.to_le_bytes());
+    record[4..8].copy_from_slice(&config.baud_rate.to_le_bytes());
+    record[8..12].copy_from_slice(&config.ref_clock_hz.unwrap_or(0).to_le_bytes());
+    record[12] = match framing.stop_bits {
+        StopBits::One => 1,
+        StopBits::Two => 2,
+    };
+
+    let crc = checksum::crc16_xmodem(&record[..14]);
+    record[14..16].copy_from_slice(&crc.to_le_bytes());
+
+    record
+}
+
+/// The settings in a record of `encode_persisted_config()`, or `None` if there is no intact one,
+/// e.g. after a cold boot.
+fn decode_persisted_config(record: &[u8]) -> Option<(UartConfig, UartFraming)> {
+    let word = |i: usize| {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&record[i..i + 4]);
+        u32::from_le_bytes(bytes)
+    };
+
+    if record.len() < PERSISTED_CONFIG_LEN || word(0) != PERSIST_MAGIC {
+        return None;
+    }
+
+    let crc = u16::from_le_bytes([record[14], record[15]]);
+    if crc != checksum::crc16_xmodem(&record[..14]) {
+        return None;
+    }
+
+    let stop_bits = match record[12] {
+        1 => StopBits::One,
+        2 => StopBits::Two,
+        _ => return None,
+    };
+    let config = UartConfig {
+        baud_rate: word(4),
+        ref_clock_hz: Some(word(8)).filter(|x| *x != 0),
+    };
+
+    Some((config, UartFraming { stop_bits }))
+}
+
+/// Like `loopback_test_over()`, but time the round trip of each byte with the clock `now`.
+///
+/// Wrong data is reported in the result, so that the timing is available regardless. Bytes that
+/// do not come back at all are an error.
+fn timed_loopback_over(
+    link: &impl xmodem::interface::Link,
+    timeout: Duration,
+    mut now: impl FnMut() -> Duration,
+) -> Result<LoopbackTiming, &'static str> {
+    let mut timing = LoopbackTiming {
+        matched: true,
+        round_trip: Duration::from_secs(0),
+        max_round_trip: Duration::from_secs(0),
+    };
+    let mut total = Duration::from_secs(0);
+
+    for (i, byte) in LOOPBACK_PATTERN.iter().enumerate() {
+        let sent = now();
+        link.send(*byte);
+
+        let received = match link.recv(timeout) {
+            Some(x) => x,
+            None if i == 0 => return Err("Physical loopback: no loopback detected"),
+            None => return Err("Physical loopback: lost part of the pattern"),
+        };
+        let round_trip = now().checked_sub(sent).unwrap_or_default();
+
+        timing.matched &= received == *byte;
+        timing.max_round_trip = timing.max_round_trip.max(round_trip);
+        total += round_trip;
+    }
+    timing.round_trip = total / LOOPBACK_PATTERN.len() as u32;
+
+    Ok(timing)
+}
+
+/// The integer and fractional baud rate divisors for `baud_rate`, given a UART clock of
+/// `clock_hz`.
+///
+/// The divisor is `clock_hz / (16 * baud_rate)`, with a fractional part of 6 bits. In units of
+/// 1/64, that is `4 * clock_hz / baud_rate`, which is rounded to nearest. Fails if the rate that
+/// the divisors actually produce is more than `tolerance` percent off.
+fn baud_divisors_with(
+    clock_hz: u32,
+    baud_rate: u32,
+    tolerance: u32,
+) -> Result<(u32, u32), &'static str> {
+    if baud_rate == 0 {
+        return Err("Baud rate must not be zero");
+    }
+
+    let clock_hz = u64::from(clock_hz);
+    let baud_rate = u64::from(baud_rate);
+    let divisor = (4 * clock_hz + baud_rate / 2) / baud_rate;
+
+    let integer = divisor >> 6;
+    if integer == 0 || integer > 0xffff {
+        return Err("Baud rate out of range");
+    }
+
+    let actual = 4 * clock_hz / divisor;
+    let deviation = if actual > baud_rate {
+        actual - baud_rate
+    } else {
+        baud_rate - actual
+    };
+    if deviation * 100 > baud_rate * u64::from(tolerance) {
+        return Err("Baud rate deviates from the target beyond the tolerance");
+    }
+
+    Ok((integer as u32, (divisor & 0x3f) as u32))
+}
+
+/// Returns an error if `ref_clock_hz` is too low for a UART reference clock.
+fn check_ref_clock(ref_clock_hz: u32) -> Result<(), &'static str> {
+    if ref_clock_hz < MIN_REF_CLOCK_HZ {
+        return Err("UART reference clock is implausibly low");
+    }
+
+    Ok(())
+}
+
+/// Returns an error unless `offset` is that of a register in the block.
+#[cfg(feature = "register_poke")]
+fn check_register_offset(offset: usize) -> Result<(), &'static str> {
+    if offset >= core::mem::size_of::<RegisterBlock>() {
+        return Err("Register offset is beyond the register block");
+    }
+
+    if offset % 4 != 0 {
+        return Err("Register offset is not word-aligned");
+    }
+
+    Ok(())
+}
+
+/// How long `bits` bit times last at `baud_rate`, rounded up to the next nanosecond.
+fn break_duration(bits: u32, baud_rate: u32) -> Duration {
+    let baud_rate = u64::from(baud_rate.max(1));
+    let nanos = (u64::from(bits) * 1_000_000_000 + baud_rate - 1) / baud_rate;
+
+    Duration::from_nanos(nanos)
+}
+
+#[cfg(test)]
+impl FaultInjector {
+    fn new(faults: WriteFaults) -> Self {
+        Self {
+            percent: faults.percent,
+            // Xorshift gets stuck at zero.
+            state: if faults.seed == 0 { 1 } else { faults.seed },
+        }
+    }
+
+    /// Returns true if the next write fails.
+    fn roll(&mut self) -> bool {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+
+        self.state % 100 < self.percent
+    }
+}
+
+impl RxHealth {
+    const fn new() -> Self {
+        Self {
+            rx_irqs: 0,
+            empty_rx_irqs: 0,
+            chars_read: 0,
+            chars_read_in_irq: 0,
+        }
+    }
+}
+
+impl RxTriggerLevel {
+    fn ifls(self) -> register::FieldValue<u32, IFLS::Register> {
+        match self {
+            Self::OneEighth => IFLS::RXIFLSEL::OneEigth,
+            Self::OneQuarter => IFLS::RXIFLSEL::OneQuarter,
+            Self::OneHalf => IFLS::RXIFLSEL::OneHalf,
+            Self::ThreeQuarters => IFLS::RXIFLSEL::ThreeQuarters,
+            Self::SevenEighths => IFLS::RXIFLSEL::SevenEights,
+        }
+    }
+
+    fn from_rxiflsel(value: u32) -> Self {
+        match value {
+            0b000 => Self::OneEighth,
+            0b001 => Self::OneQuarter,
+            0b010 => Self::OneHalf,
+            0b011 => Self::ThreeQuarters,
+            // The remaining encodings are reserved and never written.
+            _ => Self::SevenEighths,
+        }
+    }
+}
+
+impl StopBits {
+    fn lcrh(self) -> register::FieldValue<u32, LCRH::Register> {
+        match self {
+            Self::One => LCRH::STP2::One,
+            Self::Two => LCRH::STP2::Two,
+        }
+    }
+}
+
+impl FramingErrorMonitor {
+    const fn new() -> Self {
+        Self {
+            window_bytes: 0,
+            window_errors: 0,
+            baud_mismatch_suspected: false,
+        }
+    }
+
+    /// Account for a received byte.
+    ///
+    /// Returns true if a baud rate mismatch is suspected now, but was not before.
+    fn record(&mut self, framing_error: bool) -> bool {
+        self.window_bytes += 1;
+        if framing_error {
+            self.window_errors += 1;
+        }
+
+        if self.window_bytes < FRAMING_ERROR_WINDOW {
+            return false;
+        }
+
+        let was_suspected = self.baud_mismatch_suspected;
+        self.baud_mismatch_suspected = self.window_errors > FRAMING_ERROR_THRESHOLD;
+        self.window_bytes = 0;
+        self.window_errors = 0;
+
+        self.baud_mismatch_suspected && !was_suspected
+    }
+}
+
+impl RxLineMonitor {
+    const fn new() -> Self {
+        Self {
+            window_bytes: 0,
+            window_errors: 0,
+            window_all_ones: 0,
+            breaks_in_a_row: 0,
+            state: RxLineState::Normal,
+        }
+    }
+
+    /// Account for a received data register value.
+    fn record(&mut self, dr: u16) {
+        if dr & DR_BE != 0 {
+            self.breaks_in_a_row += 1;
+        } else {
+            self.breaks_in_a_row = 0;
+        }
+
+        if self.breaks_in_a_row >= RX_LINE_STUCK_LOW_BREAKS {
+            self.state = RxLineState::StuckLow;
+        } else if self.state == RxLineState::StuckLow && dr & (DR_FE | DR_PE | DR_BE) == 0 {
+            // The line came back.
+            self.state = RxLineState::Normal;
+        }
+
+        self.window_bytes += 1;
+        if dr & (DR_FE | DR_PE | DR_BE) != 0 {
+            self.window_errors += 1;
+        }
+        if dr & 0xff == 0xff {
+            self.window_all_ones += 1;
+        }
+
+        if self.window_bytes < RX_LINE_WINDOW {
+            return;
+        }
+
+        if self.state != RxLineState::StuckLow {
+            self.state = if self.window_all_ones == RX_LINE_WINDOW {
+                RxLineState::StuckHigh
+            } else if self.window_errors > RX_LINE_WINDOW / 4 {
+                RxLineState::Noisy
+            } else {
+                RxLineState::Normal
+            };
+        }
+        self.window_bytes = 0;
+        self.window_errors = 0;
+        self.window_all_ones = 0;
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl RxHealth {
+    /// Bytes read by polling instead of the IRQ handler.
+    ///
+    /// While interrupt mode is on, this should not grow. If it does, RX interrupts do not arrive.
+    pub fn chars_polled(&self) -> usize {
+        self.chars_read.saturating_sub(self.chars_read_in_irq)
+    }
+}
+
+impl UartConfig {
+    /// Create an instance for `baud_rate`, with the reference clock in use.
+    pub const fn new(baud_rate: u32) -> Self {
+        Self {
+            baud_rate,
+            ref_clock_hz: None,
+        }
+    }
+
+    /// Drive the divisors from a reference clock of `ref_clock_hz`.
+    ///
+    /// Clocks below 1 MHz are rejected as misconfiguration, zero included.
+    pub fn with_ref_clock_hz(self, ref_clock_hz: u32) -> Result<Self, &'static str> {
+        check_ref_clock(ref_clock_hz)?;
+
+        Ok(Self {
+            ref_clock_hz: Some(ref_clock_hz),
+            ..self
+        })
+    }
+}
+
+impl fmt::Display for LoopbackTiming {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Physical loopback: pattern {}, round trip {} us (max {} us)",
+            if self.matched { "matched" } else { "differs" },
+            self.round_trip.as_micros(),
+            self.max_round_trip.as_micros()
+        )
+    }
+}
+
+impl fmt::Display for RxRingDump {
+    /// 16 bytes per line, e.g. `0010: 41 42 0d                  |AB.|`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (line, chunk) in self.bytes[..self.len].chunks(16).enumerate() {
+            write!(f, "{:04x}:", line * 16)?;
+            for byte in chunk {
+                write!(f, " {:02x}", byte)?;
+            }
+            for _ in chunk.len()..16 {
+                f.write_str("   ")?;
+            }
+
+            f.write_str("  |")?;
+            for byte in chunk {
+                let c = if byte.is_ascii_graphic() || *byte == b' ' {
+                    char::from(*byte)
+                } else {
+                    '.'
+                };
+                fmt::Write::write_char(f, c)?;
+            }
+            f.write_str("|\n")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for UartStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn fifo_level(empty: bool, full: bool) -> &'static str {
+            match (empty, full) {
+                (true, _) => "empty",
+                (false, true) => "full",
+                (false, false) => "partly filled",
+            }
+        }
+
+        let parity = match (self.parity_enabled, self.even_parity) {
+            (false, _) => 'N',
+            (true, true) => 'E',
+            (true, false) => 'O',
+        };
+        let stop_bits = match self.stop_bits {
+            StopBits::One => 1,
+            StopBits::Two => 2,
+        };
+
+        writeln!(
+            f,
+            "Line:       {} baud, {}{}{}, FIFOs {}",
+            self.config.baud_rate,
+            self.data_bits,
+            parity,
+            stop_bits,
+            if self.fifos_enabled { "on" } else { "off" }
+        )?;
+        writeln!(
+            f,
+            "TX:         {}, FIFO {}, {}",
+            if self.tx_enabled {
+                "on"
+            } else {
+                "powered down"
+            },
+            fifo_level(self.tx_fifo_empty, self.tx_fifo_full),
+            if self.tx_busy { "busy" } else { "idle" }
+        )?;
+        writeln!(
+            f,
+            "RX:         {}, FIFO {}, {} buffered, IRQ at {:?}",
+            if self.rx_paused { "paused" } else { "on" },
+            fifo_level(self.rx_fifo_empty, self.rx_fifo_full),
+            self.rx_buffered,
+            self.rx_trigger_level
+        )?;
+        writeln!(
+            f,
+            "IRQs:       enabled {:#05x}, pending {:#05x}",
+            self.irqs_enabled, self.irqs_pending
+        )?;
+        writeln!(
+            f,
+            "Errors:     flags {:#x}, {} overruns, line {:?}, baud mismatch {}",
+            self.rx_error_flags,
+            self.overruns,
+            self.rx_line_state,
+            if self.baud_mismatch_suspected {
+                "suspected"
+            } else {
+                "not suspected"
+            }
+        )?;
+        write!(
+            f,
+            "Statistics: {} written, {} read, {} of them in {} RX IRQs, {} empty",
+            self.chars_written,
+            self.rx_health.chars_read,
+            self.rx_health.chars_read_in_irq,
+            self.rx_health.rx_irqs,
+            self.rx_health.empty_rx_irqs
+        )
+    }
+}
+
+impl InitialCapture {
+    const fn new() -> Self {
+        Self {
+            bytes: [0; INITIAL_CAPTURE_SIZE],
+            len: 0,
+        }
+    }
+
+    /// Keep `byte` if there is room left.
+    fn record(&mut self, byte: u8) {
+        if self.len < INITIAL_CAPTURE_SIZE {
+            self.bytes[self.len] = byte;
+            self.len += 1;
+        }
+    }
+
+    /// The captured bytes, in the order they were received.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    /// Returns true if the capture is full and does not take any more bytes.
+    pub fn is_complete(&self) -> bool {
+        self.len == INITIAL_CAPTURE_SIZE
+    }
+}
+
+impl RxRecording {
+    /// Create an empty instance.
+    pub const fn new() -> Self {
+        Self {
+            slots: [RxSlot {
+                byte: 0,
+                flags: 0,
+                timestamp: Duration::from_secs(0),
+            }; RX_RECORDING_SIZE],
+            len: 0,
+        }
+    }
+
+    /// The number of recorded bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if nothing was recorded.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The recorded bytes in order, each with its time relative to the first one.
+    pub fn iter(&self) -> impl Iterator<Item = (u8, Duration)> + '_ {
+        self.slots[..self.len].iter().map(|x| (x.byte, x.timestamp))
+    }
+}
+
+impl UartRegisters for Registers {
+    fn dr(&self) -> u32 {
+        self.DR.get()
+    }
+
+    fn set_dr(&self, value: u32) {
+        self.DR.set(value)
+    }
+
+    fn rsrecr(&self) -> u32 {
+        self.RSRECR.get()
+    }
+
+    fn set_rsrecr(&self, value: u32) {
+        self.RSRECR.set(value)
+    }
+
+    fn fr(&self) -> LocalRegisterCopy<u32, FR::Register> {
+        self.FR.extract()
+    }
+
+    fn ibrd(&self) -> u32 {
+        self.IBRD.get()
+    }
+
+    fn set_ibrd(&self, value: u32) {
+        self.IBRD.set(value)
+    }
+
+    fn fbrd(&self) -> u32 {
+        self.FBRD.get()
+    }
+
+    fn set_fbrd(&self, value: u32) {
+        self.FBRD.set(value)
+    }
+
+    fn lcrh(&self) -> LocalRegisterCopy<u32, LCRH::Register> {
+        self.LCRH.extract()
+    }
+
+    fn set_lcrh(&self, value: u32) {
+        self.LCRH.set(value)
+    }
+
+    fn cr(&self) -> LocalRegisterCopy<u32, CR::Register> {
+        self.CR.extract()
+    }
+
+    fn set_cr(&self, value: u32) {
+        self.CR.set(value)
+    }
+
+    fn ifls(&self) -> LocalRegisterCopy<u32, IFLS::Register> {
+        self.IFLS.extract()
+    }
+
+    fn set_ifls(&self, value: u32) {
+        self.IFLS.set(value)
+    }
+
+    fn imsc(&self) -> LocalRegisterCopy<u32, IMSC::Register> {
+        self.IMSC.extract()
+    }
+
+    fn set_imsc(&self, value: u32) {
+        self.IMSC.set(value)
+    }
+
+    fn mis(&self) -> LocalRegisterCopy<u32, MIS::Register> {
+        self.MIS.extract()
+    }
+
+    fn set_icr(&self, value: u32) {
+        self.ICR.set(value)
+    }
+
+    fn dmacr(&self) -> LocalRegisterCopy<u32, DMACR::Register> {
+        self.DMACR.extract()
+    }
+
+    fn set_itcr(&self, value: u32) {
+        self.ITCR.set(value)
+    }
+
+    fn tdr(&self) -> u32 {
+        self.TDR.get()
+    }
+
+    fn set_tdr(&self, value: u32) {
+        self.TDR.set(value)
+    }
+}
+
+impl EarlyUart {
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    pub const unsafe fn new(mmio_start_addr: usize) -> Self {
+        Self {
+            registers: Registers::new(mmio_start_addr),
+        }
+    }
+}
+
+impl PL011UartInner {
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    pub const unsafe fn new(mmio_start_addr: usize) -> Self {
+        Self::with_registers(Registers::new(mmio_start_addr))
+    }
+
+    /// Set up baud rate and characteristics.
+    ///
+    /// The calculation for the BRD given a target rate of 2300400 and a clock set to 48 MHz is:
+    /// `(48_000_000/16)/230400 = 13,02083`. `13` goes to the `IBRD` (integer field). The `FBRD`
+    /// (fractional field) is only 6 bits so `0,0208*64 = 1,3312 rounded to 1` will give the best
+    /// approximation we can get. A 5 % error margin is acceptable for UART and we're now at 0,01 %.
+    ///
+    /// This results in 8N1 and 230400 baud (we set the clock to 48 MHz in config.txt).
+    ///
+    /// Fails if the resulting baud rate is off by more than the tolerance, see
+    /// `PL011Uart::set_baud_tolerance()`, so that a misconfigured clock shows up here instead of
+    /// as framing errors later on.
+    ///
+    /// If write verification is enabled, the programmed registers are read back afterwards. See
+    /// `verify_config()`.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    pub unsafe fn init(&mut self, new_mmio_start_addr: Option<usize>) -> Result<(), &'static str> {
+        self.init_polling(new_mmio_start_addr)?;
+        self.enable_interrupts()
+    }
+
+    /// Like `init()`, but with all interrupts masked, so that the UART can only be polled.
+    ///
+    /// For early boot, when the interrupt controller is not up yet. See `enable_interrupts()` for
+    /// the switch to interrupt driven reception.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    pub unsafe fn init_polling(
+        &mut self,
+        new_mmio_start_addr: Option<usize>,
+    ) -> Result<(), &'static str> {
+        if let Some(addr) = new_mmio_start_addr {
+            self.registers = Registers::new(addr);
+        }
+
+        self.configure_polling()
+    }
+}
+
+impl<T: UartRegisters> PL011UartInner<T> {
+    /// Create an instance on top of `registers`.
+    pub const fn with_registers(registers: T) -> Self {
+        Self {
+            registers,
+            lcrh: LocalRegisterCopy::new(0),
+            // As the firmware leaves it.
+            cr: LocalRegisterCopy::new(
+                CR::UARTEN::Enabled.value | CR::TXE::Enabled.value | CR::RXE::Enabled.value,
+            ),
+            divisors: (0, 0),
+            tx_only: false,
+            fifos_enabled: true,
+            verify_writes: false,
+            rx_buffer: RingBuffer::new(),
+            initial_capture: None,
+            connect_grace: None,
+            grace_window: None,
+            rx_error_retry: None,
+            rx_error_retries: 0,
+            last_rx: None,
+            line_buffered: false,
+            line_buffer: RingBuffer::new(),
+            timestamp_prefix: false,
+            at_line_start: true,
+            echo_mode: console::EchoMode::Verbatim,
+            translate_cr: true,
+            input_codec: console::InputCodec::Latin1,
+            utf8_decoder: console::Utf8Decoder::new(),
+            on_rx_level: None,
+            on_rx_timeout: None,
+            on_rx_byte: None,
+            on_line: None,
+            line_ended_cr: false,
+            on_tx_ready: None,
+            on_cts_change: None,
+            tx_activity_led: None,
+            tx_activity_level: false,
+            #[cfg(test)]
+            write_faults: None,
+            framing_errors: FramingErrorMonitor::new(),
+            rx_line: RxLineMonitor::new(),
+            on_baud_mismatch: None,
+            auto_baud: None,
+            write_yield_interval: None,
+            tx_inter_frame_idle: Duration::from_secs(0),
+            tx_power_down: false,
+            tx_powered_down: false,
+            keep_alive: None,
+            baud_rate: INIT_BAUD_RATE,
+            baud_tolerance: DEFAULT_BAUD_TOLERANCE,
+            ref_clock_hz: UART_CLOCK_HZ,
+            rx_trigger_level: RxTriggerLevel::OneEighth,
+            rx_irqs_enabled: false,
+            rx_paused: false,
+            overruns: 0,
+            rx_irq_stats: RxHealth::new(),
+            chars_written: 0,
+            chars_read: 0,
+        }
+    }
+
+    /// `init_polling()` on the current registers.
+    fn configure_polling(&mut self) -> Result<(), &'static str> {
+        use time::interface::TimeManager;
+
+        let (integer, fractional) =
+            baud_divisors_with(self.ref_clock_hz, INIT_BAUD_RATE, self.baud_tolerance)?;
+        self.open_grace_window_at(time::time_manager().uptime());
+
+        // Turn it off temporarily.
+        self.write_cr(0);
+
+        self.registers.set_icr(ICR::ALL::CLEAR.value);
+        self.write_divisors(integer, fractional);
+        self.baud_rate = INIT_BAUD_RATE;
+        self.lcrh.modify(LCRH::WLEN::EightBit + self.fen()); // 8N1 + Fifo on, unless disabled
+        self.registers.set_lcrh(self.lcrh.get());
+        self.registers.set_ifls(IFLS::RXIFLSEL::OneEigth.value); // RX FIFO fill level at 1/8
+        self.rx_trigger_level = RxTriggerLevel::OneEighth;
+        self.registers.set_imsc(self.cts_irq_mask()); // All IRQs masked, but a requested CTS one
+        self.rx_irqs_enabled = false;
+        self.rx_paused = false;
+        self.write_cr((CR::UARTEN::Enabled + CR::TXE::Enabled + self.rxe()).value);
+        self.tx_powered_down = false;
+
+        if self.verify_writes {
+            self.verify_config()?;
+        }
+
+        Ok(())
+    }
+
+    /// Switch from polling to interrupt driven reception.
+    ///
+    /// Bytes that arrived while polling are moved to the software buffer first, so none of them is
+    /// stranded in the FIFO without an interrupt that announces it. While RX is paused, the
+    /// interrupts stay masked until it resumes.
+    pub fn enable_interrupts(&mut self) -> Result<(), &'static str> {
+        self.buffer_rx_fifo();
+
+        self.registers.set_icr(ICR::ALL::CLEAR.value);
+        self.rx_irqs_enabled = true;
+        self.write_rx_irq_mask();
+
+        if self.verify_writes {
+            self.verify_config()?;
+        }
+
+        Ok(())
+    }
+
+    /// Program the RX interrupt mask: RX and RX timeout are unmasked, unless reception is polled
+    /// or paused.
+    fn write_rx_irq_mask(&mut self) {
+        let enable = self.rx_irqs_enabled && !self.rx_paused;
+
+        // The other interrupts are left as they are.
+        let mut imsc = self.registers.imsc();
+        if enable {
+            imsc.modify(IMSC::RXIM::Enabled + IMSC::RTIM::Enabled); // RX IRQ + RX timeout IRQ
+        } else {
+            imsc.modify(IMSC::RXIM::Disabled + IMSC::RTIM::Disabled);
+        }
+        self.registers.set_imsc(imsc.get());
+    }
+
+    /// The IMSC bit for the CTS change interrupt, set if there is a hook for it.
+    fn cts_irq_mask(&self) -> u32 {
+        if self.on_cts_change.is_some() {
+            IMSC::CTSMIM::Enabled.value
+        } else {
+            0
+        }
+    }
+
+    /// Set the hook for CTS changes, and unmask the interrupt for them only while there is one.
+    fn set_cts_change_hook(&mut self, hook: Option<CtsChangeHook>) {
+        self.on_cts_change = hook;
+
+        let mut imsc = self.registers.imsc();
+        imsc.modify(IMSC::CTSMIM::Disabled);
+        self.registers.set_imsc(imsc.get() | self.cts_irq_mask());
+    }
+
+    /// Stop moving received bytes from the RX FIFO into the software buffer.
+    ///
+    /// The RX interrupts are masked, because with nobody draining the FIFO, the level interrupt
+    /// would stay asserted.
+    fn pause_rx(&mut self) {
+        self.rx_paused = true;
+        self.write_rx_irq_mask();
+    }
+
+    /// Undo `pause_rx()`. What piled up in the RX FIFO is moved behind the already buffered bytes
+    /// before the interrupts are unmasked again.
+    fn resume_rx(&mut self) {
+        if !self.rx_paused {
+            return;
+        }
+
+        self.rx_paused = false;
+        self.buffer_rx_fifo();
+
+        if self.rx_irqs_enabled {
+            self.registers
+                .set_icr((ICR::RXIC::SET + ICR::RTIC::SET).value);
+        }
+        self.write_rx_irq_mask();
+    }
+
+    /// Check that the registers programmed by `init()` hold the expected values.
+    ///
+    /// Catches bus errors or a partially powered peripheral. IFLS and IMSC are compared against
+    /// the configuration they follow from, LCRH, CR, IBRD and FBRD against the shadow copies of
+    /// the values last written to them. The first mismatch in that order is reported, except that
+    /// IFLS and IMSC are reported together.
+    fn verify_config(&self) -> Result<(), &'static str> {
+        let imsc = if self.rx_irqs_enabled && !self.rx_paused {
+            (IMSC::RXIM::Enabled + IMSC::RTIM::Enabled).value
+        } else {
+            0
+        } | self.cts_irq_mask();
+
+        let ifls_ok = self.registers.ifls().get() == self.rx_trigger_level.ifls().value;
+        let imsc_ok = self.registers.imsc().get() == imsc;
+
+        match (ifls_ok, imsc_ok) {
+            (true, true) => (),
+            (false, true) => return Err("PL011 register readback mismatch: IFLS"),
+            (true, false) => return Err("PL011 register readback mismatch: IMSC"),
+            (false, false) => return Err("PL011 register readback mismatch: IFLS, IMSC"),
+        }
+
+        let shadowed = [
+            (
+                self.registers.lcrh().get() == self.lcrh.get(),
+                "PL011 register readback mismatch: LCRH",
+            ),
+            (
+                self.registers.cr().get() == self.cr.get(),
+                "PL011 register readback mismatch: CR",
+            ),
+            (
+                self.registers.ibrd() == self.divisors.0,
+                "PL011 register readback mismatch: IBRD",
+            ),
+            (
+                self.registers.fbrd() == self.divisors.1,
+                "PL011 register readback mismatch: FBRD",
+            ),
+        ];
+
+        match shadowed.iter().find(|(ok, _)| !ok) {
+            Some((_, err)) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    fn rx_trigger_level(&self) -> RxTriggerLevel {
+        RxTriggerLevel::from_rxiflsel(self.registers.ifls().read(IFLS::RXIFLSEL))
+    }
+
+    fn set_rx_trigger_level(&mut self, level: RxTriggerLevel) {
+        let mut ifls = self.registers.ifls();
+        ifls.modify(level.ifls());
+        self.registers.set_ifls(ifls.get());
+        self.rx_trigger_level = level;
+    }
+
+    /// Apply a new line configuration.
+    ///
+    /// Pending output is sent at the old baud rate first. Received bytes are discarded, since they
+    /// might be garbled by the switch. Statistics are kept.
+    fn reconfigure(&mut self, config: UartConfig) -> Result<(), &'static str> {
+        self.reconfigure_with_framing(config, self.framing())
+    }
+
+    /// Like `reconfigure()`, and the framing changes along with the rest.
+    fn reconfigure_with_framing(
+        &mut self,
+        config: UartConfig,
+        framing: UartFraming,
+    ) -> Result<(), &'static str> {
+        let ref_clock_hz = config.ref_clock_hz.unwrap_or(self.ref_clock_hz);
+        // The fields are public, so the config may not have been checked while it was built.
+        check_ref_clock(ref_clock_hz)?;
+
+        let old_ref_clock_hz = core::mem::replace(&mut self.ref_clock_hz, ref_clock_hz);
+        let result = self.reconfigure_serial(config.baud_rate, framing);
+        if result.is_err() {
+            self.ref_clock_hz = old_ref_clock_hz;
+        }
+
+        result
+    }
+
+    /// The current serial settings, as a record for `restore_config_from()`.
+    fn persisted_config(&self) -> [u8; PERSISTED_CONFIG_LEN] {
+        let config = UartConfig {
+            baud_rate: self.baud_rate,
+            ref_clock_hz: Some(self.ref_clock_hz),
+        };
+
+        encode_persisted_config(config, self.framing())
+    }
+
+    /// Switch to the settings in the persisted `record`. Returns false, keeping the current
+    /// settings, if there are none.
+    fn restore_config_from(&mut self, record: &[u8]) -> Result<bool, &'static str> {
+        match decode_persisted_config(record) {
+            Some((config, framing)) => self.reconfigure_with_framing(config, framing).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    /// Like `reconfigure()`, and the framing changes along with the baud rate.
+    ///
+    /// The UART is disabled from before the divisors are written until after LCRH is, so it never
+    /// runs with a mix of old and new settings.
+    fn reconfigure_serial(
+        &mut self,
+        baud_rate: u32,
+        framing: UartFraming,
+    ) -> Result<(), &'static str> {
+        let (integer, fractional) =
+            baud_divisors_with(self.ref_clock_hz, baud_rate, self.baud_tolerance)?;
+
+        self.flush_line_buffer();
+        self.drain_tx();
+        self.write_cr(0);
+
+        self.write_divisors(integer, fractional);
+        // The divisors only take effect with a write to LCRH, which the new framing provides.
+        self.lcrh.modify(framing.stop_bits.lcrh());
+        self.registers.set_lcrh(self.lcrh.get());
+
+        self.rx_buffer.clear();
+        while !self.registers.fr().matches_all(FR::RXFE::SET) {
+            self.registers.dr();
+        }
+        self.registers.set_icr(ICR::ALL::CLEAR.value);
+
+        self.write_cr((CR::UARTEN::Enabled + CR::TXE::Enabled + self.rxe()).value);
+        self.tx_powered_down = false;
+        self.baud_rate = baud_rate;
+
+        Ok(())
+    }
+
+    /// Write CR, keeping the shadow copy in sync.
+    fn write_cr(&mut self, value: u32) {
+        self.cr.set(value);
+        self.registers.set_cr(value);
+    }
+
+    /// Write IBRD and FBRD, keeping the shadow copy in sync.
+    fn write_divisors(&mut self, integer: u32, fractional: u32) {
+        self.divisors = (integer, fractional);
+        self.registers.set_ibrd(integer);
+        self.registers.set_fbrd(fractional);
+    }
+
+    /// The RXE setting matching the selected TX-only mode.
+    fn rxe(&self) -> register::FieldValue<u32, CR::Register> {
+        if self.tx_only {
+            CR::RXE::Disabled
+        } else {
+            CR::RXE::Enabled
+        }
+    }
+
+    /// Returns an error while the receiver is disabled, since `DR` holds no received data then.
+    ///
+    /// Checks the shadow copy of CR, so it costs no register access.
+    fn rx_enabled(&self) -> Result<(), &'static str> {
+        if !self.cr.is_set(CR::RXE) {
+            return Err("PL011 receiver is disabled");
+        }
+
+        Ok(())
+    }
+
+    /// Switch the receiver off or back on, e.g. for a UART whose RX line is not connected.
+    fn set_tx_only(&mut self, tx_only: bool) {
+        self.tx_only = tx_only;
+
+        if self.cr.is_set(CR::UARTEN) {
+            let cr = (self.cr.get() & !CR::RXE::SET.value) | self.rxe().value;
+            self.write_cr(cr);
+        }
+    }
+
+    /// The FEN setting matching the selected FIFO mode.
+    fn fen(&self) -> register::FieldValue<u32, LCRH::Register> {
+        if self.fifos_enabled {
+            LCRH::FEN::FifosEnabled
+        } else {
+            LCRH::FEN::FifosDisabled
+        }
+    }
+
+    /// Switch between FIFO mode and character mode.
+    ///
+    /// The UART must be disabled while FEN changes, so it is turned off and on again around the
+    /// update. Switching also flushes the hardware FIFOs.
+    fn set_fifos_enabled(&mut self, enable: bool) {
+        self.drain_tx();
+        self.write_cr(0);
+
+        self.fifos_enabled = enable;
+        self.modify_lcrh(self.fen());
+
+        self.write_cr((CR::UARTEN::Enabled + CR::TXE::Enabled + self.rxe()).value);
+        self.tx_powered_down = false;
+    }
+
+    /// Drop all received data, both in the software buffer and in the RX FIFO.
+    fn discard_rx(&mut self) {
+        self.rx_buffer.clear();
+
+        // Read from the RX FIFO until it is indicating empty.
+        while !self.registers.fr().matches_all(FR::RXFE::SET) {
+            self.registers.dr();
+        }
+    }
+
+    /// Send out what is pending, then drop all received data and clear the receive errors.
+    fn reset_fifos(&mut self) {
+        self.flush_line_buffer();
+        // Also waits for BUSY, so that the character in the shift register is not cut off.
+        self.drain_tx();
+
+        self.discard_rx();
+
+        // Any write clears the error flags.
+        self.registers.set_rsrecr(0);
+    }
+
+    /// Block until the TX FIFO is empty and the last character has left the shift register.
+    fn wait_tx_idle(&self) {
+        while self.registers.fr().matches_all(FR::BUSY::SET) {
+            cpu::nop();
+        }
+    }
+
+    /// Returns true if the TX FIFO holds characters or the shift register is still busy.
+    ///
+    /// `BUSY` covers the last character being shifted out while `TXFE` already reads as empty.
+    fn output_pending(&self) -> bool {
+        !self.registers.fr().matches_all(FR::TXFE::SET)
+            || self.registers.fr().matches_all(FR::BUSY::SET)
+    }
+
+    /// Block until everything written has physically left the UART.
+    ///
+    /// Needed before halting, because the transmitter stops together with the core.
+    pub fn drain_tx(&self) {
+        self.drain_tx_with(cpu::nop);
+    }
+
+    /// Like `drain_tx()`, but calls `wait` between polls.
+    fn drain_tx_with(&self, mut wait: impl FnMut()) {
+        while self.output_pending() {
+            wait();
+        }
+    }
+
+    /// Send out the line buffer and drain TX, but give up once `now()` reaches `deadline`.
+    ///
+    /// Returns true if everything has left the UART.
+    fn flush_until(&mut self, deadline: Duration, now: impl Fn() -> Duration) -> bool {
+        while !self.line_buffer.is_empty() {
+            if now() >= deadline {
+                return false;
+            }
+
+            // Only take from the line buffer when the FIFO is empty, so that `transmit_char()` does
+            // not spin, even for a character that takes several bytes.
+            if self.registers.fr().matches_all(FR::TXFE::SET) {
+                if let Some(c) = self.line_buffer.pop() {
+                    self.transmit_char(c);
+                }
+            }
+        }
+
+        while self.output_pending() {
+            if now() >= deadline {
+                return false;
+            }
+        }
+        self.power_down_tx_if_idle();
+
+        true
+    }
+
+    /// Switch the transmitter off if power-down is enabled and nothing is left to send.
+    ///
+    /// Only an idle transmitter is switched off, so no character gets cut.
+    fn power_down_tx_if_idle(&mut self) {
+        if !self.tx_power_down
+            || self.tx_powered_down
+            || self.output_pending()
+            || !self.line_buffer.is_empty()
+        {
+            return;
+        }
+
+        // CR is written as a whole, so the other sections' enable bits are written along.
+        self.write_cr((CR::UARTEN::Enabled + CR::TXE::Disabled + self.rxe()).value);
+        self.tx_powered_down = true;
+    }
+
+    /// Switch the transmitter back on if it was powered down. Must precede every write to `DR`.
+    fn power_up_tx(&mut self) {
+        if !self.tx_powered_down {
+            return;
+        }
+
+        // The last character completes even after TXE was cleared. Let it finish before the
+        // transmitter is switched again.
+        self.wait_tx_idle();
+        self.write_cr((CR::UARTEN::Enabled + CR::TXE::Enabled + self.rxe()).value);
+        self.tx_powered_down = false;
+    }
+
+    /// Update the line control settings.
+    ///
+    /// LCRH must not change while a character is on the wire, so this drains TX first.
+    fn modify_lcrh(&mut self, field: register::FieldValue<u32, LCRH::Register>) {
+        self.wait_tx_idle();
+
+        self.lcrh.modify(field);
+        self.registers.set_lcrh(self.lcrh.get());
+    }
+
+    /// Returns true if the stick parity bit is used as the 9th bit of a multidrop bus.
+    fn is_multidrop(&self) -> bool {
+        self.lcrh
+            .matches_all(LCRH::PEN::Enabled + LCRH::SPS::Enabled)
+    }
+
+    /// Returns true if characters are currently sent with the 9th bit set, marking an address.
+    fn is_address_mark(&self) -> bool {
+        self.is_multidrop() && self.lcrh.matches_all(LCRH::EPS::Odd)
+    }
+
+    /// Enable or disable multidrop mode.
+    ///
+    /// In multidrop mode, the parity bit is stuck to the value of an emulated 9th data bit. It is
+    /// 1 for address bytes and 0 for data bytes.
+    fn set_multidrop(&mut self, enable: bool) {
+        if enable {
+            // Start out in data mode.
+            self.modify_lcrh(LCRH::PEN::Enabled + LCRH::SPS::Enabled + LCRH::EPS::Even);
+        } else {
+            self.modify_lcrh(LCRH::PEN::Disabled + LCRH::SPS::Disabled + LCRH::EPS::Odd);
+        }
+    }
+
+    /// Send a byte with the 9th bit set, addressing a node on a multidrop bus.
+    fn write_address_byte(&mut self, address: u8) -> Result<(), &'static str> {
+        if !self.is_multidrop() {
+            return Err("Multidrop mode is not enabled");
+        }
+
+        if !self.is_address_mark() {
+            self.modify_lcrh(LCRH::EPS::Odd);
+        }
+        self.write_to_fifo(address as u32);
+
+        Ok(())
+    }
+
+    /// Put a value into the TX FIFO, waiting for an empty slot if needed.
+    fn write_to_fifo(&mut self, value: u32) {
+        // Spin while TX FIFO full is set, waiting for an empty slot.
+        while !self.try_write_to_fifo(value) {
+            cpu::nop();
+        }
+    }
+
+    /// Put a value into the TX FIFO if it has an empty slot. Returns true if it did.
+    fn try_write_to_fifo(&mut self, value: u32) -> bool {
+        if self.registers.fr().matches_all(FR::TXFF::SET) {
+            return false;
+        }
+
+        self.power_up_tx();
+
+        // Write the character to the buffer.
+        self.registers.set_dr(value);
+
+        self.chars_written += 1;
+
+        true
+    }
+
+    /// Returns true if the next write should fail, as configured by `set_write_faults()`.
+    #[cfg(test)]
+    fn write_fault(&mut self) -> bool {
+        self.write_faults
+            .as_mut()
+            .map_or(false, FaultInjector::roll)
+    }
+
+    /// Faults are only injected in test builds.
+    #[cfg(not(test))]
+    #[inline(always)]
+    fn write_fault(&mut self) -> bool {
+        false
+    }
+
+    /// Flip the TX activity LED, if there is one.
+    ///
+    /// Called once per write call, not per byte, so that bulk writes do not pay for a GPIO access
+    /// per byte.
+    fn toggle_tx_activity_led(&mut self) {
+        if let Some(led) = self.tx_activity_led {
+            self.tx_activity_level = !self.tx_activity_level;
+            led.set_level(self.tx_activity_level);
+        }
+    }
+
+    fn write_atomic(&mut self, bytes: &[u8]) -> Result<(), &'static str> {
+        let depth = if self.fifos_enabled { TX_FIFO_DEPTH } else { 1 };
+        if bytes.len() > depth {
+            return Err("Message does not fit into the TX FIFO, use write_all()");
+        }
+
+        self.flush_line_buffer();
+
+        // The FIFO does not report its free slots, only whether it is empty.
+        while !self.registers.fr().matches_all(FR::TXFE::SET) {
+            cpu::nop();
+        }
+
+        for byte in bytes {
+            self.write_to_fifo(u32::from(*byte));
+        }
+        self.toggle_tx_activity_led();
+
+        Ok(())
+    }
+
+    /// Run `f` with the line switched to `framing`, and restore the steady-state framing after.
+    ///
+    /// Each switch waits for TX to go idle, so no character goes out with the wrong framing.
+    fn with_framing<R>(&mut self, framing: UartFraming, f: impl FnOnce(&mut Self) -> R) -> R {
+        let steady = self.framing();
+
+        self.flush_line_buffer();
+        self.modify_lcrh(framing.stop_bits.lcrh());
+        let ret = f(self);
+        self.modify_lcrh(steady.stop_bits.lcrh());
+
+        ret
+    }
+
+    /// The framing currently programmed.
+    fn framing(&self) -> UartFraming {
+        let stop_bits = if self.lcrh.is_set(LCRH::STP2) {
+            StopBits::Two
+        } else {
+            StopBits::One
+        };
+
+        UartFraming { stop_bits }
+    }
+
+    /// Hold the line low for `bits` bit times at the current baud rate, waiting with `spin_for`.
+    fn send_break_with(&mut self, bits: u32, spin_for: impl FnOnce(Duration)) {
+        if bits == 0 {
+            return;
+        }
+
+        // Whatever was written before must be on the wire in full, not cut off by the break.
+        self.flush_line_buffer();
+        self.power_up_tx();
+        self.modify_lcrh(LCRH::BRK::SET);
+        spin_for(break_duration(bits, self.baud_rate));
+
+        // No character is being sent, so there is nothing to wait for.
+        self.lcrh.modify(LCRH::BRK::CLEAR);
+        self.registers.set_lcrh(self.lcrh.get());
+    }
+
+    /// Pass `pattern` through both FIFOs in test mode, without touching the pins.
+    ///
+    /// Each byte is written to DR and read back from the TX FIFO through TDR, then written to the
+    /// RX FIFO through TDR and read back from DR. Transmitter and receiver are off meanwhile, so
+    /// nothing goes out on the line and nothing from the line gets in between. Test mode is left
+    /// and the FIFOs are flushed before returning, also on a mismatch.
+    fn fifo_self_test(&mut self, pattern: &[u8]) -> Result<(), &'static str> {
+        self.flush_line_buffer();
+        self.drain_tx();
+
+        // Received bytes would be read back in place of the pattern.
+        self.buffer_rx_fifo();
+        if !self.registers.fr().matches_all(FR::RXFE::SET) {
+            return Err("Self-test: RX FIFO is not empty, resume RX first");
+        }
+
+        self.write_cr(CR::UARTEN::Enabled.value);
+        self.registers.set_itcr(ITCR::ITCR1::SET.value);
+
+        let mut ret = Ok(());
+        for byte in pattern.iter() {
+            self.registers.set_dr(u32::from(*byte));
+            if self.registers.tdr() & 0xff != u32::from(*byte) {
+                ret = Err("Self-test: TX FIFO readback differs from written data");
+                break;
+            }
+
+            self.registers.set_tdr(u32::from(*byte));
+            if self.registers.dr() & 0xff != u32::from(*byte) {
+                ret = Err("Self-test: RX FIFO readback differs from written data");
+                break;
+            }
+        }
+
+        self.registers.set_itcr(0);
+
+        // Toggling FEN flushes both FIFOs, in case a byte of the pattern was left behind.
+        self.registers
+            .set_lcrh(self.lcrh.get() & !LCRH::FEN::FifosEnabled.value);
+        self.registers.set_lcrh(self.lcrh.get());
+        self.registers.set_icr(ICR::ALL::CLEAR.value);
+
+        self.write_cr((CR::UARTEN::Enabled + CR::TXE::Enabled + self.rxe()).value);
+        self.tx_powered_down = false;
+
+        ret
+    }
+
+    /// The number of bits on the wire per character, with the current framing.
+    fn bits_per_char(&self) -> u32 {
+        let stop_bits = if self.lcrh.is_set(LCRH::STP2) { 2 } else { 1 };
+        let parity_bits = if self.lcrh.is_set(LCRH::PEN) { 1 } else { 0 };
+
+        1 + 5 + self.lcrh.read(LCRH::WLEN) + parity_bits + stop_bits
+    }
+
+    /// Measure the actual baud rate in internal loopback mode, with the clock `now`.
+    ///
+    /// Sends `BAUD_CHECK_BYTES` bytes back to back, and times from the reception of the first to
+    /// that of the last one. This leaves out the delay until the transmitter starts, and in
+    /// between, the characters follow each other without gaps. Nothing goes out on the line. The
+    /// FIFOs are flushed before returning, also on an error.
+    ///
+    /// Fails if input is waiting in the RX FIFO. That is left alone, as buffering it here would
+    /// echo it and hand it to the RX callback.
+    fn measure_baud_with(
+        &mut self,
+        mut now: impl FnMut() -> Duration,
+    ) -> Result<u32, &'static str> {
+        self.flush_line_buffer();
+        self.drain_tx();
+
+        // Received bytes would be counted in place of the looped back ones.
+        if !self.registers.fr().matches_all(FR::RXFE::SET) {
+            return Err("Baud check: RX FIFO is not empty, resume RX first");
+        }
+
+        self.write_cr(
+            (CR::UARTEN::Enabled + CR::TXE::Enabled + CR::RXE::Enabled + CR::LBE::Enabled).value,
+        );
+
+        let mut sent = 0;
+        let mut received = 0;
+        let mut first = None;
+        let mut last = now();
+        let mut deadline = time::deadline_after(last, LOOPBACK_TIMEOUT);
+        while received < BAUD_CHECK_BYTES {
+            if sent < BAUD_CHECK_BYTES && !self.registers.fr().matches_all(FR::TXFF::SET) {
+                self.registers.set_dr(0x55);
+                sent += 1;
+            }
+
+            if self.registers.fr().matches_all(FR::RXFE::SET) {
+                if now() >= deadline {
+                    break;
+                }
+                continue;
+            }
+
+            self.registers.dr();
+            last = now();
+            first.get_or_insert(last);
+            deadline = time::deadline_after(last, LOOPBACK_TIMEOUT);
+            received += 1;
+        }
+
+        // Toggling FEN flushes both FIFOs, in case bytes were left behind. Only then loopback is
+        // left, so that none of them go out on the line.
+        self.registers
+            .set_lcrh(self.lcrh.get() & !LCRH::FEN::FifosEnabled.value);
+        self.registers.set_lcrh(self.lcrh.get());
+        self.registers.set_icr(ICR::ALL::CLEAR.value);
+
+        self.write_cr((CR::UARTEN::Enabled + CR::TXE::Enabled + self.rxe()).value);
+        self.tx_powered_down = false;
+
+        if received < BAUD_CHECK_BYTES {
+            return Err("Baud check: looped back data got lost");
+        }
+
+        let elapsed = last.checked_sub(first.unwrap_or(last)).unwrap_or_default();
+        if elapsed.as_nanos() == 0 {
+            return Err("Baud check: timer too coarse for the measurement");
+        }
+
+        let bits = u128::from(self.bits_per_char()) * (BAUD_CHECK_BYTES as u128 - 1);
+
+        Ok((bits * 1_000_000_000 / elapsed.as_nanos()) as u32)
+    }
+
+    /// `PL011Uart::verify_baud()` with the clock `now`.
+    fn verify_baud_with(&mut self, now: impl FnMut() -> Duration) -> Result<u32, &'static str> {
+        let measured = self.measure_baud_with(now)?;
+
+        let baud_rate = u64::from(self.baud_rate);
+        let deviation = if u64::from(measured) > baud_rate {
+            u64::from(measured) - baud_rate
+        } else {
+            baud_rate - u64::from(measured)
+        };
+        if deviation * 100 > baud_rate * u64::from(self.baud_tolerance) {
+            return Err("Baud check: measured rate deviates from the configured one");
+        }
+
+        Ok(measured)
+    }
+
+    /// Send a data byte immediately, bypassing the line buffer.
+    fn transmit_byte(&mut self, byte: u8) {
+        // Data bytes on a multidrop bus must go out with the 9th bit cleared.
+        if self.is_address_mark() {
+            self.modify_lcrh(LCRH::EPS::Even);
+        }
+
+        self.write_to_fifo(u32::from(byte));
+    }
+
+    /// Send a character UTF-8 encoded, bypassing the line buffer.
+    fn transmit_char(&mut self, c: char) {
+        let mut buf = [0; 4];
+
+        for byte in c.encode_utf8(&mut buf).bytes() {
+            self.transmit_byte(byte);
+        }
+    }
+
+    /// Send out everything accumulated in the line buffer.
+    fn flush_line_buffer(&mut self) {
+        while let Some(c) = self.line_buffer.pop() {
+            self.transmit_char(c);
+        }
+    }
+
+    /// Enable or disable line-buffered mode.
+    ///
+    /// Disabling it sends out an incomplete line, if any.
+    fn set_line_buffered(&mut self, enable: bool) {
+        if !enable {
+            self.flush_line_buffer();
+        }
+
+        self.line_buffered = enable;
+    }
+
+    /// Send a character, starting the line with a timestamp prefix if enabled.
+    fn write_char(&mut self, c: char) {
+        use time::interface::TimeManager;
+
+        self.write_char_with(c, || time::time_manager().uptime())
+    }
+
+    /// `write_char()` with the time source for the prefix passed in.
+    ///
+    /// Whether a line has started is tracked across calls, so a line that is assembled by several
+    /// writes still gets a single prefix.
+    fn write_char_with(&mut self, c: char, uptime: impl FnOnce() -> Duration) {
+        if self.timestamp_prefix && self.at_line_start {
+            for p in format_timestamp(uptime()).as_str().chars() {
+                self.queue_char(p);
+            }
+        }
+        self.at_line_start = c == '\n';
+
+        self.queue_char(c);
+    }
+
+    /// Send a character.
+    ///
+    /// In line-buffered mode, characters are held back until a newline is written, so that a
+    /// complete line goes out in one go. A line that does not fit into the buffer is sent in
+    /// parts.
+    fn queue_char(&mut self, c: char) {
+        if !self.line_buffered {
+            self.transmit_char(c);
+            return;
+        }
+
+        if self.line_buffer.is_full() {
+            self.flush_line_buffer();
+        }
+        let _ = self.line_buffer.push(c);
+
+        if c == '\n' {
+            self.flush_line_buffer();
+        }
+    }
+
+    /// Send a character, or hand it back in non-blocking mode if the TX FIFO is full.
+    ///
+    /// Only direct transmission can be refused. In line-buffered mode, the character is always
+    /// accepted, and sending out a completed line may wait.
+    fn write_char_mode(&mut self, c: char, blocking_mode: BlockingMode) -> Result<(), char> {
+        if self.write_fault() {
+            return Err(c);
+        }
+
+        if blocking_mode == BlockingMode::NonBlocking
+            && !self.line_buffered
+            && self.registers.fr().matches_all(FR::TXFF::SET)
+        {
+            return Err(c);
+        }
+
+        self.write_char(c);
+
+        Ok(())
+    }
+
+    /// Send the keep-alive byte if TX has been idle for its idle time at `now`. Returns true if it
+    /// was sent.
+    ///
+    /// Any write counts as activity and restarts the idle time. The byte only goes out if nothing
+    /// is waiting to be sent, so it never ends up in between real data. It is not counted as
+    /// written.
+    fn poll_keep_alive_at(&mut self, now: Duration) -> bool {
+        let chars_written = self.chars_written;
+        let tx_idle = !self.output_pending() && self.line_buffer.is_empty();
+        let state = match &mut self.keep_alive {
+            Some(x) => x,
+            None => return false,
+        };
+
+        let idle_since = match state.idle_since {
+            Some(x) if state.chars_written == chars_written => x,
+            _ => {
+                state.chars_written = chars_written;
+                state.idle_since = Some(now);
+                return false;
+            }
+        };
+
+        if !tx_idle || now.checked_sub(idle_since).unwrap_or_default() < state.config.idle_time {
+            return false;
+        }
+
+        state.idle_since = Some(now);
+        let byte = state.config.byte;
+        self.power_up_tx();
+        self.registers.set_dr(u32::from(byte));
+
+        true
+    }
+
+    /// Retrieve a raw byte from the RX FIFO.
+    fn read_byte(&mut self, blocking_mode: BlockingMode) -> Option<u8> {
+        // The error flags are discarded.
+        self.read_raw(blocking_mode).map(|x| x as u8)
+    }
+
+    /// Open the grace window after init, if one is configured.
+    fn open_grace_window_at(&mut self, now: Duration) {
+        self.grace_window = self.connect_grace.map(|x| match x {
+            ConnectGrace::Duration(x) => GraceWindow::Until(time::deadline_after(now, x)),
+            ConnectGrace::Bytes(x) => GraceWindow::Bytes(x),
+        });
+    }
+
+    /// Returns true if the received value `raw` is a NUL byte within the grace window.
+    ///
+    /// Counts the byte against the window, and closes the window once it is over.
+    fn swallow_in_grace_at(&mut self, raw: u16, now: Duration) -> bool {
+        let open = match &mut self.grace_window {
+            None => return false,
+            Some(GraceWindow::Until(deadline)) => now < *deadline,
+            Some(GraceWindow::Bytes(0)) => false,
+            Some(GraceWindow::Bytes(left)) => {
+                *left -= 1;
+                true
+            }
+        };
+
+        if !open {
+            self.grace_window = None;
+            return false;
+        }
+
+        // A break or a framing error reads as NUL, too, only with error flags.
+        raw & 0xff == 0
+    }
+
+    /// Retrieve the full data register value for the next entry of the RX FIFO.
+    ///
+    /// Bits 7:0 hold the data, bits 11:8 the overrun, break, parity and framing error flags. NUL
+    /// bytes in the grace window after init are read, but skipped.
+    fn read_raw(&mut self, blocking_mode: BlockingMode) -> Option<u16> {
+        use time::interface::TimeManager;
+
+        loop {
+            let ret = self.read_raw_unfiltered(blocking_mode)?;
+
+            // Outside of the grace window, which is most of the time, the clock is not read.
+            let swallowed = self.grace_window.is_some()
+                && self.swallow_in_grace_at(ret, time::time_manager().uptime());
+            if !swallowed && !self.drop_for_retry(ret) {
+                return Some(ret);
+            }
+        }
+    }
+
+    /// Returns true if the received value `raw` has an error and is to be dropped, as configured
+    /// by `set_rx_error_retry()`. Sends the NAK for it, if any.
+    fn drop_for_retry(&mut self, raw: u16) -> bool {
+        let retry = match self.rx_error_retry {
+            Some(x) => x,
+            None => return false,
+        };
+
+        // An overrun happened after the byte, which is intact.
+        if raw & (DR_FE | DR_PE | DR_BE) == 0 || self.rx_error_retries >= retry.max_retries {
+            self.rx_error_retries = 0;
+            return false;
+        }
+
+        self.rx_error_retries += 1;
+        if let Some(nak) = retry.nak {
+            self.transmit_byte(nak);
+        }
+
+        true
+    }
+
+    /// `read_raw()` without the grace window.
+    fn read_raw_unfiltered(&mut self, blocking_mode: BlockingMode) -> Option<u16> {
+        use time::interface::TimeManager;
+
+        if self.rx_enabled().is_err() {
+            return None;
+        }
+
+        // Deferred to here, so that the byte which completed the window is still handed out.
+        if let Some(baud_rate) = self.auto_baud.as_mut().and_then(|x| x.pending.take()) {
+            self.switch_auto_baud(baud_rate);
+        }
+
+        // If RX FIFO is empty,
+        if self.registers.fr().matches_all(FR::RXFE::SET) {
+            // immediately return in non-blocking mode.
+            if blocking_mode == BlockingMode::NonBlocking {
+                return None;
+            }
+
+            // Otherwise, wait until a char was received.
+            while self.registers.fr().matches_all(FR::RXFE::SET) {
+                cpu::nop();
+            }
+        }
+
+        // Read data and error flags.
+        let ret = (self.registers.dr() & 0xfff) as u16;
+
+        // The FIFO overflowed after this byte, which is still valid. The flag stays set until it
+        // is cleared, so clear it right away.
+        if ret & DR_OE != 0 {
+            self.registers.set_rsrecr(0);
+            self.overruns += 1;
+        }
+
+        if self.framing_errors.record(ret & DR_FE != 0) {
+            if let Some(hook) = self.on_baud_mismatch {
+                hook();
+            }
+        }
+
+        // A framing error window just ended.
+        if self.framing_errors.window_bytes == 0 {
+            self.update_auto_baud();
+        }
+
+        self.rx_line.record(ret);
+
+        if let Some(capture) = &mut self.initial_capture {
+            capture.record(ret as u8);
+        }
+
+        self.last_rx = Some(time::time_manager().uptime());
+
+        // Update statistics.
+        self.chars_read += 1;
+
+        Some(ret)
+    }
+
+    /// Account for the framing error window that just ended, and decide whether to try another
+    /// baud rate.
+    fn update_auto_baud(&mut self) {
+        let current = self.baud_rate;
+        let suspected = self.framing_errors.baud_mismatch_suspected;
+        let state = match &mut self.auto_baud {
+            Some(x) => x,
+            None => return,
+        };
+
+        if !suspected {
+            // The search, if any, ends on the current rate.
+            state.bad_windows = 0;
+            state.search = None;
+            return;
+        }
+
+        state.bad_windows += 1;
+        if state.bad_windows < state.config.sustained_windows {
+            return;
+        }
+        state.bad_windows = 0;
+
+        let (original, switches) = state.search.get_or_insert((current, 0));
+        let original = *original;
+        let mut candidates = state
+            .config
+            .rates
+            .iter()
+            .copied()
+            .filter(|x| *x != original);
+        let num_candidates = candidates.clone().count();
+
+        state.pending = match (*switches).cmp(&num_candidates) {
+            cmp::Ordering::Less => candidates.nth(*switches),
+            // No rate did better. Go back to where the search started, and stay there until the
+            // errors subside.
+            cmp::Ordering::Equal => Some(original),
+            cmp::Ordering::Greater => None,
+        };
+
+        if state.pending.is_some() {
+            *switches += 1;
+        }
+    }
+
+    /// Switch to `baud_rate` for the baud rate search. The new rate gets a fresh error window.
+    fn switch_auto_baud(&mut self, baud_rate: u32) {
+        if self.reconfigure(UartConfig::new(baud_rate)).is_ok() {
+            self.framing_errors = FramingErrorMonitor::new();
+        }
+    }
+
+    fn clear_errors(&mut self, reset_counters: bool) {
+        // Any value clears all error flags.
+        self.registers.set_rsrecr(0);
+
+        if reset_counters {
+            self.overruns = 0;
+            self.framing_errors = FramingErrorMonitor::new();
+            self.rx_line = RxLineMonitor::new();
+        }
+    }
+
+    /// The RX line state at `now`.
+    ///
+    /// Noise and glitches only show while bytes arrive, so once the line was quiet for a while,
+    /// it counts as normal again. A stuck low line is quiet after its breaks, because the UART
+    /// only reports one break per low period, so that verdict holds until a clean byte arrives.
+    fn rx_line_state_at(&self, now: Duration) -> RxLineState {
+        match self.rx_line.state {
+            RxLineState::StuckLow => RxLineState::StuckLow,
+            x if self.last_rx.is_some()
+                && self.idle_since_last_rx_at(now) < RX_LINE_STATE_MAX_AGE =>
+            {
+                x
+            }
+            _ => RxLineState::Normal,
+        }
+    }
+
+    /// Everything that `UartStatus` reports, with the RX line judged at `now`.
+    ///
+    /// DR is not read, so no received byte is consumed.
+    fn status_at(&self, now: Duration) -> UartStatus {
+        let fr = self.registers.fr();
+        let stop_bits = if self.lcrh.is_set(LCRH::STP2) {
+            StopBits::Two
+        } else {
+            StopBits::One
+        };
+
+        UartStatus {
+            config: UartConfig {
+                baud_rate: self.baud_rate,
+                ref_clock_hz: Some(self.ref_clock_hz),
+            },
+            data_bits: 5 + self.lcrh.read(LCRH::WLEN),
+            parity_enabled: self.lcrh.is_set(LCRH::PEN),
+            even_parity: self.lcrh.is_set(LCRH::EPS),
+            stop_bits,
+            fifos_enabled: self.fifos_enabled,
+            tx_enabled: !self.tx_powered_down,
+            rx_paused: self.rx_paused,
+            tx_fifo_empty: fr.is_set(FR::TXFE),
+            tx_fifo_full: fr.is_set(FR::TXFF),
+            rx_fifo_empty: fr.is_set(FR::RXFE),
+            rx_fifo_full: fr.is_set(FR::RXFF),
+            tx_busy: fr.is_set(FR::BUSY),
+            rx_trigger_level: self.rx_trigger_level(),
+            irqs_enabled: self.registers.imsc().get(),
+            irqs_pending: self.registers.mis().get(),
+            rx_error_flags: self.registers.rsrecr(),
+            overruns: self.overruns,
+            baud_mismatch_suspected: self.framing_errors.baud_mismatch_suspected,
+            rx_line_state: self.rx_line_state_at(now),
+            rx_buffered: self.rx_buffer.len(),
+            chars_written: self.chars_written,
+            rx_health: self.rx_health(),
+        }
+    }
+
+    /// Time between the last received byte and `now`. Before any byte was received, that is the
+    /// time since boot.
+    fn idle_since_last_rx_at(&self, now: Duration) -> Duration {
+        now.checked_sub(self.last_rx.unwrap_or_default())
+            .unwrap_or_default()
+    }
+
+    /// Echo a received character according to the echo mode.
+    ///
+    /// Called once for every character when it is taken from the RX FIFO, no matter if that
+    /// happens from the IRQ handler or from a polled read.
+    fn echo(&mut self, c: char) {
+        // Echoes are not output of the kernel, so they do not count toward `chars_written`.
+        let chars_written = self.chars_written;
+
+        match self.echo_mode {
+            console::EchoMode::Off => (),
+            console::EchoMode::Caret if c.is_ascii_control() && c != '\n' => {
+                // 0x7f (DEL) maps to '?', everything else to the range '@'..='_'.
+                self.transmit_byte(b'^');
+                self.transmit_byte((c as u8) ^ 0x40);
+            }
+            // `c` stems from a single received byte, which is echoed as it came in. Otherwise,
+            // the bytes of multi-byte UTF-8 characters would be encoded again.
+            _ => self.transmit_byte(c as u8),
+        }
+        self.chars_written = chars_written;
+
+        // The echo moves the cursor as well.
+        if self.echo_mode != console::EchoMode::Off {
+            self.at_line_start = c == '\n';
+        }
+    }
+
+    /// Move everything that is waiting in the RX FIFO into the software buffer.
+    ///
+    /// The FIFO is drained into a batch on the stack first, and the batch is processed after, so
+    /// that reading the FIFO is not held up by echoing. A full batch may have left bytes behind,
+    /// so another one follows. In character mode, there is only the single byte in the holding
+    /// register, and every received byte raises its own interrupt.
+    ///
+    /// Echoes the received characters back. If the buffer is full, newly received bytes are
+    /// dropped. All bytes of a batch get the same timestamp.
+    fn buffer_rx_fifo(&mut self) {
+        use time::interface::TimeManager;
+
+        if self.rx_paused {
+            return;
+        }
+
+        let mut batch = [0; RX_BATCH_SIZE];
+        let batch_len = if self.fifos_enabled { batch.len() } else { 1 };
+
+        loop {
+            let len = self.drain_rx_fifo(&mut batch[..batch_len]);
+
+            let timestamp = time::time_manager().uptime();
+            for raw in batch[..len].iter() {
+                self.buffer_rx_raw_at(*raw, timestamp);
+            }
+
+            if len < RX_BATCH_SIZE {
+                break;
+            }
+        }
+    }
+
+    /// Read bytes from the RX FIFO into `batch` until either is exhausted, together with their
+    /// error flags, as for `read_raw()`. Returns the number of bytes read.
+    fn drain_rx_fifo(&mut self, batch: &mut [u16]) -> usize {
+        let mut len = 0;
+
+        while len < batch.len() {
+            match self.read_raw(BlockingMode::NonBlocking) {
+                Some(raw) => batch[len] = raw,
+                None => break,
+            }
+            len += 1;
+        }
+
+        len
+    }
+
+    /// Echo a byte received at `timestamp` and put it into the software buffer, dropping it if the
+    /// buffer is full. Bytes consumed by the RX callback are neither echoed nor buffered.
+    fn buffer_rx_byte_at(&mut self, byte: u8, timestamp: Duration) {
+        self.buffer_rx_raw_at(u16::from(byte), timestamp)
+    }
+
+    /// `buffer_rx_byte_at()` for a data register value, whose error flags are kept with the byte.
+    fn buffer_rx_raw_at(&mut self, raw: u16, timestamp: Duration) {
+        let byte = raw as u8;
+        if let Some(callback) = self.on_rx_byte {
+            if callback(byte) {
+                return;
+            }
+        }
+
+        self.echo(self.receive_char(byte));
+
+        let _ = self.rx_buffer.push(RxSlot {
+            byte,
+            flags: (raw >> 8) as u8,
+            timestamp,
+        });
+    }
+
+    /// Copy the software buffer in logical order, oldest byte first.
+    fn rx_ring_dump(&self) -> RxRingDump {
+        let mut dump = RxRingDump {
+            bytes: [0; RX_BUFFER_SIZE],
+            len: self.rx_buffer.len(),
+        };
+        for (dst, src) in dump.bytes.iter_mut().zip(self.rx_buffer.iter()) {
+            *dst = src.byte;
+        }
+
+        dump
+    }
+
+    /// Take the oldest byte from the software buffer.
+    fn pop_rx_byte(&mut self) -> Option<u8> {
+        self.rx_buffer.pop().map(|x| x.byte)
+    }
+
+    /// Take the oldest byte from the software buffer, with its error flags as in `read_raw()`.
+    fn pop_rx_raw(&mut self) -> Option<u16> {
+        self.rx_buffer
+            .pop()
+            .map(|x| u16::from(x.flags) << 8 | u16::from(x.byte))
+    }
+
+    /// Take the next piece of a line from the software buffer for the line callback.
+    ///
+    /// That is a complete line, whose newline is taken but not copied, or the first
+    /// `RX_LINE_CHUNK_SIZE` bytes of a longer one. Returns its length and whether the line
+    /// continues, or `None` if neither is buffered yet.
+    ///
+    /// With input translation enabled, a carriage return ends a line as well, like it reads as a
+    /// newline. A `\r\n` pair ends only one.
+    fn take_line_chunk(&mut self, chunk: &mut [u8; RX_LINE_CHUNK_SIZE]) -> Option<(usize, bool)> {
+        if self.line_ended_cr {
+            let next = self.rx_buffer.iter().next()?.byte;
+            if next == b'\n' {
+                self.pop_rx_byte();
+            }
+            self.line_ended_cr = false;
+        }
+
+        let translate_cr = self.translate_cr;
+        let end = self
+            .rx_buffer
+            .iter()
+            .position(|x| x.byte == b'\n' || (translate_cr && x.byte == b'\r'));
+        let len = match end {
+            Some(x) => x.min(chunk.len()),
+            None if self.rx_buffer.len() >= chunk.len() => chunk.len(),
+            None => return None,
+        };
+
+        for slot in chunk[..len].iter_mut() {
+            *slot = self.pop_rx_byte().unwrap_or_default();
+        }
+
+        let complete = end == Some(len);
+        if complete {
+            self.line_ended_cr = self.pop_rx_byte() == Some(b'\r');
+        }
+
+        Some((len, !complete))
+    }
+
+    /// Take the oldest received byte with its timestamp, from the software buffer first, then
+    /// from the RX FIFO, stamped with `now()`.
+    fn read_timed(&mut self, now: impl FnOnce() -> Duration) -> Option<(u8, Duration)> {
+        if let Some(slot) = self.rx_buffer.pop() {
+            return Some((slot.byte, slot.timestamp));
+        }
+
+        let byte = self.read_byte(BlockingMode::NonBlocking)?;
+
+        Some((byte, now()))
+    }
+
+    /// Handle pending interrupts.
+    ///
+    /// A level interrupt means the RX FIFO crossed its fill threshold, a timeout means that no
+    /// more data arrived for a while after a burst, which makes it a good frame delimiter. Both
+    /// can be pending at once, in which case the level hook is called first.
+    ///
+    /// Only the interrupts that were seen in `MIS` are cleared. One that is raised after the read
+    /// stays pending and causes another call, instead of being acknowledged unhandled.
+    fn handle_pending_irqs(&mut self) {
+        let pending = self.registers.mis();
+
+        if pending.is_set(MIS::RXMIS) {
+            self.registers.set_icr(ICR::RXIC::SET.value);
+        }
+
+        // Check for any kind of RX interrupt.
+        if pending.matches_any(MIS::RXMIS::SET + MIS::RTMIS::SET) {
+            let chars_read = self.chars_read;
+            self.buffer_rx_fifo();
+
+            let drained = self.chars_read - chars_read;
+            self.rx_irq_stats.rx_irqs += 1;
+            self.rx_irq_stats.chars_read_in_irq += drained;
+            if drained == 0 {
+                self.rx_irq_stats.empty_rx_irqs += 1;
+            }
+        }
+
+        // Clearing the timeout before draining would let it expire again on the bytes that are
+        // still in the FIFO.
+        if pending.is_set(MIS::RTMIS) {
+            self.registers.set_icr(ICR::RTIC::SET.value);
+        }
+
+        if pending.is_set(MIS::RXMIS) {
+            if let Some(hook) = self.on_rx_level {
+                hook();
+            }
+        }
+
+        if pending.is_set(MIS::RTMIS) {
+            if let Some(hook) = self.on_rx_timeout {
+                hook();
+            }
+        }
+
+        // The TX interrupt stays asserted for as long as the FIFO is below its watermark, which
+        // would retrigger forever once there is nothing left to send. So it is one-shot.
+        if pending.is_set(MIS::TXMIS) {
+            let mut imsc = self.registers.imsc();
+            imsc.modify(IMSC::TXIM::Disabled);
+            self.registers.set_imsc(imsc.get());
+            self.registers.set_icr(ICR::TXIC::SET.value);
+
+            if let Some(hook) = self.on_tx_ready {
+                hook();
+            }
+        }
+
+        // The level is read after clearing, so that a change in between triggers again instead of
+        // getting lost.
+        if pending.is_set(MIS::CTSMMIS) {
+            self.registers.set_icr(ICR::CTSMIC::SET.value);
+
+            if let Some(hook) = self.on_cts_change {
+                hook(self.registers.fr().is_set(FR::CTS));
+            }
+        }
+
+        self.power_down_tx_if_idle();
+    }
+
+    fn enabled_interrupts(&self) -> EnabledIrqs {
+        let imsc = self.registers.imsc();
+
+        EnabledIrqs {
+            rx: imsc.is_set(IMSC::RXIM),
+            rx_timeout: imsc.is_set(IMSC::RTIM),
+            tx: imsc.is_set(IMSC::TXIM),
+            cts: imsc.is_set(IMSC::CTSMIM),
+        }
+    }
+
+    fn dma_status(&self) -> DmaStatus {
+        let dmacr = self.registers.dmacr();
+
+        DmaStatus {
+            tx_enabled: dmacr.is_set(DMACR::TXDMAE),
+            rx_enabled: dmacr.is_set(DMACR::RXDMAE),
+            rx_stops_on_error: dmacr.is_set(DMACR::DMAONERR),
+        }
+    }
+
+    /// Unmask the TX interrupt, so that the TX ready hook is called once the FIFO drained.
+    fn arm_tx_ready(&mut self) {
+        // The interrupt fires when the FIFO level passes the watermark. An empty FIFO will not
+        // pass it anymore.
+        if self.registers.fr().matches_all(FR::TXFE::SET) {
+            if let Some(hook) = self.on_tx_ready {
+                hook();
+            }
+            return;
+        }
+
+        let mut imsc = self.registers.imsc();
+        imsc.modify(IMSC::TXIM::Enabled);
+        self.registers.set_imsc(imsc.get());
+    }
+
+    /// Convert a received byte to a character, translating carriage returns unless switched off.
+    fn receive_char(&self, byte: u8) -> char {
+        if self.translate_cr {
+            convert_char(byte)
+        } else {
+            byte as char
+        }
+    }
+
+    fn rx_health(&self) -> RxHealth {
+        RxHealth {
+            chars_read: self.chars_read,
+            ..self.rx_irq_stats
+        }
+    }
+
+    /// Returns true if an RX interrupt is waiting for the lock to be released.
+    fn rx_irq_pending(&self) -> bool {
+        self.rx_irqs_enabled
+            && self
+                .registers
+                .mis()
+                .matches_any(MIS::RXMIS::SET + MIS::RTMIS::SET)
+    }
+
+    /// Retrieve a received byte, echoing it like a character.
+    ///
+    /// Bytes buffered by the IRQ handler are returned first. These have been echoed already.
+    fn read_byte_echoed(&mut self, blocking_mode: BlockingMode) -> Option<u8> {
+        if let Some(byte) = self.pop_rx_byte() {
+            return Some(byte);
+        }
+
+        let byte = self.read_byte(blocking_mode)?;
+        self.echo(self.receive_char(byte));
+
+        Some(byte)
+    }
+
+    /// Retrieve a character, one per byte.
+    ///
+    /// Fails while the receiver is disabled and nothing is buffered, instead of waiting for
+    /// characters that cannot arrive.
+    fn read_char_converting(
+        &mut self,
+        blocking_mode: BlockingMode,
+    ) -> Result<Option<char>, &'static str> {
+        if self.rx_buffer.is_empty() {
+            self.rx_enabled()?;
+        }
+
+        Ok(self
+            .read_byte_echoed(blocking_mode)
+            .map(|byte| self.receive_char(byte)))
+    }
+}
+
+impl EarlyUart {
+    /// Send a byte as soon as there is room in the TX FIFO.
+    fn write_byte(&mut self, byte: u8) {
+        while self.registers.FR.matches_all(FR::TXFF::SET) {
+            cpu::nop();
+        }
+
+        self.registers.DR.set(u32::from(byte));
+    }
+}
+
+impl fmt::Write for EarlyUart {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            self.write_byte(byte);
+        }
+
+        Ok(())
+    }
+}
+
+/// Implementing `core::fmt::Write` enables usage of the `format_args!` macros, which in turn are
+/// used to implement the `kernel`'s `print!` and `println!` macros. By implementing `write_str()`,
+/// we get `write_fmt()` automatically.
+///
+/// The function takes an `&mut self`, so it must be implemented for the inner struct.
+///
+/// See [`src/print.rs`].
+///
+/// [`src/print.rs`]: ../../print/index.html
+impl<T: UartRegisters> fmt::Write for PL011UartInner<T> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            self.write_char(c);
+        }
+
+        Ok(())
+    }
+}
+
+impl PL011Uart {
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide correct MMIO descriptors.
+    /// - The user must ensure to provide correct IRQ numbers.
+    pub const unsafe fn new(
+        phys_mmio_descriptor: memory::mmu::MMIODescriptor<Physical>,
+        irq_number: bsp::device_driver::IRQNumber,
+        clocks: &'static (dyn ClockControl + Sync),
+    ) -> Self {
+        Self {
+            phys_mmio_descriptor,
+            virt_mmio_start_addr: AtomicUsize::new(0),
+            inner: IRQSafeNullLock::new(PL011UartInner::new(
+                phys_mmio_descriptor.start_addr().into_usize(),
+            )),
+            irq_number,
+            clocks,
+            drop_reentrant_writes: AtomicBool::new(false),
+        }
+    }
+
+    /// Enable or disable multidrop mode, e.g. for RS-485 buses.
+    ///
+    /// In multidrop mode, the parity bit acts as a 9th data bit that tells address bytes apart
+    /// from data bytes. Everything written through the console interface is sent as data.
+    pub fn set_multidrop(&self, enable: bool) {
+        self.inner.lock(|inner| inner.set_multidrop(enable));
+    }
+
+    /// Enable or disable line-buffered output.
+    ///
+    /// When enabled, console output is held back until a newline is written and then emitted as a
+    /// whole, so that lines printed by different callers do not interleave.
+    pub fn set_line_buffered(&self, enable: bool) {
+        self.inner.lock(|inner| inner.set_line_buffered(enable));
+    }
+
+    /// Enable or disable prefixing each output line with the uptime, e.g. `[   12.345678] `.
+    ///
+    /// Raw bytes from `write_byte()` are not considered, since they are not text.
+    pub fn set_timestamp_prefix(&self, enable: bool) {
+        self.inner.lock(|inner| inner.timestamp_prefix = enable);
+    }
+
+    /// Switch the receiver off, for a UART that only sends, or back on.
+    ///
+    /// While it is off, reads fail or return nothing instead of handing out whatever `DR` holds.
+    /// Blocking reads, which cannot fail, return NUL. Bytes that were received before stay
+    /// readable.
+    pub fn set_tx_only(&self, tx_only: bool) {
+        self.inner.lock(|inner| inner.set_tx_only(tx_only));
+    }
+
+    /// Enable or disable the hardware FIFOs.
+    ///
+    /// Disabling them is a fallback for clones and emulators that lose bytes in FIFO mode. The
+    /// UART then works in character mode, with a single byte of buffering in each direction. Must
+    /// be called after the UART has been initialized, since it briefly disables and re-enables it.
+    pub fn set_fifos_enabled(&self, enable: bool) {
+        self.inner.lock(|inner| inner.set_fifos_enabled(enable));
+    }
+
+    /// Change the line configuration at runtime.
+    ///
+    /// Output that is still pending goes out with the old configuration, bytes received around
+    /// the switch are discarded. The statistics are preserved. Baud rates that the UART clock
+    /// cannot produce within the tolerance are rejected without touching the line.
+    pub fn reconfigure(&self, config: UartConfig) -> Result<(), &'static str> {
+        self.inner.lock(|inner| inner.reconfigure(config))
+    }
+
+    /// Change the baud rate and the framing together, in a single transition under the lock.
+    ///
+    /// Behaves like `reconfigure()` otherwise. Use it for any runtime change of the serial
+    /// parameters, so that the UART never runs with new divisors and old framing, or vice versa.
+    pub fn reconfigure_serial(
+        &self,
+        baud_rate: u32,
+        framing: UartFraming,
+    ) -> Result<(), &'static str> {
+        self.inner
+            .lock(|inner| inner.reconfigure_serial(baud_rate, framing))
+    }
+
+    /// Leave the current serial settings for the kernel that runs after a warm reboot or a
+    /// chainload, so that it can continue at them, see `restore_persisted_config()`.
+    pub fn persist_config(&self) {
+        let record = self.inner.lock(|inner| inner.persisted_config());
+
+        let area = unsafe { &mut bsp::memory::persist_area()[..PERSISTED_CONFIG_LEN] };
+        area.copy_from_slice(&record);
+        // The caches do not survive the reset.
+        memory::cache::clean_range(area.as_ptr() as usize, area.len());
+    }
+
+    /// Switch to the serial settings that the previous kernel left with `persist_config()`, to be
+    /// called during early init.
+    ///
+    /// Returns false if there are none, e.g. after a cold boot, or if they are corrupt. The
+    /// defaults of `init()` stay in place then. Behaves like `reconfigure()` otherwise.
+    pub fn restore_persisted_config(&self) -> Result<bool, &'static str> {
+        let record = unsafe { &bsp::memory::persist_area()[..PERSISTED_CONFIG_LEN] };
+
+        self.inner.lock(|inner| inner.restore_config_from(record))
+    }
+
+    /// Set how far, in percent, the baud rate that the divisors produce may deviate from the
+    /// requested one. Checked by `init()` and `reconfigure()`. Defaults to 2 %.
+    ///
+    /// The rounding of the divisors alone stays below 0.8 %, so the default catches rates that
+    /// are far off only, e.g. because of a changed UART clock.
+    pub fn set_baud_tolerance(&self, tolerance: u32) {
+        self.inner.lock(|inner| inner.baud_tolerance = tolerance);
+    }
+
+    /// Set the frequency of the UART reference clock that `init()` computes the divisors from.
+    /// Defaults to the 48 MHz of config.txt.
+    ///
+    /// Must be set before the driver is initialized to take effect. Afterwards, the clock is
+    /// switched with `reconfigure()` and [`UartConfig::with_ref_clock_hz()`]. Clocks below 1 MHz
+    /// are rejected, like there.
+    pub fn set_ref_clock_hz(&self, ref_clock_hz: u32) -> Result<(), &'static str> {
+        check_ref_clock(ref_clock_hz)?;
+        self.inner.lock(|inner| inner.ref_clock_hz = ref_clock_hz);
+
+        Ok(())
+    }
+
+    /// The current line configuration.
+    pub fn config(&self) -> UartConfig {
+        self.inner.lock(|inner| UartConfig {
+            baud_rate: inner.baud_rate,
+            ref_clock_hz: Some(inner.ref_clock_hz),
+        })
+    }
+
+    /// Run `f` with the baud rate switched to `baud_rate`, e.g. for a bulk transfer, and switch
+    /// back afterwards.
+    ///
+    /// Both switches behave like `reconfigure()`. How the peer learns about them is up to the
+    /// caller. The old rate is also restored if `f` returns early by unwinding, but the kernel
+    /// currently halts on panic instead.
+    pub fn with_baud<R>(&self, baud_rate: u32, f: impl FnOnce() -> R) -> Result<R, &'static str> {
+        struct Restore<'a> {
+            uart: &'a PL011Uart,
+            config: UartConfig,
+        }
+
+        impl Drop for Restore<'_> {
+            fn drop(&mut self) {
+                // The old configuration was applied before, so it cannot be rejected.
+                let _ = self.uart.reconfigure(self.config);
+            }
+        }
+
+        let config = self.config();
+        self.reconfigure(UartConfig::new(baud_rate))?;
+        let _restore = Restore { uart: self, config };
+
+        Ok(f())
+    }
+
+    /// Enable or disable reading back the registers programmed during init.
+    ///
+    /// Must be set before the driver is initialized to take effect. Off by default, because the
+    /// readback costs cycles.
+    pub fn set_verify_writes(&self, enable: bool) {
+        self.inner.lock(|inner| inner.verify_writes = enable);
+    }
+
+    /// Set the hooks that are called when the RX FIFO level or the RX timeout interrupt fired.
+    ///
+    /// Received data is already buffered when the hooks run.
+    pub fn set_rx_irq_hooks(
+        &self,
+        on_rx_level: Option<RxIRQHook>,
+        on_rx_timeout: Option<RxIRQHook>,
+    ) {
+        self.inner.lock(|inner| {
+            inner.on_rx_level = on_rx_level;
+            inner.on_rx_timeout = on_rx_timeout;
+        });
+    }
+
+    /// Set the callback that is called for every byte moved from the RX FIFO to the software
+    /// buffer.
+    ///
+    /// In interrupt mode, that happens in the IRQ handler, so the callback can react with minimal
+    /// latency. For the same reason, it must be short and must never block. Bytes read directly
+    /// from the FIFO while polling do not pass the callback.
+    pub fn set_rx_callback(&self, callback: Option<RxByteCallback>) {
+        self.inner.lock(|inner| inner.on_rx_byte = callback);
+    }
+
+    /// Set the callback that is handed every line received into the software buffer.
+    ///
+    /// Lines end with a newline, or with a carriage return while input translation is enabled.
+    /// Longer ones than 64 bytes are handed over in pieces, with all
+    /// but the last one flagged as continued. The callback must not run in interrupt context, and
+    /// the kernel has no deferred work yet, so it is called from `poll_lines()` only. Lines taken
+    /// for the callback are gone from the buffer, so that reads do not see them.
+    pub fn set_line_callback(&self, callback: Option<LineCallback>) {
+        self.inner.lock(|inner| inner.on_line = callback);
+    }
+
+    /// Hand the lines received so far to the line callback. See `set_line_callback()`.
+    ///
+    /// The UART is locked only while a line is taken from the buffer, not while the callback runs.
+    pub fn poll_lines(&self) {
+        let mut chunk = [0; RX_LINE_CHUNK_SIZE];
+
+        loop {
+            let taken = self.inner.lock(|inner| {
+                let callback = inner.on_line?;
+                let (len, continued) = inner.take_line_chunk(&mut chunk)?;
+
+                Some((callback, len, continued))
+            });
+
+            match taken {
+                Some((callback, len, continued)) => callback(&chunk[..len], continued),
+                None => break,
+            }
+        }
+    }
+
+    /// Like [`PL011Uart::set_rx_callback()`], but returns the previous callback.
+    ///
+    /// The callback only runs with the UART locked, so the old one is never called once this
+    /// returns. Bytes still waiting in the RX FIFO are handed to the new one before the lock is
+    /// released, which moves them into the software buffer like an interrupt would.
+    pub fn replace_rx_callback(&self, callback: Option<RxByteCallback>) -> Option<RxByteCallback> {
+        self.inner.lock(|inner| {
+            let old = core::mem::replace(&mut inner.on_rx_byte, callback);
+            inner.buffer_rx_fifo();
+
+            old
+        })
+    }
+
+    /// Stop moving received bytes into the software buffer, e.g. while a command is processed.
+    ///
+    /// Unlike switching RX off, the UART keeps receiving into its FIFO, until that is full and
+    /// further bytes are lost as overruns. Reads still get the buffered bytes, then those in the
+    /// FIFO.
+    pub fn pause_rx(&self) {
+        self.inner.lock(|inner| inner.pause_rx());
+    }
+
+    /// Undo `pause_rx()`. Bytes that piled up in the RX FIFO are buffered after the ones from
+    /// before the pause, in the order they arrived.
+    pub fn resume_rx(&self) {
+        self.inner.lock(|inner| inner.resume_rx());
+    }
+
+    /// Set the hook that is called when the TX FIFO drained to its watermark.
+    pub fn set_tx_ready_hook(&self, hook: Option<TxReadyHook>) {
+        self.inner.lock(|inner| inner.on_tx_ready = hook);
+    }
+
+    /// Set the hook that is called from the IRQ handler when the remote asserts or deasserts CTS,
+    /// e.g. to pause and resume output. `None` masks the interrupt again.
+    ///
+    /// The interrupt is unmasked only while there is a hook. It stays so across `init()`.
+    pub fn set_cts_change_hook(&self, hook: Option<CtsChangeHook>) {
+        self.inner.lock(|inner| inner.set_cts_change_hook(hook));
+    }
+
+    /// Blink `led` on TX activity, as a heartbeat for headless debugging. `None` disables it.
+    ///
+    /// The LED toggles once per write call, independent of how many bytes it sends. A previous LED
+    /// is switched off.
+    pub fn set_tx_activity_led(&self, led: Option<ActivityLed>) {
+        self.inner.lock(|inner| {
+            if let Some(old) = core::mem::replace(&mut inner.tx_activity_led, led) {
+                old.set_level(false);
+            }
+            inner.tx_activity_level = false;
+        });
+    }
+
+    /// Check the whole path to the pins, with TX externally connected to RX.
+    ///
+    /// Unlike the UART's internal loopback, this also covers the GPIO function selection and the
+    /// wiring. Pending input is discarded first, and echo is switched off for the duration of the
+    /// test. If nothing at all comes back, the jumper is missing or the pins are not routed to the
+    /// UART.
+    pub fn physical_loopback_test(&self) -> Result<(), &'static str> {
+        console::interface::Read::clear(self);
+
+        self.with_echo_off(|| loopback_test_over(self, LOOPBACK_TIMEOUT))
+    }
+
+    /// Like `physical_loopback_test()`, but measure how long each byte of the pattern takes to come
+    /// back, to tell wiring faults from software faults during bring-up.
+    ///
+    /// Wrong data does not fail the test, it is reported in the result next to the timing. The
+    /// round trip includes the time on the wire, so at 921600 baud, it is at least about 11 us.
+    pub fn timed_loopback_test(&self) -> Result<LoopbackTiming, &'static str> {
+        use time::interface::TimeManager;
+
+        console::interface::Read::clear(self);
+
+        self.with_echo_off(|| {
+            timed_loopback_over(self, LOOPBACK_TIMEOUT, || time::time_manager().uptime())
+        })
+    }
+
+    /// Check the TX and RX FIFOs with the UART's test registers, e.g. for a manufacturing test.
+    ///
+    /// Works at the register level and independent of the pins, unlike
+    /// `physical_loopback_test()`. Pending output is sent and pending input is buffered first.
+    /// Normal operation resumes afterwards, with the transmitter on.
+    pub fn manufacturing_self_test(&self) -> Result<(), &'static str> {
+        self.inner
+            .lock(|inner| inner.fifo_self_test(&LOOPBACK_PATTERN))
+    }
+
+    /// Check that the UART actually runs at the configured baud rate, e.g. to catch a wrong
+    /// reference clock.
+    ///
+    /// Times 16 bytes in the UART's internal loopback mode, so nothing goes out on the line, and
+    /// returns the measured rate. Fails if that deviates from the configured one by more than the
+    /// baud tolerance, see `set_baud_tolerance()`. Pending output is sent first. Fails if input is
+    /// waiting in the RX FIFO. IRQs are masked for the duration, which is about 0.7 ms at 230400
+    /// baud.
+    pub fn verify_baud(&self) -> Result<u32, &'static str> {
+        use time::interface::TimeManager;
+
+        self.inner
+            .lock(|inner| inner.verify_baud_with(|| time::time_manager().uptime()))
+    }
+
+    /// Have the TX ready hook called once, as soon as the TX FIFO drained to its watermark of
+    /// 1/8.
+    ///
+    /// Meant for producers that refill the FIFO just in time. They write until the FIFO is
+    /// full, arm, and continue when the hook signals them. If the FIFO is empty already, the hook
+    /// is called right away. Switching to interrupt mode with `enable_interrupts()` disarms.
+    pub fn arm_tx_ready(&self) {
+        self.inner.lock(|inner| inner.arm_tx_ready());
+    }
+
+    /// Receive a file using XMODEM-CRC.
+    ///
+    /// Echo is switched off for the duration of the transfer.
+    pub fn xmodem_receive(&self, dest: &mut [u8]) -> Result<usize, xmodem::XmodemError> {
+        self.with_echo_off(|| xmodem::receive(self, dest))
+    }
+
+    /// Time since the last byte was received, or since boot if none was received yet.
+    ///
+    /// Meant for protocols that delimit frames by idle gaps, e.g. 3.5 characters for Modbus RTU.
+    /// A byte counts as received when it is taken from the RX FIFO. In interrupt mode, that can be
+    /// up to the RX timeout of 32 bit periods after it arrived, which the detection must allow for.
+    pub fn idle_since_last_rx(&self) -> Duration {
+        use time::interface::TimeManager;
+
+        self.inner
+            .lock(|inner| inner.idle_since_last_rx_at(time::time_manager().uptime()))
+    }
+
+    /// Choose what happens to console output while the UART is locked already, i.e. from code that
+    /// runs within the driver, like a debug print in its IRQ handler or in an RX callback.
+    ///
+    /// Defaults to [`ReentrantWrite::Raw`]. Locking a second time is never an option, it would
+    /// hand out the inner state twice.
+    pub fn set_reentrant_write(&self, behavior: ReentrantWrite) {
+        self.drop_reentrant_writes
+            .store(behavior == ReentrantWrite::Drop, Ordering::Relaxed);
+    }
+
+    /// Drop received NUL bytes for a while after init, for terminals that send a break or a burst
+    /// of garbage on connect. Off by default, since NUL can be legitimate data in binary
+    /// protocols.
+    ///
+    /// Must be set before the driver is initialized to take effect. Other bytes are delivered as
+    /// usual, also during the window.
+    pub fn set_connect_grace(&self, grace: Option<ConnectGrace>) {
+        self.inner.lock(|inner| inner.connect_grace = grace);
+    }
+
+    /// Drop bytes received with a framing, parity or break error, and optionally send a NAK for
+    /// each, so that the peer sends it again. `None` delivers them with their error flags, as by
+    /// default.
+    ///
+    /// Applies to all reads, including the ones of the IRQ handler. Overruns do not count as
+    /// errors, since the byte that reports one is intact.
+    pub fn set_rx_error_retry(&self, retry: Option<RxErrorRetry>) {
+        self.inner.lock(|inner| {
+            inner.rx_error_retry = retry;
+            inner.rx_error_retries = 0;
+        });
+    }
+
+    /// Start capturing the next received bytes, dropping an earlier capture.
+    ///
+    /// The capture only copies the bytes. They are delivered to readers as usual.
+    pub fn start_initial_capture(&self) {
+        self.inner
+            .lock(|inner| inner.initial_capture = Some(InitialCapture::new()));
+    }
+
+    /// The bytes captured so far. Empty if capturing was never started.
+    ///
+    /// Returned by value, because the capture lives behind the UART's lock.
+    pub fn initial_capture(&self) -> InitialCapture {
+        self.inner
+            .lock(|inner| inner.initial_capture.unwrap_or_else(InitialCapture::new))
+    }
+
+    /// Fill `segments` in order with received bytes, without intermediate copies.
+    ///
+    /// Lets a parser direct the fields of a message into different buffers, e.g. a header and a
+    /// payload. Empty segments are skipped. Returns early if no byte arrives for a while, so the
+    /// total count can be smaller than the combined size of the segments. Echo is switched off
+    /// for the duration of the call.
+    pub fn read_scatter(&self, segments: &mut [&mut [u8]]) -> usize {
+        self.with_echo_off(|| read_scatter_from(self, segments, SCATTER_IDLE_TIMEOUT))
+    }
+
+    /// The RX FIFO fill level at which the RX interrupt currently fires, as read from the hardware.
+    pub fn rx_trigger_level(&self) -> RxTriggerLevel {
+        self.inner.lock(|inner| inner.rx_trigger_level())
+    }
+
+    /// Change the RX FIFO fill level at which the RX interrupt fires, e.g. for tuning latency
+    /// against interrupt load.
+    ///
+    /// Takes effect right away, without the reset that `init()` does. The TX level is kept. The
+    /// next `init()` resets the level to 1/8.
+    pub fn set_rx_trigger_level(&self, level: RxTriggerLevel) {
+        self.inner.lock(|inner| inner.set_rx_trigger_level(level));
+    }
+
+    /// Returns true if this instance is the UART behind the kernel's console.
+    ///
+    /// For code that writes to a UART directly, and would duplicate output that goes through the
+    /// console if it is the same UART. The BSP fixes its console at build time, so there always is
+    /// one; it is just not this instance.
+    pub fn is_active_console(&self) -> bool {
+        core::ptr::eq(self, bsp::console::uart())
+    }
+
+    /// Get both FIFOs and the receive errors to a clean state, e.g. to recover from a burst of
+    /// errors, without the reset of baud rate and framing that `init()` does.
+    ///
+    /// Sends out all pending output, including its last character, then drops everything that was
+    /// received, and clears RSRECR. All of it happens in one go, with the UART locked.
+    pub fn reset_fifos(&self) {
+        self.inner.lock(|inner| inner.reset_fifos());
+    }
+
+    /// Read the 16550 register at `offset`, through a [`Uart16550Shim`].
+    ///
+    /// For code that was written against a 16550. Registers without a PL011 equivalent return an
+    /// error.
+    pub fn read_16550(&self, offset: usize) -> Result<u8, &'static str> {
+        self.inner
+            .lock(|inner| Uart16550Shim::new(&inner.registers).read(offset))
+    }
+
+    /// Write `value` to the 16550 register at `offset`, through a [`Uart16550Shim`].
+    ///
+    /// Registers without a PL011 equivalent return an error.
+    pub fn write_16550(&self, offset: usize, value: u8) -> Result<(), &'static str> {
+        self.inner
+            .lock(|inner| Uart16550Shim::new(&inner.registers).write(offset, value))
+    }
+
+    /// Read the register at `offset` into the register block, bypassing the driver.
+    ///
+    /// For experiments from a debug shell. Some reads have side effects, e.g. one of `DR` takes a
+    /// byte from the RX FIFO, which the driver then never sees.
+    #[cfg(feature = "register_poke")]
+    pub fn peek_register(&self, offset: usize) -> Result<u32, &'static str> {
+        check_register_offset(offset)?;
+
+        self.inner.lock(|inner| {
+            let addr = inner.registers.start_addr() + offset;
+            Ok(unsafe { core::ptr::read_volatile(addr as *const u32) })
+        })
+    }
+
+    /// Write `value` to the register at `offset` into the register block, bypassing the driver.
+    ///
+    /// # Safety
+    ///
+    /// - The write changes the UART behind the driver's back. Anything from lost data to a dead
+    ///   console can follow, and is the caller's responsibility.
+    #[cfg(feature = "register_poke")]
+    pub unsafe fn poke_register(&self, offset: usize, value: u32) -> Result<(), &'static str> {
+        check_register_offset(offset)?;
+
+        self.inner.lock(|inner| {
+            let addr = inner.registers.start_addr() + offset;
+            core::ptr::write_volatile(addr as *mut u32, value);
+        });
+
+        Ok(())
+    }
+
+    /// The interrupts that are currently unmasked, read back from the hardware.
+    ///
+    /// Covers all interrupts the driver uses. The modem status and error interrupts are never
+    /// unmasked.
+    pub fn enabled_interrupts(&self) -> EnabledIrqs {
+        self.inner.lock(|inner| inner.enabled_interrupts())
+    }
+
+    /// The DMA requests the UART raises, for debugging stuck transfers.
+    ///
+    /// An unconfigured UART reports all requests disabled. There is no DMA driver yet, so the
+    /// state of the channel on the other end is not included.
+    pub fn dma_status(&self) -> DmaStatus {
+        self.inner.lock(|inner| inner.dma_status())
+    }
+
+    /// RX interrupt counts next to the number of bytes read, taken together under the lock.
+    ///
+    /// Bytes that are read by polling although interrupts are on point to interrupts that do not
+    /// arrive, interrupts that find nothing to read to misrouted or spurious ones.
+    pub fn rx_health(&self) -> RxHealth {
+        self.inner.lock(|inner| inner.rx_health())
+    }
+
+    /// Configuration, state, errors and statistics taken together under the lock, e.g. for a
+    /// debug command.
+    pub fn status(&self) -> UartStatus {
+        use time::interface::TimeManager;
+
+        self.inner
+            .lock(|inner| inner.status_at(time::time_manager().uptime()))
+    }
+
+    /// The number of times the RX FIFO overflowed, losing received bytes.
+    pub fn overruns(&self) -> usize {
+        self.inner.lock(|inner| inner.overruns)
+    }
+
+    /// Clear the sticky framing, parity, break and overrun flags, e.g. to recover after a burst of
+    /// errors. With `reset_counters`, the overrun count and the baud mismatch suspicion start
+    /// over, too.
+    ///
+    /// The flags live apart from the FIFO, so bytes that are being received are not affected.
+    pub fn clear_errors(&self, reset_counters: bool) {
+        self.inner.lock(|inner| inner.clear_errors(reset_counters));
+    }
+
+    /// Returns true if the framing error rate of received data suggests that the sender uses a
+    /// different baud rate.
+    pub fn baud_mismatch_suspected(&self) -> bool {
+        self.inner
+            .lock(|inner| inner.framing_errors.baud_mismatch_suspected)
+    }
+
+    /// Set the hook that is called when a baud rate mismatch starts being suspected.
+    pub fn set_baud_mismatch_hook(&self, hook: Option<BaudMismatchHook>) {
+        self.inner.lock(|inner| inner.on_baud_mismatch = hook);
+    }
+
+    /// Try other baud rates when framing errors persist, or never if `None`. Off by default.
+    ///
+    /// After a baud rate mismatch was suspected for `sustained_windows` windows in a row, the
+    /// driver switches to the next of the `rates`, skipping the one the search started from. The
+    /// search ends at the first rate that receives a window without a suspected mismatch. If no
+    /// rate does, the driver returns to the rate it started from and stays there until the errors
+    /// subside. So each rate is tried at most once per search, and a working connection is never
+    /// touched. Switches behave like `reconfigure()` and happen at the next read.
+    pub fn set_auto_baud(&self, auto_baud: Option<AutoBaud>) {
+        self.inner.lock(|inner| {
+            inner.auto_baud = auto_baud.map(|config| AutoBaudState {
+                config,
+                bad_windows: 0,
+                search: None,
+                pending: None,
+            })
+        });
+    }
+
+    /// Make writes fail at random, or never again if `None`.
+    ///
+    /// A failing `write_char_mode()` hands the character back, a failing `write_fmt()` returns
+    /// an error, and failing `write_byte()` and `write_bytes()` drop the byte. The decisions only
+    /// depend on the seed and on the order of the writes, so test runs are reproducible.
+    #[cfg(test)]
+    pub fn set_write_faults(&self, faults: Option<WriteFaults>) {
+        self.inner
+            .lock(|inner| inner.write_faults = faults.map(FaultInjector::new));
+    }
+
+    /// Feed bytes into the receive path as if they arrived on the wire.
+    ///
+    /// Lets tests drive code that reads from the console without real RX hardware. The bytes
+    /// are echoed and buffered, and dropped like received bytes if the buffer is full.
+    #[cfg(test)]
+    pub fn inject_rx(&self, bytes: &[u8]) {
+        use time::interface::TimeManager;
+
+        let timestamp = time::time_manager().uptime();
+        self.inner.lock(|inner| {
+            for byte in bytes {
+                inner.buffer_rx_byte_at(*byte, timestamp);
+            }
+        });
+    }
+
+    /// Retrieve the next received byte together with its error flags, if any is available.
+    ///
+    /// Bits 7:0 hold the data, bits 11:8 the overrun, break, parity and framing error flags.
+    /// Bytes that the IRQ handler already moved to the software buffer are returned first, with
+    /// the flags they were received with.
+    pub fn read_raw(&self) -> Option<u16> {
+        self.inner.lock(|inner| match inner.pop_rx_raw() {
+            Some(raw) => Some(raw),
+            None => inner.read_raw(BlockingMode::NonBlocking),
+        })
+    }
+
+    /// Diagnose the RX line from the recently received bytes, e.g. to find cabling problems during
+    /// bring-up.
+    ///
+    /// A line without traffic is reported as normal, because it cannot be told apart from a
+    /// legitimately idle one.
+    pub fn rx_line_state(&self) -> RxLineState {
+        use time::interface::TimeManager;
+
+        self.inner
+            .lock(|inner| inner.rx_line_state_at(time::time_manager().uptime()))
+    }
+
+    /// Retrieve the next received byte together with the uptime at which it was received, if any
+    /// is available.
+    ///
+    /// For bytes that the IRQ handler buffered, that is when it moved them out of the RX FIFO.
+    /// Bytes that are still in the FIFO are stamped when they are read, so for precise timing,
+    /// RX interrupts should be enabled and the trigger level low.
+    pub fn read_timed(&self) -> Option<(u8, Duration)> {
+        use time::interface::TimeManager;
+
+        self.inner
+            .lock(|inner| inner.read_timed(|| time::time_manager().uptime()))
+    }
+
+    /// Record a session of received bytes with their timing into `into`, replacing what it held.
+    ///
+    /// The recording ends when it is full at 256 bytes, or once no byte arrived for a second.
+    /// The bytes are consumed, so they do not reach other readers. The timing is that of
+    /// `read_timed()`, so RX interrupts should be enabled for it to be precise.
+    pub fn record_rx(&self, into: &mut RxRecording) {
+        use time::interface::TimeManager;
+
+        record_rx_from(
+            into,
+            // Lock per attempt, so that the IRQ handler has a chance to run in between.
+            || {
+                self.inner
+                    .lock(|inner| inner.read_timed(|| time::time_manager().uptime()))
+            },
+            || time::time_manager().uptime(),
+            RX_RECORDING_IDLE_TIMEOUT,
+        );
+    }
+
+    /// Feed the bytes of `recording` into the receive path, each at its recorded time relative to
+    /// the first one, e.g. to reproduce a parser bug.
+    ///
+    /// The bytes go through the RX callback, echo and the software buffer like received ones,
+    /// and are dropped if the buffer is full. Timing is best-effort: the call busy-waits for each
+    /// byte, and if it falls behind, for example because of interrupts, the late bytes are
+    /// counted in the returned timing.
+    pub fn replay_rx(&self, recording: &RxRecording) -> RxReplayTiming {
+        use time::interface::TimeManager;
+
+        replay_rx_to(
+            recording,
+            || time::time_manager().uptime(),
+            |byte, t| self.inner.lock(|inner| inner.buffer_rx_byte_at(byte, t)),
+        )
+    }
+
+    /// Copy received bytes into `out` without consuming them. Returns the number of bytes copied.
+    ///
+    /// Lets a parser check whether a complete frame has arrived before reading it. The RX FIFO
+    /// is moved into the software buffer first, so that all peeked bytes are returned by the
+    /// following reads, in the same order. Doing so echoes them and drops their error flags, like
+    /// it happens in interrupt mode.
+    pub fn peek_rx(&self, out: &mut [u8]) -> usize {
+        self.inner.lock(|inner| {
+            inner.buffer_rx_fifo();
+
+            let mut len = 0;
+            for (dst, src) in out.iter_mut().zip(inner.rx_buffer.iter()) {
+                *dst = src.byte;
+                len += 1;
+            }
+
+            len
+        })
+    }
+
+    /// Call `f` with all received bytes as one contiguous slice, without consuming them.
+    ///
+    /// For parsers that want to look at a whole frame at once, regardless of where it wrapped
+    /// around in the software buffer. The bytes are copied into order on the stack, and the lock
+    /// is held while `f` runs, so no IRQ can push bytes in between. Keep `f` short.
+    pub fn compact_rx<R>(&self, f: impl FnOnce(&[u8]) -> R) -> R {
+        self.inner.lock(|inner| {
+            inner.buffer_rx_fifo();
+
+            let mut linear = [0; RX_BUFFER_SIZE];
+            for (dst, src) in linear.iter_mut().zip(inner.rx_buffer.iter()) {
+                *dst = src.byte;
+            }
+
+            f(&linear[..inner.rx_buffer.len()])
+        })
+    }
+
+    /// Print the received bytes as a hexdump on the console, without consuming them.
+    ///
+    /// For looking into what confused a protocol parser. The bytes are copied under the lock, and
+    /// printed after releasing it, so this also works if this UART is the console. Only the
+    /// software buffer is dumped. Bytes still in the RX FIFO are left there, so that neither echo
+    /// nor the RX callback sees them early.
+    pub fn dump_rx_ring(&self) {
+        let dump = self.inner.lock(|inner| inner.rx_ring_dump());
+
+        crate::print!("{}", dump);
+    }
+
+    /// Send `keep_alive.byte` whenever TX was idle for `keep_alive.idle_time`, or never if `None`.
+    ///
+    /// Keeps lines in a defined state that would otherwise float, e.g. with some RS-485
+    /// transceivers. The kernel has no timer callbacks yet, so idle times are only noticed while
+    /// `poll_keep_alive()` is called periodically.
+    pub fn set_keep_alive(&self, keep_alive: Option<KeepAlive>) {
+        self.inner.lock(|inner| {
+            inner.keep_alive = keep_alive.map(|config| KeepAliveState {
+                config,
+                chars_written: inner.chars_written,
+                idle_since: None,
+            })
+        });
+    }
+
+    /// Send the keep-alive byte if TX has been idle long enough. See `set_keep_alive()`.
+    ///
+    /// The idle time is measured from the first call after the last write at the earliest.
+    pub fn poll_keep_alive(&self) {
+        use time::interface::TimeManager;
+
+        self.inner
+            .lock(|inner| inner.poll_keep_alive_at(time::time_manager().uptime()));
+    }
+
+    /// Let `write_bytes()` release the lock after every `interval` bytes, or never if `None`.
+    ///
+    /// Releasing the lock unmasks IRQs for a moment, so that pending ones, e.g. for RX, are
+    /// handled during long writes. The price is atomicity: output of other writers can end up in
+    /// between the chunks.
+    pub fn set_write_yield_interval(&self, interval: Option<NonZeroUsize>) {
+        self.inner
+            .lock(|inner| inner.write_yield_interval = interval);
+    }
+
+    /// Make `write_all()` and `write_atomic()` hold the line idle for `idle` after a frame.
+    ///
+    /// The methods then only return once the last byte has left the UART and the line was quiet
+    /// for `idle`, e.g. so that an RS-485 transceiver can be switched to receive, and the next
+    /// frame starts after the required gap. Zero, the default, disables the wait.
+    pub fn set_tx_inter_frame_idle(&self, idle: Duration) {
+        self.inner.lock(|inner| inner.tx_inter_frame_idle = idle);
+    }
+
+    /// Switch the transmitter off whenever TX runs empty, and back on with the next write.
+    ///
+    /// Saves a little power on idle, battery powered deployments. TX counts as empty once a
+    /// `flush()` completes, or when the UART raises an interrupt while it is idle. Disabling
+    /// switches the transmitter on again right away.
+    pub fn set_tx_power_down(&self, enable: bool) {
+        self.inner.lock(|inner| {
+            inner.tx_power_down = enable;
+
+            if enable {
+                inner.power_down_tx_if_idle();
+            } else {
+                inner.power_up_tx();
+            }
+        });
+    }
+
+    /// Send raw bytes, e.g. a binary log dump.
+    ///
+    /// The bytes bypass the line buffer and multidrop handling. A pending incomplete line is sent
+    /// first. See `set_write_yield_interval()` for how long the lock is held. Independent of that,
+    /// the lock is released early when an RX interrupt is pending, so that the RX FIFO does not
+    /// overrun during a long write.
+    pub fn write_bytes(&self, bytes: &[u8]) {
+        self.write_bytes_with(bytes, || ());
+    }
+
+    /// Send `packet` framed with SLIP, for carrying packet data, e.g. IP, over the line.
+    ///
+    /// The packet is encoded and sent under a single lock, so that no other output can end up
+    /// inside it. Like with `write_bytes()`, a pending incomplete line is sent first. Received
+    /// packets are reassembled with [`slip::Decoder`].
+    pub fn write_slip(&self, packet: &[u8]) {
+        self.inner.lock(|inner| {
+            inner.flush_line_buffer();
+            inner.toggle_tx_activity_led();
+
+            slip::encode(packet, |byte| {
+                if !inner.write_fault() {
+                    inner.write_to_fifo(u32::from(byte));
+                }
+            });
+        });
+    }
+
+    /// Send a break that lasts `bits` bit times, e.g. the break of at least 13 bits that starts a
+    /// LIN frame.
+    ///
+    /// The duration follows from the current baud rate and is timed with the system timer. Pending
+    /// output is sent first. The UART stays locked, with IRQs masked, until the break is over.
+    pub fn send_break_bits(&self, bits: u32) {
+        use time::interface::TimeManager;
+
+        self.inner
+            .lock(|inner| inner.send_break_with(bits, |x| time::time_manager().spin_for(x)));
+    }
+
+    /// Send a single character with a framing that differs from the steady-state one, e.g. for
+    /// bridging to a device that needs two stop bits.
+    ///
+    /// The line is switched and restored under the lock, with TX drained before each switch, so
+    /// that neither adjacent characters nor the character itself are sent with the wrong framing.
+    pub fn write_char_with_framing(&self, c: char, framing: UartFraming) {
+        self.inner
+            .lock(|inner| inner.with_framing(framing, |inner| inner.transmit_char(c)));
+    }
+
+    /// Send a short message in one piece, e.g. a protocol frame that must not be split.
+    ///
+    /// Waits until the TX FIFO is empty, then fills it with `bytes` under a single lock, so no
+    /// other output or flow control event can get in between. Messages longer than the FIFO are
+    /// rejected; use `write_all()` for those. The wait happens with IRQs masked, for at most a
+    /// FIFO's worth of characters. The inter-frame idle is waited for with IRQs unmasked.
+    pub fn write_atomic(&self, bytes: &[u8]) -> Result<(), &'static str> {
+        use time::interface::TimeManager;
+
+        if bytes.is_empty() {
+            return Ok(());
+        }
+
+        self.inner.lock(|inner| inner.write_atomic(bytes))?;
+        self.finish_frame_with(|| time::time_manager().uptime());
+
+        Ok(())
+    }
+
+    /// Send raw bytes like `write_bytes()`, but give up if the TX FIFO did not accept all of them
+    /// within `timeout`, e.g. because the line is held up by flow control.
+    ///
+    /// Flushing a pending incomplete line is not covered by the timeout, and neither is the
+    /// inter-frame idle.
+    pub fn write_all(&self, bytes: &[u8], timeout: Duration) -> Result<(), console::WriteError> {
+        use time::interface::TimeManager;
+
+        let deadline = time::time_manager().deadline_in(timeout);
+
+        self.write_all_until(bytes, deadline, || time::time_manager().uptime())
+    }
+
+    /// Switch the clock on, map the registers and run `init` on them.
+    ///
+    /// # Safety
+    ///
+    /// - Same as `DeviceDriver::init()`.
+    unsafe fn map_and_init(
+        &self,
+        init: impl FnOnce(&mut PL011UartInner) -> Result<(), &'static str>,
+    ) -> Result<(), driver::DriverError> {
+        use driver::interface::DeviceDriver;
+
+        // Accessing the registers of a clock-gated peripheral can hang the bus.
+        self.clocks.set_clock_enabled(ClockId::Uart, true)?;
+
+        let registers: Registers =
+            memory::mmu::map_mmio_registers(self.compatible(), &self.phys_mmio_descriptor)?;
+        let virt_addr = registers.start_addr();
+
+        self.inner.lock(|inner| {
+            inner.registers = registers;
+            init(inner)
+        })?;
+
+        // Release, so that whoever sees the address, e.g. the panic handler on another core, also
+        // sees the mapping and the register setup.
+        self.virt_mmio_start_addr
+            .store(virt_addr, Ordering::Release);
+        boot::record_console_ready();
+
+        Ok(())
+    }
+
+    /// Bring up the UART for polled operation only, with all of its interrupts masked.
+    ///
+    /// An alternative to `DeviceDriver::init()` for early boot, when the interrupt controller is
+    /// not ready yet. Call [`PL011Uart::enable_interrupts()`] once it is.
+    ///
+    /// # Safety
+    ///
+    /// - Same as `DeviceDriver::init()`.
+    pub unsafe fn init_polling(&self) -> Result<(), driver::DriverError> {
+        self.map_and_init(|inner| inner.init_polling(None))
+    }
+
+    /// Unmask the RX interrupts after `init_polling()`. Received bytes are kept.
+    pub fn enable_interrupts(&self) -> Result<(), &'static str> {
+        self.inner.lock(|inner| inner.enable_interrupts())
+    }
+
+    /// Lock the UART for console output.
+    ///
+    /// While the MMU is in transition, the registers are accessed through their physical address,
+    /// because the virtual one might not be valid. The check happens under the lock, so the
+    /// transition cannot start or end in the middle of a write.
+    fn lock_console<R>(&self, f: impl FnOnce(&mut PL011UartInner) -> R) -> R {
+        self.inner.lock(|inner| {
+            if !memory::mmu::in_transition() {
+                return f(inner);
+            }
+
+            let phys_registers =
+                unsafe { Registers::new(self.phys_mmio_descriptor.start_addr().into_usize()) };
+            let virt_registers = core::mem::replace(&mut inner.registers, phys_registers);
+            let ret = f(inner);
+            inner.registers = virt_registers;
+
+            ret
+        })
+    }
+
+    /// A writer for console output from within the driver, e.g. a debug print in its IRQ handler,
+    /// while the UART is locked already. `None` if such output is dropped.
+    ///
+    /// Locking again would hand out the inner state a second time, so the output goes to the
+    /// registers directly, like with `EarlyUart`. It is neither counted nor line-buffered, and may
+    /// interleave with the output that was interrupted.
+    fn reentrant_writer(&self) -> Option<EarlyUart> {
+        use driver::interface::DeviceDriver;
+
+        if self.drop_reentrant_writes.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        // Same as in `lock_console()`. Before init, the registers are not set up yet.
+        let addr = if memory::mmu::in_transition() {
+            self.phys_mmio_descriptor.start_addr().into_usize()
+        } else {
+            self.virt_mmio_start_addr()?
+        };
+
+        Some(unsafe { EarlyUart::new(addr) })
+    }
+
+    /// Retrieve a received byte, echoing it like a character, until `deadline`. Waits forever
+    /// without one.
+    fn read_byte_echoed_until(&self, deadline: Option<Duration>) -> Option<u8> {
+        use time::interface::TimeManager;
+
+        let deadline = match deadline {
+            Some(x) => x,
+            None => {
+                return self
+                    .inner
+                    .lock(|inner| inner.read_byte_echoed(BlockingMode::Blocking))
+            }
+        };
+
+        loop {
+            // Lock per attempt, so that the IRQ handler has a chance to run in between.
+            let byte = self
+                .inner
+                .lock(|inner| inner.read_byte_echoed(BlockingMode::NonBlocking));
+
+            if byte.is_some() {
+                return byte;
+            }
+
+            if time::time_manager().uptime() >= deadline {
+                return None;
+            }
+        }
+    }
+
+    /// Retrieve a character for [`console::InputCodec::Utf8`], waiting for its first byte for at
+    /// most `timeout`, or forever without one.
+    ///
+    /// The lock is not held while waiting for continuation bytes, so the decoder is taken out of
+    /// the inner state for the duration of the read.
+    fn read_utf8_char(&self, timeout: Option<Duration>) -> Option<char> {
+        use time::interface::TimeManager;
+
+        let deadline = timeout.map(|x| time::time_manager().deadline_in(x));
+        let mut decoder = self.inner.lock(|inner| inner.utf8_decoder);
+
+        let c = decoder.decode(|continuation| {
+            if !continuation {
+                return self.read_byte_echoed_until(deadline);
+            }
+
+            // A sender that stops in the middle of a sequence must not hang the read.
+            let deadline = time::time_manager().deadline_in(UTF8_CONTINUATION_TIMEOUT);
+            self.read_byte_echoed_until(Some(deadline))
+        });
+
+        self.inner.lock(|inner| {
+            inner.utf8_decoder = decoder;
+
+            // Carriage returns are translated as for single byte characters.
+            c.map(|c| {
+                if c.is_ascii() {
+                    inner.receive_char(c as u8)
+                } else {
+                    c
+                }
+            })
+        })
+    }
+
+    /// Run `f` with the echo switched off, restoring the echo mode afterwards.
+    fn with_echo_off<T>(&self, f: impl FnOnce() -> T) -> T {
+        let echo_mode = self.inner.lock(|inner| {
+            let mode = inner.echo_mode;
+            inner.echo_mode = console::EchoMode::Off;
+
+            mode
+        });
+
+        let ret = f();
+
+        self.inner.lock(|inner| inner.echo_mode = echo_mode);
+
+        ret
+    }
+
+    /// Like `write_bytes()`, but calls `between_chunks` whenever the lock was released.
+    fn write_bytes_with(&self, bytes: &[u8], mut between_chunks: impl FnMut()) {
+        // Not even worth the lock, which might have to wait for the IRQ handler.
+        if bytes.is_empty() {
+            return;
+        }
+
+        let interval = self.inner.lock(|inner| {
+            inner.flush_line_buffer();
+            inner.toggle_tx_activity_led();
+            inner.write_yield_interval
+        });
+        let chunk_size = interval.map_or(bytes.len(), NonZeroUsize::get).max(1);
+        let mut rx_yields = 0;
+        let mut pos = 0;
+
+        while pos < bytes.len() {
+            if pos > 0 {
+                between_chunks();
+            }
+
+            pos = self.inner.lock(|inner| {
+                let start = pos;
+                let end = bytes.len().min(start + chunk_size);
+
+                let mut pos = start;
+                while pos < end {
+                    // At least one byte per round, so that the write always makes progress.
+                    if pos > start && rx_yields < MAX_RX_YIELDS_PER_WRITE && inner.rx_irq_pending()
+                    {
+                        rx_yields += 1;
+                        break;
+                    }
+
+                    if !inner.write_fault() {
+                        inner.write_to_fifo(u32::from(bytes[pos]));
+                    }
+                    pos += 1;
+                }
+
+                pos
+            });
+        }
+    }
+
+    /// Like `write_all()`, but against an absolute deadline and clock.
+    fn write_all_until(
+        &self,
+        bytes: &[u8],
+        deadline: Duration,
+        mut now: impl FnMut() -> Duration,
+    ) -> Result<(), console::WriteError> {
+        if bytes.is_empty() {
+            return Ok(());
+        }
+
+        self.inner.lock(|inner| {
+            inner.flush_line_buffer();
+            inner.toggle_tx_activity_led();
+        });
+
+        let mut sent = 0;
+        while sent < bytes.len() {
+            if now() >= deadline {
+                return Err(console::WriteError::TimedOut(sent));
+            }
+
+            // Lock per attempt, so that the IRQ handler has a chance to run in between.
+            if self
+                .inner
+                .lock(|inner| inner.try_write_to_fifo(u32::from(bytes[sent])))
+            {
+                sent += 1;
+            }
+        }
+        self.finish_frame_with(&mut now);
+
+        Ok(())
+    }
+
+    /// Wait for the frame to leave the UART, then for the inter-frame idle, if there is one.
+    fn finish_frame_with(&self, mut now: impl FnMut() -> Duration) {
+        let idle = self.inner.lock(|inner| inner.tx_inter_frame_idle);
+        if idle == Duration::from_secs(0) {
+            return;
+        }
+
+        // Lock per poll, so that the IRQ handler has a chance to run in between. The time is taken
+        // after the check, so the idle cannot start before BUSY cleared.
+        let quiet_since = loop {
+            let pending = self.inner.lock(|inner| inner.output_pending());
+            let t = now();
+            if !pending {
+                break t;
+            }
+            cpu::nop();
+        };
+
+        let end = time::deadline_after(quiet_since, idle);
+        while now() < end {
+            cpu::nop();
+        }
+    }
+
+    /// Send an address byte on a multidrop bus.
+    ///
+    /// Fails if multidrop mode is not enabled.
+    pub fn write_address_byte(&self, address: u8) -> Result<(), &'static str> {
+        self.inner.lock(|inner| inner.write_address_byte(address))
+    }
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+use synchronization::interface::Mutex;
+
+impl driver::interface::DeviceDriver for PL011Uart {
+    fn compatible(&self) -> &'static str {
+        "BCM PL011 UART"
+    }
+
+    #[cfg(feature = "mmio_counters")]
+    fn mmio_accesses(&self) -> usize {
+        self.inner.lock(|inner| inner.registers.accesses())
+    }
+
+    unsafe fn init(&self) -> Result<(), driver::DriverError> {
+        self.map_and_init(|inner| inner.init(None))
+    }
+
+    /// The serial settings, in the same record as for `persist_config()`.
+    fn save_config(&self, buf: &mut [u8]) -> usize {
+        let record = self.inner.lock(|inner| inner.persisted_config());
+        let len = record.len().min(buf.len());
+        buf[..len].copy_from_slice(&record[..len]);
+
+        len
+    }
+
+    fn restore_config(&self, config: &[u8]) -> Result<(), driver::DriverError> {
+        self.inner.lock(|inner| inner.restore_config_from(config))?;
+
+        Ok(())
+    }
+
+    fn register_and_enable_irq_handler(&'static self) -> Result<(), &'static str> {
+        use bsp::exception::asynchronous::irq_manager;
+        use exception::asynchronous::{interface::IRQManager, IRQDescriptor, IRQPriority};
+
+        // Console input is not time critical, so let other devices, e.g. timers, go first.
+        let descriptor = IRQDescriptor {
+            name: "BCM PL011 UART",
+            handler: self,
+            priority: IRQPriority::Low,
+        };
+
+        irq_manager().register_handler(self.irq_number, descriptor)?;
+        irq_manager().enable(self.irq_number);
+
+        Ok(())
+    }
+
+    fn virt_mmio_start_addr(&self) -> Option<usize> {
+        // Pairs with the Release store in `init()`.
+        let addr = self.virt_mmio_start_addr.load(Ordering::Acquire);
+
+        if addr == 0 {
+            return None;
+        }
+
+        Some(addr)
+    }
+
+    unsafe fn post_mmio_remap(&self, new_mmio_start_addr: usize) -> Result<(), &'static str> {
+        if self.virt_mmio_start_addr().is_none() {
+            return Err("PL011 UART not initialized yet");
+        }
+
+        self.inner
+            .lock(|inner| inner.registers = Registers::new(new_mmio_start_addr));
+
+        // Same as in `init()`.
+        self.virt_mmio_start_addr
+            .store(new_mmio_start_addr, Ordering::Release);
+
+        Ok(())
+    }
+}
+
+impl console::interface::Write for PL011Uart {
+    /// Passthrough of `args` to the `core::fmt::Write` implementation, but guarded by a Mutex to
+    /// serialize access.
+    fn write_char_mode(&self, c: char, blocking: bool) -> Result<(), char> {
+        if self.inner.is_locked() {
+            if let Some(mut raw) = self.reentrant_writer() {
+                let _ = fmt::Write::write_char(&mut raw, c);
+            }
+            return Ok(());
+        }
+
+        let blocking_mode = if blocking {
+            BlockingMode::Blocking
+        } else {
+            BlockingMode::NonBlocking
+        };
+
+        self.lock_console(|inner| {
+            inner.toggle_tx_activity_led();
+            inner.write_char_mode(c, blocking_mode)
+        })
+    }
+
+    fn write_byte(&self, b: u8) {
+        if self.inner.is_locked() {
+            if let Some(mut raw) = self.reentrant_writer() {
+                raw.write_byte(b);
+            }
+            return;
+        }
+
+        self.lock_console(|inner| {
+            // Keep the order with a pending incomplete line.
+            inner.flush_line_buffer();
+            inner.toggle_tx_activity_led();
+            if !inner.write_fault() {
+                inner.transmit_byte(b);
+            }
+        });
+    }
+
+    fn write_fmt(&self, args: core::fmt::Arguments) -> fmt::Result {
+        // Nothing to format, e.g. `format_args!("")`.
+        if args.as_str() == Some("") {
+            return Ok(());
+        }
+
+        // E.g. a debug print from the IRQ handler.
+        if self.inner.is_locked() {
+            return match self.reentrant_writer() {
+                Some(mut raw) => fmt::Write::write_fmt(&mut raw, args),
+                None => Ok(()),
+            };
+        }
+
+        // Fully qualified syntax for the call to `core::fmt::Write::write:fmt()` to increase
+        // readability.
+        self.lock_console(|inner| {
+            inner.toggle_tx_activity_led();
+            if inner.write_fault() {
+                return Err(fmt::Error);
+            }
+
+            fmt::Write::write_fmt(inner, args)
+        })
+    }
+
+    fn flush(&self) {
+        // The locked write that is in progress flushes when it is done.
+        if self.inner.is_locked() {
+            return;
+        }
+
+        // Spin until the TX FIFO is empty and the last character has been shifted out.
+        self.lock_console(|inner| {
+            inner.flush_line_buffer();
+            inner.drain_tx();
+            inner.power_down_tx_if_idle();
+        });
+    }
+
+    fn flush_best_effort(&self, deadline: Duration) -> bool {
+        use time::interface::TimeManager;
+
+        self.lock_console(|inner| inner.flush_until(deadline, || time::time_manager().uptime()))
+    }
+
+    fn output_pending(&self) -> bool {
+        self.lock_console(|inner| inner.output_pending())
+    }
+}
+
+impl console::interface::Read for PL011Uart {
+    fn read_char(&self) -> char {
+        if self.inner.lock(|inner| inner.input_codec) == console::InputCodec::Utf8 {
+            return self.read_utf8_char(None).unwrap_or(RX_DISABLED_CHAR);
+        }
+
+        self.inner
+            .lock(|inner| inner.read_char_converting(BlockingMode::Blocking))
+            // A blocking read returns a character unless the receiver is disabled.
+            .unwrap_or_default()
+            .unwrap_or(RX_DISABLED_CHAR)
+    }
+
+    fn read_char_timeout(&self, timeout: Duration) -> Option<char> {
+        use time::interface::TimeManager;
+
+        if self.inner.lock(|inner| inner.input_codec) == console::InputCodec::Utf8 {
+            return self.read_utf8_char(Some(timeout));
+        }
+
+        let deadline = time::time_manager().deadline_in(timeout);
+
+        loop {
+            // Lock per attempt, so that the IRQ handler has a chance to run in between.
+            let c = self
+                .inner
+                .lock(|inner| inner.read_char_converting(BlockingMode::NonBlocking))
+                .unwrap_or_default();
+
+            if c.is_some() {
+                return c;
+            }
+
+            if time::time_manager().uptime() >= deadline {
+                return None;
+            }
+        }
+    }
+
+    fn read_byte(&self) -> u8 {
+        self.inner.lock(|inner| match inner.pop_rx_byte() {
+            Some(byte) => byte,
+            None => inner
+                .read_byte(BlockingMode::Blocking)
+                .unwrap_or(RX_DISABLED_BYTE),
+        })
+    }
+
+    fn read_byte_timeout(&self, timeout: Duration) -> Option<u8> {
+        xmodem::interface::Link::recv(self, timeout)
+    }
+
+    fn clear(&self) {
+        self.inner.lock(|inner| inner.discard_rx())
+    }
+}
+
+impl console::interface::Statistics for PL011Uart {
+    fn chars_written(&self) -> usize {
         self.inner.lock(|inner| inner.chars_written)
     }
 
-    fn chars_read(&self) -> usize {
-        self.inner.lock(|inner| inner.chars_read)
+    fn chars_read(&self) -> usize {
+        self.inner.lock(|inner| inner.chars_read)
+    }
+}
+
+impl console::interface::LineDiscipline for PL011Uart {
+    /// Defaults to [`console::EchoMode::Verbatim`].
+    fn set_echo_mode(&self, mode: console::EchoMode) -> console::EchoMode {
+        self.inner
+            .lock(|inner| core::mem::replace(&mut inner.echo_mode, mode))
+    }
+
+    fn set_input_translation(&self, enable: bool) -> bool {
+        self.inner
+            .lock(|inner| core::mem::replace(&mut inner.translate_cr, enable))
+    }
+
+    /// A byte that was held back by the UTF-8 decoder is dropped.
+    fn set_input_codec(&self, codec: console::InputCodec) -> console::InputCodec {
+        self.inner.lock(|inner| {
+            inner.utf8_decoder = console::Utf8Decoder::new();
+            core::mem::replace(&mut inner.input_codec, codec)
+        })
+    }
+}
+
+impl telemetry::interface::Link for PL011Uart {
+    fn write_atomic(&self, frame: &[u8]) -> Result<(), &'static str> {
+        PL011Uart::write_atomic(self, frame)
+    }
+}
+
+impl xmodem::interface::Link for PL011Uart {
+    fn recv(&self, timeout: Duration) -> Option<u8> {
+        use time::interface::TimeManager;
+
+        let deadline = time::time_manager().deadline_in(timeout);
+
+        loop {
+            // Lock per attempt, so that the IRQ handler has a chance to run in between.
+            let byte = self.inner.lock(|inner| match inner.pop_rx_byte() {
+                Some(byte) => Some(byte),
+                None => inner.read_byte(BlockingMode::NonBlocking),
+            });
+
+            if byte.is_some() {
+                return byte;
+            }
+
+            if time::time_manager().uptime() >= deadline {
+                return None;
+            }
+        }
+    }
+
+    fn send(&self, byte: u8) {
+        self.inner
+            .lock(|inner| inner.write_to_fifo(u32::from(byte)));
+    }
+}
+
+impl exception::asynchronous::interface::IRQHandler for PL011Uart {
+    fn handle(&self) -> Result<(), &'static str> {
+        self.inner.lock(|inner| inner.handle_pending_irqs());
+
+        // Not locked anymore, so the held back output can go out through this UART.
+        print::poll_interactive_gate();
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::{
+        cell::{Cell, RefCell},
+        sync::atomic::AtomicUsize,
+    };
+    use test_macros::kernel_test;
+
+    /// RAM-backed stand-in for the UART's MMIO register block.
+    ///
+    /// Either mapped like the real block, through `start_addr()`, or used directly as the
+    /// `UartRegisters` of an inner struct.
+    pub(super) struct MockRegisters([Cell<u32>; 0x90 / 4]);
+
+    impl MockRegisters {
+        pub(super) fn new() -> Self {
+            // `Cell<u32>` has the same layout as `u32`.
+            Self(unsafe { core::mem::transmute([0_u32; 0x90 / 4]) })
+        }
+
+        fn start_addr(&mut self) -> usize {
+            self.0.as_ptr() as usize
+        }
+
+        fn get(&self, offset: usize) -> u32 {
+            unsafe { core::ptr::read_volatile(self.0[offset / 4].as_ptr()) }
+        }
+
+        pub(super) fn set(&mut self, offset: usize, value: u32) {
+            self.store(offset, value)
+        }
+
+        /// `set()` through a shared reference.
+        fn store(&self, offset: usize, value: u32) {
+            unsafe { core::ptr::write_volatile(self.0[offset / 4].as_ptr(), value) }
+        }
+    }
+
+    impl UartRegisters for MockRegisters {
+        fn dr(&self) -> u32 {
+            self.get(0x00)
+        }
+
+        fn set_dr(&self, value: u32) {
+            self.store(0x00, value)
+        }
+
+        fn rsrecr(&self) -> u32 {
+            self.get(0x04)
+        }
+
+        fn set_rsrecr(&self, value: u32) {
+            self.store(0x04, value)
+        }
+
+        fn fr(&self) -> LocalRegisterCopy<u32, FR::Register> {
+            LocalRegisterCopy::new(self.get(0x18))
+        }
+
+        fn ibrd(&self) -> u32 {
+            self.get(0x24)
+        }
+
+        fn set_ibrd(&self, value: u32) {
+            self.store(0x24, value)
+        }
+
+        fn fbrd(&self) -> u32 {
+            self.get(0x28)
+        }
+
+        fn set_fbrd(&self, value: u32) {
+            self.store(0x28, value)
+        }
+
+        fn lcrh(&self) -> LocalRegisterCopy<u32, LCRH::Register> {
+            LocalRegisterCopy::new(self.get(0x2c))
+        }
+
+        fn set_lcrh(&self, value: u32) {
+            self.store(0x2c, value)
+        }
+
+        fn cr(&self) -> LocalRegisterCopy<u32, CR::Register> {
+            LocalRegisterCopy::new(self.get(0x30))
+        }
+
+        fn set_cr(&self, value: u32) {
+            self.store(0x30, value)
+        }
+
+        fn ifls(&self) -> LocalRegisterCopy<u32, IFLS::Register> {
+            LocalRegisterCopy::new(self.get(0x34))
+        }
+
+        fn set_ifls(&self, value: u32) {
+            self.store(0x34, value)
+        }
+
+        fn imsc(&self) -> LocalRegisterCopy<u32, IMSC::Register> {
+            LocalRegisterCopy::new(self.get(0x38))
+        }
+
+        fn set_imsc(&self, value: u32) {
+            self.store(0x38, value)
+        }
+
+        fn mis(&self) -> LocalRegisterCopy<u32, MIS::Register> {
+            LocalRegisterCopy::new(self.get(0x40))
+        }
+
+        fn set_icr(&self, value: u32) {
+            self.store(0x44, value)
+        }
+
+        fn dmacr(&self) -> LocalRegisterCopy<u32, DMACR::Register> {
+            LocalRegisterCopy::new(self.get(0x48))
+        }
+
+        fn set_itcr(&self, value: u32) {
+            self.store(0x80, value)
+        }
+
+        fn tdr(&self) -> u32 {
+            self.get(0x8c)
+        }
+
+        fn set_tdr(&self, value: u32) {
+            self.store(0x8c, value)
+        }
+    }
+
+    /// Clock control that claims every clock is running.
+    struct MockClocks;
+
+    impl ClockControl for MockClocks {
+        fn set_clock_enabled(
+            &self,
+            _clock: ClockId,
+            _enable: bool,
+        ) -> Result<(), driver::DriverError> {
+            Ok(())
+        }
+    }
+
+    /// A UART on the registers of `mock`, with the IRQ of the real one.
+    fn mock_uart(mock: &mut MockRegisters) -> PL011Uart {
+        use bsp::exception::asynchronous::irq_map;
+        use memory::mmu::{Address, MMIODescriptor};
+
+        unsafe {
+            PL011Uart::new(
+                MMIODescriptor::new(Address::new(mock.start_addr()), 0x90),
+                irq_map::PL011_UART,
+                &MockClocks,
+            )
+        }
+    }
+
+    /// Counts the level changes of an LED, and the calls that set its level.
+    struct MockLed {
+        level: AtomicBool,
+        toggles: AtomicUsize,
+        sets: AtomicUsize,
+    }
+
+    impl MockLed {
+        const fn new() -> Self {
+            Self {
+                level: AtomicBool::new(false),
+                toggles: AtomicUsize::new(0),
+                sets: AtomicUsize::new(0),
+            }
+        }
+    }
+
+    impl OutputPin for MockLed {
+        fn set_level(&self, high: bool) {
+            self.sets.fetch_add(1, Ordering::Relaxed);
+            if self.level.swap(high, Ordering::Relaxed) != high {
+                self.toggles.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Stands in for the TX pin, the jumper and the RX pin of a physical loopback.
+    ///
+    /// A connected wire reflects TX to RX, with the bits in `stuck_high` set. With a clock, every
+    /// `recv()` advances it, by `delay` if a byte came back, by the timeout otherwise.
+    struct MockWire<'a> {
+        connected: bool,
+        stuck_high: u8,
+        delay: Duration,
+        uptime: Option<&'a Cell<Duration>>,
+        on_wire: Cell<Option<u8>>,
+    }
+
+    impl<'a> MockWire<'a> {
+        fn new(connected: bool, stuck_high: u8) -> Self {
+            Self {
+                connected,
+                stuck_high,
+                delay: Duration::from_secs(0),
+                uptime: None,
+                on_wire: Cell::new(None),
+            }
+        }
+
+        fn with_clock(self, delay: Duration, uptime: &'a Cell<Duration>) -> Self {
+            Self {
+                delay,
+                uptime: Some(uptime),
+                ..self
+            }
+        }
+    }
+
+    impl xmodem::interface::Link for MockWire<'_> {
+        fn recv(&self, timeout: Duration) -> Option<u8> {
+            let byte = self.on_wire.take();
+
+            if let Some(uptime) = self.uptime {
+                let waited = if byte.is_some() { self.delay } else { timeout };
+                uptime.set(uptime.get() + waited);
+            }
+
+            byte
+        }
+
+        fn send(&self, byte: u8) {
+            if self.connected {
+                self.on_wire.set(Some(byte | self.stuck_high));
+            }
+        }
+    }
+
+    fn lcrh(mock: &MockRegisters) -> LocalRegisterCopy<u32, LCRH::Register> {
+        LocalRegisterCopy::new(mock.get(0x2c))
+    }
+
+    /// In multidrop mode, address bytes are sent with the stick parity bit set and data bytes
+    /// with it cleared.
+    #[kernel_test]
+    fn multidrop_marks_address_bytes() {
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+
+        assert!(inner.write_address_byte(0x42).is_err());
+
+        inner.set_multidrop(true);
+        inner.write_address_byte(0x42).unwrap();
+        assert_eq!(mock.get(0x00), 0x42);
+        assert!(lcrh(&mock).matches_all(LCRH::SPS::Enabled + LCRH::EPS::Odd));
+
+        inner.write_char('a');
+        assert_eq!(mock.get(0x00), 'a' as u32);
+        assert!(lcrh(&mock).matches_all(LCRH::SPS::Enabled + LCRH::EPS::Even));
+
+        inner.set_multidrop(false);
+        assert!(lcrh(&mock).matches_all(LCRH::PEN::Disabled + LCRH::SPS::Disabled));
+    }
+
+    /// Output counts as pending while the shift register is busy, even if the FIFO is empty.
+    #[kernel_test]
+    fn output_pending_considers_busy() {
+        let mut mock = MockRegisters::new();
+        let inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+
+        let txfe = FR::TXFE::SET.value;
+        let busy = FR::BUSY::SET.value;
+
+        mock.set(0x18, txfe | busy);
+        assert!(inner.output_pending());
+
+        mock.set(0x18, 0);
+        assert!(inner.output_pending());
+
+        mock.set(0x18, txfe);
+        assert!(!inner.output_pending());
+    }
+
+    /// Draining only finishes once the FIFO is empty and the transmitter is not busy anymore.
+    #[kernel_test]
+    fn drain_tx_waits_for_busy() {
+        let mut mock = MockRegisters::new();
+        let inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+
+        let txfe = FR::TXFE::SET.value;
+        let busy = FR::BUSY::SET.value;
+        mock.set(0x18, busy);
+
+        let mut polls = 0;
+        inner.drain_tx_with(|| {
+            polls += 1;
+            match polls {
+                2 => mock.set(0x18, txfe | busy),
+                4 => mock.set(0x18, txfe),
+                _ => (),
+            }
+        });
+
+        assert_eq!(polls, 4);
+    }
+
+    /// The FIFO-less fallback clears FEN and the IRQ handler then takes a single byte per
+    /// interrupt.
+    #[kernel_test]
+    fn fifo_less_fallback_reads_per_byte() {
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+        inner.echo_mode = console::EchoMode::Off;
+
+        mock.set(0x18, FR::TXFE::SET.value);
+        inner.set_fifos_enabled(false);
+        assert!(lcrh(&mock).matches_all(LCRH::FEN::FifosDisabled));
+        assert_eq!(
+            mock.get(0x30),
+            (CR::UARTEN::Enabled + CR::TXE::Enabled + CR::RXE::Enabled).value
+        );
+
+        // With RXFE clear, the mock never runs empty. A FIFO mode read would not terminate.
+        mock.set(0x00, 'x' as u32);
+        mock.set(0x40, MIS::RXMIS::SET.value);
+        inner.handle_pending_irqs();
+        assert_eq!(inner.pop_rx_byte(), Some(b'x'));
+        assert_eq!(inner.pop_rx_byte(), None);
+
+        inner.set_fifos_enabled(true);
+        assert!(lcrh(&mock).matches_all(LCRH::FEN::FifosEnabled));
+    }
+
+    /// A best-effort flush gives up at the deadline if TX does not drain.
+    #[kernel_test]
+    fn flush_until_gives_up_at_deadline() {
+        use core::cell::Cell;
+
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+        inner.set_line_buffered(true);
+        inner.write_char('a');
+
+        // Every look at the clock advances it by a millisecond.
+        let clock = Cell::new(Duration::from_secs(0));
+        let now = || {
+            clock.set(clock.get() + Duration::from_millis(1));
+            clock.get()
+        };
+
+        // TXFE never sets.
+        mock.set(0x18, 0);
+        assert!(!inner.flush_until(Duration::from_millis(10), now));
+        assert_eq!(mock.get(0x00), 0);
+        assert!(clock.get() >= Duration::from_millis(10));
+
+        mock.set(0x18, FR::TXFE::SET.value);
+        assert!(inner.flush_until(Duration::from_millis(20), now));
+        assert_eq!(mock.get(0x00), 'a' as u32);
+    }
+
+    /// Characters are sent UTF-8 encoded, one FIFO entry per byte.
+    #[kernel_test]
+    fn write_char_encodes_utf8() {
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+
+        // The mock only keeps the last write, which is the last byte of the encoding.
+        inner.write_char('€');
+        assert_eq!(inner.chars_written, 3);
+        assert_eq!(mock.get(0x00), 0xac);
+
+        inner.write_char('😀');
+        assert_eq!(inner.chars_written, 7);
+        assert_eq!(mock.get(0x00), 0x80);
+
+        // Echo sends received bytes back unchanged.
+        inner.echo(convert_char(0xe2));
+        assert_eq!(inner.chars_written, 8);
+        assert_eq!(mock.get(0x00), 0xe2);
+    }
+
+    /// In line-buffered mode, output is held back until the newline arrives.
+    #[kernel_test]
+    fn line_buffered_emits_on_newline() {
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+        inner.set_line_buffered(true);
+
+        fmt::Write::write_str(&mut inner, "ab").unwrap();
+        assert_eq!(inner.chars_written, 0);
+
+        fmt::Write::write_str(&mut inner, "c\n").unwrap();
+        assert_eq!(inner.chars_written, 4);
+        assert_eq!(mock.get(0x00), '\n' as u32);
+
+        // An overlong line is sent in parts.
+        for _ in 0..LINE_BUFFER_SIZE + 1 {
+            inner.write_char('x');
+        }
+        assert_eq!(inner.chars_written, 4 + LINE_BUFFER_SIZE);
+
+        // An incomplete line goes out when line buffering ends.
+        inner.set_line_buffered(false);
+        assert_eq!(inner.chars_written, 4 + LINE_BUFFER_SIZE + 1);
+    }
+
+    /// With timestamp prefixes enabled, each line starts with the uptime exactly once, even if it
+    /// is written in several parts.
+    #[kernel_test]
+    fn timestamp_prefix_once_per_line() {
+        let uptime = Duration::from_micros(12_345_678);
+        assert_eq!(format_timestamp(uptime).as_str(), "[   12.345678] ");
+
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+        inner.timestamp_prefix = true;
+        let prefix_len = format_timestamp(uptime).as_str().len();
+
+        let mut queries = 0;
+        let mut write = |inner: &mut PL011UartInner, s: &str| {
+            for c in s.chars() {
+                inner.write_char_with(c, || {
+                    queries += 1;
+                    uptime
+                });
+            }
+        };
+
+        write(&mut inner, "ab");
+        assert_eq!(inner.chars_written, prefix_len + 2);
+        write(&mut inner, "c\n");
+        assert_eq!(inner.chars_written, prefix_len + 4);
+        assert_eq!(mock.get(0x00), u32::from(b'\n'));
+
+        write(&mut inner, "d");
+        assert_eq!(inner.chars_written, 2 * prefix_len + 5);
+        assert_eq!(queries, 2);
+    }
+
+    /// Polled reads echo according to the echo mode. Echoes do not count as written characters.
+    #[kernel_test]
+    fn polled_read_echoes() {
+        let mut inner = PL011UartInner::with_registers(LoggingRegisters::new());
+
+        // Received bytes come from `rx_fifo`, echoed ones end up in DR of the backing mock.
+        inner
+            .registers
+            .rx_fifo
+            .borrow_mut()
+            .push_overwriting('a' as u32);
+        assert_eq!(
+            inner.read_char_converting(BlockingMode::Blocking),
+            Ok(Some('a'))
+        );
+        assert_eq!(inner.registers.regs.get(0x00), 'a' as u32);
+
+        inner.echo_mode = console::EchoMode::Caret;
+        inner.registers.rx_fifo.borrow_mut().push_overwriting(0x03);
+        assert_eq!(
+            inner.read_char_converting(BlockingMode::Blocking),
+            Ok(Some('\x03'))
+        );
+        assert_eq!(inner.registers.regs.get(0x00), 'C' as u32);
+
+        inner.echo_mode = console::EchoMode::Off;
+        inner.registers.regs.store(0x00, 0);
+        inner
+            .registers
+            .rx_fifo
+            .borrow_mut()
+            .push_overwriting('b' as u32);
+        assert_eq!(
+            inner.read_char_converting(BlockingMode::Blocking),
+            Ok(Some('b'))
+        );
+        assert_eq!(inner.registers.regs.get(0x00), 0);
+        assert_eq!(inner.chars_written, 0);
+    }
+
+    /// Raw reads keep the error flags that character reads drop.
+    #[kernel_test]
+    fn read_raw_keeps_error_flags() {
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+        inner.echo_mode = console::EchoMode::Off;
+
+        // Parity error on an 'A'.
+        mock.set(0x00, 0x200 | 0x41);
+        assert_eq!(inner.read_raw(BlockingMode::NonBlocking), Some(0x241));
+        assert_eq!(
+            inner.read_char_converting(BlockingMode::NonBlocking),
+            Ok(Some('A'))
+        );
+
+        mock.set(0x18, FR::RXFE::SET.value);
+        assert_eq!(inner.read_raw(BlockingMode::NonBlocking), None);
+
+        // A framing error on a 'B' that the IRQ handler moved to the software buffer.
+        let mut buffered = MockRegisters::new();
+        let uart = mock_uart(&mut buffered);
+        buffered.set(0x00, u32::from(DR_FE) | 0x42);
+        uart.inner.lock(|inner| {
+            inner.fifos_enabled = false;
+            inner.echo_mode = console::EchoMode::Off;
+            inner.buffer_rx_fifo();
+        });
+
+        buffered.set(0x18, FR::RXFE::SET.value);
+        assert_eq!(uart.read_raw(), Some(DR_FE | 0x42));
+        assert_eq!(uart.read_raw(), None);
+    }
+
+    /// RX interrupts are counted together with the bytes they drained.
+    #[kernel_test]
+    fn rx_health_tracks_irqs_and_reads() {
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+        inner.fifos_enabled = false;
+        inner.echo_mode = console::EchoMode::Off;
+
+        mock.set(0x40, MIS::RXMIS::SET.value);
+        inner.handle_pending_irqs();
+        inner.handle_pending_irqs();
+        assert!(inner.read_byte(BlockingMode::NonBlocking).is_some());
+
+        mock.set(0x18, FR::RXFE::SET.value);
+        inner.handle_pending_irqs();
+
+        let health = inner.rx_health();
+        assert_eq!(
+            health,
+            RxHealth {
+                rx_irqs: 3,
+                empty_rx_irqs: 1,
+                chars_read: 3,
+                chars_read_in_irq: 2,
+            }
+        );
+        assert_eq!(health.chars_polled(), 1);
+    }
+
+    /// An overrun is counted and cleared, and the byte that carried the flag is kept.
+    #[kernel_test]
+    fn overrun_is_cleared_and_counted() {
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+        inner.echo_mode = console::EchoMode::Off;
+        mock.set(0x04, 0xf);
+
+        mock.set(0x00, u32::from(DR_OE) | 0x41);
+        assert_eq!(
+            inner.read_raw(BlockingMode::NonBlocking),
+            Some(DR_OE | 0x41)
+        );
+        assert_eq!(mock.get(0x04), 0);
+        assert_eq!(inner.overruns, 1);
+
+        mock.set(0x00, 0x42);
+        assert_eq!(
+            inner.read_char_converting(BlockingMode::NonBlocking),
+            Ok(Some('B'))
+        );
+        assert_eq!(inner.overruns, 1);
+    }
+
+    /// The keep-alive byte goes out after every idle period, and writes restart the period.
+    #[kernel_test]
+    fn keep_alive_sent_when_idle() {
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+        let ms = Duration::from_millis;
+        mock.set(0x18, FR::TXFE::SET.value);
+
+        inner.keep_alive = Some(KeepAliveState {
+            config: KeepAlive {
+                byte: 0xff,
+                idle_time: ms(10),
+            },
+            chars_written: 0,
+            idle_since: None,
+        });
+
+        assert!(!inner.poll_keep_alive_at(ms(0)));
+        assert!(!inner.poll_keep_alive_at(ms(9)));
+        assert!(inner.poll_keep_alive_at(ms(10)));
+        assert_eq!(mock.get(0x00), 0xff);
+        assert!(!inner.poll_keep_alive_at(ms(19)));
+        assert!(inner.poll_keep_alive_at(ms(20)));
+        assert_eq!(inner.chars_written, 0);
+
+        // Data is flowing.
+        inner.write_to_fifo(u32::from(b'a'));
+        assert!(!inner.poll_keep_alive_at(ms(30)));
+        inner.write_to_fifo(u32::from(b'b'));
+        assert!(!inner.poll_keep_alive_at(ms(39)));
+        assert!(!inner.poll_keep_alive_at(ms(48)));
+        assert_eq!(mock.get(0x00), u32::from(b'b'));
+
+        // Nothing is sent while the FIFO is not drained yet.
+        mock.set(0x18, 0);
+        assert!(!inner.poll_keep_alive_at(ms(60)));
+        mock.set(0x18, FR::TXFE::SET.value);
+        assert!(inner.poll_keep_alive_at(ms(61)));
+        assert_eq!(mock.get(0x00), 0xff);
+    }
+
+    /// Injected input is returned by subsequent reads, excess bytes are dropped.
+    #[kernel_test]
+    fn injected_rx_is_read_back() {
+        let mut mock = MockRegisters::new();
+        mock.set(0x18, FR::RXFE::SET.value);
+        let uart = mock_uart(&mut mock);
+
+        uart.inject_rx(b"ls -l\r");
+        let mut buf = [0; 16];
+        let len = console::read_line(&uart, &mut buf, None).len();
+        assert_eq!(&buf[..len], b"ls -l");
+
+        // Bytes that do not fit anymore are dropped.
+        for _ in 0..RX_BUFFER_SIZE + 1 {
+            uart.inject_rx(b"x");
+        }
+        for _ in 0..RX_BUFFER_SIZE {
+            assert_eq!(uart.read_raw(), Some(u16::from(b'x')));
+        }
+        assert_eq!(uart.read_raw(), None);
+    }
+
+    /// Peeked bytes, including ones still in the RX FIFO, are returned unchanged by the reads that
+    /// follow.
+    #[kernel_test]
+    fn peek_rx_is_consistent_with_read() {
+        let mut mock = MockRegisters::new();
+        mock.set(0x00, u32::from(b'c'));
+        let uart = mock_uart(&mut mock);
+        uart.inner.lock(|inner| inner.fifos_enabled = false);
+
+        uart.inject_rx(b"ab");
+        let mut out = [0; 8];
+        assert_eq!(uart.peek_rx(&mut out), 3);
+        assert_eq!(&out[..3], b"abc");
+
+        mock.set(0x18, FR::RXFE::SET.value);
+        let mut out = [0; 2];
+        assert_eq!(uart.peek_rx(&mut out), 2);
+        assert_eq!(&out, b"ab");
+
+        for byte in b"abc".iter() {
+            assert_eq!(uart.read_raw(), Some(u16::from(*byte)));
+        }
+        assert_eq!(uart.read_raw(), None);
+        assert_eq!(uart.peek_rx(&mut out), 0);
+    }
+
+    /// Compaction returns the buffered bytes in order, even when they wrap around the end of the
+    /// software buffer.
+    #[kernel_test]
+    fn compact_rx_linearizes_wrapped_buffer() {
+        let mut mock = MockRegisters::new();
+        mock.set(0x18, FR::RXFE::SET.value);
+        let uart = mock_uart(&mut mock);
+
+        let mut bytes = [0; RX_BUFFER_SIZE];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+
+        uart.inject_rx(&bytes[..200]);
+        for _ in 0..200 {
+            assert!(uart.read_raw().is_some());
+        }
+
+        // Starts at index 200 and wraps around after 56 bytes.
+        uart.inject_rx(&bytes[..100]);
+        assert!(uart.compact_rx(|rx| rx == &bytes[..100]));
+
+        assert_eq!(uart.read_raw(), Some(0));
+        assert!(uart.compact_rx(|rx| rx == &bytes[1..100]));
+    }
+
+    /// The TX interrupt calls the hook and masks itself, so that an idle TX FIFO does not
+    /// retrigger.
+    #[kernel_test]
+    fn tx_irq_calls_ready_hook_once() {
+        use exception::asynchronous::interface::IRQHandler;
+
+        static HOOK_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        fn on_tx_ready() {
+            HOOK_CALLS.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let mut mock = MockRegisters::new();
+        let uart = mock_uart(&mut mock);
+        uart.set_tx_ready_hook(Some(on_tx_ready));
+
+        uart.arm_tx_ready();
+        assert_eq!(mock.get(0x38), IMSC::TXIM::Enabled.value);
+        assert_eq!(HOOK_CALLS.load(Ordering::Relaxed), 0);
+
+        // The FIFO drained below the watermark.
+        mock.set(0x40, MIS::TXMIS::SET.value);
+        uart.handle().unwrap();
+        assert_eq!(HOOK_CALLS.load(Ordering::Relaxed), 1);
+        assert_eq!(mock.get(0x38), 0);
+
+        // Arming with an empty FIFO does not wait for an interrupt that will never come.
+        mock.set(0x18, FR::TXFE::SET.value);
+        uart.arm_tx_ready();
+        assert_eq!(HOOK_CALLS.load(Ordering::Relaxed), 2);
+        assert_eq!(mock.get(0x38), 0);
+    }
+
+    /// A CTS change interrupt is cleared and hands the hook the level that CTS changed to.
+    #[kernel_test]
+    fn cts_irq_calls_hook_with_new_level() {
+        use exception::asynchronous::interface::IRQHandler;
+
+        // The number of calls, and the last level in the lowest bit.
+        static HOOK_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        fn on_cts_change(ready: bool) {
+            let calls = HOOK_CALLS.load(Ordering::Relaxed) >> 1;
+            HOOK_CALLS.store(((calls + 1) << 1) | usize::from(ready), Ordering::Relaxed);
+        }
+
+        let mut mock = MockRegisters::new();
+        let uart = mock_uart(&mut mock);
+        mock.set(0x38, IMSC::RXIM::Enabled.value);
+        uart.set_cts_change_hook(Some(on_cts_change));
+        assert_eq!(
+            mock.get(0x38),
+            (IMSC::RXIM::Enabled + IMSC::CTSMIM::Enabled).value
+        );
+
+        // The remote asserts CTS.
+        mock.set(0x18, FR::TXFE::SET.value | FR::CTS::SET.value);
+        mock.set(0x40, MIS::CTSMMIS::SET.value);
+        uart.handle().unwrap();
+        assert_eq!(HOOK_CALLS.load(Ordering::Relaxed), 0b11);
+        assert_eq!(mock.get(0x44), ICR::CTSMIC::SET.value);
+
+        // And deasserts it again.
+        mock.set(0x18, FR::TXFE::SET.value);
+        uart.handle().unwrap();
+        assert_eq!(HOOK_CALLS.load(Ordering::Relaxed), 0b100);
+
+        uart.set_cts_change_hook(None);
+        assert_eq!(mock.get(0x38), IMSC::RXIM::Enabled.value);
+    }
+
+    /// The RX callback sees every byte in order, and consumed ones are not buffered.
+    #[kernel_test]
+    fn rx_callback_sees_bytes_in_order() {
+        use console::interface::LineDiscipline;
+        use core::sync::atomic::AtomicU32;
+
+        static SEEN: AtomicU32 = AtomicU32::new(0);
+
+        fn on_rx_byte(byte: u8) -> bool {
+            let seen = SEEN.load(Ordering::Relaxed);
+            SEEN.store(seen << 8 | u32::from(byte), Ordering::Relaxed);
+
+            byte == b'b'
+        }
+
+        let mut mock = MockRegisters::new();
+        mock.set(0x18, FR::RXFE::SET.value);
+        let uart = mock_uart(&mut mock);
+        uart.set_echo_mode(console::EchoMode::Off);
+
+        uart.set_rx_callback(Some(&on_rx_byte));
+        uart.inject_rx(b"abc");
+        assert_eq!(SEEN.load(Ordering::Relaxed), 0x61_62_63);
+
+        assert_eq!(uart.read_raw(), Some(u16::from(b'a')));
+        assert_eq!(uart.read_raw(), Some(u16::from(b'c')));
+        assert_eq!(uart.read_raw(), None);
+
+        uart.set_rx_callback(None);
+        uart.inject_rx(b"b");
+        assert_eq!(uart.read_raw(), Some(u16::from(b'b')));
+    }
+
+    /// The line callback gets each completed line once it is polled, and long lines in pieces.
+    #[kernel_test]
+    fn line_callback_gets_completed_lines() {
+        use console::{interface::LineDiscipline, FmtBuf};
+
+        static LINES: IRQSafeNullLock<FmtBuf<96>> = IRQSafeNullLock::new(FmtBuf::new());
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        fn on_line(line: &[u8], continued: bool) {
+            CALLS.fetch_add(1, Ordering::Relaxed);
+            LINES.lock(|lines| {
+                let text = core::str::from_utf8(line).unwrap();
+                let end = if continued { "+|" } else { "|" };
+                fmt::Write::write_fmt(lines, format_args!("{}{}", text, end)).unwrap();
+            });
+        }
+
+        let mut mock = MockRegisters::new();
+        mock.set(0x18, FR::RXFE::SET.value);
+        let uart = mock_uart(&mut mock);
+        uart.set_echo_mode(console::EchoMode::Off);
+        uart.set_line_callback(Some(&on_line));
+
+        uart.inject_rx(b"ab\ncd");
+        uart.poll_lines();
+        uart.inject_rx(b"e\n");
+        uart.poll_lines();
+        assert_eq!(CALLS.load(Ordering::Relaxed), 2);
+        LINES.lock(|lines| assert_eq!(lines.as_str(), "ab|cde|"));
+        assert_eq!(uart.read_raw(), None);
+
+        uart.inject_rx(&[b'x'; 70]);
+        uart.inject_rx(b"\n");
+        uart.poll_lines();
+        assert_eq!(CALLS.load(Ordering::Relaxed), 4);
+        LINES.lock(|lines| {
+            let text = lines.as_str();
+            assert!(text[7..71].bytes().all(|x| x == b'x'));
+            assert_eq!(&text[71..], "+|xxxxxx|");
+        });
+    }
+
+    /// With input translation, carriage returns end lines, too, and a `\r\n` pair ends only one,
+    /// even if it is split across polls.
+    #[kernel_test]
+    fn line_callback_ends_lines_at_carriage_returns() {
+        use console::{interface::LineDiscipline, FmtBuf};
+
+        static LINES: IRQSafeNullLock<FmtBuf<32>> = IRQSafeNullLock::new(FmtBuf::new());
+
+        fn on_line(line: &[u8], _continued: bool) {
+            LINES.lock(|lines| {
+                let text = core::str::from_utf8(line).unwrap();
+                fmt::Write::write_fmt(lines, format_args!("{}|", text)).unwrap();
+            });
+        }
+
+        let mut mock = MockRegisters::new();
+        mock.set(0x18, FR::RXFE::SET.value);
+        let uart = mock_uart(&mut mock);
+        uart.set_echo_mode(console::EchoMode::Off);
+        uart.set_line_callback(Some(&on_line));
+
+        uart.inject_rx(b"ab\rcd\r\nef\r");
+        uart.poll_lines();
+        uart.inject_rx(b"\ngh\n");
+        uart.poll_lines();
+        LINES.lock(|lines| assert_eq!(lines.as_str(), "ab|cd|ef|gh|"));
+        assert_eq!(uart.read_raw(), None);
+    }
+
+    /// Input translation can be switched through the console interface, and switching reports
+    /// the previous setting for restoring it.
+    #[kernel_test]
+    fn input_translation_is_switchable() {
+        use console::interface::{LineDiscipline, Read};
+
+        let mut mock = MockRegisters::new();
+        mock.set(0x00, u32::from(b'\r'));
+        let uart = mock_uart(&mut mock);
+        uart.inner.lock(|inner| inner.fifos_enabled = false);
+        uart.set_echo_mode(console::EchoMode::Off);
+        assert_eq!(uart.read_char(), '\n');
+
+        let line_discipline: &dyn LineDiscipline = &uart;
+        assert!(line_discipline.set_input_translation(false));
+        assert_eq!(uart.read_char(), '\r');
+
+        assert!(!line_discipline.set_input_translation(true));
+        assert_eq!(uart.read_char(), '\n');
+    }
+
+    /// After a replacement, pending and later bytes go to the new callback only.
+    #[kernel_test]
+    fn replaced_rx_callback_sees_no_more_bytes() {
+        use console::interface::LineDiscipline;
+
+        static OLD_CALLS: AtomicUsize = AtomicUsize::new(0);
+        static NEW_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        fn old_callback(_byte: u8) -> bool {
+            OLD_CALLS.fetch_add(1, Ordering::Relaxed);
+            true
+        }
+        fn new_callback(_byte: u8) -> bool {
+            NEW_CALLS.fetch_add(1, Ordering::Relaxed);
+            true
+        }
+
+        let mut mock = MockRegisters::new();
+        let uart = mock_uart(&mut mock);
+        uart.inner.lock(|inner| inner.fifos_enabled = false);
+        uart.set_echo_mode(console::EchoMode::Off);
+        uart.set_rx_callback(Some(&old_callback));
+
+        // A byte is waiting in the holding register.
+        mock.set(0x00, u32::from(b'x'));
+        assert!(uart.replace_rx_callback(Some(&new_callback)).is_some());
+        assert_eq!(NEW_CALLS.load(Ordering::Relaxed), 1);
+
+        mock.set(0x18, FR::RXFE::SET.value);
+        uart.inject_rx(b"yz");
+        assert_eq!(NEW_CALLS.load(Ordering::Relaxed), 3);
+        assert_eq!(OLD_CALLS.load(Ordering::Relaxed), 0);
+    }
+
+    /// A framing change applies to what is sent in between, and the steady-state framing is
+    /// restored afterwards.
+    #[kernel_test]
+    fn framing_is_changed_and_restored() {
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+        inner.modify_lcrh(LCRH::WLEN::EightBit);
+
+        let two_stop_bits = UartFraming {
+            stop_bits: StopBits::Two,
+        };
+        let lcrh_during = inner.with_framing(two_stop_bits, |inner| {
+            inner.transmit_char('z');
+            lcrh(&mock)
+        });
+
+        assert!(lcrh_during.matches_all(LCRH::STP2::Two + LCRH::WLEN::EightBit));
+        assert!(lcrh(&mock).matches_all(LCRH::STP2::One + LCRH::WLEN::EightBit));
+        assert_eq!(mock.get(0x00), u32::from(b'z'));
+    }
+
+    /// While RX is paused, interrupts leave the received bytes in the FIFO. Resuming moves them
+    /// behind the bytes buffered before.
+    #[kernel_test]
+    fn paused_rx_stays_in_fifo_until_resumed() {
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+        let rx_imsc = (IMSC::RXIM::Enabled + IMSC::RTIM::Enabled).value;
+
+        mock.set(0x18, FR::RXFE::SET.value);
+        inner.enable_interrupts().unwrap();
+        inner.fifos_enabled = false;
+        inner.buffer_rx_byte_at(b'x', Duration::from_secs(0));
+
+        inner.pause_rx();
+        assert_eq!(mock.get(0x38) & rx_imsc, 0);
+
+        // A byte arrives and an interrupt was pending already.
+        mock.set(0x18, 0);
+        mock.set(0x00, u32::from(b'a'));
+        mock.set(0x40, MIS::RXMIS::SET.value);
+        inner.handle_pending_irqs();
+        assert_eq!(inner.rx_buffer.len(), 1);
+        assert_eq!(inner.chars_read, 0);
+
+        inner.resume_rx();
+        assert_eq!(mock.get(0x38), rx_imsc);
+        assert_eq!(inner.pop_rx_byte(), Some(b'x'));
+        assert_eq!(inner.pop_rx_byte(), Some(b'a'));
+    }
+
+    /// Injected write faults reach the caller, and the same seed drops the same bytes.
+    #[kernel_test]
+    fn write_faults_are_reported_and_reproducible() {
+        use console::interface::{Statistics, Write};
+
+        let mut mock = MockRegisters::new();
+        let uart = mock_uart(&mut mock);
+
+        uart.set_write_faults(Some(WriteFaults {
+            seed: 1,
+            percent: 100,
+        }));
+        assert_eq!(uart.write_char_mode('a', true), Err('a'));
+        assert!(uart.write_fmt(format_args!("b")).is_err());
+
+        // Upper layers see the failure, too.
+        let shell: crate::shell::Shell<1> = crate::shell::Shell::new();
+        assert_eq!(shell.dispatch("unknown", &uart), Err("Console error"));
+        assert_eq!(uart.chars_written(), 0);
+
+        let faults = Some(WriteFaults {
+            seed: 42,
+            percent: 50,
+        });
+        let mut written = [0; 2];
+        for x in written.iter_mut() {
+            let before = uart.chars_written();
+            uart.set_write_faults(faults);
+            uart.write_bytes(&[b'x'; 64]);
+            *x = uart.chars_written() - before;
+        }
+        assert!(written[0] > 0 && written[0] < 64);
+        assert_eq!(written[0], written[1]);
+
+        uart.set_write_faults(None);
+        assert_eq!(uart.write_char_mode('c', true), Ok(()));
+    }
+
+    /// Timed reads return each byte with the time it was received at, in order.
+    #[kernel_test]
+    fn read_timed_pairs_bytes_with_timestamps() {
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+        mock.set(0x18, FR::RXFE::SET.value);
+
+        inner.buffer_rx_byte_at(b'a', Duration::from_millis(3));
+        inner.buffer_rx_byte_at(b'b', Duration::from_millis(7));
+
+        let now = || Duration::from_millis(100);
+        assert_eq!(
+            inner.read_timed(now),
+            Some((b'a', Duration::from_millis(3)))
+        );
+        assert_eq!(
+            inner.read_timed(now),
+            Some((b'b', Duration::from_millis(7)))
+        );
+        assert_eq!(inner.read_timed(now), None);
+    }
+
+    /// A recorded session is replayed with the same bytes at the same relative times. Bytes that
+    /// could not be fed on time are counted as late.
+    #[kernel_test]
+    fn recorded_rx_replays_same_bytes() {
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+        mock.set(0x18, FR::RXFE::SET.value);
+        inner.echo_mode = console::EchoMode::Off;
+
+        let ms = Duration::from_millis;
+        let session = [(b'A', ms(10)), (b'T', ms(11)), (b'\r', ms(15))];
+        let mut recording = RxRecording::new();
+        let mut received = session.iter().copied();
+        let mut clock = ms(10);
+
+        record_rx_from(
+            &mut recording,
+            || received.next(),
+            || {
+                clock += ms(1);
+                clock
+            },
+            ms(5),
+        );
+        assert_eq!(recording.len(), 3);
+        let mut recorded = recording.iter();
+        assert_eq!(recorded.next(), Some((b'A', ms(0))));
+        assert_eq!(recorded.next(), Some((b'T', ms(1))));
+        assert_eq!(recorded.next(), Some((b'\r', ms(5))));
+
+        // The clock stalls at the start, then jumps 3 ms past the last byte's time.
+        let times = [ms(100), ms(100), ms(100), ms(101), ms(108)];
+        let mut clock = times.iter().copied();
+        let timing = replay_rx_to(
+            &recording,
+            || clock.next().unwrap_or(ms(200)),
+            |byte, t| inner.buffer_rx_byte_at(byte, t),
+        );
+        assert_eq!(
+            timing,
+            RxReplayTiming {
+                late: 1,
+                max_lag: ms(3)
+            }
+        );
+
+        let now = || ms(300);
+        assert_eq!(inner.read_timed(now), Some((b'A', ms(100))));
+        assert_eq!(inner.read_timed(now), Some((b'T', ms(101))));
+        assert_eq!(inner.read_timed(now), Some((b'\r', ms(108))));
+        assert_eq!(inner.read_timed(now), None);
+    }
+
+    /// A break is held for the requested number of bit times at the current baud rate, and the
+    /// rest of the line settings survive it.
+    #[kernel_test]
+    fn break_lasts_requested_bits() {
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+        inner.modify_lcrh(LCRH::WLEN::EightBit);
+
+        let mut spun = None;
+        inner.baud_rate = 19_200;
+        inner.send_break_with(13, |x| spun = Some((x, lcrh(&mock))));
+
+        // 13 / 19200 s is 677083.3 ns.
+        let (duration, lcrh_during) = spun.unwrap();
+        assert_eq!(duration, Duration::from_nanos(677_084));
+        assert!(lcrh_during.matches_all(LCRH::BRK::SET + LCRH::WLEN::EightBit));
+        assert!(lcrh(&mock).matches_all(LCRH::BRK::CLEAR + LCRH::WLEN::EightBit));
+
+        inner.baud_rate = 115_200;
+        inner.send_break_with(13, |x| spun = Some((x, lcrh(&mock))));
+        assert_eq!(spun.unwrap().0, Duration::from_nanos(112_848));
+
+        spun = None;
+        inner.send_break_with(0, |x| spun = Some((x, lcrh(&mock))));
+        assert!(spun.is_none());
+    }
+
+    /// Atomic writes accept up to a FIFO's worth of bytes, and reject longer messages untouched.
+    #[kernel_test]
+    fn write_atomic_fits_into_fifo() {
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+        mock.set(0x18, FR::TXFE::SET.value);
+
+        assert!(inner.write_atomic(&[b'x'; TX_FIFO_DEPTH + 1]).is_err());
+        assert_eq!(inner.chars_written, 0);
+
+        assert_eq!(inner.write_atomic(b"hello"), Ok(()));
+        assert_eq!(inner.chars_written, 5);
+        assert_eq!(mock.get(0x00), u32::from(b'o'));
+
+        inner.fifos_enabled = false;
+        assert!(inner.write_atomic(b"hi").is_err());
+        assert_eq!(inner.write_atomic(b"!"), Ok(()));
+    }
+
+    /// With an inter-frame idle, a write returns only after BUSY cleared and the idle passed.
+    #[kernel_test]
+    fn write_all_waits_for_busy_and_inter_frame_idle() {
+        use core::cell::Cell;
+
+        let mut mock = MockRegisters::new();
+        let uart = mock_uart(&mut mock);
+        let deadline = Duration::from_millis(100);
+        let ms = Cell::new(0);
+
+        // Without an idle, the write returns as soon as the FIFO took the bytes.
+        mock.set(0x18, FR::BUSY::SET.value);
+        let now = || {
+            ms.set(ms.get() + 1);
+            Duration::from_millis(ms.get())
+        };
+        assert_eq!(uart.write_all_until(b"ab", deadline, now), Ok(()));
+        assert_eq!(ms.get(), 2);
+
+        // The last byte leaves the UART at 3 ms. The idle is measured from the check after.
+        uart.set_tx_inter_frame_idle(Duration::from_millis(5));
+        ms.set(0);
+        let now = || {
+            ms.set(ms.get() + 1);
+            if ms.get() == 3 {
+                mock.set(0x18, FR::TXFE::SET.value);
+            }
+            Duration::from_millis(ms.get())
+        };
+        assert_eq!(uart.write_all_until(b"ab", deadline, now), Ok(()));
+        assert_eq!(ms.get(), 4 + 5);
+    }
+
+    /// A write call toggles the activity LED once, no matter how many bytes it sends.
+    #[kernel_test]
+    fn write_toggles_tx_activity_led() {
+        use console::interface::Write;
+
+        static LED: MockLed = MockLed::new();
+
+        let mut mock = MockRegisters::new();
+        let uart = mock_uart(&mut mock);
+
+        uart.write_bytes(b"no led");
+        uart.set_tx_activity_led(Some(&LED));
+        assert_eq!(LED.toggles.load(Ordering::Relaxed), 0);
+
+        uart.write_bytes(&[b'x'; 64]);
+        assert_eq!(LED.toggles.load(Ordering::Relaxed), 1);
+        assert!(LED.level.load(Ordering::Relaxed));
+
+        uart.write_fmt(format_args!("hello\n")).unwrap();
+        assert_eq!(LED.toggles.load(Ordering::Relaxed), 2);
+        assert!(!LED.level.load(Ordering::Relaxed));
+
+        // Removing the LED leaves it off.
+        uart.write_byte(b'y');
+        uart.set_tx_activity_led(None);
+        uart.write_byte(b'z');
+        assert!(!LED.level.load(Ordering::Relaxed));
+        assert_eq!(LED.toggles.load(Ordering::Relaxed), 4);
+    }
+
+    /// Empty writes return before taking the lock, so they neither touch the registers nor the
+    /// activity LED. An empty line still sends its newline.
+    #[kernel_test]
+    fn empty_writes_are_no_ops() {
+        use console::interface::{Statistics, Write};
+
+        static LED: MockLed = MockLed::new();
+
+        let mut mock = MockRegisters::new();
+        mock.set(0x18, FR::TXFE::SET.value | FR::RXFE::SET.value);
+        mock.set(0x00, 0xaa);
+        let uart = mock_uart(&mut mock);
+        uart.set_tx_activity_led(Some(&LED));
+        let sets = LED.sets.load(Ordering::Relaxed);
+
+        uart.write_bytes(&[]);
+        assert_eq!(uart.write_all(&[], Duration::from_secs(0)), Ok(()));
+        assert_eq!(uart.write_atomic(&[]), Ok(()));
+        assert_eq!(uart.write_fmt(format_args!("")), Ok(()));
+        assert_eq!(LED.sets.load(Ordering::Relaxed), sets);
+        assert_eq!(mock.get(0x00), 0xaa);
+        assert_eq!(uart.chars_written(), 0);
+
+        uart.write_line("");
+        assert_eq!(mock.get(0x00), u32::from(b'\n'));
+        assert_eq!(uart.chars_written(), 1);
+    }
+
+    /// While the MMU is in transition, console output goes to the physical register base, and
+    /// afterwards to the virtual one again.
+    #[kernel_test]
+    fn console_uses_physical_base_during_transition() {
+        use console::interface::Write;
+
+        let mut phys_mock = MockRegisters::new();
+        let mut virt_mock = MockRegisters::new();
+        phys_mock.set(0x18, FR::TXFE::SET.value | FR::RXFE::SET.value);
+        virt_mock.set(0x18, FR::TXFE::SET.value | FR::RXFE::SET.value);
+        let uart = mock_uart(&mut phys_mock);
+        uart.inner
+            .lock(|inner| inner.registers = unsafe { Registers::new(virt_mock.start_addr()) });
+
+        uart.write_byte(b'a');
+        unsafe {
+            memory::mmu::transition(|| {
+                assert!(memory::mmu::in_transition());
+                uart.write_byte(b'b');
+            })
+        };
+        assert!(!memory::mmu::in_transition());
+        assert_eq!(phys_mock.get(0x00), u32::from(b'b'));
+        assert_eq!(virt_mock.get(0x00), u32::from(b'a'));
+
+        uart.write_fmt(format_args!("c")).unwrap();
+        assert_eq!(virt_mock.get(0x00), u32::from(b'c'));
+        assert_eq!(phys_mock.get(0x00), u32::from(b'b'));
+    }
+
+    /// Clearing errors resets the error status register, and the counters only on request.
+    #[kernel_test]
+    fn clear_errors_resets_error_status() {
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+
+        mock.set(0x04, 0xf);
+        inner.overruns = 2;
+        inner.framing_errors.baud_mismatch_suspected = true;
+
+        inner.clear_errors(false);
+        assert_eq!(mock.get(0x04), 0);
+        assert_eq!(inner.overruns, 2);
+
+        mock.set(0x04, 0xf);
+        inner.clear_errors(true);
+        assert_eq!(mock.get(0x04), 0);
+        assert_eq!(inner.overruns, 0);
+        assert!(!inner.framing_errors.baud_mismatch_suspected);
+    }
+
+    /// A burst of framing errors raises the suspicion once, a clean window clears it again.
+    #[kernel_test]
+    fn framing_errors_suggest_baud_mismatch() {
+        static HOOK_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        fn on_baud_mismatch() {
+            HOOK_CALLS.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+        inner.echo_mode = console::EchoMode::Off;
+        inner.on_baud_mismatch = Some(on_baud_mismatch);
+
+        let mut read_window = |dr: u32| {
+            mock.set(0x00, dr);
+            for _ in 0..FRAMING_ERROR_WINDOW {
+                inner.read_raw(BlockingMode::NonBlocking);
+            }
+
+            inner.framing_errors.baud_mismatch_suspected
+        };
+
+        assert!(!read_window(0x41));
+        assert!(read_window(u32::from(DR_FE) | 0x41));
+        assert!(read_window(u32::from(DR_FE) | 0x41));
+        assert_eq!(HOOK_CALLS.load(Ordering::Relaxed), 1);
+
+        assert!(!read_window(0x41));
+    }
+
+    /// Sustained framing errors make the driver try the next baud rate, until one receives clean
+    /// bytes. If none does, it returns to the rate it started from and stops searching.
+    #[kernel_test]
+    fn auto_baud_settles_on_clean_rate() {
+        const RATES: [u32; 3] = [921_600, 230_400, 115_200];
+
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+        inner.echo_mode = console::EchoMode::Off;
+        inner.auto_baud = Some(AutoBaudState {
+            config: AutoBaud {
+                rates: &RATES,
+                sustained_windows: 2,
+            },
+            bad_windows: 0,
+            search: None,
+            pending: None,
+        });
+
+        // Receive a window of bytes, then let the driver switch with the RX FIFO empty.
+        let mut read_window = |dr: u32| {
+            mock.set(0x18, FR::TXFE::SET.value);
+            mock.set(0x00, dr);
+            for _ in 0..FRAMING_ERROR_WINDOW {
+                inner.read_raw(BlockingMode::NonBlocking);
+            }
+
+            mock.set(0x18, FR::TXFE::SET.value | FR::RXFE::SET.value);
+            inner.read_raw(BlockingMode::NonBlocking);
+
+            (inner.baud_rate, mock.get(0x24))
+        };
+        let garbage = u32::from(DR_FE) | 0xff;
+
+        // A single bad window is not sustained.
+        assert_eq!(read_window(garbage).0, 230_400);
+        assert_eq!(read_window(garbage), (921_600, 3));
+
+        // The first candidate fails as well, the second one receives clean bytes.
+        assert_eq!(read_window(garbage).0, 921_600);
+        assert_eq!(read_window(garbage), (115_200, 26));
+        assert_eq!(read_window(0x41).0, 115_200);
+        assert_eq!(read_window(0x41).0, 115_200);
+
+        // A new search starts from the working rate and ends there if nothing is better.
+        for rate in [921_600, 230_400, 115_200].iter() {
+            read_window(garbage);
+            assert_eq!(read_window(garbage).0, *rate);
+        }
+        for _ in 0..4 {
+            assert_eq!(read_window(garbage).0, 115_200);
+        }
+    }
+
+    /// Continuous breaks are reported as a stuck low line until a clean byte arrives. Errors and
+    /// glitches are forgotten after a quiet period.
+    #[kernel_test]
+    fn continuous_break_is_stuck_low() {
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+        inner.echo_mode = console::EchoMode::Off;
+
+        let now = inner.last_rx.unwrap_or_default();
+        assert_eq!(inner.rx_line_state_at(now), RxLineState::Normal);
+
+        mock.set(0x00, u32::from(DR_BE | DR_FE));
+        for _ in 0..RX_LINE_STUCK_LOW_BREAKS {
+            inner.read_raw(BlockingMode::NonBlocking);
+        }
+        let later = inner.last_rx.unwrap() + 10 * RX_LINE_STATE_MAX_AGE;
+        assert_eq!(inner.rx_line_state_at(later), RxLineState::StuckLow);
+
+        mock.set(0x00, 0x41);
+        inner.read_raw(BlockingMode::NonBlocking);
+        assert_eq!(inner.rx_line_state_at(later), RxLineState::Normal);
+
+        // The window that began with the breaks still contains them.
+        mock.set(0x00, 0xff);
+        for _ in 0..2 * RX_LINE_WINDOW {
+            inner.read_raw(BlockingMode::NonBlocking);
+        }
+        let now = inner.last_rx.unwrap();
+        assert_eq!(inner.rx_line_state_at(now), RxLineState::StuckHigh);
+        assert_eq!(
+            inner.rx_line_state_at(now + RX_LINE_STATE_MAX_AGE),
+            RxLineState::Normal
+        );
+    }
+
+    /// A non-blocking write hands the character back if the TX FIFO is full, a blocking one goes
+    /// through once there is room.
+    #[kernel_test]
+    fn write_char_mode_respects_full_fifo() {
+        use console::interface::Write;
+
+        let mut mock = MockRegisters::new();
+        let uart = mock_uart(&mut mock);
+
+        mock.set(0x18, FR::TXFF::SET.value);
+        assert_eq!(uart.write_char_mode('a', false), Err('a'));
+        assert_eq!(mock.get(0x00), 0);
+
+        mock.set(0x18, 0);
+        assert_eq!(uart.write_char_mode('b', false), Ok(()));
+        assert_eq!(mock.get(0x00), 'b' as u32);
+        assert_eq!(uart.write_char_mode('c', true), Ok(()));
+        assert_eq!(mock.get(0x00), 'c' as u32);
+    }
+
+    /// A FIFO that stops draining makes the write time out, reporting the bytes that got in.
+    #[kernel_test]
+    fn write_all_times_out_on_full_fifo() {
+        let mut mock = MockRegisters::new();
+        let uart = mock_uart(&mut mock);
+        let deadline = Duration::from_millis(10);
+
+        let mut ms = 0;
+        let now = || {
+            ms += 1;
+            Duration::from_millis(ms)
+        };
+        assert_eq!(uart.write_all_until(b"ab", deadline, now), Ok(()));
+        assert_eq!(mock.get(0x00), u32::from(b'b'));
+
+        // The FIFO fills up after two more bytes and never drains.
+        let mut ms = 0;
+        let now = || {
+            ms += 1;
+            if ms == 3 {
+                mock.set(0x18, FR::TXFF::SET.value);
+            }
+            Duration::from_millis(ms)
+        };
+        assert_eq!(
+            uart.write_all_until(b"cdef", deadline, now),
+            Err(console::WriteError::TimedOut(2))
+        );
+        assert_eq!(mock.get(0x00), u32::from(b'd'));
+    }
+
+    /// The UART clock is switched on before any register is mapped or touched.
+    #[kernel_test]
+    fn init_enables_uart_clock_first() {
+        use bsp::exception::asynchronous::irq_map;
+        use driver::interface::DeviceDriver;
+        use memory::mmu::{Address, MMIODescriptor};
+
+        static REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+        /// Records the request and fails it, so that init stops right after.
+        struct GatedClocks;
+
+        impl ClockControl for GatedClocks {
+            fn set_clock_enabled(
+                &self,
+                clock: ClockId,
+                enable: bool,
+            ) -> Result<(), driver::DriverError> {
+                assert_eq!((clock, enable), (ClockId::Uart, true));
+                REQUESTS.fetch_add(1, Ordering::Relaxed);
+
+                Err(driver::DriverError::Transient("Clock still gated"))
+            }
+        }
+
+        let mut mock = MockRegisters::new();
+        let uart = unsafe {
+            PL011Uart::new(
+                MMIODescriptor::new(Address::new(mock.start_addr()), 0x90),
+                irq_map::PL011_UART,
+                &GatedClocks,
+            )
+        };
+
+        assert_eq!(
+            unsafe { uart.init() },
+            Err(driver::DriverError::Transient("Clock still gated"))
+        );
+        assert_eq!(REQUESTS.load(Ordering::Relaxed), 1);
+        assert_eq!(uart.virt_mmio_start_addr(), None);
+        assert_eq!(mock.get(0x30), 0);
+    }
+
+    /// The initial capture keeps the first bytes received after it was started, while all bytes
+    /// are still buffered as usual.
+    #[kernel_test]
+    fn initial_capture_keeps_first_bytes() {
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+
+        // Character mode, so that each call takes a single byte from the mock.
+        inner.fifos_enabled = false;
+        inner.echo_mode = console::EchoMode::Off;
+
+        mock.set(0x00, u32::from(b'-'));
+        inner.buffer_rx_fifo();
+        inner.initial_capture = Some(InitialCapture::new());
+
+        let num_bytes = INITIAL_CAPTURE_SIZE + 4;
+        for i in 0..num_bytes {
+            mock.set(0x00, i as u32);
+            inner.buffer_rx_fifo();
+        }
+
+        let capture = inner.initial_capture.unwrap();
+        assert!(capture.is_complete());
+        assert_eq!(capture.as_bytes().len(), INITIAL_CAPTURE_SIZE);
+        assert!(capture
+            .as_bytes()
+            .iter()
+            .enumerate()
+            .all(|(i, x)| *x == i as u8));
+
+        assert_eq!(inner.pop_rx_byte(), Some(b'-'));
+        for i in 0..num_bytes {
+            assert_eq!(inner.pop_rx_byte(), Some(i as u8));
+        }
+        assert_eq!(inner.pop_rx_byte(), None);
+    }
+
+    /// The idle time is measured from the last received byte, or from boot before the first one.
+    #[kernel_test]
+    fn idle_since_last_rx_measures_gap() {
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+        let gap = Duration::from_micros(1750);
+
+        assert_eq!(inner.idle_since_last_rx_at(gap), gap);
+
+        mock.set(0x00, u32::from(b'x'));
+        assert!(inner.read_raw(BlockingMode::Blocking).is_some());
+        let rx_time = inner.last_rx.unwrap();
+
+        assert_eq!(inner.idle_since_last_rx_at(rx_time + gap), gap);
+        assert_eq!(inner.idle_since_last_rx_at(rx_time), Duration::from_secs(0));
+    }
+
+    /// Polling mode keeps all interrupts masked. Switching to interrupt mode unmasks RX, and bytes
+    /// that arrived in between end up in the software buffer.
+    #[kernel_test]
+    fn init_polling_masks_irqs_until_enabled() {
+        let rx_irqs = (IMSC::RXIM::Enabled + IMSC::RTIM::Enabled).value;
+
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+        inner.verify_writes = true;
+
+        // Character mode, so that the mock's never empty RX holding register is read only once.
+        inner.fifos_enabled = false;
+
+        mock.set(0x38, rx_irqs);
+        assert_eq!(unsafe { inner.init_polling(None) }, Ok(()));
+        assert_eq!(mock.get(0x38) & rx_irqs, 0);
+
+        mock.set(0x00, u32::from(b'z'));
+        assert_eq!(inner.enable_interrupts(), Ok(()));
+        assert_eq!(mock.get(0x38), rx_irqs);
+        assert_eq!(inner.pop_rx_byte(), Some(b'z'));
+    }
+
+    /// With write verification enabled, registers that do not hold the programmed value are
+    /// reported.
+    #[kernel_test]
+    fn verify_writes_reports_mismatch() {
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+        inner.verify_writes = true;
+        mock.set(0x18, FR::RXFE::SET.value);
+
+        assert_eq!(unsafe { inner.init(None) }, Ok(()));
+
+        // The IMSC write did not take effect.
+        mock.set(0x38, 0);
+        assert_eq!(
+            inner.verify_config(),
+            Err("PL011 register readback mismatch: IMSC")
+        );
+
+        mock.set(0x34, IFLS::RXIFLSEL::OneHalf.value);
+        assert_eq!(
+            inner.verify_config(),
+            Err("PL011 register readback mismatch: IFLS, IMSC")
+        );
+    }
+
+    /// A register that did not take the programmed value, here LCRH and then IBRD, fails the
+    /// register setup of `init()` once write verification is enabled.
+    #[kernel_test]
+    fn init_with_verify_writes_reports_mismatch() {
+        let regs = LoggingRegisters::new();
+        regs.regs.store(0x18, FR::TXFE::SET.value);
+        regs.stuck.set(Some(0x2c));
+        let mut inner = PL011UartInner::with_registers(regs);
+        inner.verify_writes = true;
+
+        // The part of `init()` that programs the registers it was given.
+        assert_eq!(
+            inner.configure_polling(),
+            Err("PL011 register readback mismatch: LCRH")
+        );
+
+        inner.registers.stuck.set(Some(0x24));
+        inner.registers.regs.store(0x24, 0);
+        assert_eq!(
+            inner.configure_polling(),
+            Err("PL011 register readback mismatch: IBRD")
+        );
+    }
+
+    /// With a yield interval, large writes release the lock between chunks, and other writers can
+    /// get in.
+    #[kernel_test]
+    fn write_bytes_yields_between_chunks() {
+        use console::interface::{Statistics, Write};
+
+        let mut mock = MockRegisters::new();
+        let uart = mock_uart(&mut mock);
+        let bytes = b"0123456789";
+
+        let mut yields = 0;
+        uart.write_bytes_with(bytes, || yields += 1);
+        assert_eq!(yields, 0);
+
+        uart.set_write_yield_interval(NonZeroUsize::new(4));
+        let mut last_bytes = [0; 2];
+        uart.write_bytes_with(bytes, || {
+            last_bytes[yields] = mock.get(0x00);
+            yields += 1;
+
+            // Another writer getting in between the chunks.
+            uart.write_char('-');
+        });
+
+        assert_eq!(yields, 2);
+        assert_eq!(last_bytes, [u32::from(b'3'), u32::from(b'7')]);
+        assert_eq!(mock.get(0x00), u32::from(b'9'));
+        assert_eq!(uart.chars_written(), 2 * bytes.len() + 2);
+    }
+
+    /// A pending RX interrupt makes a long write release the lock, but only a bounded number of
+    /// times, so that continuous RX neither overruns nor stalls the write.
+    #[kernel_test]
+    fn write_bytes_yields_to_pending_rx() {
+        use console::interface::LineDiscipline;
+        use exception::asynchronous::interface::IRQHandler;
+
+        let mut mock = MockRegisters::new();
+        let uart = mock_uart(&mut mock);
+        uart.set_echo_mode(console::EchoMode::Off);
+        uart.inner.lock(|inner| {
+            inner.fifos_enabled = false;
+            inner.rx_irqs_enabled = true;
+        });
+
+        // The RX interrupt never stops.
+        mock.set(0x40, MIS::RXMIS::SET.value);
+
+        let bytes = [b'x'; 64];
+        let mut yields = 0;
+        uart.write_bytes_with(&bytes, || {
+            yields += 1;
+            uart.handle().unwrap();
+        });
+
+        assert_eq!(yields, MAX_RX_YIELDS_PER_WRITE);
+        assert_eq!(
+            uart.inner.lock(|inner| inner.rx_buffer.len()),
+            MAX_RX_YIELDS_PER_WRITE
+        );
+        assert_eq!(uart.inner.lock(|inner| inner.chars_written), bytes.len());
+    }
+
+    /// The virtual MMIO start address is only reported once it has been published by `init()`.
+    ///
+    /// `map_and_init()` publishes it with a Release store, after the registers have been set up.
+    /// The Acquire load in `virt_mmio_start_addr()` pairs with it, so a reader that gets the
+    /// address also observes the completed setup.
+    #[kernel_test]
+    fn virt_mmio_start_addr_is_published_after_setup() {
+        use driver::interface::DeviceDriver;
+
+        // The MMU stays off, so the registers must not be touched at the remapped address.
+        unsafe { memory::mmu::kernel_init_translation_tables() };
+
+        let mut mock = MockRegisters::new();
+        let uart = mock_uart(&mut mock);
+        assert_eq!(uart.virt_mmio_start_addr(), None);
+
+        let mut mapped = 0;
+        let ret = unsafe {
+            uart.map_and_init(|inner| {
+                assert_eq!(uart.virt_mmio_start_addr(), None);
+                mapped = inner.registers.start_addr();
+
+                Ok(())
+            })
+        };
+        assert_eq!(ret, Ok(()));
+        assert!(mapped != mock.start_addr());
+        assert_eq!(uart.virt_mmio_start_addr(), Some(mapped));
+    }
+
+    /// Switching to a remapped register block keeps the statistics and buffered input, and does
+    /// not reprogram the device.
+    #[kernel_test]
+    fn post_mmio_remap_preserves_state() {
+        use console::interface::{Read, Statistics, Write};
+        use driver::interface::DeviceDriver;
+
+        let mut old_mock = MockRegisters::new();
+        let mut new_mock = MockRegisters::new();
+        let uart = mock_uart(&mut old_mock);
+        assert!(unsafe { uart.post_mmio_remap(new_mock.start_addr()) }.is_err());
+
+        // Stand-in for `init()`, which would map the registers through the MMU.
+        uart.virt_mmio_start_addr
+            .store(old_mock.start_addr(), Ordering::Release);
+        uart.inject_rx(b"xy");
+        assert_eq!(uart.read_char(), 'x');
+        uart.write_char('a');
+        let chars_written = uart.chars_written();
+        let chars_read = uart.chars_read();
+
+        assert_eq!(
+            unsafe { uart.post_mmio_remap(new_mock.start_addr()) },
+            Ok(())
+        );
+        assert_eq!(uart.virt_mmio_start_addr(), Some(new_mock.start_addr()));
+        assert_eq!(new_mock.get(0x30), 0);
+        assert_eq!(uart.chars_written(), chars_written);
+        assert_eq!(uart.chars_read(), chars_read);
+        assert_eq!(uart.read_char(), 'y');
+
+        uart.write_char('b');
+        assert_eq!(old_mock.get(0x00), u32::from(b'a'));
+        assert_eq!(new_mock.get(0x00), u32::from(b'b'));
+        assert_eq!(uart.chars_written(), chars_written + 1);
+    }
+
+    /// Received bytes are distributed over the segments in order, skipping empty ones. Once the
+    /// input is idle, the call returns with what arrived so far.
+    #[kernel_test]
+    fn read_scatter_fills_segments_in_order() {
+        let mut mock = MockRegisters::new();
+        let uart = mock_uart(&mut mock);
+        mock.set(0x18, FR::RXFE::SET.value);
+
+        let mut header = [0; 3];
+        let mut empty = [0; 0];
+        let mut payload = [0; 4];
+        uart.inject_rx(b"hdrab");
+
+        let mut segments: [&mut [u8]; 3] = [&mut header, &mut empty, &mut payload];
+        assert_eq!(uart.read_scatter(&mut segments), 5);
+        assert_eq!(&header, b"hdr");
+        assert_eq!(payload, [b'a', b'b', 0, 0]);
+    }
+
+    /// The pattern passes if every byte comes back unchanged. Otherwise, the error tells whether
+    /// nothing or wrong data arrived.
+    #[kernel_test]
+    fn physical_loopback_detects_path_errors() {
+        let wire = MockWire::new;
+
+        assert_eq!(loopback_test_over(&wire(true, 0), LOOPBACK_TIMEOUT), Ok(()));
+        assert_eq!(
+            loopback_test_over(&wire(false, 0), LOOPBACK_TIMEOUT),
+            Err("Physical loopback: nothing received, check TX-RX jumper and GPIO setup")
+        );
+        assert_eq!(
+            loopback_test_over(&wire(true, 0x01), LOOPBACK_TIMEOUT),
+            Err("Physical loopback: received data differs from sent data")
+        );
     }
-}
 
-impl exception::asynchronous::interface::IRQHandler for PL011Uart {
-    fn handle(&self) -> Result<(), &'static str> {
-        self.inner.lock(|inner| {
-            let pending = inner.registers.MIS.extract();
+    /// The timed loopback test measures the delay of the wire, and tells a missing loopback from
+    /// one that changes the data.
+    #[kernel_test]
+    fn timed_loopback_measures_round_trip() {
+        let uptime = Cell::new(Duration::from_secs(1));
+        let wire = |connected, stuck_high| {
+            MockWire::new(connected, stuck_high).with_clock(Duration::from_micros(87), &uptime)
+        };
 
-            // Clear all pending IRQs.
-            inner.registers.ICR.write(ICR::ALL::CLEAR);
+        let timing = timed_loopback_over(&wire(true, 0), LOOPBACK_TIMEOUT, || uptime.get());
+        assert_eq!(
+            timing,
+            Ok(LoopbackTiming {
+                matched: true,
+                round_trip: Duration::from_micros(87),
+                max_round_trip: Duration::from_micros(87),
+            })
+        );
 
-            // Check for any kind of RX interrupt.
-            if pending.matches_any(MIS::RXMIS::SET + MIS::RTMIS::SET) {
-                // Echo any received characters.
-                while let Some(c) = inner.read_char_converting(BlockingMode::NonBlocking) {
-                    inner.write_char(c)
-                }
+        let mut report = console::FmtBuf::<80>::new();
+        fmt::Write::write_fmt(&mut report, format_args!("{}", timing.unwrap())).unwrap();
+        assert_eq!(
+            report.as_str(),
+            "Physical loopback: pattern matched, round trip 87 us (max 87 us)"
+        );
+
+        let timing = timed_loopback_over(&wire(true, 0x01), LOOPBACK_TIMEOUT, || uptime.get());
+        assert!(!timing.unwrap().matched);
+        assert_eq!(
+            timed_loopback_over(&wire(false, 0), LOOPBACK_TIMEOUT, || uptime.get()),
+            Err("Physical loopback: no loopback detected")
+        );
+    }
+
+    /// A raw byte goes out as a single FIFO entry, past the line buffer.
+    #[kernel_test]
+    fn write_byte_is_not_translated() {
+        use console::interface::{Statistics, Write};
+
+        let mut mock = MockRegisters::new();
+        let uart = mock_uart(&mut mock);
+        uart.set_line_buffered(true);
+
+        uart.write_byte(0x0a);
+        assert_eq!(mock.get(0x00), 0x0a);
+        assert_eq!(uart.chars_written(), 1);
+
+        uart.write_byte(0xff);
+        assert_eq!(mock.get(0x00), 0xff);
+        assert_eq!(uart.chars_written(), 2);
+    }
+
+    /// The early UART writes straight to the data register, without any driver state.
+    #[kernel_test]
+    fn early_uart_writes_data_register() {
+        use console::interface::Statistics;
+        use fmt::Write;
+
+        let mut mock = MockRegisters::new();
+        let mut early_uart = unsafe { EarlyUart::new(mock.start_addr()) };
+        let chars_written = bsp::console::console().chars_written();
+
+        early_uart.write_str("hi").unwrap();
+        assert_eq!(mock.get(0x00), u32::from(b'i'));
+
+        let answer = 42;
+        write!(early_uart, "{}", answer).unwrap();
+        assert_eq!(mock.get(0x00), u32::from(b'2'));
+
+        assert_eq!(bsp::console::console().chars_written(), chars_written);
+    }
+
+    /// Reconfiguring sends pending output first, drops received bytes and keeps the statistics.
+    #[kernel_test]
+    fn reconfigure_flushes_and_keeps_statistics() {
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+        mock.set(0x18, FR::TXFE::SET.value | FR::RXFE::SET.value);
+
+        assert_eq!(
+            baud_divisors_with(UART_CLOCK_HZ, 230_400, DEFAULT_BAUD_TOLERANCE),
+            Ok((13, 1))
+        );
+        assert!(inner.reconfigure(UartConfig::new(0)).is_err());
+
+        inner.set_line_buffered(true);
+        inner.write_char('a');
+        inner.chars_read = 5;
+        let _ = inner.rx_buffer.push(RxSlot {
+            byte: b'x',
+            flags: 0,
+            timestamp: Duration::from_secs(0),
+        });
+
+        inner.reconfigure(UartConfig::new(115_200)).unwrap();
+
+        assert!(inner.line_buffer.is_empty());
+        assert_eq!(mock.get(0x00), 'a' as u32);
+        assert_eq!(mock.get(0x24), 26);
+        assert_eq!(mock.get(0x28), 3);
+        assert_eq!(
+            mock.get(0x30),
+            (CR::UARTEN::Enabled + CR::TXE::Enabled + CR::RXE::Enabled).value
+        );
+        assert_eq!(inner.pop_rx_byte(), None);
+        assert_eq!(inner.chars_written, 1);
+        assert_eq!(inner.chars_read, 5);
+    }
+
+    /// The baud rate is switched for the duration of the closure only.
+    #[kernel_test]
+    fn with_baud_restores_baud_rate() {
+        let mut mock = MockRegisters::new();
+        mock.set(0x18, FR::TXFE::SET.value | FR::RXFE::SET.value);
+        let uart = mock_uart(&mut mock);
+
+        let divisors = uart
+            .with_baud(921_600, || {
+                assert_eq!(uart.config().baud_rate, 921_600);
+                (mock.get(0x24), mock.get(0x28))
+            })
+            .unwrap();
+        assert_eq!(divisors, (3, 16));
+        assert_eq!(uart.config().baud_rate, INIT_BAUD_RATE);
+        assert_eq!((mock.get(0x24), mock.get(0x28)), (13, 1));
+
+        assert!(uart.with_baud(0, || panic!("Must not run")).is_err());
+        assert_eq!(uart.config().baud_rate, INIT_BAUD_RATE);
+    }
+
+    /// Level and timeout interrupts are dispatched to their respective hooks.
+    #[kernel_test]
+    fn rx_irqs_dispatch_to_hooks() {
+        static LEVEL_CALLS: AtomicUsize = AtomicUsize::new(0);
+        static TIMEOUT_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        fn on_level() {
+            LEVEL_CALLS.fetch_add(1, Ordering::Relaxed);
+        }
+        fn on_timeout() {
+            TIMEOUT_CALLS.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+        inner.on_rx_level = Some(on_level);
+        inner.on_rx_timeout = Some(on_timeout);
+
+        // Nothing to drain from the RX FIFO.
+        mock.set(0x18, FR::RXFE::SET.value);
+
+        let combinations = [
+            (0, (0, 0)),
+            (MIS::RXMIS::SET.value, (1, 0)),
+            (MIS::RTMIS::SET.value, (1, 1)),
+            (MIS::RXMIS::SET.value | MIS::RTMIS::SET.value, (2, 2)),
+        ];
+        for (mis, (level, timeout)) in combinations.iter() {
+            mock.set(0x40, *mis);
+            inner.handle_pending_irqs();
+
+            assert_eq!(LEVEL_CALLS.load(Ordering::Relaxed), *level);
+            assert_eq!(TIMEOUT_CALLS.load(Ordering::Relaxed), *timeout);
+        }
+    }
+
+    /// Setting the RX trigger level leaves the TX level field untouched.
+    #[kernel_test]
+    fn rx_trigger_level_keeps_tx_level() {
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+
+        let tx_level = IFLS::TXIFLSEL::ThreeQuarters.value;
+        mock.set(0x34, tx_level);
+        assert_eq!(inner.rx_trigger_level(), RxTriggerLevel::OneEighth);
+
+        inner.set_rx_trigger_level(RxTriggerLevel::OneHalf);
+        assert_eq!(mock.get(0x34), IFLS::RXIFLSEL::OneHalf.value | tx_level);
+        assert_eq!(inner.rx_trigger_level(), RxTriggerLevel::OneHalf);
+    }
+
+    /// DMA status is decoded from DMACR.
+    #[kernel_test]
+    fn dma_status_reflects_dmacr() {
+        let mut mock = MockRegisters::new();
+        let inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+
+        let disabled = DmaStatus {
+            tx_enabled: false,
+            rx_enabled: false,
+            rx_stops_on_error: false,
+        };
+        assert_eq!(inner.dma_status(), disabled);
+
+        mock.set(0x48, DMACR::TXDMAE::SET.value);
+        assert_eq!(
+            inner.dma_status(),
+            DmaStatus {
+                tx_enabled: true,
+                ..disabled
+            }
+        );
+    }
+
+    /// The handler acknowledges only what it saw in `MIS`, so an interrupt raised after the read
+    /// is not cleared along with it.
+    #[kernel_test]
+    fn irq_clears_only_observed_interrupts() {
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+        mock.set(0x18, FR::RXFE::SET.value);
+
+        let cases = [
+            (MIS::RXMIS::SET.value, ICR::RXIC::SET.value),
+            (MIS::RTMIS::SET.value, ICR::RTIC::SET.value),
+            (MIS::TXMIS::SET.value, ICR::TXIC::SET.value),
+        ];
+        for (mis, icr) in cases.iter() {
+            mock.set(0x44, 0);
+            mock.set(0x40, *mis);
+            inner.handle_pending_irqs();
+
+            assert_eq!(mock.get(0x44), *icr);
+        }
+
+        // The timeout is acknowledged last, after the FIFO has been drained.
+        mock.set(0x40, MIS::RXMIS::SET.value | MIS::RTMIS::SET.value);
+        inner.handle_pending_irqs();
+        assert_eq!(mock.get(0x44), ICR::RTIC::SET.value);
+    }
+
+    /// With power-down on, a flush that finds TX empty clears TXE, and the next write sets it
+    /// again before the byte goes out.
+    #[kernel_test]
+    fn tx_powers_down_when_empty_and_up_on_write() {
+        use console::interface::Write;
+
+        let mut mock = MockRegisters::new();
+        mock.set(0x18, FR::TXFE::SET.value);
+        mock.set(
+            0x30,
+            (CR::UARTEN::Enabled + CR::TXE::Enabled + CR::RXE::Enabled).value,
+        );
+        let uart = mock_uart(&mut mock);
+        let txe = CR::TXE::Enabled.value;
+
+        uart.flush();
+        assert_eq!(mock.get(0x30) & txe, txe);
+
+        uart.set_tx_power_down(true);
+        assert_eq!(mock.get(0x30) & txe, 0);
+
+        uart.write_byte(b'x');
+        assert_eq!(mock.get(0x30) & txe, txe);
+        assert_eq!(mock.get(0x00), u32::from(b'x'));
+
+        uart.flush();
+        assert_eq!(mock.get(0x30) & txe, 0);
+
+        uart.set_tx_power_down(false);
+        assert_eq!(mock.get(0x30) & txe, txe);
+    }
+
+    /// The status reflects the programmed configuration and the registers that are read.
+    #[kernel_test]
+    fn status_reflects_registers() {
+        let rx_irqs = (IMSC::RXIM::Enabled + IMSC::RTIM::Enabled).value;
+
+        let mut mock = MockRegisters::new();
+        mock.set(0x18, FR::TXFE::SET.value | FR::RXFE::SET.value);
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+
+        assert_eq!(unsafe { inner.init_polling(None) }, Ok(()));
+        assert_eq!(inner.enable_interrupts(), Ok(()));
+        mock.set(0x40, MIS::RTMIS::SET.value);
+        mock.set(0x04, 0b1000);
+
+        let status = inner.status_at(Duration::from_secs(0));
+        assert_eq!(status.config.baud_rate, INIT_BAUD_RATE);
+        assert_eq!(status.data_bits, 8);
+        assert!(!status.parity_enabled);
+        assert_eq!(status.stop_bits, StopBits::One);
+        assert!(status.fifos_enabled && status.tx_enabled && !status.rx_paused);
+        assert!(status.tx_fifo_empty && status.rx_fifo_empty && !status.tx_busy);
+        assert_eq!(status.rx_trigger_level, RxTriggerLevel::OneEighth);
+        assert_eq!(status.irqs_enabled, rx_irqs);
+        assert_eq!(status.irqs_pending, MIS::RTMIS::SET.value);
+        assert_eq!(status.rx_error_flags, 0b1000);
+        assert_eq!(status.rx_line_state, RxLineState::Normal);
+
+        let mut report = console::FmtBuf::<512>::new();
+        fmt::Write::write_fmt(&mut report, format_args!("{}", status)).unwrap();
+        assert!(report
+            .as_str()
+            .starts_with("Line:       230400 baud, 8N1, FIFOs on\n"));
+        assert!(report.as_str().contains("IRQs:       enabled 0x050"));
+    }
+
+    /// The self-test enables test FIFO access, checks the pattern through TDR and leaves test mode
+    /// with the UART back in normal operation.
+    #[kernel_test]
+    fn fifo_self_test_round_trips_through_tdr() {
+        let normal = (CR::UARTEN::Enabled + CR::TXE::Enabled + CR::RXE::Enabled).value;
+
+        let mut mock = MockRegisters::new();
+        mock.set(0x18, FR::TXFE::SET.value | FR::RXFE::SET.value);
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+
+        // Plain memory stands in for the FIFOs, so the readbacks return what is preset.
+        mock.set(0x8c, 0xa5);
+        mock.set(0x00, 0xa5);
+        assert_eq!(inner.fifo_self_test(&[0xa5]), Ok(()));
+        assert_eq!(mock.get(0x80), 0);
+        assert_eq!(mock.get(0x30), normal);
+
+        assert_eq!(
+            inner.fifo_self_test(&[0x5a]),
+            Err("Self-test: TX FIFO readback differs from written data")
+        );
+        assert_eq!(mock.get(0x80), 0);
+        assert_eq!(mock.get(0x30), normal);
+    }
+
+    /// With the UTF-8 codec, a multi-byte sequence is read as a single character, and a
+    /// sequence that is cut short, by another byte or by the sender going quiet, as U+FFFD.
+    #[kernel_test]
+    fn utf8_codec_assembles_multi_byte_chars() {
+        use console::interface::{LineDiscipline, Read};
+
+        let mut mock = MockRegisters::new();
+        mock.set(0x18, FR::TXFE::SET.value | FR::RXFE::SET.value);
+        let uart = mock_uart(&mut mock);
+
+        uart.inject_rx("€".as_bytes());
+        assert_eq!(
+            uart.set_input_codec(console::InputCodec::Utf8),
+            console::InputCodec::Latin1
+        );
+        assert_eq!(uart.read_char(), '€');
+        assert_eq!(uart.read_char_timeout(Duration::from_millis(1)), None);
+
+        // The byte that cuts the sequence short is not lost.
+        uart.inject_rx(b"\xe2\x82\r");
+        assert_eq!(uart.read_char(), '\u{fffd}');
+        assert_eq!(uart.read_char(), '\n');
+
+        uart.inject_rx(b"\xe2\x82");
+        assert_eq!(uart.read_char(), '\u{fffd}');
+    }
+
+    /// A baud rate that the divisors can only approximate by more than the tolerance is rejected,
+    /// also at init.
+    #[kernel_test]
+    fn baud_rate_beyond_tolerance_is_rejected() {
+        let err = "Baud rate deviates from the target beyond the tolerance";
+
+        // 1 MHz / (16 * 61000) is rounded to 66/64, which produces 60606 baud, 0.65 % too slow.
+        assert_eq!(baud_divisors_with(1_000_000, 61_000, 0), Err(err));
+        assert_eq!(baud_divisors_with(1_000_000, 61_000, 1), Ok((1, 2)));
+
+        let mut mock = MockRegisters::new();
+        mock.set(0x18, FR::TXFE::SET.value | FR::RXFE::SET.value);
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+
+        inner.baud_tolerance = 0;
+        assert_eq!(unsafe { inner.init_polling(None) }, Err(err));
+        assert_eq!(mock.get(0x24), 0);
+
+        inner.baud_tolerance = DEFAULT_BAUD_TOLERANCE;
+        assert_eq!(unsafe { inner.init_polling(None) }, Ok(()));
+        assert_eq!(mock.get(0x24), 13);
+    }
+
+    /// Persisted settings survive the round trip through a record, and a corrupt or missing one
+    /// leaves the defaults in place.
+    #[kernel_test]
+    fn persisted_config_round_trips() {
+        let mut inner = PL011UartInner::with_registers(MockRegisters::new());
+        inner
+            .registers
+            .store(0x18, FR::TXFE::SET.value | FR::RXFE::SET.value);
+        inner.configure_polling().unwrap();
+
+        let config = UartConfig {
+            baud_rate: 115_200,
+            ref_clock_hz: Some(UART_CLOCK_HZ),
+        };
+        let two_stop_bits = UartFraming {
+            stop_bits: StopBits::Two,
+        };
+        let record = encode_persisted_config(config, two_stop_bits);
+        assert_eq!(
+            decode_persisted_config(&record),
+            Some((config, two_stop_bits))
+        );
+
+        // Garbage after a cold boot, a changed byte and a foreign magic.
+        let mut corrupt = record;
+        corrupt[5] ^= 0x01;
+        let mut foreign = record;
+        foreign[0] = 0;
+        for bad in [[0; PERSISTED_CONFIG_LEN], corrupt, foreign].iter() {
+            assert_eq!(inner.restore_config_from(bad), Ok(false));
+        }
+        assert_eq!(inner.baud_rate, INIT_BAUD_RATE);
+        assert_eq!(inner.framing().stop_bits, StopBits::One);
+
+        assert_eq!(inner.restore_config_from(&record), Ok(true));
+        assert_eq!(inner.baud_rate, 115_200);
+        assert_eq!(inner.framing(), two_stop_bits);
+    }
+
+    /// The inner logic runs on a mock behind `UartRegisters`, without any memory-mapped block.
+    #[kernel_test]
+    fn inner_runs_on_mock_registers() {
+        let mut inner = PL011UartInner::with_registers(MockRegisters::new());
+        inner
+            .registers
+            .store(0x18, FR::TXFE::SET.value | FR::RXFE::SET.value);
+
+        assert_eq!(inner.configure_polling(), Ok(()));
+        assert_eq!(inner.registers.get(0x24), 13);
+        assert_eq!(inner.registers.get(0x28), 1);
+        assert!(lcrh(&inner.registers).matches_all(LCRH::WLEN::EightBit + LCRH::FEN::FifosEnabled));
+        assert_eq!(inner.enable_interrupts(), Ok(()));
+        assert_eq!(
+            inner.registers.get(0x38),
+            (IMSC::RXIM::Enabled + IMSC::RTIM::Enabled).value
+        );
+
+        inner.write_char('a');
+        assert_eq!(inner.registers.get(0x00), u32::from(b'a'));
+
+        // A received byte is read from DR once the FIFO stops indicating empty.
+        inner.echo_mode = console::EchoMode::Off;
+        assert_eq!(
+            inner.read_char_converting(BlockingMode::NonBlocking),
+            Ok(None)
+        );
+        inner.registers.store(0x00, u32::from(b'\r'));
+        inner.registers.store(0x18, FR::TXFE::SET.value);
+        assert_eq!(
+            inner.read_char_converting(BlockingMode::NonBlocking),
+            Ok(Some('\n'))
+        );
+    }
+
+    /// With the receiver off, a read fails instead of taking whatever `DR` holds for a character.
+    #[kernel_test]
+    fn read_in_tx_only_mode_fails() {
+        let mut mock = MockRegisters::new();
+        mock.set(0x18, FR::TXFE::SET.value);
+        mock.set(0x00, u32::from(b'x'));
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+
+        inner.set_tx_only(true);
+        assert_eq!(
+            mock.get(0x30),
+            (CR::UARTEN::Enabled + CR::TXE::Enabled).value
+        );
+        assert_eq!(
+            inner.read_char_converting(BlockingMode::Blocking),
+            Err("PL011 receiver is disabled")
+        );
+        assert_eq!(inner.read_raw(BlockingMode::NonBlocking), None);
+        assert_eq!(inner.chars_read, 0);
+
+        // Survives a reconfiguration, which drains the RX FIFO until it indicates empty.
+        mock.set(0x18, FR::TXFE::SET.value | FR::RXFE::SET.value);
+        inner.reconfigure(UartConfig::new(115_200)).unwrap();
+        assert!(inner.rx_enabled().is_err());
+        mock.set(0x18, FR::TXFE::SET.value);
+
+        inner.set_tx_only(false);
+        assert_eq!(
+            inner.read_char_converting(BlockingMode::NonBlocking),
+            Ok(Some('x'))
+        );
+    }
+
+    /// Blocking reads, which cannot fail, return NUL in TX-only mode instead of panicking.
+    #[kernel_test]
+    fn blocking_read_in_tx_only_mode_returns_nul() {
+        use console::interface::{LineDiscipline, Read, Statistics};
+
+        let mut mock = MockRegisters::new();
+        mock.set(0x18, FR::TXFE::SET.value);
+        mock.set(0x00, u32::from(b'x'));
+        let uart = mock_uart(&mut mock);
+
+        uart.set_tx_only(true);
+        assert_eq!(uart.read_char(), '\0');
+        assert_eq!(uart.read_byte(), 0);
+
+        uart.set_input_codec(console::InputCodec::Utf8);
+        assert_eq!(uart.read_char(), '\0');
+        assert_eq!(uart.chars_read(), 0);
+    }
+
+    /// Passes register accesses on to a `MockRegisters`, logging the writes to the line settings
+    /// as pairs of offset and value. Received data comes from `rx_fifo`, with FR::RXFE telling
+    /// whether it is empty. With CR::LBE set, data written to DR goes to `rx_fifo`.
+    struct LoggingRegisters {
+        regs: MockRegisters,
+        writes: RefCell<RingBuffer<(usize, u32), 8>>,
+        rx_fifo: RefCell<RingBuffer<u32, 16>>,
+        /// A register whose writes are logged, but do not take effect.
+        stuck: Cell<Option<usize>>,
+    }
+
+    impl LoggingRegisters {
+        fn new() -> Self {
+            Self {
+                regs: MockRegisters::new(),
+                writes: RefCell::new(RingBuffer::new()),
+                rx_fifo: RefCell::new(RingBuffer::new()),
+                stuck: Cell::new(None),
+            }
+        }
+
+        fn log(&self, offset: usize, value: u32) {
+            self.writes.borrow_mut().push_overwriting((offset, value));
+            if self.stuck.get() != Some(offset) {
+                self.regs.store(offset, value);
+            }
+        }
+    }
+
+    impl UartRegisters for LoggingRegisters {
+        fn dr(&self) -> u32 {
+            self.rx_fifo.borrow_mut().pop().unwrap_or(0)
+        }
+
+        fn set_dr(&self, value: u32) {
+            if self.regs.get(0x30) & CR::LBE::Enabled.value != 0 {
+                self.rx_fifo.borrow_mut().push_overwriting(value & 0xff);
             }
+
+            self.regs.set_dr(value)
+        }
+
+        fn rsrecr(&self) -> u32 {
+            self.regs.rsrecr()
+        }
+
+        fn set_rsrecr(&self, value: u32) {
+            self.regs.set_rsrecr(value)
+        }
+
+        fn fr(&self) -> LocalRegisterCopy<u32, FR::Register> {
+            let mut fr = self.regs.fr();
+            if self.rx_fifo.borrow().is_empty() {
+                fr.modify(FR::RXFE::SET);
+            } else {
+                fr.modify(FR::RXFE::CLEAR);
+            }
+
+            fr
+        }
+
+        fn ibrd(&self) -> u32 {
+            self.regs.ibrd()
+        }
+
+        fn set_ibrd(&self, value: u32) {
+            self.log(0x24, value)
+        }
+
+        fn fbrd(&self) -> u32 {
+            self.regs.fbrd()
+        }
+
+        fn set_fbrd(&self, value: u32) {
+            self.log(0x28, value)
+        }
+
+        fn lcrh(&self) -> LocalRegisterCopy<u32, LCRH::Register> {
+            self.regs.lcrh()
+        }
+
+        fn set_lcrh(&self, value: u32) {
+            self.log(0x2c, value)
+        }
+
+        fn cr(&self) -> LocalRegisterCopy<u32, CR::Register> {
+            self.regs.cr()
+        }
+
+        fn set_cr(&self, value: u32) {
+            self.log(0x30, value)
+        }
+
+        fn ifls(&self) -> LocalRegisterCopy<u32, IFLS::Register> {
+            self.regs.ifls()
+        }
+
+        fn set_ifls(&self, value: u32) {
+            self.regs.set_ifls(value)
+        }
+
+        fn imsc(&self) -> LocalRegisterCopy<u32, IMSC::Register> {
+            self.regs.imsc()
+        }
+
+        fn set_imsc(&self, value: u32) {
+            self.regs.set_imsc(value)
+        }
+
+        fn mis(&self) -> LocalRegisterCopy<u32, MIS::Register> {
+            self.regs.mis()
+        }
+
+        fn set_icr(&self, value: u32) {
+            self.regs.set_icr(value)
+        }
+
+        fn dmacr(&self) -> LocalRegisterCopy<u32, DMACR::Register> {
+            self.regs.dmacr()
+        }
+
+        fn set_itcr(&self, value: u32) {
+            self.regs.set_itcr(value)
+        }
+
+        fn tdr(&self) -> u32 {
+            self.regs.tdr()
+        }
+
+        fn set_tdr(&self, value: u32) {
+            self.regs.set_tdr(value)
+        }
+    }
+
+    /// The baud rate measured in internal loopback matches the configured one if each byte takes
+    /// one character time. A clock that runs slow shows as a deviation. Loopback is left
+    /// afterwards.
+    #[kernel_test]
+    fn loopback_baud_matches_configuration() {
+        let regs = LoggingRegisters::new();
+        regs.regs.store(0x18, FR::TXFE::SET.value);
+        let mut inner = PL011UartInner::with_registers(regs);
+        inner.modify_lcrh(LCRH::WLEN::EightBit);
+        assert_eq!(inner.bits_per_char(), 10);
+
+        // The loopback is instant, and the clock is read once per received byte, so it advancing
+        // by one character time per reading models the time on the wire.
+        let char_time = break_duration(10, INIT_BAUD_RATE);
+        let mut uptime = Duration::from_secs(1);
+        let measured = inner
+            .verify_baud_with(|| {
+                uptime += char_time;
+                uptime
+            })
+            .unwrap();
+        assert!(measured <= INIT_BAUD_RATE && measured > INIT_BAUD_RATE * 99 / 100);
+        assert!(!inner.cr.is_set(CR::LBE));
+        assert!(inner.registers.rx_fifo.borrow().is_empty());
+
+        // Waiting input is neither counted nor consumed.
+        inner
+            .registers
+            .rx_fifo
+            .borrow_mut()
+            .push_overwriting(u32::from(b'x'));
+        assert_eq!(
+            inner.verify_baud_with(|| uptime),
+            Err("Baud check: RX FIFO is not empty, resume RX first")
+        );
+        assert_eq!(
+            inner.registers.rx_fifo.borrow_mut().pop(),
+            Some(u32::from(b'x'))
+        );
+        assert!(inner.rx_buffer.is_empty());
+
+        // 5 % slow, beyond the tolerance of 2 %.
+        assert_eq!(
+            inner.verify_baud_with(|| {
+                uptime += char_time * 105 / 100;
+                uptime
+            }),
+            Err("Baud check: measured rate deviates from the configured one")
+        );
+
+        // Nothing comes back with the receive path stuck.
+        inner
+            .registers
+            .regs
+            .store(0x18, (FR::TXFE::SET + FR::TXFF::SET).value);
+        assert_eq!(
+            inner.verify_baud_with(|| {
+                uptime += Duration::from_millis(1);
+                uptime
+            }),
+            Err("Baud check: looped back data got lost")
+        );
+    }
+
+    /// Baud rate and framing change in one transition: disable, divisors, LCRH to latch them,
+    /// enable.
+    #[kernel_test]
+    fn serial_reconfiguration_writes_registers_in_order() {
+        let enabled = (CR::UARTEN::Enabled + CR::TXE::Enabled + CR::RXE::Enabled).value;
+
+        let regs = LoggingRegisters::new();
+        regs.regs.store(0x18, FR::TXFE::SET.value);
+        let mut inner = PL011UartInner::with_registers(regs);
+
+        let two_stop_bits = UartFraming {
+            stop_bits: StopBits::Two,
+        };
+        assert!(inner.reconfigure_serial(0, two_stop_bits).is_err());
+        assert!(inner.registers.writes.borrow().is_empty());
+
+        inner.reconfigure_serial(115_200, two_stop_bits).unwrap();
+
+        let lcrh = LCRH::STP2::Two.value;
+        let mut writes = [(0, 0); 5];
+        assert_eq!(inner.registers.writes.borrow().len(), writes.len());
+        for (slot, write) in writes
+            .iter_mut()
+            .zip(inner.registers.writes.borrow().iter())
+        {
+            *slot = *write;
+        }
+        assert_eq!(
+            writes,
+            [
+                (0x30, 0),
+                (0x24, 26),
+                (0x28, 3),
+                (0x2c, lcrh),
+                (0x30, enabled)
+            ]
+        );
+        assert_eq!(inner.framing(), two_stop_bits);
+        assert_eq!(inner.baud_rate, 115_200);
+    }
+
+    /// A FIFO holding more than a batch is drained in several batches, with all bytes buffered in
+    /// the order they arrived. In character mode, only a single byte is taken.
+    #[kernel_test]
+    fn full_rx_fifo_is_drained_in_batches() {
+        let regs = LoggingRegisters::new();
+        regs.regs.store(0x18, FR::TXFE::SET.value);
+        for byte in b'a'..=b'p' {
+            regs.rx_fifo.borrow_mut().push(u32::from(byte)).unwrap();
+        }
+        let mut inner = PL011UartInner::with_registers(regs);
+        inner.fifos_enabled = true;
+
+        inner.buffer_rx_fifo();
+        assert!(inner.registers.rx_fifo.borrow().is_empty());
+        assert_eq!(inner.registers.regs.get(0x00), u32::from(b'p'));
+        assert_eq!(inner.rx_buffer.len(), 16);
+        for byte in b'a'..=b'p' {
+            assert_eq!(inner.pop_rx_byte(), Some(byte));
+        }
+
+        inner
+            .registers
+            .rx_fifo
+            .borrow_mut()
+            .push(u32::from(b'x'))
+            .unwrap();
+        inner
+            .registers
+            .rx_fifo
+            .borrow_mut()
+            .push(u32::from(b'y'))
+            .unwrap();
+        inner.fifos_enabled = false;
+
+        inner.buffer_rx_fifo();
+        assert_eq!(inner.pop_rx_byte(), Some(b'x'));
+        assert_eq!(inner.pop_rx_byte(), None);
+    }
+
+    /// The register map follows the register block: DR is read-write at its start, and all
+    /// entries are 32 bit wide, ordered and within the block.
+    #[kernel_test]
+    fn register_map_matches_register_block() {
+        let dr = REGISTER_MAP.iter().find(|x| x.name == "DR").unwrap();
+        assert_eq!(dr.offset, 0x00);
+        assert_eq!(dr.access, RegisterAccess::ReadWrite);
+
+        let fr = REGISTER_MAP.iter().find(|x| x.name == "FR").unwrap();
+        assert_eq!((fr.offset, fr.access), (0x18, RegisterAccess::ReadOnly));
+
+        assert_eq!(REGISTER_MAP.len(), 16);
+        for (prev, next) in REGISTER_MAP.iter().zip(REGISTER_MAP.iter().skip(1)) {
+            assert!(prev.offset < next.offset);
+        }
+        for desc in REGISTER_MAP {
+            assert_eq!(desc.width, 32);
+            assert!(desc.offset + 4 <= core::mem::size_of::<RegisterBlock>());
+        }
+    }
+
+    /// Resetting the FIFOs leaves nothing to read and no receive errors.
+    #[kernel_test]
+    fn reset_fifos_empties_rx_and_clears_errors() {
+        let regs = LoggingRegisters::new();
+        regs.regs.store(0x18, FR::TXFE::SET.value);
+        regs.regs.store(0x04, 0b1111);
+        for byte in b"abc" {
+            regs.rx_fifo.borrow_mut().push(u32::from(*byte)).unwrap();
+        }
+        let mut inner = PL011UartInner::with_registers(regs);
+        let slot = RxSlot {
+            byte: b'x',
+            flags: 0,
+            timestamp: Duration::from_secs(0),
+        };
+        assert!(inner.rx_buffer.push(slot).is_ok());
+
+        inner.reset_fifos();
+        assert!(inner.registers.fr().is_set(FR::RXFE));
+        assert_eq!(inner.registers.regs.get(0x04), 0);
+        assert_eq!(inner.read_raw(BlockingMode::NonBlocking), None);
+        assert_eq!(inner.pop_rx_byte(), None);
+    }
+
+    /// A custom reference clock changes the divisors for the same baud rate. Implausibly low
+    /// clocks are rejected.
+    #[kernel_test]
+    fn custom_ref_clock_changes_divisors() {
+        let mut mock = MockRegisters::new();
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+        mock.set(0x18, FR::TXFE::SET.value | FR::RXFE::SET.value);
+
+        assert!(UartConfig::new(115_200).with_ref_clock_hz(0).is_err());
+        assert!(UartConfig::new(115_200)
+            .with_ref_clock_hz(MIN_REF_CLOCK_HZ - 1)
+            .is_err());
+
+        inner.reconfigure(UartConfig::new(115_200)).unwrap();
+        assert_eq!((mock.get(0x24), mock.get(0x28)), (26, 3));
+
+        // 3 MHz / (16 * 115200) = 1.627, with 0.627 * 64 rounding to 40.
+        let config = UartConfig::new(115_200)
+            .with_ref_clock_hz(3_000_000)
+            .unwrap();
+        inner.reconfigure(config).unwrap();
+        assert_eq!((mock.get(0x24), mock.get(0x28)), (1, 40));
+
+        // Kept for later configs that leave the clock out.
+        inner.reconfigure(UartConfig::new(57_600)).unwrap();
+        assert_eq!((mock.get(0x24), mock.get(0x28)), (3, 16));
+        assert_eq!(inner.ref_clock_hz, 3_000_000);
+
+        let bad = UartConfig {
+            baud_rate: 57_600,
+            ref_clock_hz: Some(0),
+        };
+        assert!(inner.reconfigure(bad).is_err());
+        assert_eq!(inner.ref_clock_hz, 3_000_000);
+    }
+
+    /// A reference clock that is set before init is used for the divisors of `init()`.
+    #[kernel_test]
+    fn ref_clock_set_before_init_drives_init_divisors() {
+        let mut mock = MockRegisters::new();
+        mock.set(0x18, FR::TXFE::SET.value | FR::RXFE::SET.value);
+        let uart = mock_uart(&mut mock);
+
+        assert!(uart.set_ref_clock_hz(MIN_REF_CLOCK_HZ - 1).is_err());
+        assert_eq!(uart.set_ref_clock_hz(6_000_000), Ok(()));
+
+        // 6 MHz / (16 * 230400) = 1.628, with 0.628 * 64 rounding to 40.
+        uart.inner
+            .lock(|inner| unsafe { inner.init(None) })
+            .unwrap();
+        assert_eq!((mock.get(0x24), mock.get(0x28)), (1, 40));
+    }
+
+    /// Only the UART behind the console is the active console, not another instance.
+    #[kernel_test]
+    fn only_console_uart_is_active_console() {
+        assert!(bsp::console::uart().is_active_console());
+
+        let mut mock = MockRegisters::new();
+        let uart = mock_uart(&mut mock);
+        assert!(!uart.is_active_console());
+    }
+
+    /// Under the retry policy, an errored byte is answered with a NAK instead of being delivered,
+    /// for up to the maximum number of retries in a row.
+    #[kernel_test]
+    fn rx_error_is_answered_with_nak() {
+        const NAK: u8 = 0x15;
+
+        let regs = LoggingRegisters::new();
+        regs.regs.store(0x18, FR::TXFE::SET.value);
+        for raw in [u32::from(DR_FE) | 0x61, 0x62].iter() {
+            regs.rx_fifo.borrow_mut().push(*raw).unwrap();
+        }
+        let mut inner = PL011UartInner::with_registers(regs);
+        inner.rx_error_retry = Some(RxErrorRetry {
+            nak: Some(NAK),
+            max_retries: 2,
         });
 
-        Ok(())
+        assert_eq!(inner.read_raw(BlockingMode::NonBlocking), Some(0x62));
+        assert_eq!(inner.chars_written, 1);
+        assert_eq!(inner.registers.regs.get(0x00), u32::from(NAK));
+
+        // The third parity error in a row is delivered.
+        let parity_error = u32::from(DR_PE) | 0x63;
+        for _ in 0..3 {
+            inner
+                .registers
+                .rx_fifo
+                .borrow_mut()
+                .push(parity_error)
+                .unwrap();
+        }
+        assert_eq!(
+            inner.read_raw(BlockingMode::NonBlocking),
+            Some(parity_error as u16)
+        );
+        assert_eq!(inner.chars_written, 3);
+        assert_eq!(inner.read_raw(BlockingMode::NonBlocking), None);
+    }
+
+    /// The RX dump starts with the oldest byte, also after the software buffer wrapped around.
+    #[kernel_test]
+    fn rx_ring_dump_is_in_logical_order() {
+        let mut inner = PL011UartInner::with_registers(MockRegisters::new());
+        let slot = |byte| RxSlot {
+            byte,
+            flags: 0,
+            timestamp: Duration::from_secs(0),
+        };
+
+        // Move the start of the ring to 8 bytes before its end.
+        for _ in 0..RX_BUFFER_SIZE - 8 {
+            assert!(inner.rx_buffer.push(slot(0)).is_ok());
+            inner.rx_buffer.pop();
+        }
+        for byte in b"0123456789abcdefXY\r".iter() {
+            assert!(inner.rx_buffer.push(slot(*byte)).is_ok());
+        }
+
+        let mut out = console::FmtBuf::<256>::new();
+        fmt::Write::write_fmt(&mut out, format_args!("{}", inner.rx_ring_dump())).unwrap();
+        assert_eq!(
+            out.as_str(),
+            "0000: 30 31 32 33 34 35 36 37 38 39 61 62 63 64 65 66  |0123456789abcdef|\n\
+             0010: 58 59 0d                                         |XY.|\n"
+        );
+        assert_eq!(inner.rx_buffer.len(), 19);
+    }
+
+    /// Console output while the UART is locked goes to the data register directly instead of
+    /// through the driver, or nowhere if configured so.
+    #[kernel_test]
+    fn reentrant_write_falls_back_to_raw_path() {
+        use console::interface::Write;
+
+        let mut mock = MockRegisters::new();
+        let uart = mock_uart(&mut mock);
+        uart.virt_mmio_start_addr
+            .store(mock.start_addr(), Ordering::Release);
+
+        uart.inner.lock(|_| {
+            assert!(uart.inner.is_locked());
+            uart.write_fmt(format_args!("{}", 'x')).unwrap();
+        });
+        assert!(!uart.inner.is_locked());
+        assert_eq!(mock.get(0x00), u32::from(b'x'));
+        assert_eq!(uart.inner.lock(|inner| inner.chars_written), 0);
+
+        uart.set_reentrant_write(ReentrantWrite::Drop);
+        uart.inner.lock(|_| uart.write_byte(b'y'));
+        assert_eq!(mock.get(0x00), u32::from(b'x'));
+    }
+
+    /// NUL bytes are dropped during the grace window after init, and delivered after it. Without
+    /// a configured window, they are always delivered.
+    #[kernel_test]
+    fn nul_bytes_in_connect_grace_are_dropped() {
+        let mut mock = MockRegisters::new();
+        mock.set(0x18, FR::TXFE::SET.value);
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+
+        assert_eq!(inner.read_raw(BlockingMode::NonBlocking), Some(0));
+
+        // Two NULs are read and dropped, the third one stands.
+        inner.connect_grace = Some(ConnectGrace::Bytes(2));
+        inner.open_grace_window_at(Duration::from_secs(0));
+        let chars_read = inner.chars_read;
+        assert_eq!(inner.read_raw(BlockingMode::NonBlocking), Some(0));
+        assert_eq!(inner.chars_read, chars_read + 3);
+        assert_eq!(inner.read_raw(BlockingMode::NonBlocking), Some(0));
+
+        let t0 = Duration::from_secs(1);
+        inner.connect_grace = Some(ConnectGrace::Duration(Duration::from_millis(10)));
+        inner.open_grace_window_at(t0);
+        assert!(inner.swallow_in_grace_at(0, t0 + Duration::from_millis(9)));
+        assert!(!inner.swallow_in_grace_at(u16::from(b'a'), t0 + Duration::from_millis(9)));
+        assert!(!inner.swallow_in_grace_at(0, t0 + Duration::from_millis(10)));
+        assert!(inner.grace_window.is_none());
+    }
+
+    /// After init, the RX and RX timeout interrupts are unmasked, and the TX interrupt is not.
+    #[kernel_test]
+    fn init_enables_rx_interrupts_only() {
+        let mut mock = MockRegisters::new();
+        mock.set(0x18, FR::TXFE::SET.value | FR::RXFE::SET.value);
+        mock.set(0x38, 0x7ff);
+        let mut inner = unsafe { PL011UartInner::new(mock.start_addr()) };
+
+        assert_eq!(unsafe { inner.init(None) }, Ok(()));
+        assert_eq!(
+            inner.enabled_interrupts(),
+            EnabledIrqs {
+                rx: true,
+                rx_timeout: true,
+                tx: false,
+                cts: false,
+            }
+        );
+
+        inner.pause_rx();
+        assert_eq!(
+            inner.enabled_interrupts(),
+            EnabledIrqs {
+                rx: false,
+                rx_timeout: false,
+                tx: false,
+                cts: false,
+            }
+        );
+    }
+
+    /// Registers are accessed by offset, as long as it is a word-aligned one within the block.
+    #[cfg(feature = "register_poke")]
+    #[kernel_test]
+    fn register_poke_checks_offset() {
+        let mut mock = MockRegisters::new();
+        mock.set(0x34, 0x12);
+        let uart = mock_uart(&mut mock);
+
+        assert_eq!(uart.peek_register(0x34), Ok(0x12));
+        assert_eq!(unsafe { uart.poke_register(0x8c, 0xa5) }, Ok(()));
+        assert_eq!(mock.get(0x8c), 0xa5);
+
+        assert_eq!(
+            uart.peek_register(0x90),
+            Err("Register offset is beyond the register block")
+        );
+        assert_eq!(
+            unsafe { uart.poke_register(usize::MAX, 0) },
+            Err("Register offset is beyond the register block")
+        );
+        assert_eq!(
+            uart.peek_register(0x35),
+            Err("Register offset is not word-aligned")
+        );
     }
 }
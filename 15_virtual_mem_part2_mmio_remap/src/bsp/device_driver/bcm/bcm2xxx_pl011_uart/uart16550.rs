@@ -0,0 +1,227 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! 16550 compatibility shim.
+//!
+//! Lets code that was written against the registers of a 16550 UART drive the PL011. The 16550
+//! registers are addressed by their offset, and each access is mapped onto the PL011 registers:
+//!
+//! | Offset | 16550    | PL011                                                            |
+//! |--------|----------|------------------------------------------------------------------|
+//! | 0      | RBR, THR | DR. Reads return the data without the error flags.               |
+//! | 1      | IER      | IMSC. ERBFI is RXIM and RTIM, ETBEI is TXIM.                     |
+//! | 5      | LSR      | FR for the FIFO state, RSRECR for the errors, which reads clear. |
+//!
+//! # Differences
+//!
+//! - The PL011 keeps the line settings and the divisors in registers of their own, which are
+//!   programmed in a different way. IIR, FCR, LCR, MCR, MSR, SCR and the divisor latch have no
+//!   equivalent here and return an error instead. So do the line status and modem status interrupts
+//!   of IER, and IER writes that enable them.
+//! - THRE and TEMT follow the 16550 with its FIFOs enabled: THRE is set once the TX FIFO is empty,
+//!   TEMT once the last bit left the UART as well.
+//! - LSR bit 7, the error in the RX FIFO, is never set. The PL011 cannot tell without reading the
+//!   FIFO.
+//! - Accesses go straight to the registers, around the driver. Its statistics and buffers do not
+//!   see them.
+
+use super::{UartRegisters, FR, IMSC};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// 16550 register offsets.
+const RBR_THR: usize = 0;
+const IER: usize = 1;
+const LSR: usize = 5;
+
+/// 16550 IER bits.
+const IER_ERBFI: u8 = 1 << 0;
+const IER_ETBEI: u8 = 1 << 1;
+
+/// 16550 LSR bits.
+const LSR_DR: u8 = 1 << 0;
+const LSR_OE: u8 = 1 << 1;
+const LSR_PE: u8 = 1 << 2;
+const LSR_FE: u8 = 1 << 3;
+const LSR_BI: u8 = 1 << 4;
+const LSR_THRE: u8 = 1 << 5;
+const LSR_TEMT: u8 = 1 << 6;
+
+/// PL011 RSRECR bits.
+const RSRECR_FE: u32 = 1 << 0;
+const RSRECR_PE: u32 = 1 << 1;
+const RSRECR_BE: u32 = 1 << 2;
+const RSRECR_OE: u32 = 1 << 3;
+
+/// The 16550 registers are 8 bytes.
+const NUM_REGISTERS: usize = 8;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Accesses the PL011 registers as if they were those of a 16550. See the [module
+/// documentation](self).
+pub struct Uart16550Shim<'a, T: UartRegisters> {
+    registers: &'a T,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Returns an error for offsets that are not those of a 16550 register, or whose register has no
+/// equivalent.
+fn unsupported(offset: usize) -> &'static str {
+    if offset >= NUM_REGISTERS {
+        return "Offset is beyond the 16550 registers";
+    }
+
+    "16550 register has no PL011 equivalent"
+}
+
+impl<'a, T: UartRegisters> Uart16550Shim<'a, T> {
+    fn read_lsr(&self) -> u8 {
+        let fr = self.registers.fr();
+        let errors = self.registers.rsrecr();
+
+        // On the 16550, reading LSR clears the error bits.
+        if errors != 0 {
+            self.registers.set_rsrecr(0);
+        }
+
+        let mut lsr = 0;
+        for (set, bit) in [
+            (!fr.is_set(FR::RXFE), LSR_DR),
+            (errors & RSRECR_OE != 0, LSR_OE),
+            (errors & RSRECR_PE != 0, LSR_PE),
+            (errors & RSRECR_FE != 0, LSR_FE),
+            (errors & RSRECR_BE != 0, LSR_BI),
+            (fr.is_set(FR::TXFE), LSR_THRE),
+            (fr.is_set(FR::TXFE) && !fr.is_set(FR::BUSY), LSR_TEMT),
+        ]
+        .iter()
+        {
+            if *set {
+                lsr |= bit;
+            }
+        }
+
+        lsr
+    }
+
+    fn read_ier(&self) -> u8 {
+        let imsc = self.registers.imsc();
+
+        let mut ier = 0;
+        if imsc.is_set(IMSC::RXIM) || imsc.is_set(IMSC::RTIM) {
+            ier |= IER_ERBFI;
+        }
+        if imsc.is_set(IMSC::TXIM) {
+            ier |= IER_ETBEI;
+        }
+
+        ier
+    }
+
+    fn write_ier(&self, value: u8) -> Result<(), &'static str> {
+        if value & !(IER_ERBFI | IER_ETBEI) != 0 {
+            return Err("16550 interrupt has no PL011 equivalent");
+        }
+
+        let mut imsc = self.registers.imsc();
+        imsc.modify(if value & IER_ERBFI != 0 {
+            IMSC::RXIM::Enabled + IMSC::RTIM::Enabled
+        } else {
+            IMSC::RXIM::Disabled + IMSC::RTIM::Disabled
+        });
+        imsc.modify(if value & IER_ETBEI != 0 {
+            IMSC::TXIM::Enabled
+        } else {
+            IMSC::TXIM::Disabled
+        });
+        self.registers.set_imsc(imsc.get());
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl<'a, T: UartRegisters> Uart16550Shim<'a, T> {
+    /// Create an instance.
+    pub const fn new(registers: &'a T) -> Self {
+        Self { registers }
+    }
+
+    /// Read the 16550 register at `offset`.
+    pub fn read(&self, offset: usize) -> Result<u8, &'static str> {
+        match offset {
+            RBR_THR => Ok(self.registers.dr() as u8),
+            IER => Ok(self.read_ier()),
+            LSR => Ok(self.read_lsr()),
+            _ => Err(unsupported(offset)),
+        }
+    }
+
+    /// Write `value` to the 16550 register at `offset`.
+    pub fn write(&self, offset: usize, value: u8) -> Result<(), &'static str> {
+        match offset {
+            RBR_THR => {
+                self.registers.set_dr(u32::from(value));
+                Ok(())
+            }
+            IER => self.write_ier(value),
+            _ => Err(unsupported(offset)),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bsp::device_driver::bcm::bcm2xxx_pl011_uart::tests::MockRegisters;
+    use test_macros::kernel_test;
+
+    /// LSR reflects the FIFO state in FR and the errors in RSRECR, which reading it clears.
+    #[kernel_test]
+    fn line_status_maps_flag_register() {
+        let mut mock = MockRegisters::new();
+        mock.set(0x18, FR::TXFE::SET.value);
+        mock.set(0x04, RSRECR_OE | RSRECR_FE);
+        let shim = Uart16550Shim::new(&mock);
+
+        assert_eq!(
+            shim.read(LSR),
+            Ok(LSR_DR | LSR_OE | LSR_FE | LSR_THRE | LSR_TEMT)
+        );
+        assert_eq!(shim.read(LSR), Ok(LSR_DR | LSR_THRE | LSR_TEMT));
+
+        // The last byte is still being sent.
+        mock.set(
+            0x18,
+            FR::RXFE::SET.value | FR::TXFE::SET.value | FR::BUSY::SET.value,
+        );
+        let shim = Uart16550Shim::new(&mock);
+        assert_eq!(shim.read(LSR), Ok(LSR_THRE));
+
+        mock.set(0x18, FR::RXFE::SET.value | FR::TXFF::SET.value);
+        let shim = Uart16550Shim::new(&mock);
+        assert_eq!(shim.read(LSR), Ok(0));
+
+        assert!(shim.read(6).is_err());
+        assert!(shim.read(NUM_REGISTERS).is_err());
+        assert!(shim.write(IER, 1 << 2).is_err());
+        shim.write(IER, IER_ERBFI).unwrap();
+        assert_eq!(shim.read(IER), Ok(IER_ERBFI));
+    }
+}
@@ -0,0 +1,184 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Power Management (PM) Driver.
+//!
+//! Only the watchdog part is used, which is the only way to reset the board from software.
+
+use crate::{
+    bsp::device_driver::common::MMIODerefWrapper, cpu, driver, memory, memory::mmu::Physical,
+    synchronization, synchronization::InitStateLock,
+};
+use register::{mmio::*, register_bitfields, register_structs, LocalRegisterCopy};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+// PM registers.
+//
+// There is no official documentation of this block. The definitions follow the Linux
+// `bcm2835_wdt` driver.
+register_bitfields! {
+    u32,
+
+    /// Reset Control
+    PM_RSTC [
+        /// Writes are ignored unless the password is present.
+        PASSWD OFFSET(24) NUMBITS(8) [
+            Value = 0x5a
+        ],
+
+        /// Reset type to perform when the watchdog expires.
+        WRCFG OFFSET(4) NUMBITS(2) [
+            Clear = 0b00,
+            FullReset = 0b10
+        ]
+    ],
+
+    /// Watchdog
+    PM_WDOG [
+        /// Writes are ignored unless the password is present.
+        PASSWD OFFSET(24) NUMBITS(8) [
+            Value = 0x5a
+        ],
+
+        /// Watchdog timeout in ticks of roughly 16 µs.
+        TIME_SET OFFSET(0) NUMBITS(20) []
+    ]
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    RegisterBlock {
+        (0x00 => _reserved1),
+        (0x1c => PM_RSTC: ReadWrite<u32, PM_RSTC::Register>),
+        (0x20 => PM_RSTS: ReadWrite<u32>),
+        (0x24 => PM_WDOG: ReadWrite<u32, PM_WDOG::Register>),
+        (0x28 => @END),
+    }
+}
+
+/// Abstraction for the associated MMIO registers.
+type Registers = MMIODerefWrapper<RegisterBlock>;
+
+/// Watchdog ticks until the reset triggers.
+const RESET_TIMEOUT_TICKS: u32 = 10;
+
+/// A single password-protected register write.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum PMWrite {
+    Wdog(u32),
+    Rstc(u32),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Representation of the PM HW.
+pub struct PowerManagement {
+    phys_mmio_descriptor: memory::mmu::MMIODescriptor<Physical>,
+    registers: InitStateLock<Registers>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// The register writes that trigger a full reset, in order.
+///
+/// The watchdog is armed first, then the reset type is selected, which starts the countdown.
+/// `current_rstc` is needed because the unrelated bits of `PM_RSTC` must be preserved.
+fn full_reset_sequence(current_rstc: u32) -> [PMWrite; 2] {
+    let mut rstc = LocalRegisterCopy::<u32, PM_RSTC::Register>::new(current_rstc);
+    rstc.modify(PM_RSTC::PASSWD::Value + PM_RSTC::WRCFG::FullReset);
+
+    let mut wdog = LocalRegisterCopy::<u32, PM_WDOG::Register>::new(0);
+    wdog.modify(PM_WDOG::PASSWD::Value + PM_WDOG::TIME_SET.val(RESET_TIMEOUT_TICKS));
+
+    [PMWrite::Wdog(wdog.get()), PMWrite::Rstc(rstc.get())]
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl PowerManagement {
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide correct MMIO descriptors.
+    pub const unsafe fn new(phys_mmio_descriptor: memory::mmu::MMIODescriptor<Physical>) -> Self {
+        Self {
+            phys_mmio_descriptor,
+            registers: InitStateLock::new(Registers::new(
+                phys_mmio_descriptor.start_addr().into_usize(),
+            )),
+        }
+    }
+
+    /// Reset the board using the watchdog.
+    pub fn reset(&self) -> ! {
+        self.registers.read(|regs| {
+            for write in full_reset_sequence(regs.PM_RSTC.get()).iter() {
+                match *write {
+                    PMWrite::Wdog(x) => regs.PM_WDOG.set(x),
+                    PMWrite::Rstc(x) => regs.PM_RSTC.set(x),
+                }
+            }
+        });
+
+        // The reset hits within microseconds.
+        cpu::wait_forever()
+    }
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+use synchronization::interface::ReadWriteEx;
+
+impl driver::interface::DeviceDriver for PowerManagement {
+    fn compatible(&self) -> &'static str {
+        "BCM Power Management"
+    }
+
+    #[cfg(feature = "mmio_counters")]
+    fn mmio_accesses(&self) -> usize {
+        self.registers.read(|regs| regs.accesses())
+    }
+
+    unsafe fn init(&self) -> Result<(), driver::DriverError> {
+        let registers =
+            memory::mmu::map_mmio_registers(self.compatible(), &self.phys_mmio_descriptor)?;
+
+        self.registers.write(|regs| *regs = registers);
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_macros::kernel_test;
+
+    /// The watchdog is armed before the reset is requested, both with the password set.
+    #[kernel_test]
+    fn full_reset_sequence_order_and_password() {
+        let password = 0x5a00_0000;
+        let unrelated_bits = 0x0000_0103;
+
+        let sequence = full_reset_sequence(0xff00_0030 | unrelated_bits);
+
+        assert_eq!(sequence[0], PMWrite::Wdog(password | RESET_TIMEOUT_TICKS));
+        assert_eq!(sequence[1], PMWrite::Rstc(password | 0x20 | unrelated_bits));
+    }
+}
@@ -6,12 +6,22 @@
 
 use core::{marker::PhantomData, ops};
 
+#[cfg(feature = "mmio_counters")]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
 //--------------------------------------------------------------------------------------------------
 // Public Definitions
 //--------------------------------------------------------------------------------------------------
 
+/// Access to a block of MMIO registers at a fixed address.
+///
+/// With the `mmio_counters` feature, every access to a register through the wrapper is counted.
+/// The register types do their reads and writes behind the wrapper's back, so the two are not
+/// told apart, and a read-modify-write counts once.
 pub struct MMIODerefWrapper<T> {
     start_addr: usize,
+    #[cfg(feature = "mmio_counters")]
+    accesses: AtomicUsize,
     phantom: PhantomData<fn() -> T>,
 }
 
@@ -24,15 +34,78 @@ impl<T> MMIODerefWrapper<T> {
     pub const unsafe fn new(start_addr: usize) -> Self {
         Self {
             start_addr,
+            #[cfg(feature = "mmio_counters")]
+            accesses: AtomicUsize::new(0),
             phantom: PhantomData,
         }
     }
+
+    /// The start address of the wrapped register block.
+    pub const fn start_addr(&self) -> usize {
+        self.start_addr
+    }
+
+    /// The number of register accesses through this instance.
+    #[cfg(feature = "mmio_counters")]
+    pub fn accesses(&self) -> usize {
+        self.accesses.load(Ordering::Relaxed)
+    }
 }
 
 impl<T> ops::Deref for MMIODerefWrapper<T> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
+        #[cfg(feature = "mmio_counters")]
+        self.accesses.fetch_add(1, Ordering::Relaxed);
+
         unsafe { &*(self.start_addr as *const _) }
     }
 }
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(all(test, feature = "mmio_counters"))]
+mod tests {
+    use super::*;
+    use register::{mmio::*, register_bitfields, register_structs};
+    use test_macros::kernel_test;
+
+    register_bitfields! {
+        u32,
+
+        A [
+            FLAG OFFSET(4) NUMBITS(1) []
+        ]
+    }
+
+    register_structs! {
+        #[allow(non_snake_case)]
+        RegisterBlock {
+            (0x00 => A: ReadWrite<u32, A::Register>),
+            (0x04 => B: ReadWrite<u32>),
+            (0x08 => @END),
+        }
+    }
+
+    /// Every register access through the wrapper is counted once.
+    #[kernel_test]
+    fn register_accesses_are_counted() {
+        let mut memory = [0u32; 2];
+        let regs: MMIODerefWrapper<RegisterBlock> =
+            unsafe { MMIODerefWrapper::new(memory.as_mut_ptr() as usize) };
+        assert_eq!(regs.accesses(), 0);
+
+        regs.A.set(1);
+        regs.B.set(2);
+        assert_eq!(regs.A.get() + regs.B.get(), 3);
+        assert_eq!(regs.accesses(), 4);
+
+        // A read-modify-write goes through a single access.
+        regs.A.modify(A::FLAG::SET);
+        assert_eq!(regs.accesses(), 5);
+        assert_eq!(memory[0], 0x11);
+    }
+}
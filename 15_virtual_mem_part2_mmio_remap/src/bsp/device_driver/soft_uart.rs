@@ -0,0 +1,207 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Bit-banged software UART, TX only.
+//!
+//! A fallback for debugging when no hardware UART is usable, e.g. because its pins are taken.
+//! Frames are 8N1: a low start bit, eight data bits LSB first and a high stop bit. The bit timing
+//! comes from busy waiting, so it is slow and keeps the CPU busy for the whole transmission.
+
+use crate::{console, exception, time::interface::TimeManager};
+use core::{fmt, time::Duration};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Start bit, eight data bits and stop bit.
+const FRAME_BITS: usize = 10;
+
+/// Line levels of a frame, in the order they are sent. `true` is high.
+type Frame = [bool; FRAME_BITS];
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A pin that can be driven high or low.
+pub trait OutputPin {
+    /// Set the pin's level. `true` is high.
+    fn set_level(&self, high: bool);
+}
+
+/// Representation of a software UART.
+pub struct SoftUart<P: OutputPin, T: 'static + TimeManager> {
+    pin: P,
+    timer: &'static T,
+    baud_rate: u32,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn frame(byte: u8) -> Frame {
+    let mut frame = [false; FRAME_BITS];
+
+    for (i, level) in frame[1..9].iter_mut().enumerate() {
+        *level = byte & (1 << i) != 0;
+    }
+    frame[FRAME_BITS - 1] = true;
+
+    frame
+}
+
+impl<P: OutputPin, T: TimeManager> SoftUart<P, T> {
+    /// The time from the leading edge of the start bit to the end of bit `n` of a frame.
+    fn bit_end(&self, n: usize) -> Duration {
+        Duration::from_nanos((n as u64 + 1) * 1_000_000_000 / u64::from(self.baud_rate))
+    }
+
+    fn send_byte(&self, byte: u8) {
+        // Every edge has a deadline relative to the start bit, so that the time spent between the
+        // spins and the rounding of the bit time do not add up over the frame. A late edge still
+        // shortens its bit, so IRQs must not get in between.
+        exception::asynchronous::exec_with_irq_masked(|| {
+            let start = self.timer.uptime();
+
+            for (i, level) in frame(byte).iter().enumerate() {
+                self.pin.set_level(*level);
+
+                let deadline = start + self.bit_end(i);
+                let now = self.timer.uptime();
+                if deadline > now {
+                    self.timer.spin_for(deadline - now);
+                }
+            }
+        });
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl<P: OutputPin, T: TimeManager> SoftUart<P, T> {
+    /// Create an instance that sends on `pin` with `baud_rate`, and drive the line to idle.
+    ///
+    /// Fails if the bit time cannot be resolved by `timer`.
+    pub fn new(pin: P, timer: &'static T, baud_rate: u32) -> Result<Self, &'static str> {
+        if baud_rate == 0 {
+            return Err("Baud rate must not be zero");
+        }
+
+        let bit_time = Duration::from_nanos(1_000_000_000 / u64::from(baud_rate));
+        if bit_time < timer.resolution() {
+            return Err("Baud rate too high for the timer");
+        }
+
+        pin.set_level(true);
+
+        Ok(Self {
+            pin,
+            timer,
+            baud_rate,
+        })
+    }
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+
+impl<P: OutputPin, T: TimeManager> fmt::Write for &SoftUart<P, T> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            console::interface::Write::write_char(*self, c);
+        }
+
+        Ok(())
+    }
+}
+
+impl<P: OutputPin, T: TimeManager> console::interface::Write for SoftUart<P, T> {
+    /// Always blocks, because there is no buffer to queue characters in.
+    fn write_char_mode(&self, c: char, _blocking: bool) -> Result<(), char> {
+        let mut buf = [0; 4];
+
+        for byte in c.encode_utf8(&mut buf).bytes() {
+            self.send_byte(byte);
+        }
+
+        Ok(())
+    }
+
+    fn write_byte(&self, b: u8) {
+        self.send_byte(b);
+    }
+
+    fn write_fmt(&self, args: fmt::Arguments) -> fmt::Result {
+        fmt::Write::write_fmt(&mut &*self, args)
+    }
+
+    /// Nothing to do. A byte is on the wire when `write_byte()` returns.
+    fn flush(&self) {}
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::mock::MockTimer;
+    use console::interface::Write;
+    use core::cell::{Cell, RefCell};
+    use test_macros::kernel_test;
+
+    static TIMER: MockTimer = MockTimer::new(52);
+
+    /// Records each level together with the number of delays that preceded it.
+    struct MockPin {
+        levels: RefCell<[(bool, usize); 16]>,
+        num_levels: Cell<usize>,
+    }
+
+    impl OutputPin for &MockPin {
+        fn set_level(&self, high: bool) {
+            self.levels.borrow_mut()[self.num_levels.get()] = (high, TIMER.spins());
+            self.num_levels.set(self.num_levels.get() + 1);
+        }
+    }
+
+    /// A byte is sent as start bit, data bits LSB first and stop bit, each held for one bit time.
+    /// The bit times do not round down per bit, but over the whole frame.
+    #[kernel_test]
+    fn soft_uart_sends_8n1_frame() {
+        let pin = MockPin {
+            levels: RefCell::new([(false, 0); 16]),
+            num_levels: Cell::new(0),
+        };
+
+        assert!(SoftUart::new(&pin, &TIMER, 0).is_err());
+        assert!(SoftUart::new(&pin, &TIMER, 100_000_000).is_err());
+        assert_eq!(pin.num_levels.get(), 0);
+
+        let uart = SoftUart::new(&pin, &TIMER, 9600).unwrap();
+        assert_eq!(pin.levels.borrow()[0], (true, 0));
+
+        // 0x4b is 0b0100_1011.
+        uart.write_byte(0x4b);
+        let expected = [
+            false, // Start bit.
+            true, true, false, true, false, false, true, false, // Data bits.
+            true,  // Stop bit.
+        ];
+
+        assert_eq!(pin.num_levels.get(), 1 + FRAME_BITS);
+        for (i, level) in expected.iter().enumerate() {
+            assert_eq!(pin.levels.borrow()[1 + i], (*level, i));
+        }
+        assert_eq!(TIMER.spins(), FRAME_BITS);
+        // 10 bits at 9600 baud are 1041666.7 ns, against 10 * 104166 ns per bit.
+        assert_eq!(TIMER.uptime(), Duration::from_nanos(1_041_666));
+    }
+}
@@ -21,13 +21,22 @@ use memory::map::mmio;
 static GPIO: device_driver::GPIO =
     unsafe { device_driver::GPIO::new(MMIODescriptor::new(mmio::GPIO_START, mmio::GPIO_SIZE)) };
 
+static CLOCKS: device_driver::FirmwareClocks = unsafe {
+    device_driver::FirmwareClocks::new(MMIODescriptor::new(mmio::MAILBOX_START, mmio::MAILBOX_SIZE))
+};
+
 static PL011_UART: device_driver::PL011Uart = unsafe {
     device_driver::PL011Uart::new(
         MMIODescriptor::new(mmio::PL011_UART_START, mmio::PL011_UART_SIZE),
         exception::asynchronous::irq_map::PL011_UART,
+        &CLOCKS,
     )
 };
 
+static POWER_MANAGEMENT: device_driver::PowerManagement = unsafe {
+    device_driver::PowerManagement::new(MMIODescriptor::new(mmio::PM_START, mmio::PM_SIZE))
+};
+
 #[cfg(feature = "bsp_rpi3")]
 static INTERRUPT_CONTROLLER: device_driver::InterruptController = unsafe {
     device_driver::InterruptController::new(
@@ -5,9 +5,95 @@
 //! BSP console facilities.
 
 use super::memory;
-use crate::{bsp::device_driver, console, cpu};
+use crate::{
+    bsp::device_driver, console, cpu, synchronization, synchronization::IRQSafeNullLock, time,
+};
 use core::fmt;
 
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The UART used by the panic handler.
+///
+/// Once dropped, it waits until the output has physically left the UART. Otherwise, the message
+/// could be cut off when the core halts, or when the UART is initialized again for the next print.
+///
+/// Holds no UART if the UART is not reachable. Output is dropped then.
+struct PanicConsole(Option<device_driver::PanicUart>);
+
+/// The pin that `set_tx_activity_led()` hands to the UART.
+struct TxActivityLed(IRQSafeNullLock<Option<device_driver::GPIOOutputPin>>);
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static TX_ACTIVITY_LED: TxActivityLed = TxActivityLed(IRQSafeNullLock::new(None));
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl fmt::Write for PanicConsole {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        match self.0 {
+            Some(ref mut uart) => uart.write_str(s),
+            None => Ok(()),
+        }
+    }
+}
+
+impl device_driver::OutputPin for TxActivityLed {
+    fn set_level(&self, high: bool) {
+        use synchronization::interface::Mutex;
+
+        self.0.lock(|pin| {
+            if let Some(pin) = pin {
+                pin.set_level(high);
+            }
+        });
+    }
+}
+
+impl Drop for PanicConsole {
+    fn drop(&mut self) {
+        if let Some(ref uart) = self.0 {
+            uart.drain_tx();
+        }
+    }
+}
+
+/// The MMIO start address under which a device can be reached right now.
+///
+/// Once the driver remapped the MMIO, that is the virtual address. Before the MMU is on, it is the
+/// physical one. In between, there is none, because the physical address would fault.
+fn reachable_mmio_start_addr(virt: Option<usize>, phys: usize) -> Option<usize> {
+    match virt {
+        Some(x) => Some(x),
+        None if !crate::memory::mmu::is_enabled() => Some(phys),
+        None => None,
+    }
+}
+
+/// Set up the panic UART at `uart_mmio_start_addr`.
+///
+/// # Safety
+///
+/// - The user must ensure to provide a correct MMIO start address.
+unsafe fn panic_console_at(uart_mmio_start_addr: Option<usize>) -> PanicConsole {
+    let panic_uart = uart_mmio_start_addr.map(|addr| {
+        let mut panic_uart = device_driver::PanicUart::new(addr);
+
+        panic_uart
+            .init(None)
+            .unwrap_or_else(|_| cpu::wait_forever());
+        panic_uart
+    });
+
+    PanicConsole(panic_uart)
+}
+
 //--------------------------------------------------------------------------------------------------
 // Public Code
 //--------------------------------------------------------------------------------------------------
@@ -19,30 +105,58 @@ use core::fmt;
 /// with synchronization primitives, which increases chances that we get to print something, even
 /// when the kernel's default GPIO or UART instances happen to be locked at the time of the panic.
 ///
+/// If the MMU is on, but the drivers did not remap their MMIO yet, nothing is printed.
+///
 /// # Safety
 ///
 /// - Use only for printing during a panic.
 pub unsafe fn panic_console_out() -> impl fmt::Write {
     use crate::driver::interface::DeviceDriver;
 
-    let mut panic_gpio = device_driver::PanicGPIO::new(memory::map::mmio::GPIO_START.into_usize());
-    let mut panic_uart =
-        device_driver::PanicUart::new(memory::map::mmio::PL011_UART_START.into_usize());
+    let gpio_mmio_start_addr = reachable_mmio_start_addr(
+        super::GPIO.virt_mmio_start_addr(),
+        memory::map::mmio::GPIO_START.into_usize(),
+    );
+    let uart_mmio_start_addr = reachable_mmio_start_addr(
+        super::PL011_UART.virt_mmio_start_addr(),
+        memory::map::mmio::PL011_UART_START.into_usize(),
+    );
 
-    // If remapping of the driver's MMIO already happened, take the remapped start address.
-    // Otherwise, take a chance with the default physical address.
-    let maybe_gpio_mmio_start_addr = super::GPIO.virt_mmio_start_addr();
-    let maybe_uart_mmio_start_addr = super::PL011_UART.virt_mmio_start_addr();
+    // The pins are routed through the GPIO, so without it, the UART is not reachable either.
+    let gpio_mmio_start_addr = match gpio_mmio_start_addr {
+        Some(x) => x,
+        None => return panic_console_at(None),
+    };
 
+    let mut panic_gpio = device_driver::PanicGPIO::new(gpio_mmio_start_addr);
     panic_gpio
-        .init(maybe_gpio_mmio_start_addr)
+        .init(None)
         .unwrap_or_else(|_| cpu::wait_forever());
     panic_gpio.map_pl011_uart();
-    panic_uart
-        .init(maybe_uart_mmio_start_addr)
-        .unwrap_or_else(|_| cpu::wait_forever());
 
-    panic_uart
+    panic_console_at(uart_mmio_start_addr)
+}
+
+/// A writer for the earliest boot messages, before any driver is initialized.
+///
+/// Relies on the firmware having set up the UART, like it does with `enable_uart=1` in
+/// config.txt, and on the GPIO pins being routed to it. There is no locking.
+///
+/// # Safety
+///
+/// - Concurrent use with the kernel's UART instance can interleave or corrupt output.
+///
+/// Returns `None` while the UART is not reachable, i.e. after the MMU is on and before the driver
+/// mapped its registers.
+pub unsafe fn early_console_out() -> Option<impl fmt::Write> {
+    use crate::driver::interface::DeviceDriver;
+
+    let uart_mmio_start_addr = reachable_mmio_start_addr(
+        super::PL011_UART.virt_mmio_start_addr(),
+        memory::map::mmio::PL011_UART_START.into_usize(),
+    )?;
+
+    Some(device_driver::EarlyUart::new(uart_mmio_start_addr))
 }
 
 /// Return a reference to the console.
@@ -50,6 +164,45 @@ pub fn console() -> &'static impl console::interface::All {
     &super::PL011_UART
 }
 
+/// Return a reference to the UART backing the console.
+///
+/// Use this for UART-specific functionality that is not covered by the generic console interface.
+pub fn uart() -> &'static device_driver::PL011Uart {
+    &super::PL011_UART
+}
+
+/// Blink the LED on GPIO `pin` whenever the console sends something. `None` disables it.
+///
+/// A heartbeat for headless debugging. The GPIO driver must be initialized already.
+pub fn set_tx_activity_led(pin: Option<usize>) -> Result<(), &'static str> {
+    use synchronization::interface::Mutex;
+
+    let pin = pin.map(|x| super::GPIO.output_pin(x)).transpose()?;
+    let enabled = pin.is_some();
+
+    super::PL011_UART.set_tx_activity_led(None);
+    TX_ACTIVITY_LED.0.lock(|x| *x = pin);
+    if enabled {
+        super::PL011_UART.set_tx_activity_led(Some(&TX_ACTIVITY_LED));
+    }
+
+    Ok(())
+}
+
+/// Create a bit-banged console that sends on GPIO `pin`.
+///
+/// A fallback for when the PL011 is not usable, e.g. because pins 14 and 15 are taken. It can
+/// only send, and it keeps the CPU busy, with IRQs masked, for the whole time a byte is on the
+/// wire. The GPIO driver must be initialized already.
+pub fn soft_uart_console(
+    pin: usize,
+    baud_rate: u32,
+) -> Result<impl console::interface::Write, &'static str> {
+    let pin = super::GPIO.output_pin(pin)?;
+
+    device_driver::SoftUart::new(pin, time::time_manager(), baud_rate)
+}
+
 //--------------------------------------------------------------------------------------------------
 // Testing
 //--------------------------------------------------------------------------------------------------
@@ -59,3 +212,36 @@ pub fn console() -> &'static impl console::interface::All {
 ///
 /// For the RPi, nothing needs to be done.
 pub fn qemu_bring_up_console() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use console::interface::Statistics;
+    use core::{fmt::Write, ptr};
+    use test_macros::kernel_test;
+
+    /// The panic writer sends straight to the data register. It can be used while the console's
+    /// lock is held, and the console never sees the bytes.
+    #[kernel_test]
+    fn panic_writer_bypasses_console_lock() {
+        // Large enough for the PL011's register block.
+        let mut registers = [0u32; 0x90 / 4];
+        // FR: TX FIFO empty, RX FIFO empty.
+        registers[0x18 / 4] = (1 << 7) | (1 << 4);
+        let addr = registers.as_mut_ptr() as usize;
+        let chars_written = uart().chars_written();
+
+        uart().compact_rx(|_| {
+            let mut writer = unsafe { panic_console_at(Some(addr)) };
+            writer.write_str("ok").unwrap();
+        });
+
+        let dr = unsafe { ptr::read_volatile(registers.as_ptr()) };
+        assert_eq!(dr, u32::from(b'k'));
+        assert_eq!(uart().chars_written(), chars_written);
+
+        // Unreachable UARTs swallow the output.
+        let mut writer = unsafe { panic_console_at(None) };
+        assert!(writer.write_str("lost").is_ok());
+    }
+}
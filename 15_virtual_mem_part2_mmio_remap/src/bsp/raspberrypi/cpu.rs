@@ -10,3 +10,12 @@
 
 /// Used by `arch` code to find the early boot core.
 pub const BOOT_CORE_ID: usize = 0;
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Reset the whole board.
+pub fn board_reset() -> ! {
+    super::POWER_MANAGEMENT.reset()
+}
@@ -12,7 +12,7 @@ use crate::driver;
 
 /// Device Driver Manager type.
 struct BSPDriverManager {
-    device_drivers: [&'static (dyn DeviceDriver + Sync); 3],
+    device_drivers: [&'static (dyn DeviceDriver + Sync); 5],
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -22,8 +22,10 @@ struct BSPDriverManager {
 static BSP_DRIVER_MANAGER: BSPDriverManager = BSPDriverManager {
     device_drivers: [
         &super::GPIO,
+        &super::CLOCKS,
         &super::PL011_UART,
         &super::INTERRUPT_CONTROLLER,
+        &super::POWER_MANAGEMENT,
     ],
 };
 
@@ -47,11 +49,11 @@ impl driver::interface::DriverManager for BSPDriverManager {
     }
 
     fn early_print_device_drivers(&self) -> &[&'static (dyn DeviceDriver + Sync)] {
-        &self.device_drivers[0..=1]
+        &self.device_drivers[0..=2]
     }
 
     fn non_early_print_device_drivers(&self) -> &[&'static (dyn DeviceDriver + Sync)] {
-        &self.device_drivers[2..]
+        &self.device_drivers[3..]
     }
 
     fn post_early_print_device_driver_init(&self) {
@@ -34,6 +34,22 @@
 //! |                                             |
 //! | DATA_END_INCLUSIVE                          | 0x8_0000 + __ro_size + __data_size - 1
 //! +---------------------------------------------+
+//! |                                             |
+//! | Unmapped                                    |
+//! |                                             |
+//! +---------------------------------------------+
+//! | CHAINLOAD_START                             | 0x100_0000
+//! |                                             |
+//! | Mapped on demand for the chainloader        |
+//! |                                             |
+//! | CHAINLOAD_END_INCLUSIVE                     | 0x1FF_FFFF
+//! +---------------------------------------------+
+//! | PERSIST_START                               | 0x200_0000
+//! |                                             |
+//! | Kept across warm reboots                    |
+//! |                                             |
+//! | PERSIST_END_INCLUSIVE                       | 0x200_FFFF
+//! +---------------------------------------------+
 
 pub mod mmu;
 
@@ -64,24 +80,53 @@ pub(super) mod map {
 
     pub const BOOT_CORE_STACK_SIZE:                  usize = 0x1_0000;
 
+    /// Where the chainloader puts received images. Far enough above the kernel binary to not
+    /// overlap with it.
+    pub const CHAINLOAD_START:                       usize = 0x100_0000;
+    pub const CHAINLOAD_SIZE:                        usize = 0x100_0000;
+
+    /// Data that the kernel leaves for the next one across a warm reboot, e.g. the serial
+    /// settings. Above the chainload area, so that a received image cannot overwrite it.
+    pub const PERSIST_START:                         usize = 0x200_0000;
+    pub const PERSIST_SIZE:                          usize = 0x1_0000;
+
     /// Physical devices.
     #[cfg(feature = "bsp_rpi3")]
     pub mod mmio {
         use super::*;
 
+        #[allow(dead_code)] // Only used in const context, which the lint does not see.
+        pub const START:               Address<Physical> = Address::new(0x3F00_0000);
+
         pub const PERIPHERAL_IC_START: Address<Physical> = Address::new(0x3F00_B200);
         pub const PERIPHERAL_IC_SIZE:  usize             =              0x24;
 
+        pub const MAILBOX_START:       Address<Physical> = Address::new(0x3F00_B880);
+        pub const MAILBOX_SIZE:        usize             =              0x3C;
+
+        pub const PM_START:            Address<Physical> = Address::new(0x3F10_0000);
+        pub const PM_SIZE:             usize             =              0x28;
+
         pub const GPIO_START:          Address<Physical> = Address::new(0x3F20_0000);
         pub const GPIO_SIZE:           usize             =              0xA0;
 
         pub const PL011_UART_START:    Address<Physical> = Address::new(0x3F20_1000);
-        pub const PL011_UART_SIZE:     usize             =              0x48;
+        pub const PL011_UART_SIZE:     usize             =              0x90;
 
         pub const LOCAL_IC_START:      Address<Physical> = Address::new(0x4000_0000);
         pub const LOCAL_IC_SIZE:       usize             =              0x100;
 
         pub const END:                 Address<Physical> = Address::new(0x4001_0000);
+
+        #[allow(dead_code)]
+        pub const ALL: [(Address<Physical>, usize); 6] = [
+            (PERIPHERAL_IC_START, PERIPHERAL_IC_SIZE),
+            (MAILBOX_START,       MAILBOX_SIZE),
+            (PM_START,            PM_SIZE),
+            (GPIO_START,          GPIO_SIZE),
+            (PL011_UART_START,    PL011_UART_SIZE),
+            (LOCAL_IC_START,      LOCAL_IC_SIZE),
+        ];
     }
 
     /// Physical devices.
@@ -89,11 +134,20 @@ pub(super) mod map {
     pub mod mmio {
         use super::*;
 
+        #[allow(dead_code)] // Only used in const context, which the lint does not see.
+        pub const START:            Address<Physical> = Address::new(0xFE00_0000);
+
+        pub const MAILBOX_START:    Address<Physical> = Address::new(0xFE00_B880);
+        pub const MAILBOX_SIZE:     usize             =              0x3C;
+
+        pub const PM_START:         Address<Physical> = Address::new(0xFE10_0000);
+        pub const PM_SIZE:          usize             =              0x28;
+
         pub const GPIO_START:       Address<Physical> = Address::new(0xFE20_0000);
         pub const GPIO_SIZE:        usize             =              0xA0;
 
         pub const PL011_UART_START: Address<Physical> = Address::new(0xFE20_1000);
-        pub const PL011_UART_SIZE:  usize             =              0x48;
+        pub const PL011_UART_SIZE:  usize             =              0x90;
 
         pub const GICD_START:       Address<Physical> = Address::new(0xFF84_1000);
         pub const GICD_SIZE:        usize             =              0x824;
@@ -102,6 +156,16 @@ pub(super) mod map {
         pub const GICC_SIZE:        usize             =              0x14;
 
         pub const END:              Address<Physical> = Address::new(0xFF85_0000);
+
+        #[allow(dead_code)]
+        pub const ALL: [(Address<Physical>, usize); 6] = [
+            (MAILBOX_START,    MAILBOX_SIZE),
+            (PM_START,         PM_SIZE),
+            (GPIO_START,       GPIO_SIZE),
+            (PL011_UART_START, PL011_UART_SIZE),
+            (GICD_START,       GICD_SIZE),
+            (GICC_START,       GICC_SIZE),
+        ];
     }
 
     pub const END: Address<Physical> = mmio::END;
@@ -111,6 +175,30 @@ pub(super) mod map {
 // Private Code
 //--------------------------------------------------------------------------------------------------
 
+/// Returns true if all MMIO apertures lie within the board's MMIO window.
+#[allow(dead_code)]
+const fn mmio_apertures_in_window() -> bool {
+    let mut i = 0;
+    while i < map::mmio::ALL.len() {
+        let (start, size) = map::mmio::ALL[i];
+
+        if start.into_usize() < map::mmio::START.into_usize()
+            || start.into_usize() + size > map::mmio::END.into_usize()
+        {
+            return false;
+        }
+        i += 1;
+    }
+
+    true
+}
+
+// Catches addresses of another board, e.g. a Pi 3 UART address in a Pi 4 build.
+const _: () = assert!(
+    mmio_apertures_in_window(),
+    "An MMIO aperture lies outside of the board's MMIO window"
+);
+
 /// Start address of the Read-Only (RO) range.
 ///
 /// # Safety
@@ -169,6 +257,18 @@ fn phys_addr_space_end() -> Address<Physical> {
 // Public Code
 //--------------------------------------------------------------------------------------------------
 
+/// The area that keeps its contents across a warm reboot, as long as the board stays powered.
+///
+/// Identity mapped, like the kernel binary, so it is accessible with the MMU on and off. Its
+/// contents are garbage after a cold boot, so users must tag and check what they put there.
+///
+/// # Safety
+///
+/// - Every call hands out a mutable reference to the same memory. Users must not overlap.
+pub unsafe fn persist_area() -> &'static mut [u8] {
+    core::slice::from_raw_parts_mut(map::PERSIST_START as *mut u8, map::PERSIST_SIZE)
+}
+
 /// Exclusive end address of the boot core's stack.
 #[inline(always)]
 pub fn phys_boot_core_stack_end() -> Address<Physical> {
@@ -192,3 +292,29 @@ pub fn bss_range_inclusive() -> RangeInclusive<*mut u64> {
 
     range
 }
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_macros::kernel_test;
+
+    /// The peripheral addresses are those of the selected board.
+    #[kernel_test]
+    fn mmio_addresses_match_board() {
+        #[cfg(feature = "bsp_rpi3")]
+        let expected_uart_start = 0x3F20_1000;
+
+        #[cfg(feature = "bsp_rpi4")]
+        let expected_uart_start = 0xFE20_1000;
+
+        assert_eq!(
+            map::mmio::PL011_UART_START.into_usize(),
+            expected_uart_start
+        );
+        assert!(mmio_apertures_in_window());
+    }
+}
@@ -9,8 +9,8 @@ use crate::{
     memory::{
         mmu as kernel_mmu,
         mmu::{
-            interface, AccessPermissions, AttributeFields, Granule64KiB, MemAttributes, Page,
-            PageSliceDescriptor, Physical, Virtual,
+            interface, AccessPermissions, Address, AttributeFields, Granule64KiB, MemAttributes,
+            Page, PageSliceDescriptor, Physical, Virtual,
         },
     },
 };
@@ -57,6 +57,20 @@ fn virt_data_page_desc() -> PageSliceDescriptor<Virtual> {
     PageSliceDescriptor::from_addr(super::virt_data_start(), num_pages)
 }
 
+/// The pages that receive a chainloaded image.
+fn virt_chainload_page_desc() -> PageSliceDescriptor<Virtual> {
+    let num_pages = size_to_num_pages(super::map::CHAINLOAD_SIZE);
+
+    PageSliceDescriptor::from_addr(Address::new(super::map::CHAINLOAD_START), num_pages)
+}
+
+/// The pages that are kept across a warm reboot.
+fn virt_persist_page_desc() -> PageSliceDescriptor<Virtual> {
+    let num_pages = size_to_num_pages(super::map::PERSIST_SIZE);
+
+    PageSliceDescriptor::from_addr(Address::new(super::map::PERSIST_START), num_pages)
+}
+
 // The binary is still identity mapped, so we don't need to convert in the following.
 
 /// The boot core's stack.
@@ -125,9 +139,49 @@ pub unsafe fn kernel_map_binary() -> Result<(), &'static str> {
         },
     )?;
 
+    let persist_pages = virt_persist_page_desc();
+    kernel_mmu::kernel_map_pages_at(
+        "Persistent data",
+        &persist_pages.into(),
+        &persist_pages,
+        &AttributeFields {
+            mem_attributes: MemAttributes::CacheableDRAM,
+            acc_perms: AccessPermissions::ReadWrite,
+            execute_never: true,
+        },
+    )?;
+
     Ok(())
 }
 
+/// Map the chainloader's load area and return it as a slice.
+///
+/// The area is identity mapped and executable, because the image is run in place after the MMU
+/// has been switched off.
+///
+/// # Safety
+///
+/// - Must only be called once, because every call hands out an exclusive reference to the area.
+pub unsafe fn kernel_map_chainload_area() -> Result<&'static mut [u8], &'static str> {
+    let virt_pages = virt_chainload_page_desc();
+
+    kernel_mmu::kernel_map_pages_at(
+        "Chainload area",
+        &virt_pages.into(),
+        &virt_pages,
+        &AttributeFields {
+            mem_attributes: MemAttributes::CacheableDRAM,
+            acc_perms: AccessPermissions::ReadWrite,
+            execute_never: false,
+        },
+    )?;
+
+    Ok(core::slice::from_raw_parts_mut(
+        virt_pages.start_addr().into_usize() as *mut u8,
+        virt_pages.size(),
+    ))
+}
+
 //--------------------------------------------------------------------------------------------------
 // Testing
 //--------------------------------------------------------------------------------------------------
@@ -140,7 +194,15 @@ mod tests {
     /// Check alignment of the kernel's virtual memory layout sections.
     #[kernel_test]
     fn virt_mem_layout_sections_are_64KiB_aligned() {
-        for i in [virt_stack_page_desc, virt_ro_page_desc, virt_data_page_desc].iter() {
+        for i in [
+            virt_stack_page_desc,
+            virt_ro_page_desc,
+            virt_data_page_desc,
+            virt_chainload_page_desc,
+            virt_persist_page_desc,
+        ]
+        .iter()
+        {
             let start: usize = i().start_addr().into_usize();
             let end: usize = i().end_addr().into_usize();
 
@@ -157,6 +219,8 @@ mod tests {
             virt_stack_page_desc().into_usize_range_inclusive(),
             virt_ro_page_desc().into_usize_range_inclusive(),
             virt_data_page_desc().into_usize_range_inclusive(),
+            virt_chainload_page_desc().into_usize_range_inclusive(),
+            virt_persist_page_desc().into_usize_range_inclusive(),
         ];
 
         for (i, first_range) in layout.iter().enumerate() {
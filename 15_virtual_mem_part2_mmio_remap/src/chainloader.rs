@@ -0,0 +1,195 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Receive a kernel image over the console and run it.
+//!
+//! The sender transmits, with all numbers in little endian:
+//!
+//! 1. The image size in bytes as `u32`.
+//! 2. The image.
+//! 3. The [`checksum::sum32()`] of the image as `u32`.
+//!
+//! The image is received into the BSP's chainload area, which lies outside of the running kernel.
+//! Therefore, a failed transfer leaves the kernel intact and can simply be retried.
+
+use crate::{bsp, checksum, console, cpu, info};
+use core::fmt;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Reasons for rejecting an image.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ChainloaderError {
+    /// The announced size exceeds the load area. Nothing was received.
+    ImageTooLarge {
+        /// Size announced by the sender.
+        size: usize,
+
+        /// Size of the load area.
+        capacity: usize,
+    },
+
+    /// The received data does not match the checksum sent along with it.
+    ChecksumMismatch {
+        /// Checksum sent along with the image.
+        expected: u32,
+
+        /// Checksum calculated over the received image.
+        received: u32,
+    },
+
+    /// The load area is not available.
+    NoLoadArea(&'static str),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn read_u32(input: &impl console::interface::Read) -> u32 {
+    let mut bytes = [0; 4];
+    for byte in bytes.iter_mut() {
+        *byte = input.read_byte();
+    }
+
+    u32::from_le_bytes(bytes)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl fmt::Display for ChainloaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ImageTooLarge { size, capacity } => write!(
+                f,
+                "Image of {} bytes does not fit into {} bytes",
+                size, capacity
+            ),
+            Self::ChecksumMismatch { expected, received } => write!(
+                f,
+                "Checksum mismatch: expected {:#010x}, received {:#010x}",
+                expected, received
+            ),
+            Self::NoLoadArea(x) => write!(f, "No load area: {}", x),
+        }
+    }
+}
+
+/// Receive an image into `dest`, using blocking reads.
+///
+/// Returns the size of the image.
+pub fn receive(
+    input: &impl console::interface::Read,
+    dest: &mut [u8],
+) -> Result<usize, ChainloaderError> {
+    let size = read_u32(input) as usize;
+    if size > dest.len() {
+        return Err(ChainloaderError::ImageTooLarge {
+            size,
+            capacity: dest.len(),
+        });
+    }
+
+    let image = &mut dest[..size];
+    for byte in image.iter_mut() {
+        *byte = input.read_byte();
+    }
+
+    let expected = read_u32(input);
+    let received = checksum::sum32(image);
+    if received != expected {
+        return Err(ChainloaderError::ChecksumMismatch { expected, received });
+    }
+
+    Ok(size)
+}
+
+/// Receive an image over the kernel's console and jump to it.
+///
+/// Only returns if no valid image was received.
+///
+/// # Safety
+///
+/// - Must only be called once, see `bsp::memory::mmu::kernel_map_chainload_area()`.
+/// - See `cpu::jump_to_image()`.
+pub unsafe fn load_and_run() -> ChainloaderError {
+    use console::interface::{LineDiscipline, Write};
+
+    let load_area = match bsp::memory::mmu::kernel_map_chainload_area() {
+        Ok(x) => x,
+        Err(x) => return ChainloaderError::NoLoadArea(x),
+    };
+
+    info!(
+        "Chainloader: Waiting for an image of up to {} bytes",
+        load_area.len()
+    );
+
+    // Echoing would send the image right back to the sender.
+    let uart = bsp::console::uart();
+    let echo_mode = uart.set_echo_mode(console::EchoMode::Off);
+
+    let size = match receive(bsp::console::console(), load_area) {
+        Ok(x) => x,
+        Err(x) => {
+            uart.set_echo_mode(echo_mode);
+            return x;
+        }
+    };
+
+    info!("Chainloader: Received {} bytes, jumping to the image", size);
+    bsp::console::console().flush();
+
+    // The image continues at the current serial settings, if it restores them.
+    bsp::console::uart().persist_config();
+    cpu::jump_to_image(&load_area[..size])
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use console::mock::MockConsole;
+    use test_macros::kernel_test;
+
+    /// A valid image is received completely, corrupt or oversized ones are rejected.
+    #[kernel_test]
+    fn receive_verifies_image() {
+        // Size 3, image, checksum 0x1 + 0x2 + 0xff = 0x102.
+        let mut transfer = [3, 0, 0, 0, 0x01, 0x02, 0xff, 0x02, 0x01, 0, 0];
+        let mut dest = [0; 4];
+
+        assert_eq!(
+            receive(&MockConsole::with_input(&transfer), &mut dest),
+            Ok(3)
+        );
+        assert_eq!(&dest[..3], &[0x01, 0x02, 0xff]);
+
+        transfer[5] = 0x03;
+        assert_eq!(
+            receive(&MockConsole::with_input(&transfer), &mut dest),
+            Err(ChainloaderError::ChecksumMismatch {
+                expected: 0x102,
+                received: 0x103
+            })
+        );
+
+        let mut small_dest = [0; 2];
+        assert_eq!(
+            receive(&MockConsole::with_input(&transfer), &mut small_dest),
+            Err(ChainloaderError::ImageTooLarge {
+                size: 3,
+                capacity: 2
+            })
+        );
+        assert_eq!(small_dest, [0; 2]);
+    }
+}
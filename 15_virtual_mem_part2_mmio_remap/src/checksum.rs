@@ -0,0 +1,54 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Checksums for data received over unreliable links.
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// The sum of all bytes, wrapping on overflow.
+///
+/// Cheap to compute on both ends of a link. Catches corrupted and dropped bytes, but not bytes
+/// that arrive in the wrong order.
+pub fn sum32(data: &[u8]) -> u32 {
+    data.iter()
+        .fold(0u32, |sum, byte| sum.wrapping_add(u32::from(*byte)))
+}
+
+/// CRC-16 as used by XMODEM (polynomial 0x1021, initial value 0, no reflection).
+pub fn crc16_xmodem(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+
+    for byte in data {
+        crc ^= u16::from(*byte) << 8;
+
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_macros::kernel_test;
+
+    /// The CRC matches the standard check value of the algorithm.
+    #[kernel_test]
+    fn crc16_xmodem_check_value() {
+        assert_eq!(crc16_xmodem(b""), 0);
+        assert_eq!(crc16_xmodem(b"123456789"), 0x31c3);
+    }
+}
@@ -0,0 +1,248 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Fixed-capacity collections.
+//!
+//! The kernel has no heap, so everything in here is backed by storage whose size is known at
+//! compile time.
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A first-in, first-out queue with room for `N` elements.
+///
+/// The buffer never grows. When it is full, [`RingBuffer::push()`] hands the new element back to
+/// the caller, while [`RingBuffer::push_overwriting()`] makes room by dropping the oldest element.
+pub struct RingBuffer<T, const N: usize> {
+    data: [Option<T>; N],
+    head: usize,
+    len: usize,
+}
+
+/// Iterator over the elements of a [`RingBuffer`], from oldest to newest.
+pub struct Iter<'a, T, const N: usize> {
+    ring_buffer: &'a RingBuffer<T, { N }>,
+    pos: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl<T: Copy, const N: usize> RingBuffer<T, { N }> {
+    /// Create an instance.
+    pub const fn new() -> Self {
+        assert!(N > 0);
+
+        Self {
+            data: [None; N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// The maximum number of elements the buffer can hold.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// The number of elements currently stored.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if no elements are stored.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns true if no further element can be pushed without overwriting.
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// Append an element at the back.
+    ///
+    /// If the buffer is full, it is left untouched and the element is returned as the error value.
+    pub fn push(&mut self, item: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(item);
+        }
+
+        let tail = (self.head + self.len) % N;
+        self.data[tail] = Some(item);
+        self.len += 1;
+
+        Ok(())
+    }
+
+    /// Append an element at the back, dropping the oldest element if the buffer is full.
+    ///
+    /// Returns the dropped element, if any.
+    pub fn push_overwriting(&mut self, item: T) -> Option<T> {
+        let overwritten = if self.is_full() { self.pop() } else { None };
+
+        // Cannot fail, there is at least one free slot now.
+        let _ = self.push(item);
+
+        overwritten
+    }
+
+    /// Remove the element at the front.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let item = self.data[self.head].take();
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+
+        item
+    }
+
+    /// Remove all elements.
+    pub fn clear(&mut self) {
+        while self.pop().is_some() {}
+    }
+
+    /// Iterate over the elements, from oldest to newest, without removing them.
+    pub fn iter(&self) -> Iter<T, { N }> {
+        Iter {
+            ring_buffer: self,
+            pos: 0,
+        }
+    }
+}
+
+impl<'a, T: Copy, const N: usize> Iterator for Iter<'a, T, { N }> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.ring_buffer.len {
+            return None;
+        }
+
+        let index = (self.ring_buffer.head + self.pos) % N;
+        self.pos += 1;
+
+        self.ring_buffer.data[index].as_ref()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_macros::kernel_test;
+
+    /// Elements come out in the order they went in.
+    #[kernel_test]
+    fn ring_buffer_is_fifo() {
+        let mut rb: RingBuffer<u8, 4> = RingBuffer::new();
+
+        assert!(rb.is_empty());
+        assert_eq!(rb.pop(), None);
+
+        for i in 0..3 {
+            assert_eq!(rb.push(i), Ok(()));
+        }
+        assert_eq!(rb.len(), 3);
+
+        for i in 0..3 {
+            assert_eq!(rb.pop(), Some(i));
+        }
+        assert!(rb.is_empty());
+        assert_eq!(rb.pop(), None);
+    }
+
+    /// A full buffer rejects new elements and keeps its contents.
+    #[kernel_test]
+    fn ring_buffer_rejects_when_full() {
+        let mut rb: RingBuffer<u8, 2> = RingBuffer::new();
+
+        assert_eq!(rb.push(1), Ok(()));
+        assert_eq!(rb.push(2), Ok(()));
+        assert!(rb.is_full());
+        assert_eq!(rb.push(3), Err(3));
+        assert_eq!(rb.len(), rb.capacity());
+
+        assert_eq!(rb.pop(), Some(1));
+        assert!(!rb.is_full());
+        assert_eq!(rb.push(3), Ok(()));
+        assert_eq!(rb.pop(), Some(2));
+        assert_eq!(rb.pop(), Some(3));
+    }
+
+    /// Ordering is preserved across many wrap-arounds of the internal index.
+    #[kernel_test]
+    fn ring_buffer_wraps_around() {
+        let mut rb: RingBuffer<usize, 3> = RingBuffer::new();
+
+        for i in 0..20 {
+            assert_eq!(rb.push(i), Ok(()));
+            assert_eq!(rb.push(i + 100), Ok(()));
+            assert_eq!(rb.pop(), Some(i));
+            assert_eq!(rb.pop(), Some(i + 100));
+            assert!(rb.is_empty());
+        }
+
+        // Fill completely while the head sits in the middle of the storage.
+        assert_eq!(rb.push(0), Ok(()));
+        assert_eq!(rb.pop(), Some(0));
+        for i in 0..3 {
+            assert_eq!(rb.push(i), Ok(()));
+        }
+        assert!(rb.is_full());
+        for i in 0..3 {
+            assert_eq!(rb.pop(), Some(i));
+        }
+    }
+
+    /// Overwriting drops the oldest element and keeps the remaining order intact.
+    #[kernel_test]
+    fn ring_buffer_push_overwriting() {
+        let mut rb: RingBuffer<u8, 3> = RingBuffer::new();
+
+        for i in 0..3 {
+            assert_eq!(rb.push_overwriting(i), None);
+        }
+        assert_eq!(rb.push_overwriting(3), Some(0));
+        assert_eq!(rb.push_overwriting(4), Some(1));
+        assert_eq!(rb.len(), 3);
+
+        assert_eq!(rb.pop(), Some(2));
+        assert_eq!(rb.pop(), Some(3));
+        assert_eq!(rb.pop(), Some(4));
+
+        rb.push(7).unwrap();
+        rb.clear();
+        assert!(rb.is_empty());
+        assert_eq!(rb.pop(), None);
+    }
+
+    /// Iteration yields the elements in FIFO order and leaves them in place.
+    #[kernel_test]
+    fn ring_buffer_iter() {
+        let mut rb: RingBuffer<u8, 3> = RingBuffer::new();
+        assert_eq!(rb.iter().next(), None);
+
+        for i in 0..5 {
+            rb.push_overwriting(i);
+        }
+
+        let mut iter = rb.iter();
+        assert_eq!(iter.next(), Some(&2));
+        assert_eq!(iter.next(), Some(&3));
+        assert_eq!(iter.next(), Some(&4));
+        assert_eq!(iter.next(), None);
+
+        assert_eq!(rb.len(), 3);
+        assert_eq!(rb.pop(), Some(2));
+    }
+}
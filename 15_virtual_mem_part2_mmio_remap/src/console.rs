@@ -4,25 +4,141 @@
 
 //! System console.
 
+#[cfg(test)]
+mod capture;
+mod fmt_buf;
+pub mod hex_echo;
+pub mod history;
+#[cfg(test)]
+pub mod mock;
+mod rate_limiter;
+mod safe_output;
+mod stats_sampler;
+mod utf8_decoder;
+mod write_coalescer;
+
+#[cfg(test)]
+pub(crate) use capture::record_capture;
+#[cfg(test)]
+pub use capture::{test_capture, Capture, CapturedOutput};
+pub use fmt_buf::FmtBuf;
+pub use rate_limiter::{RateLimitMode, RateLimiter};
+pub use safe_output::{write_escaped, Escaped};
+pub use stats_sampler::StatsSampler;
+pub use utf8_decoder::Utf8Decoder;
+pub use write_coalescer::WriteCoalescer;
+
+use crate::{bsp, time};
+use core::{fmt, time::Duration};
+
 //--------------------------------------------------------------------------------------------------
 // Public Definitions
 //--------------------------------------------------------------------------------------------------
 
+/// How received characters are echoed back.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EchoMode {
+    /// No echo.
+    Off,
+
+    /// Echo every character as it is.
+    Verbatim,
+
+    /// Like `Verbatim`, but control characters other than newline are shown in caret notation,
+    /// e.g. `^C` for `0x03`.
+    Caret,
+}
+
+/// How received bytes are turned into characters.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum InputCodec {
+    /// Every byte is a character of its own, U+0000 to U+00FF.
+    Latin1,
+
+    /// Multi-byte UTF-8 sequences are assembled into single characters, see [`Utf8Decoder`].
+    Utf8,
+}
+
+/// How a call to [`read_line()`] ended. All variants hold the number of bytes stored.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ReadLineStatus {
+    /// A newline arrived.
+    Complete(usize),
+
+    /// A newline arrived, but the line did not fit into the buffer. The rest of it was dropped.
+    Truncated(usize),
+
+    /// The input was idle for too long. The line might be incomplete.
+    TimedOut(usize),
+}
+
+/// Why [`interface::Read::read_exact()`] could not fill its buffer.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ReadError {
+    /// The input was idle for too long. Holds the number of bytes read before.
+    TimedOut(usize),
+}
+
+/// Why a write of a whole slice, e.g. `PL011Uart::write_all()`, did not complete.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WriteError {
+    /// The output did not drain in time. Holds the number of bytes sent before.
+    TimedOut(usize),
+}
+
 /// Console interfaces.
 pub mod interface {
-    use core::fmt;
+    use core::{fmt, time::Duration};
 
     /// Console write functions.
     pub trait Write {
-        /// Write a single character.
-        fn write_char(&self, c: char);
+        /// Write a single character, choosing per call whether to wait for room in the TX path.
+        ///
+        /// In non-blocking mode, the character is handed back if it cannot be accepted right away.
+        fn write_char_mode(&self, c: char, blocking: bool) -> Result<(), char>;
+
+        /// Write a single character, waiting for room if needed.
+        fn write_char(&self, c: char) {
+            let _ = self.write_char_mode(c, true);
+        }
+
+        /// Write a single raw byte, without any encoding or translation.
+        ///
+        /// Meant for binary protocols.
+        fn write_byte(&self, b: u8);
 
         /// Write a Rust format string.
         fn write_fmt(&self, args: fmt::Arguments) -> fmt::Result;
 
+        /// Write `s` followed by a newline.
+        ///
+        /// Both go out in a single `write_fmt()`, so for consoles that lock per call, no other
+        /// writer can get in between. The newline is subject to the console's usual newline
+        /// handling.
+        fn write_line(&self, s: &str) {
+            let _ = self.write_fmt(format_args!("{}\n", s));
+        }
+
         /// Block execution until the last character has been physically put on the TX wire
         /// (draining TX buffers/FIFOs, if any).
         fn flush(&self);
+
+        /// Like `flush()`, but gives up once the uptime reaches `deadline`.
+        ///
+        /// Returns true if all output has been put on the TX wire. Meant for paths that must make
+        /// progress even if the TX side is stuck, e.g. a reboot.
+        fn flush_best_effort(&self, _deadline: Duration) -> bool {
+            self.flush();
+
+            true
+        }
+
+        /// Returns true if characters are still waiting to be put on the TX wire.
+        ///
+        /// Useful before shutting down or sleeping, when output must not be cut off.
+        fn output_pending(&self) -> bool {
+            false
+        }
     }
 
     /// Console read functions.
@@ -32,6 +148,46 @@ pub mod interface {
             ' '
         }
 
+        /// Read a single character, giving up after `timeout`.
+        ///
+        /// Consoles that do not support timeouts block like `read_char()`.
+        fn read_char_timeout(&self, _timeout: Duration) -> Option<char> {
+            Some(self.read_char())
+        }
+
+        /// Read a single byte as it came in, without conversion or echo.
+        ///
+        /// Meant for binary data, e.g. a kernel image.
+        fn read_byte(&self) -> u8;
+
+        /// Read a single raw byte like `read_byte()`, giving up after `timeout`.
+        ///
+        /// Consoles that do not support timeouts block like `read_byte()`.
+        fn read_byte_timeout(&self, _timeout: Duration) -> Option<u8> {
+            Some(self.read_byte())
+        }
+
+        /// Fill `buf` completely with raw bytes, or fail once the input was idle for
+        /// `idle_timeout`, if given.
+        ///
+        /// Meant for fixed-size binary messages. Bytes read before a timeout stay in `buf`.
+        fn read_exact(
+            &self,
+            buf: &mut [u8],
+            idle_timeout: Option<Duration>,
+        ) -> Result<(), super::ReadError> {
+            for (i, byte) in buf.iter_mut().enumerate() {
+                *byte = match idle_timeout {
+                    None => self.read_byte(),
+                    Some(x) => self
+                        .read_byte_timeout(x)
+                        .ok_or(super::ReadError::TimedOut(i))?,
+                };
+            }
+
+            Ok(())
+        }
+
         /// Clear RX buffers, if any.
         fn clear(&self);
     }
@@ -49,6 +205,215 @@ pub mod interface {
         }
     }
 
+    /// Console line discipline.
+    pub trait LineDiscipline {
+        /// Switch the translation of received carriage returns into newlines on or off, e.g. for
+        /// a binary transfer. Returns the previous setting, so that it can be restored.
+        ///
+        /// Consoles that never translate ignore this.
+        fn set_input_translation(&self, _enable: bool) -> bool {
+            false
+        }
+
+        /// Select how received bytes are turned into characters by `read_char()`. Returns the
+        /// previous codec, so that it can be restored.
+        ///
+        /// Consoles that only know [`InputCodec::Latin1`] ignore this.
+        fn set_input_codec(&self, _codec: super::InputCodec) -> super::InputCodec {
+            super::InputCodec::Latin1
+        }
+
+        /// Select how received characters are echoed back. Returns the previous mode, so that it
+        /// can be restored.
+        ///
+        /// Consoles that never echo ignore this.
+        fn set_echo_mode(&self, _mode: super::EchoMode) -> super::EchoMode {
+            super::EchoMode::Off
+        }
+    }
+
     /// Trait alias for a full-fledged console.
-    pub trait All = Write + Read + Statistics;
+    pub trait All = Write + Read + Statistics + LineDiscipline;
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl ReadLineStatus {
+    /// The number of bytes stored.
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Complete(x) | Self::Truncated(x) | Self::TimedOut(x) => *x,
+        }
+    }
+
+    /// Returns true if no bytes were stored.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Return a writer to the UART that the panic handler prints on.
+///
+/// The writer does not take the console's lock, so it works even if the panic struck while the
+/// console was in use. It sets up the UART from scratch and waits for the output to leave it when
+/// dropped. Works both before and after the MMU is on. If the UART is not reachable, output is
+/// dropped.
+///
+/// Meant for the panic handler. Anywhere else, it disturbs the kernel's console.
+pub fn panic_writer() -> impl fmt::Write {
+    // Safety: The BSP picks the address under which the UART is reachable right now.
+    unsafe { bsp::console::panic_console_out() }
+}
+
+/// Read characters into `buf` until a newline arrives, or until the input was idle for
+/// `idle_timeout`, if given.
+///
+/// The newline is not stored. Characters that do not fit into `buf` anymore are dropped up to the
+/// newline, so that the next call starts with the next line. NUL characters, as produced by a
+/// disconnected line, are dropped as well and do not count as activity.
+///
+/// The stored bytes are valid UTF-8.
+pub fn read_line(
+    input: &impl interface::Read,
+    buf: &mut [u8],
+    idle_timeout: Option<Duration>,
+) -> ReadLineStatus {
+    use time::interface::TimeManager;
+
+    let mut len = 0;
+    let mut truncated = false;
+    let deadline_from_now = || idle_timeout.map(|x| time::time_manager().deadline_in(x));
+    let mut deadline = deadline_from_now();
+
+    loop {
+        let c = match deadline {
+            None => input.read_char(),
+            Some(deadline) => {
+                let remaining = deadline
+                    .checked_sub(time::time_manager().uptime())
+                    .unwrap_or_default();
+
+                if remaining == Duration::from_secs(0) {
+                    return ReadLineStatus::TimedOut(len);
+                }
+
+                match input.read_char_timeout(remaining) {
+                    Some(c) => c,
+                    None => return ReadLineStatus::TimedOut(len),
+                }
+            }
+        };
+
+        if c == '\0' {
+            continue;
+        }
+        deadline = deadline_from_now();
+
+        if c == '\n' {
+            if truncated {
+                return ReadLineStatus::Truncated(len);
+            }
+            return ReadLineStatus::Complete(len);
+        }
+
+        // Once a character was dropped, later ones that would still fit must go, too.
+        if !truncated && len + c.len_utf8() <= buf.len() {
+            len += c.encode_utf8(&mut buf[len..]).len();
+        } else {
+            truncated = true;
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::MockConsole;
+    use test_macros::kernel_test;
+
+    /// A line and its newline are written in one call. An empty line is just the newline.
+    #[kernel_test]
+    fn write_line_appends_newline_in_one_call() {
+        use interface::Write;
+
+        let output = MockConsole::new();
+
+        output.write_line("hi");
+        assert_eq!(&*output.output(), b"hi\n");
+        assert_eq!(output.write_fmt_calls(), 1);
+
+        output.write_line("");
+        assert_eq!(&*output.output(), b"hi\n\n");
+        assert_eq!(output.write_fmt_calls(), 2);
+    }
+
+    /// A stall mid-line returns the partial line, marked as timed out.
+    #[kernel_test]
+    fn read_line_times_out_mid_line() {
+        let timeout = Some(Duration::from_secs(1));
+        let input = MockConsole::with_input(b"ls\n-l\0\0");
+        let mut buf = [0; 8];
+
+        assert_eq!(
+            read_line(&input, &mut buf, timeout),
+            ReadLineStatus::Complete(2)
+        );
+        assert_eq!(&buf[..2], b"ls");
+
+        assert_eq!(
+            read_line(&input, &mut buf, timeout),
+            ReadLineStatus::TimedOut(2)
+        );
+        assert_eq!(&buf[..2], b"-l");
+
+        assert_eq!(
+            read_line(&input, &mut buf, timeout),
+            ReadLineStatus::TimedOut(0)
+        );
+    }
+
+    /// An overlong line is reported as truncated, and its rest does not leak into the next line.
+    #[kernel_test]
+    fn read_line_truncates_overlong_line() {
+        let input = MockConsole::with_input("abcdefä\nok\n".as_bytes());
+        let mut buf = [0; 4];
+
+        assert_eq!(
+            read_line(&input, &mut buf, Some(Duration::from_secs(1))),
+            ReadLineStatus::Truncated(4)
+        );
+        assert_eq!(&buf, b"abcd");
+
+        assert_eq!(
+            read_line(&input, &mut buf, Some(Duration::from_secs(1))),
+            ReadLineStatus::Complete(2)
+        );
+        assert_eq!(&buf[..2], b"ok");
+    }
+
+    /// Raw bytes are read until the buffer is full. A stall before reports the bytes read so far.
+    #[kernel_test]
+    fn read_exact_fills_or_times_out() {
+        use interface::Read;
+
+        let timeout = Some(Duration::from_secs(1));
+        let input = MockConsole::with_input(b"\r\n\0abcd");
+
+        let mut buf = [0; 4];
+        assert_eq!(input.read_exact(&mut buf, timeout), Ok(()));
+        assert_eq!(&buf, b"\r\n\0a");
+
+        let mut buf = [0; 4];
+        assert_eq!(
+            input.read_exact(&mut buf, timeout),
+            Err(ReadError::TimedOut(3))
+        );
+        assert_eq!(&buf[..3], b"bcd");
+    }
 }
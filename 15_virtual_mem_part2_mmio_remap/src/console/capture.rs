@@ -0,0 +1,135 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Capturing of printed output, for test assertions.
+
+use super::FmtBuf;
+use crate::{synchronization, synchronization::IRQSafeNullLock};
+use core::fmt;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Maximum number of bytes recorded while any capture is active. The rest is dropped.
+const CAPTURE_SIZE: usize = 1024;
+
+struct CaptureInner {
+    /// Shared by all active captures. Each one owns the part from where it started.
+    buf: FmtBuf<CAPTURE_SIZE>,
+    depth: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The output captured by a [`Capture`].
+pub type CapturedOutput = FmtBuf<CAPTURE_SIZE>;
+
+/// Records everything printed while it lives, see [`test_capture()`].
+pub struct Capture {
+    start: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static CAPTURE: IRQSafeNullLock<CaptureInner> = IRQSafeNullLock::new(CaptureInner {
+    buf: FmtBuf::new(),
+    depth: 0,
+});
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+use synchronization::interface::Mutex;
+
+/// Start recording everything that is printed with `print!()` and friends, until the returned
+/// guard is dropped. The output still goes to the console as well.
+///
+/// Captures nest: one that starts while another is active sees only the output from then on, and
+/// the outer one sees it, too. They must end in the reverse order in which they started. At most
+/// 1024 bytes are recorded, counted from the start of the outermost capture.
+pub fn test_capture() -> Capture {
+    CAPTURE.lock(|inner| {
+        inner.depth += 1;
+
+        Capture {
+            start: inner.buf.as_bytes().len(),
+        }
+    })
+}
+
+/// Record `args` for the active captures, if any.
+pub(crate) fn record_capture(args: fmt::Arguments) {
+    CAPTURE.lock(|inner| {
+        if inner.depth > 0 {
+            // Output beyond the capacity is dropped, as documented.
+            let _ = fmt::Write::write_fmt(&mut inner.buf, args);
+        }
+    });
+}
+
+impl Capture {
+    /// The output recorded so far.
+    pub fn output(&self) -> CapturedOutput {
+        let mut output = CapturedOutput::new();
+
+        CAPTURE.lock(|inner| {
+            // Cannot be truncated, both buffers are of the same size.
+            let _ = fmt::Write::write_str(&mut output, &inner.buf.as_str()[self.start..]);
+        });
+
+        output
+    }
+
+    /// End the capture and return what it recorded.
+    pub fn finish(self) -> CapturedOutput {
+        self.output()
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        CAPTURE.lock(|inner| {
+            inner.depth -= 1;
+            if inner.depth == 0 {
+                inner.buf.clear();
+            }
+        });
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{print, println};
+    use test_macros::kernel_test;
+
+    /// A capture sees what was printed while it was active, a nested one only its own part.
+    #[kernel_test]
+    fn capture_records_printed_output() {
+        let value = 1;
+        let outer = test_capture();
+        println!("a{}", value);
+
+        let inner = test_capture();
+        print!("b");
+        assert_eq!(inner.finish().as_str(), "b");
+
+        println!("c");
+        assert_eq!(outer.output().as_str(), "a1\nbc\n");
+        drop(outer);
+
+        // Nothing is recorded without a capture, so a new one starts out empty.
+        println!("d");
+        assert_eq!(test_capture().finish().as_str(), "");
+    }
+}
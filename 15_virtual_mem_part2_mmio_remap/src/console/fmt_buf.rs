@@ -0,0 +1,128 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Fixed-size formatting buffer.
+
+use core::fmt;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A string buffer of `N` bytes on the stack that can be the target of `write!()`.
+///
+/// Allows to assemble a message piece by piece and hand it to the console in a single call, so
+/// that it cannot be interleaved with other output:
+///
+/// ```ignore
+/// let mut buf: FmtBuf<64> = FmtBuf::new();
+/// let _ = write!(buf, "{}: {}", name, value);
+/// console.write_fmt(format_args!("{}", buf.as_str()))?;
+/// ```
+///
+/// Output that does not fit is cut off, and [`FmtBuf::is_truncated()`] returns true.
+pub struct FmtBuf<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    truncated: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl<const N: usize> FmtBuf<{ N }> {
+    /// Create an instance.
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+            truncated: false,
+        }
+    }
+
+    /// The formatted string.
+    pub fn as_str(&self) -> &str {
+        // Only whole characters are ever copied into the buffer, so this cannot fail.
+        core::str::from_utf8(self.as_bytes()).unwrap_or_default()
+    }
+
+    /// The formatted string as raw bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    /// Returns true if output was dropped because the buffer was full.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Empty the buffer and reset the truncation flag.
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.truncated = false;
+    }
+}
+
+impl<const N: usize> fmt::Write for FmtBuf<{ N }> {
+    /// Append `s`, or as many of its characters as fit.
+    ///
+    /// Returns an error once output had to be dropped. Nothing is appended after that, so the
+    /// buffer never contains a message with holes.
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.truncated {
+            return Err(fmt::Error);
+        }
+
+        let free = N - self.len;
+        let mut fitting = s.len().min(free);
+        while !s.is_char_boundary(fitting) {
+            fitting -= 1;
+        }
+
+        self.buf[self.len..self.len + fitting].copy_from_slice(&s.as_bytes()[..fitting]);
+        self.len += fitting;
+
+        if fitting < s.len() {
+            self.truncated = true;
+            return Err(fmt::Error);
+        }
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fmt::Write;
+    use test_macros::kernel_test;
+
+    /// Overflowing output is cut off at a character boundary and flagged.
+    #[kernel_test]
+    fn fmt_buf_truncates() {
+        let mut buf: FmtBuf<8> = FmtBuf::new();
+
+        assert!(write!(buf, "{}-{}", 12, "ab").is_ok());
+        assert_eq!(buf.as_str(), "12-ab");
+        assert!(!buf.is_truncated());
+
+        // 'ä' takes two bytes and does not fit anymore after "cd".
+        assert!(write!(buf, "cdä").is_err());
+        assert_eq!(buf.as_str(), "12-abcd");
+        assert!(buf.is_truncated());
+
+        // Nothing is appended after truncation, even if it would fit.
+        assert!(write!(buf, "e").is_err());
+        assert_eq!(buf.as_bytes(), b"12-abcd");
+
+        buf.clear();
+        assert!(write!(buf, "e").is_ok());
+        assert_eq!(buf.as_str(), "e");
+    }
+}
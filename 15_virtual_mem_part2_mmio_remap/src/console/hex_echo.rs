@@ -0,0 +1,110 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Hex echo diagnostic.
+//!
+//! Prints every received character together with its hex value, e.g. `A(0x41)`. Useful during
+//! bring-up to check that keyboard input reaches the kernel unaltered.
+
+use super::interface;
+use core::fmt;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Switches the hex echo on and off. Never echoed itself.
+pub const TOGGLE_CHAR: char = '\x05'; // Ctrl-E
+
+/// Ends [`run()`].
+pub const EXIT_CHAR: char = '\x04'; // Ctrl-D
+
+/// The hex echo state.
+pub struct HexEcho {
+    enabled: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl HexEcho {
+    /// Create an instance. The echo starts out enabled.
+    pub const fn new() -> Self {
+        Self { enabled: true }
+    }
+
+    /// Returns true if received characters are currently echoed.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Process a single received character.
+    pub fn feed(&mut self, c: char, out: &impl interface::Write) -> fmt::Result {
+        if c == TOGGLE_CHAR {
+            self.enabled = !self.enabled;
+            return Ok(());
+        }
+
+        if !self.enabled {
+            return Ok(());
+        }
+
+        // Do not let control characters mess with the terminal.
+        let printable = if c.is_ascii_graphic() || c == ' ' {
+            c
+        } else {
+            '.'
+        };
+
+        out.write_fmt(format_args!("{}({:#04x})", printable, c as u32))
+    }
+}
+
+/// Run the hex echo on the given console until [`EXIT_CHAR`] is received.
+pub fn run(console: &impl interface::All) -> fmt::Result {
+    let mut hex_echo = HexEcho::new();
+
+    console.write_fmt(format_args!(
+        "Hex echo. Toggle with Ctrl-E, exit with Ctrl-D.\n"
+    ))?;
+
+    loop {
+        let c = console.read_char();
+        if c == EXIT_CHAR {
+            break;
+        }
+
+        hex_echo.feed(c, console)?;
+    }
+
+    console.write_char('\n');
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::mock::MockConsole;
+    use test_macros::kernel_test;
+
+    /// Characters are echoed with their hex value, the toggle character suppresses the echo.
+    #[kernel_test]
+    fn hex_echo_formats_and_toggles() {
+        let console = MockConsole::new();
+        let mut hex_echo = HexEcho::new();
+
+        for c in ['A', '\n', TOGGLE_CHAR, 'b', TOGGLE_CHAR, 'c'].iter() {
+            hex_echo.feed(*c, &console).unwrap();
+        }
+
+        assert_eq!(&*console.output(), b"A(0x41).(0x0a)c(0x63)");
+        assert!(hex_echo.is_enabled());
+    }
+}
@@ -0,0 +1,359 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Input line history.
+//!
+//! Keeps the most recent input lines and lets the user bring them back with the arrow keys, which
+//! terminals send as the escape sequences `ESC [ A` (up) and `ESC [ B` (down). Other escape
+//! sequences are dropped, so that they do not end up in the line. Backspace and delete erase the
+//! last character.
+
+use super::{interface, ReadLineStatus};
+use crate::collections::RingBuffer;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+const BS: u8 = 0x08;
+const ESC: u8 = 0x1b;
+const DEL: u8 = 0x7f;
+
+/// An entry holds at most this many bytes. Longer lines are not recorded.
+const ENTRY_SIZE: usize = 128;
+
+type Entry = ([u8; ENTRY_SIZE], usize);
+
+/// Where in an escape sequence the input is.
+#[derive(Copy, Clone, PartialEq)]
+enum EscapeState {
+    None,
+    Escape,
+    ControlSequence,
+}
+
+/// What a received byte means to the line editor.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Key {
+    Byte(u8),
+    Up,
+    Down,
+    Enter,
+    Erase,
+    Ignored,
+}
+
+/// Turns received bytes into keys.
+struct KeyDecoder {
+    state: EscapeState,
+
+    /// The last key was a carriage return, so a newline that follows belongs to it.
+    line_ended_cr: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The last `K` lines that were entered, for [`read_line_with_history()`].
+pub struct History<const K: usize> {
+    entries: RingBuffer<Entry, K>,
+
+    /// Kept across lines, so that the end of one line is still known when the next is read.
+    decoder: KeyDecoder,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl KeyDecoder {
+    const fn new() -> Self {
+        Self {
+            state: EscapeState::None,
+            line_ended_cr: false,
+        }
+    }
+
+    fn feed(&mut self, byte: u8) -> Key {
+        let line_ended_cr = self.line_ended_cr;
+        self.line_ended_cr = false;
+
+        match self.state {
+            EscapeState::None => match byte {
+                ESC => {
+                    self.state = EscapeState::Escape;
+                    Key::Ignored
+                }
+                b'\r' => {
+                    self.line_ended_cr = true;
+                    Key::Enter
+                }
+                // A `\r\n` pair ends only one line.
+                b'\n' if line_ended_cr => Key::Ignored,
+                b'\n' => Key::Enter,
+                // Terminals send either for the backspace key.
+                BS | DEL => Key::Erase,
+                // Produced by a disconnected line.
+                0 => Key::Ignored,
+                _ => Key::Byte(byte),
+            },
+            EscapeState::Escape => {
+                // Anything but a control sequence is a two byte sequence.
+                self.state = if byte == b'[' {
+                    EscapeState::ControlSequence
+                } else {
+                    EscapeState::None
+                };
+                Key::Ignored
+            }
+            EscapeState::ControlSequence => {
+                // Parameter and intermediate bytes come before the final byte.
+                if !(0x40..=0x7e).contains(&byte) {
+                    return Key::Ignored;
+                }
+
+                self.state = EscapeState::None;
+                match byte {
+                    b'A' => Key::Up,
+                    b'B' => Key::Down,
+                    _ => Key::Ignored,
+                }
+            }
+        }
+    }
+}
+
+impl<const K: usize> History<{ K }> {
+    /// The entry `age` lines back, with 0 being the newest.
+    fn get(&self, age: usize) -> Option<&[u8]> {
+        let index = self.entries.len().checked_sub(age + 1)?;
+        let (bytes, len) = self.entries.iter().nth(index)?;
+
+        Some(&bytes[..*len])
+    }
+}
+
+/// Replace the `len` bytes of `buf` that are shown on the terminal with `line`.
+///
+/// Returns the new length. `line` is cut off if `buf` is too small for it.
+fn replace_line(out: &impl interface::Write, buf: &mut [u8], len: usize, line: &[u8]) -> usize {
+    // Continuation bytes of UTF-8 characters do not move the cursor.
+    let displayed = buf[..len].iter().filter(|x| *x & 0xc0 != 0x80).count();
+    if displayed > 0 {
+        // Move back to the start of the line and erase it.
+        let _ = out.write_fmt(format_args!("\x1b[{}D\x1b[K", displayed));
+    }
+
+    let new_len = line.len().min(buf.len());
+    buf[..new_len].copy_from_slice(&line[..new_len]);
+    for byte in buf[..new_len].iter() {
+        out.write_byte(*byte);
+    }
+
+    new_len
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl<const K: usize> History<{ K }> {
+    /// Create an instance without entries.
+    pub const fn new() -> Self {
+        Self {
+            entries: RingBuffer::new(),
+            decoder: KeyDecoder::new(),
+        }
+    }
+
+    /// The number of recorded lines.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if no line was recorded yet.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Record a line, dropping the oldest one if the history is full.
+    ///
+    /// Empty lines, lines that repeat the newest entry and lines that are too long are skipped.
+    pub fn record(&mut self, line: &[u8]) {
+        if line.is_empty() || line.len() > ENTRY_SIZE || self.get(0) == Some(line) {
+            return;
+        }
+
+        let mut bytes = [0; ENTRY_SIZE];
+        bytes[..line.len()].copy_from_slice(line);
+        self.entries.push_overwriting((bytes, line.len()));
+    }
+}
+
+/// Like [`super::read_line()`], but up and down arrow keys step through `history`, and complete
+/// lines are recorded in it.
+///
+/// Reads raw bytes and echoes them itself, so that escape sequences do not reach the terminal.
+/// The console's own echo should therefore be off. Both carriage return and newline end a line,
+/// and a `\r\n` pair ends only one.
+pub fn read_line_with_history<const K: usize>(
+    console: &impl interface::All,
+    buf: &mut [u8],
+    history: &mut History<{ K }>,
+) -> ReadLineStatus {
+    let mut len = 0;
+    let mut truncated = false;
+    // How many entries back the line is from. `None` while editing a new line.
+    let mut age: Option<usize> = None;
+
+    loop {
+        match history.decoder.feed(console.read_byte()) {
+            Key::Enter => {
+                console.write_char('\n');
+
+                if truncated {
+                    return ReadLineStatus::Truncated(len);
+                }
+
+                history.record(&buf[..len]);
+                return ReadLineStatus::Complete(len);
+            }
+            Key::Up => {
+                let older = age.map_or(0, |x| x + 1);
+
+                if let Some(line) = history.get(older) {
+                    len = replace_line(console, buf, len, line);
+                    truncated = false;
+                    age = Some(older);
+                }
+            }
+            Key::Down => {
+                age = match age {
+                    None => continue,
+                    Some(0) => None,
+                    Some(x) => Some(x - 1),
+                };
+
+                let line = age.and_then(|x| history.get(x)).unwrap_or_default();
+                len = replace_line(console, buf, len, line);
+                truncated = false;
+            }
+            Key::Erase => {
+                // Continuation bytes of UTF-8 characters go together with their lead byte.
+                let mut new_len = len;
+                while new_len > 0 && buf[new_len - 1] & 0xc0 == 0x80 {
+                    new_len -= 1;
+                }
+                new_len = new_len.saturating_sub(1);
+
+                if new_len < len {
+                    len = new_len;
+                    // Move back, overwrite the character with a space, and move back again.
+                    let _ = console.write_fmt(format_args!("\x08 \x08"));
+                }
+            }
+            Key::Byte(byte) => {
+                // Once a byte was dropped, later ones that would still fit must go, too.
+                if !truncated && len < buf.len() {
+                    buf[len] = byte;
+                    len += 1;
+                    console.write_byte(byte);
+                } else {
+                    truncated = true;
+                }
+            }
+            Key::Ignored => (),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::mock::MockConsole;
+    use test_macros::kernel_test;
+
+    /// The up arrow recalls earlier lines into the buffer, without the escape sequence leaking
+    /// into it.
+    #[kernel_test]
+    fn up_arrow_recalls_previous_line() {
+        let console = MockConsole::with_input(b"ls\rcat x\r\x1b[A\x1b[A\r\x1b[A\x1b[B\x1b[Cy\r");
+        let mut history: History<4> = History::new();
+        let mut buf = [0; 16];
+
+        assert_eq!(
+            read_line_with_history(&console, &mut buf, &mut history),
+            ReadLineStatus::Complete(2)
+        );
+        assert_eq!(
+            read_line_with_history(&console, &mut buf, &mut history),
+            ReadLineStatus::Complete(5)
+        );
+        assert_eq!(history.len(), 2);
+
+        // Two steps back is the first line.
+        assert_eq!(
+            read_line_with_history(&console, &mut buf, &mut history),
+            ReadLineStatus::Complete(2)
+        );
+        assert_eq!(&buf[..2], b"ls");
+
+        // Up and down again, then an unknown sequence, leave only what was typed.
+        assert_eq!(
+            read_line_with_history(&console, &mut buf, &mut history),
+            ReadLineStatus::Complete(1)
+        );
+        assert_eq!(&buf[..1], b"y");
+        assert_eq!(history.get(0), Some(&b"y"[..]));
+        assert_eq!(history.get(1), Some(&b"ls"[..]));
+    }
+
+    /// Backspace and delete take the last character off the line and the terminal, including all
+    /// bytes of a multi-byte character.
+    #[kernel_test]
+    fn erase_removes_last_character() {
+        let console = MockConsole::with_input("\x08ab\x7f\x08xä\x08\r".as_bytes());
+        let mut history: History<4> = History::new();
+        let mut buf = [0; 16];
+
+        assert_eq!(
+            read_line_with_history(&console, &mut buf, &mut history),
+            ReadLineStatus::Complete(1)
+        );
+        assert_eq!(&buf[..1], b"x");
+        assert_eq!(
+            &*console.output(),
+            "ab\x08 \x08\x08 \x08xä\x08 \x08\n".as_bytes()
+        );
+    }
+
+    /// A newline right after a carriage return belongs to it, even if it arrives with the next
+    /// call. Other newlines still end a line each.
+    #[kernel_test]
+    fn crlf_ends_one_line() {
+        let console = MockConsole::with_input(b"ls\r\ncat\r\n\n");
+        let mut history: History<4> = History::new();
+        let mut buf = [0; 16];
+
+        assert_eq!(
+            read_line_with_history(&console, &mut buf, &mut history),
+            ReadLineStatus::Complete(2)
+        );
+        assert_eq!(
+            read_line_with_history(&console, &mut buf, &mut history),
+            ReadLineStatus::Complete(3)
+        );
+        assert_eq!(&buf[..3], b"cat");
+        assert_eq!(
+            read_line_with_history(&console, &mut buf, &mut history),
+            ReadLineStatus::Complete(0)
+        );
+        assert_eq!(history.len(), 2);
+    }
+}
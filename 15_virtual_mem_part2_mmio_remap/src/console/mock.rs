@@ -0,0 +1,180 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Mock console, for unit tests.
+
+use super::interface;
+use core::{
+    cell::{Cell, Ref, RefCell},
+    fmt,
+    time::Duration,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Maximum number of bytes recorded. The rest is counted, but dropped.
+const OUTPUT_SIZE: usize = 128;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Plays back its input and records its output as raw bytes.
+///
+/// Bytes are recorded as they are written, so multi-byte UTF-8 characters stay intact. Once the
+/// input is used up, reads with a timeout fail and blocking reads panic.
+pub struct MockConsole<'a> {
+    input: &'a [u8],
+    pos: Cell<usize>,
+    output: RefCell<[u8; OUTPUT_SIZE]>,
+    written: Cell<usize>,
+    write_fmt_calls: Cell<usize>,
+    chars_read: Cell<usize>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl MockConsole<'_> {
+    fn record(&self, bytes: &[u8]) {
+        for byte in bytes.iter() {
+            if let Some(x) = self.output.borrow_mut().get_mut(self.written.get()) {
+                *x = *byte;
+            }
+            self.written.set(self.written.get() + 1);
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl<'a> MockConsole<'a> {
+    /// Create an instance without input.
+    pub const fn new() -> Self {
+        Self::with_input(&[])
+    }
+
+    /// Create an instance that plays back `input`.
+    pub const fn with_input(input: &'a [u8]) -> Self {
+        Self {
+            input,
+            pos: Cell::new(0),
+            output: RefCell::new([0; OUTPUT_SIZE]),
+            written: Cell::new(0),
+            write_fmt_calls: Cell::new(0),
+            chars_read: Cell::new(0),
+        }
+    }
+
+    /// The output recorded so far.
+    pub fn output(&self) -> Ref<[u8]> {
+        let len = self.written.get().min(OUTPUT_SIZE);
+
+        Ref::map(self.output.borrow(), |x| &x[..len])
+    }
+
+    /// Forget the output recorded so far.
+    pub fn clear_output(&self) {
+        self.written.set(0);
+    }
+
+    /// The number of bytes written since the output was cleared, including dropped ones.
+    pub fn written(&self) -> usize {
+        self.written.get()
+    }
+
+    /// The number of calls of `write_fmt()`.
+    pub fn write_fmt_calls(&self) -> usize {
+        self.write_fmt_calls.get()
+    }
+
+    /// Set what `chars_read()` reports, independent of the input.
+    pub fn set_chars_read(&self, chars_read: usize) {
+        self.chars_read.set(chars_read);
+    }
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+
+impl fmt::Write for &MockConsole<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.record(s.as_bytes());
+
+        Ok(())
+    }
+}
+
+impl interface::Write for MockConsole<'_> {
+    fn write_char_mode(&self, c: char, _blocking: bool) -> Result<(), char> {
+        let mut buf = [0; 4];
+        self.record(c.encode_utf8(&mut buf).as_bytes());
+
+        Ok(())
+    }
+
+    fn write_byte(&self, b: u8) {
+        self.record(&[b]);
+    }
+
+    fn write_fmt(&self, args: fmt::Arguments) -> fmt::Result {
+        self.write_fmt_calls.set(self.write_fmt_calls.get() + 1);
+
+        fmt::Write::write_fmt(&mut &*self, args)
+    }
+
+    fn flush(&self) {}
+}
+
+impl interface::Read for MockConsole<'_> {
+    fn read_char(&self) -> char {
+        self.read_char_timeout(Duration::from_secs(0))
+            .expect("Mock console input exhausted")
+    }
+
+    fn read_char_timeout(&self, _timeout: Duration) -> Option<char> {
+        let rest = &self.input[self.pos.get()..];
+        let valid = match core::str::from_utf8(rest) {
+            Ok(x) => x,
+            Err(e) => core::str::from_utf8(&rest[..e.valid_up_to()]).unwrap_or_default(),
+        };
+
+        let c = valid.chars().next()?;
+        self.pos.set(self.pos.get() + c.len_utf8());
+
+        Some(c)
+    }
+
+    fn read_byte(&self) -> u8 {
+        self.read_byte_timeout(Duration::from_secs(0))
+            .expect("Mock console input exhausted")
+    }
+
+    fn read_byte_timeout(&self, _timeout: Duration) -> Option<u8> {
+        let byte = *self.input.get(self.pos.get())?;
+        self.pos.set(self.pos.get() + 1);
+
+        Some(byte)
+    }
+
+    fn clear(&self) {}
+}
+
+impl interface::Statistics for MockConsole<'_> {
+    fn chars_written(&self) -> usize {
+        self.written.get()
+    }
+
+    fn chars_read(&self) -> usize {
+        self.chars_read.get()
+    }
+}
+
+impl interface::LineDiscipline for MockConsole<'_> {}
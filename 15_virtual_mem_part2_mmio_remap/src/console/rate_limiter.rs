@@ -0,0 +1,254 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Rate limiting of console writes.
+
+use super::interface;
+use crate::{synchronization, synchronization::IRQSafeNullLock, time};
+use core::{cmp, time::Duration};
+use time::interface::TimeManager;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+const NS_PER_S: u64 = 1_000_000_000;
+
+/// Multi-byte characters are never split, so the bucket must hold at least the longest one.
+const MIN_BURST: u32 = 4;
+
+/// Tokens are counted in nanobytes, so that refills in between whole bytes are not lost.
+struct Bucket {
+    tokens: u64,
+
+    /// When the bucket was refilled last, `None` before the first write.
+    last_refill: Option<Duration>,
+    dropped: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// What [`RateLimiter::write()`] does with text that exceeds the rate.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RateLimitMode {
+    /// Wait until the rate allows it.
+    Block,
+
+    /// Drop it and count the dropped bytes.
+    Drop,
+}
+
+/// Limits the rate of writes to a console to `bytes_per_sec`, with bursts of up to `burst` bytes,
+/// e.g. so that a chatty subsystem cannot starve other output.
+///
+/// A token bucket: every byte written takes a token, and tokens are refilled at the rate, from the
+/// system timer, up to the burst size. The bucket starts out full.
+pub struct RateLimiter {
+    bucket: IRQSafeNullLock<Bucket>,
+    bytes_per_sec: u64,
+    burst: u64,
+    mode: RateLimitMode,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl Bucket {
+    /// Add the tokens for the time since the last refill, up to `cap`.
+    fn refill(&mut self, now: Duration, bytes_per_sec: u64, cap: u64) {
+        if let Some(last) = self.last_refill {
+            let elapsed = now.checked_sub(last).unwrap_or_default();
+            let added = elapsed.as_nanos().saturating_mul(u128::from(bytes_per_sec));
+
+            self.tokens = cmp::min(u128::from(self.tokens) + added, u128::from(cap)) as u64;
+        }
+
+        self.last_refill = Some(now);
+    }
+
+    /// Take tokens for as much of the start of `s` as they cover, without splitting a character.
+    ///
+    /// Returns the length of the covered part.
+    fn take(&mut self, s: &str) -> usize {
+        let mut len = cmp::min(s.len() as u64, self.tokens / NS_PER_S) as usize;
+        while !s.is_char_boundary(len) {
+            len -= 1;
+        }
+
+        self.tokens -= len as u64 * NS_PER_S;
+
+        len
+    }
+}
+
+impl RateLimiter {
+    /// `write()` with the timer passed in.
+    fn write_with(&self, out: &impl interface::Write, mut s: &str, timer: &impl TimeManager) {
+        let cap = self.burst * NS_PER_S;
+
+        // Without a running timer, no tokens would ever come in.
+        if self.mode == RateLimitMode::Drop || !timer.is_running() {
+            self.bucket.lock(|bucket| {
+                bucket.refill(timer.uptime(), self.bytes_per_sec, cap);
+
+                // All or nothing, so that no text is cut off in the middle.
+                if bucket.tokens / NS_PER_S < s.len() as u64 {
+                    bucket.dropped += s.len();
+                    return;
+                }
+
+                bucket.take(s);
+                let _ = out.write_fmt(format_args!("{}", s));
+            });
+            return;
+        }
+
+        // The time it takes for a token to come in, but at least one tick of the timer.
+        let byte_time = cmp::max(
+            Duration::from_nanos(div_ceil(NS_PER_S, self.bytes_per_sec)),
+            timer.resolution(),
+        );
+
+        loop {
+            let before = timer.uptime();
+            let len = self.bucket.lock(|bucket| {
+                bucket.refill(before, self.bytes_per_sec, cap);
+                bucket.take(s)
+            });
+
+            if len > 0 {
+                let _ = out.write_fmt(format_args!("{}", &s[..len]));
+                s = &s[len..];
+            }
+
+            if s.is_empty() {
+                return;
+            }
+
+            // Waits outside of the lock and with IRQs unmasked, so that writers from IRQ context
+            // and the timer keep going.
+            timer.spin_for(byte_time);
+
+            // A timer that does not advance would keep the bucket empty forever.
+            if timer.uptime() == before {
+                self.bucket.lock(|bucket| bucket.dropped += s.len());
+                return;
+            }
+        }
+    }
+}
+
+/// Integer division, rounded up.
+const fn div_ceil(x: u64, y: u64) -> u64 {
+    (x + y - 1) / y
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+use synchronization::interface::Mutex;
+
+impl RateLimiter {
+    /// Create an instance.
+    ///
+    /// A rate of 0 is treated as 1 byte per second. Bursts are at least 4 bytes, which is the
+    /// longest UTF-8 character.
+    pub const fn new(bytes_per_sec: u32, burst: u32, mode: RateLimitMode) -> Self {
+        let bytes_per_sec = if bytes_per_sec == 0 { 1 } else { bytes_per_sec };
+        let burst = if burst < MIN_BURST { MIN_BURST } else { burst };
+
+        Self {
+            bucket: IRQSafeNullLock::new(Bucket {
+                tokens: burst as u64 * NS_PER_S,
+                last_refill: None,
+                dropped: 0,
+            }),
+            bytes_per_sec: bytes_per_sec as u64,
+            burst: burst as u64,
+            mode,
+        }
+    }
+
+    /// Write `s` to `out` as far as the rate allows.
+    ///
+    /// In [`RateLimitMode::Block`], waits for the rest, sending it piece by piece as tokens come
+    /// in. In [`RateLimitMode::Drop`], all of `s` is dropped if there are not enough tokens for
+    /// it, so text longer than the burst size never goes out.
+    ///
+    /// Waiting needs the system timer, so [`RateLimitMode::Block`] falls back to dropping if the
+    /// timer is not running, or if it does not advance while waiting for the rest.
+    pub fn write(&self, out: &impl interface::Write, s: &str) {
+        self.write_with(out, s, time::time_manager());
+    }
+
+    /// The number of bytes dropped in [`RateLimitMode::Drop`] so far.
+    pub fn dropped(&self) -> usize {
+        self.bucket.lock(|bucket| bucket.dropped)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{console::mock::MockConsole, time::mock::MockTimer};
+    use test_macros::kernel_test;
+
+    /// A burst within the bucket goes out right away. Beyond it, text is dropped and counted, or
+    /// waited for while the clock advances.
+    #[kernel_test]
+    fn burst_passes_and_excess_is_throttled() {
+        let out = MockConsole::new();
+        let timer = MockTimer::new(1);
+        let t0 = Duration::from_secs(1);
+        timer.set_uptime(t0);
+
+        // One byte per millisecond.
+        let limiter = RateLimiter::new(1000, 8, RateLimitMode::Drop);
+        limiter.write_with(&out, "abcdefgh", &timer);
+        limiter.write_with(&out, "xy", &timer);
+        assert_eq!(&*out.output(), b"abcdefgh");
+        assert_eq!(limiter.dropped(), 2);
+
+        timer.set_uptime(t0 + Duration::from_millis(1));
+        limiter.write_with(&out, "z", &timer);
+        assert_eq!(&*out.output(), b"abcdefghz");
+        assert_eq!(limiter.dropped(), 2);
+
+        // Spinning advances the clock, one byte time per spin.
+        out.clear_output();
+        timer.set_uptime(t0);
+        let limiter = RateLimiter::new(1000, 8, RateLimitMode::Block);
+        limiter.write_with(&out, "abcdefgh", &timer);
+        assert_eq!(timer.spins(), 0);
+
+        limiter.write_with(&out, "0123", &timer);
+        assert_eq!(&*out.output(), b"abcdefgh0123");
+        assert_eq!(timer.spins(), 4);
+        assert_eq!(timer.uptime(), t0 + Duration::from_millis(4));
+        assert_eq!(limiter.dropped(), 0);
+    }
+
+    /// With the timer stopped, no tokens come in, so blocking would never end. The excess is
+    /// dropped instead.
+    #[kernel_test]
+    fn block_drops_if_timer_is_stopped() {
+        let out = MockConsole::new();
+        let timer = MockTimer::new(1);
+        timer.set_running(false);
+
+        let limiter = RateLimiter::new(1000, 8, RateLimitMode::Block);
+        limiter.write_with(&out, "abcdefgh", &timer);
+        limiter.write_with(&out, "xy", &timer);
+        assert_eq!(&*out.output(), b"abcdefgh");
+        assert_eq!(limiter.dropped(), 2);
+        assert_eq!(timer.spins(), 0);
+    }
+}
@@ -0,0 +1,75 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Safe output of untrusted data.
+//!
+//! Received data may contain control characters, e.g. escape sequences, that would scramble the
+//! terminal if they were printed raw. On this path, only printable ASCII goes out as is, and every
+//! other byte in its escaped form, `\x1b` for ESC. Normal output is not affected.
+
+use super::interface;
+use core::fmt;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Displays bytes with everything but printable ASCII escaped.
+///
+/// Newline, carriage return and tab become `\n`, `\r` and `\t`, other bytes `\xNN`, including
+/// those beyond ASCII. Backslashes are doubled, so that the output is unambiguous.
+pub struct Escaped<'a>(pub &'a [u8]);
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl fmt::Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use fmt::Write;
+
+        for byte in self.0.iter() {
+            match *byte {
+                b'\n' => f.write_str("\\n")?,
+                b'\r' => f.write_str("\\r")?,
+                b'\t' => f.write_str("\\t")?,
+                b'\\' => f.write_str("\\\\")?,
+                x if x.is_ascii_graphic() || x == b' ' => f.write_char(char::from(x))?,
+                x => write!(f, "\\x{:02x}", x)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Write untrusted `bytes` to `out`, in the escaped form of [`Escaped`].
+pub fn write_escaped(out: &impl interface::Write, bytes: &[u8]) -> fmt::Result {
+    out.write_fmt(format_args!("{}", Escaped(bytes)))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::mock::MockConsole;
+    use test_macros::kernel_test;
+
+    /// An ESC goes out as the four characters `\x1b`, not raw. Printable ASCII passes unchanged.
+    #[kernel_test]
+    fn control_bytes_are_escaped() {
+        let out = MockConsole::new();
+
+        write_escaped(&out, b"\x1b").unwrap();
+        assert_eq!(&*out.output(), b"\\x1b");
+        assert_eq!(out.written(), 4);
+
+        out.clear_output();
+        write_escaped(&out, b"a b\n\\\xe2\x7f").unwrap();
+        assert_eq!(&*out.output(), b"a b\\n\\\\\\xe2\\x7f");
+    }
+}
@@ -0,0 +1,141 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Console throughput measurement.
+
+use super::interface;
+use core::time::Duration;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+#[derive(Copy, Clone)]
+struct Snapshot {
+    time: Duration,
+    chars_written: usize,
+    chars_read: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Computes TX and RX rates from consecutive samples of a console's statistics.
+///
+/// The kernel has no timer callbacks yet, so [`StatsSampler::sample()`] must be called
+/// periodically by the user, e.g. from a loop that uses `time_manager().spin_for()`.
+pub struct StatsSampler {
+    last: Option<Snapshot>,
+    tx_rate: u64,
+    rx_rate: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Characters per second, given the characters counted during `elapsed`.
+fn rate(chars: usize, elapsed: Duration) -> u64 {
+    let elapsed_us = elapsed.as_micros() as u64;
+    if elapsed_us == 0 {
+        return 0;
+    }
+
+    (chars as u64).saturating_mul(1_000_000) / elapsed_us
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl StatsSampler {
+    /// Create an instance.
+    pub const fn new() -> Self {
+        Self {
+            last: None,
+            tx_rate: 0,
+            rx_rate: 0,
+        }
+    }
+
+    /// Take a sample of `stats` at time `now` and update the rates.
+    ///
+    /// The rates stay at zero until the second sample.
+    pub fn sample(&mut self, now: Duration, stats: &impl interface::Statistics) {
+        let current = Snapshot {
+            time: now,
+            chars_written: stats.chars_written(),
+            chars_read: stats.chars_read(),
+        };
+
+        if let Some(last) = self.last {
+            let elapsed = current.time.checked_sub(last.time).unwrap_or_default();
+
+            self.tx_rate = rate(
+                current.chars_written.wrapping_sub(last.chars_written),
+                elapsed,
+            );
+            self.rx_rate = rate(current.chars_read.wrapping_sub(last.chars_read), elapsed);
+        }
+
+        self.last = Some(current);
+    }
+
+    /// Characters written per second between the last two samples.
+    pub fn tx_rate(&self) -> u64 {
+        self.tx_rate
+    }
+
+    /// Characters read per second between the last two samples.
+    pub fn rx_rate(&self) -> u64 {
+        self.rx_rate
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+    use test_macros::kernel_test;
+
+    struct MockStatistics {
+        chars_written: Cell<usize>,
+        chars_read: Cell<usize>,
+    }
+
+    impl interface::Statistics for MockStatistics {
+        fn chars_written(&self) -> usize {
+            self.chars_written.get()
+        }
+
+        fn chars_read(&self) -> usize {
+            self.chars_read.get()
+        }
+    }
+
+    /// Rates are the counter deltas divided by the time between samples.
+    #[kernel_test]
+    fn stats_sampler_computes_rates() {
+        let stats = MockStatistics {
+            chars_written: Cell::new(1000),
+            chars_read: Cell::new(10),
+        };
+        let mut sampler = StatsSampler::new();
+
+        sampler.sample(Duration::from_secs(3), &stats);
+        assert_eq!(sampler.tx_rate(), 0);
+        assert_eq!(sampler.rx_rate(), 0);
+
+        stats.chars_written.set(1100);
+        stats.chars_read.set(13);
+        sampler.sample(Duration::from_millis(3500), &stats);
+        assert_eq!(sampler.tx_rate(), 200);
+        assert_eq!(sampler.rx_rate(), 6);
+    }
+}
@@ -0,0 +1,130 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Assembly of received UTF-8 sequences into characters.
+
+use core::char::REPLACEMENT_CHARACTER;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Turns received bytes into characters, for [`super::InputCodec::Utf8`].
+///
+/// Anything that is not valid UTF-8 becomes U+FFFD: stray continuation bytes, sequences that are
+/// cut short, overlong encodings, surrogates and values beyond U+10FFFF. A byte that cuts a
+/// sequence short starts the next character, so a lost continuation byte costs only the one
+/// character it belonged to.
+#[derive(Copy, Clone, Default)]
+pub struct Utf8Decoder {
+    /// A byte that ended the previous sequence prematurely.
+    retry: Option<u8>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl Utf8Decoder {
+    /// Create an instance.
+    pub const fn new() -> Self {
+        Self { retry: None }
+    }
+
+    /// Decode the next character from the bytes returned by `next_byte()`.
+    ///
+    /// `next_byte()` is told whether the byte would continue a sequence, so that it can wait for
+    /// those for a short time only. Returns `None` if there was no first byte. A missing
+    /// continuation byte ends the sequence with U+FFFD.
+    pub fn decode(&mut self, mut next_byte: impl FnMut(bool) -> Option<u8>) -> Option<char> {
+        let lead = match self.retry.take() {
+            Some(x) => x,
+            None => next_byte(false)?,
+        };
+
+        // The payload bits of the lead byte, the sequence length and the smallest value that
+        // needs this length.
+        let (mut value, len, min) = match lead {
+            0x00..=0x7f => return Some(char::from(lead)),
+            0xc2..=0xdf => (u32::from(lead & 0x1f), 2, 0x80),
+            0xe0..=0xef => (u32::from(lead & 0x0f), 3, 0x800),
+            0xf0..=0xf4 => (u32::from(lead & 0x07), 4, 0x1_0000),
+            // Continuation bytes and leads that can only start overlong or too large values.
+            _ => return Some(REPLACEMENT_CHARACTER),
+        };
+
+        for _ in 1..len {
+            let byte = match next_byte(true) {
+                Some(x) => x,
+                None => return Some(REPLACEMENT_CHARACTER),
+            };
+
+            if byte & 0xc0 != 0x80 {
+                self.retry = Some(byte);
+                return Some(REPLACEMENT_CHARACTER);
+            }
+
+            value = (value << 6) | u32::from(byte & 0x3f);
+        }
+
+        if value < min {
+            return Some(REPLACEMENT_CHARACTER);
+        }
+
+        // Fails for surrogates and values beyond U+10FFFF.
+        Some(core::char::from_u32(value).unwrap_or(REPLACEMENT_CHARACTER))
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_macros::kernel_test;
+
+    /// Decode all of `input`, collecting the characters into `out`.
+    fn decode_all(input: &[u8], out: &mut [char; 8]) -> usize {
+        let mut decoder = Utf8Decoder::new();
+        let mut bytes = input.iter().copied();
+        let mut len = 0;
+
+        while let Some(c) = decoder.decode(|_| bytes.next()) {
+            out[len] = c;
+            len += 1;
+        }
+
+        len
+    }
+
+    /// Multi-byte sequences become single characters, broken ones U+FFFD without swallowing the
+    /// byte that broke them.
+    #[kernel_test]
+    fn utf8_sequences_are_assembled() {
+        let mut out = [' '; 8];
+
+        let len = decode_all("a€😀".as_bytes(), &mut out);
+        assert_eq!(out[..len], ['a', '€', '😀']);
+
+        // Cut short by 'A', a stray continuation byte, an overlong '/', a surrogate.
+        let len = decode_all(b"\xe2\x82A\x80\xc0\xaf\xed\xa0\x80", &mut out);
+        assert_eq!(
+            out[..len],
+            [
+                REPLACEMENT_CHARACTER,
+                'A',
+                REPLACEMENT_CHARACTER,
+                REPLACEMENT_CHARACTER,
+                REPLACEMENT_CHARACTER,
+                REPLACEMENT_CHARACTER
+            ]
+        );
+
+        // Truncated at the end of the input.
+        let len = decode_all(b"\xf0\x9f", &mut out);
+        assert_eq!(out[..len], [REPLACEMENT_CHARACTER]);
+    }
+}
@@ -0,0 +1,177 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Batching of small console writes.
+
+use super::interface;
+use crate::{synchronization, synchronization::IRQSafeNullLock, time};
+use core::time::Duration;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The queued text. It is always drained in full, so it never wraps.
+struct Queue<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+
+    /// When the oldest queued text was enqueued.
+    oldest: Option<Duration>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Collects small writes from many callers in a queue of `N` bytes, and hands them to the console
+/// in one `write_fmt()`.
+///
+/// Saves a lock and a round of FIFO checks per write for high-frequency logging. Text goes out in
+/// the order it was enqueued, no matter by whom.
+///
+/// The queue is drained when text does not fit anymore, on [`WriteCoalescer::flush()`], and by
+/// [`WriteCoalescer::poll()`] once the oldest text waited for `max_delay`. The kernel has no timer
+/// callbacks yet, so `poll()` must be called periodically by the user.
+pub struct WriteCoalescer<const N: usize> {
+    queue: IRQSafeNullLock<Queue<N>>,
+    max_delay: Duration,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl<const N: usize> Queue<{ N }> {
+    const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+            oldest: None,
+        }
+    }
+
+    /// Write everything to `out` in one go and empty the queue.
+    fn drain(&mut self, out: &impl interface::Write) {
+        if self.len == 0 {
+            return;
+        }
+
+        // Only whole strings are appended, so the contents are valid UTF-8.
+        let text = core::str::from_utf8(&self.buf[..self.len]).unwrap_or_default();
+        let _ = out.write_fmt(format_args!("{}", text));
+
+        self.len = 0;
+        self.oldest = None;
+    }
+}
+
+impl<const N: usize> WriteCoalescer<{ N }> {
+    /// `enqueue()` with the current time passed in.
+    fn enqueue_at(&self, out: &impl interface::Write, s: &str, now: Duration) {
+        self.queue.lock(|queue| {
+            if queue.len + s.len() > N {
+                queue.drain(out);
+            }
+
+            // Too big for the queue even when empty. Everything before it went out already.
+            if s.len() > N {
+                let _ = out.write_fmt(format_args!("{}", s));
+                return;
+            }
+
+            queue.buf[queue.len..queue.len + s.len()].copy_from_slice(s.as_bytes());
+            queue.len += s.len();
+            queue.oldest.get_or_insert(now);
+        });
+    }
+
+    /// `poll()` with the current time passed in.
+    fn poll_at(&self, out: &impl interface::Write, now: Duration) {
+        self.queue.lock(|queue| match queue.oldest {
+            Some(oldest) if now >= time::deadline_after(oldest, self.max_delay) => queue.drain(out),
+            _ => (),
+        });
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+use synchronization::interface::Mutex;
+
+impl<const N: usize> WriteCoalescer<{ N }> {
+    /// Create an instance that holds text for at most `max_delay`, as far as `poll()` is called.
+    pub const fn new(max_delay: Duration) -> Self {
+        Self {
+            queue: IRQSafeNullLock::new(Queue::new()),
+            max_delay,
+        }
+    }
+
+    /// Queue `s` for `out`. If the queue is too full for it, the queue is drained first.
+    ///
+    /// Text that is longer than the whole queue is written right away.
+    pub fn enqueue(&self, out: &impl interface::Write, s: &str) {
+        use time::interface::TimeManager;
+
+        self.enqueue_at(out, s, time::time_manager().uptime());
+    }
+
+    /// Drain the queue to `out` if the oldest text in it waited for `max_delay`.
+    pub fn poll(&self, out: &impl interface::Write) {
+        use time::interface::TimeManager;
+
+        self.poll_at(out, time::time_manager().uptime());
+    }
+
+    /// Drain the queue to `out` right away.
+    pub fn flush(&self, out: &impl interface::Write) {
+        self.queue.lock(|queue| queue.drain(out));
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::mock::MockConsole;
+    use test_macros::kernel_test;
+
+    /// Queued text goes out in enqueue order, in a single write per drain. A full queue drains
+    /// before taking more, and a poll drains only once the oldest text is due.
+    #[kernel_test]
+    fn enqueued_text_is_flushed_in_order() {
+        let out = MockConsole::new();
+        let coalescer: WriteCoalescer<8> = WriteCoalescer::new(Duration::from_millis(10));
+        let t0 = Duration::from_secs(1);
+
+        coalescer.enqueue_at(&out, "ab", t0);
+        coalescer.enqueue_at(&out, "cd", t0);
+        coalescer.enqueue_at(&out, "ef", t0);
+        assert_eq!(out.write_fmt_calls(), 0);
+
+        coalescer.flush(&out);
+        assert_eq!(&*out.output(), b"abcdef");
+        assert_eq!(out.write_fmt_calls(), 1);
+
+        // Does not fit next to the first one.
+        coalescer.enqueue_at(&out, "123456", t0);
+        coalescer.enqueue_at(&out, "789", t0);
+        assert_eq!(&*out.output(), b"abcdef123456");
+        assert_eq!(out.write_fmt_calls(), 2);
+
+        coalescer.poll_at(&out, t0 + Duration::from_millis(9));
+        assert_eq!(out.write_fmt_calls(), 2);
+        coalescer.poll_at(&out, t0 + Duration::from_millis(10));
+        assert_eq!(&*out.output(), b"abcdef123456789");
+
+        // Nothing left for an explicit flush.
+        coalescer.flush(&out);
+        assert_eq!(out.write_fmt_calls(), 3);
+    }
+}
@@ -10,3 +10,64 @@ mod arch_cpu;
 pub use arch_cpu::*;
 
 pub mod smp;
+
+use crate::{bsp, console, exception, time};
+use core::time::Duration;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// How long to wait for pending console output before going down anyway.
+const FINAL_FLUSH_TIMEOUT: Duration = Duration::from_millis(100);
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The exception levels of the architecture, see [`current_exception_level()`].
+///
+/// The kernel runs at EL1. The others are representable nonetheless, e.g. for diagnostics.
+#[allow(missing_docs)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ExceptionLevel {
+    EL0,
+    EL1,
+    EL2,
+    EL3,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Give pending console output a chance to leave, without letting a stuck UART block the caller.
+fn final_flush() {
+    use console::interface::Write;
+    use time::interface::TimeManager;
+
+    let deadline = time::time_manager().deadline_in(FINAL_FLUSH_TIMEOUT);
+    bsp::console::console().flush_best_effort(deadline);
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Flush the console and reset the board.
+///
+/// The serial settings are kept for the next kernel.
+pub fn reboot() -> ! {
+    bsp::console::uart().persist_config();
+    final_flush();
+    bsp::cpu::board_reset()
+}
+
+/// Flush the console and park the CPU with interrupts masked.
+pub fn shutdown() -> ! {
+    final_flush();
+    // Nothing runs after this, so there is no code that could rely on IRQs being unmasked.
+    unsafe { exception::asynchronous::local_irq_mask() };
+
+    wait_forever()
+}
@@ -4,12 +4,74 @@
 
 //! Driver support.
 
+use crate::{
+    bsp, memory,
+    memory::mmu::{Address, MMIODescriptor, Physical, Virtual},
+    time, warn,
+};
+use core::{fmt, time::Duration};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Maximum number of drivers a [`RegistrySnapshot`] can hold.
+const MAX_SNAPSHOT_DRIVERS: usize = 8;
+
+/// Maximum size of a driver's configuration in a [`RegistrySnapshot`].
+const MAX_SNAPSHOT_CONFIG_LEN: usize = 16;
+
+/// Size of a [`SnapshotEntry`] in the encoding of a [`RegistrySnapshot`]: ID, length, config.
+const ENCODED_ENTRY_LEN: usize = 8 + MAX_SNAPSHOT_CONFIG_LEN;
+
+/// Tags an encoded [`RegistrySnapshot`].
+const SNAPSHOT_MAGIC: u32 = 0x5352_5644; // "DVRS"
+
+/// How often the drivers that are not needed for printing are tried to be brought up.
+const INIT_ATTEMPTS: u32 = 3;
+
+/// The time between two of these attempts.
+const INIT_BACKOFF: Duration = Duration::from_millis(10);
+
+/// A driver in a [`RegistrySnapshot`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct SnapshotEntry {
+    /// Hash of the compatibility string, see `driver_id()`.
+    id: u32,
+    config: [u8; MAX_SNAPSHOT_CONFIG_LEN],
+    config_len: usize,
+}
+
 //--------------------------------------------------------------------------------------------------
 // Public Definitions
 //--------------------------------------------------------------------------------------------------
 
+/// Errors reported by device drivers.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DriverError {
+    /// A condition that might clear up by itself, e.g. a busy mailbox. Worth another try.
+    Transient(&'static str),
+
+    /// The driver cannot succeed with the current configuration. Retrying is pointless.
+    InvalidConfig(&'static str),
+}
+
+/// The drivers that were present, in registration order, and their configurations. See
+/// [`snapshot_registry()`].
+///
+/// Drivers are identified by a hash of their compatibility strings, so a snapshot holds no
+/// references into the kernel image. Encoded with [`RegistrySnapshot::encode()`], it can be kept
+/// across a warm reboot, e.g. in the persistent area.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RegistrySnapshot {
+    drivers: [SnapshotEntry; MAX_SNAPSHOT_DRIVERS],
+    num_drivers: usize,
+}
+
 /// Driver interfaces.
 pub mod interface {
+    use super::DriverError;
+
     /// Device Driver functions.
     pub trait DeviceDriver {
         /// Return a compatibility string for identifying the driver.
@@ -20,7 +82,7 @@ pub mod interface {
         /// # Safety
         ///
         /// - During init, drivers might do stuff with system-wide impact.
-        unsafe fn init(&self) -> Result<(), &'static str> {
+        unsafe fn init(&self) -> Result<(), DriverError> {
             Ok(())
         }
 
@@ -39,6 +101,41 @@ pub mod interface {
         fn virt_mmio_start_addr(&self) -> Option<usize> {
             None
         }
+
+        /// Called by the kernel after the device's MMIO has been remapped to a new virtual start
+        /// address.
+        ///
+        /// Unlike `init()`, this only switches the driver over to `new_mmio_start_addr`. The
+        /// device is not touched, so its configuration and any buffered data are kept. Fails if
+        /// the driver was not initialized yet or does not support remapping.
+        ///
+        /// # Safety
+        ///
+        /// - The user must ensure that `new_mmio_start_addr` maps the device's registers.
+        unsafe fn post_mmio_remap(&self, _new_mmio_start_addr: usize) -> Result<(), &'static str> {
+            Err("Driver does not support MMIO remapping")
+        }
+
+        /// Write the device's configuration to `buf` for a [`super::RegistrySnapshot`], returning
+        /// its length.
+        ///
+        /// Drivers whose configuration is set up by `init()` alone save nothing.
+        fn save_config(&self, _buf: &mut [u8]) -> usize {
+            0
+        }
+
+        /// Apply a configuration that was written by `save_config()`. Called after `init()`.
+        fn restore_config(&self, _config: &[u8]) -> Result<(), DriverError> {
+            Ok(())
+        }
+
+        /// The number of accesses to the device's registers since it was mapped, for profiling.
+        ///
+        /// Drivers that do not count report zero.
+        #[cfg(feature = "mmio_counters")]
+        fn mmio_accesses(&self) -> usize {
+            0
+        }
     }
 
     /// Device driver management functions.
@@ -60,3 +157,605 @@ pub mod interface {
         fn post_early_print_device_driver_init(&self);
     }
 }
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn drivers_of(
+    manager: &'static impl interface::DriverManager,
+) -> impl Iterator<Item = &'static (dyn interface::DeviceDriver + Sync)> {
+    manager.all_device_drivers().iter().copied()
+}
+
+/// Identifies a driver by its compatibility string, with the 32 bit FNV-1a hash.
+fn driver_id(compatible: &str) -> u32 {
+    compatible.bytes().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+unsafe fn init_early_print_drivers_of(
+    manager: &'static impl interface::DriverManager,
+) -> Result<(), DriverError> {
+    for driver in manager.early_print_device_drivers() {
+        driver.init()?;
+    }
+    manager.post_early_print_device_driver_init();
+
+    Ok(())
+}
+
+unsafe fn init_non_early_print_drivers_of(
+    manager: &'static impl interface::DriverManager,
+) -> Result<(), (&'static str, DriverError)> {
+    for driver in manager.non_early_print_device_drivers() {
+        retry_init(*driver, INIT_ATTEMPTS, INIT_BACKOFF).map_err(|x| (driver.compatible(), x))?;
+    }
+
+    Ok(())
+}
+
+fn snapshot_registry_of(
+    manager: &'static impl interface::DriverManager,
+) -> Result<RegistrySnapshot, &'static str> {
+    let mut snapshot = RegistrySnapshot::new();
+
+    for driver in drivers_of(manager) {
+        if snapshot.num_drivers == MAX_SNAPSHOT_DRIVERS {
+            return Err("Too many drivers for a registry snapshot");
+        }
+
+        let entry = &mut snapshot.drivers[snapshot.num_drivers];
+        entry.id = driver_id(driver.compatible());
+        entry.config_len = driver
+            .save_config(&mut entry.config)
+            .min(MAX_SNAPSHOT_CONFIG_LEN);
+        snapshot.num_drivers += 1;
+    }
+
+    Ok(snapshot)
+}
+
+unsafe fn restore_registry_of(
+    manager: &'static impl interface::DriverManager,
+    snapshot: &RegistrySnapshot,
+) -> Result<(), DriverError> {
+    if drivers_of(manager)
+        .map(|x| driver_id(x.compatible()))
+        .ne(snapshot.entries().map(|x| x.id))
+    {
+        return Err(DriverError::InvalidConfig(
+            "Drivers do not match the registry snapshot",
+        ));
+    }
+
+    init_early_print_drivers_of(manager)?;
+    init_non_early_print_drivers_of(manager).map_err(|(_, x)| x)?;
+
+    for (driver, entry) in drivers_of(manager).zip(snapshot.entries()) {
+        driver.restore_config(&entry.config[..entry.config_len])?;
+    }
+
+    Ok(())
+}
+
+impl SnapshotEntry {
+    const fn new() -> Self {
+        Self {
+            id: 0,
+            config: [0; MAX_SNAPSHOT_CONFIG_LEN],
+            config_len: 0,
+        }
+    }
+}
+
+impl RegistrySnapshot {
+    const fn new() -> Self {
+        Self {
+            drivers: [SnapshotEntry::new(); MAX_SNAPSHOT_DRIVERS],
+            num_drivers: 0,
+        }
+    }
+
+    fn entries(&self) -> impl Iterator<Item = &SnapshotEntry> {
+        self.drivers[..self.num_drivers].iter()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Iterate over all drivers of the BSP, in the order they were registered in.
+pub fn drivers() -> impl Iterator<Item = &'static (dyn interface::DeviceDriver + Sync)> {
+    drivers_of(bsp::driver::driver_manager())
+}
+
+/// Print the number of register accesses of each driver, e.g. to find one that polls too much.
+#[cfg(feature = "mmio_counters")]
+pub fn print_mmio_accesses() {
+    use crate::info;
+
+    info!("MMIO accesses:");
+    for driver in drivers() {
+        info!(
+            "      {:<44} {:>10}",
+            driver.compatible(),
+            driver.mmio_accesses()
+        );
+    }
+}
+
+impl RegistrySnapshot {
+    /// Size of the encoding, see [`RegistrySnapshot::encode()`].
+    pub const ENCODED_LEN: usize = 8 + MAX_SNAPSHOT_DRIVERS * ENCODED_ENTRY_LEN;
+
+    /// The number of drivers recorded.
+    pub fn len(&self) -> usize {
+        self.num_drivers
+    }
+
+    /// Returns true if no drivers are recorded.
+    pub fn is_empty(&self) -> bool {
+        self.num_drivers == 0
+    }
+
+    /// Encode the snapshot as bytes, for keeping it across a reboot. Little endian, like the
+    /// other records in the persistent area.
+    pub fn encode(&self) -> [u8; Self::ENCODED_LEN] {
+        let mut bytes = [0; Self::ENCODED_LEN];
+
+        bytes[0..4].copy_from_slice(&SNAPSHOT_MAGIC.to_le_bytes());
+        bytes[4..8].copy_from_slice(&(self.num_drivers as u32).to_le_bytes());
+        for (entry, chunk) in self.entries().zip(bytes[8..].chunks_mut(ENCODED_ENTRY_LEN)) {
+            chunk[0..4].copy_from_slice(&entry.id.to_le_bytes());
+            chunk[4..8].copy_from_slice(&(entry.config_len as u32).to_le_bytes());
+            chunk[8..].copy_from_slice(&entry.config);
+        }
+
+        bytes
+    }
+
+    /// The snapshot encoded in `bytes` by [`RegistrySnapshot::encode()`], or `None` if there is no
+    /// intact one.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let word = |chunk: &[u8], offset: usize| {
+            let mut x = [0; 4];
+            x.copy_from_slice(&chunk[offset..offset + 4]);
+            u32::from_le_bytes(x) as usize
+        };
+
+        if bytes.len() < Self::ENCODED_LEN || word(bytes, 0) != SNAPSHOT_MAGIC as usize {
+            return None;
+        }
+
+        let mut snapshot = Self::new();
+        snapshot.num_drivers = word(bytes, 4);
+        if snapshot.num_drivers > MAX_SNAPSHOT_DRIVERS {
+            return None;
+        }
+
+        for (entry, chunk) in snapshot
+            .drivers
+            .iter_mut()
+            .zip(bytes[8..Self::ENCODED_LEN].chunks(ENCODED_ENTRY_LEN))
+        {
+            entry.id = word(chunk, 0) as u32;
+            entry.config_len = word(chunk, 4);
+            if entry.config_len > MAX_SNAPSHOT_CONFIG_LEN {
+                return None;
+            }
+            entry.config.copy_from_slice(&chunk[8..]);
+        }
+
+        Some(snapshot)
+    }
+}
+
+/// Bring up the drivers needed for printing, e.g. the UART, and run the BSP's follow-up init.
+///
+/// Errors cannot be printed yet, and are not retried.
+///
+/// # Safety
+///
+/// - Same as for [`interface::DeviceDriver::init()`].
+pub unsafe fn init_early_print_drivers() -> Result<(), DriverError> {
+    init_early_print_drivers_of(bsp::driver::driver_manager())
+}
+
+/// Bring up the remaining drivers, in registration order, with transient failures retried.
+///
+/// Fails with the compatibility string of the first driver that could not be brought up.
+///
+/// # Safety
+///
+/// - Same as for [`retry_init()`].
+pub unsafe fn init_non_early_print_drivers() -> Result<(), (&'static str, DriverError)> {
+    init_non_early_print_drivers_of(bsp::driver::driver_manager())
+}
+
+/// Record which drivers the BSP registered, along with their configurations, for
+/// [`restore_registry()`].
+///
+/// Fails if there are more drivers than a snapshot can hold. Configurations that are larger than
+/// 16 bytes are cut off.
+pub fn snapshot_registry() -> Result<RegistrySnapshot, &'static str> {
+    snapshot_registry_of(bsp::driver::driver_manager())
+}
+
+/// Bring the drivers recorded in `snapshot` back up, e.g. after a warm reboot, without going
+/// through the regular bring-up.
+///
+/// Fails without touching any driver if the registered drivers differ from the snapshot. Live
+/// hardware state like FIFO contents cannot be recorded, so every driver is reset with its
+/// `init()`, in the same order and with the same retries as during boot. The recorded
+/// configurations are applied afterwards.
+///
+/// # Safety
+///
+/// - Same as for [`interface::DeviceDriver::init()`]. Additionally, the drivers' `init()` must be
+///   safe to call again. Drivers that map their MMIO in `init()` get the existing mapping again.
+pub unsafe fn restore_registry(snapshot: &RegistrySnapshot) -> Result<(), DriverError> {
+    restore_registry_of(bsp::driver::driver_manager(), snapshot)
+}
+
+/// Map a driver's MMIO at `virt_addr`, and switch the driver over to the new mapping with its
+/// `post_mmio_remap()`.
+///
+/// The existing mapping is kept, so the registers stay reachable through either address. Fails
+/// before mapping anything if the driver was not initialized yet. Returns the new start address of
+/// the MMIO region.
+///
+/// # Safety
+///
+/// - Same as for [`memory::mmu::kernel_map_mmio_at()`]. Additionally, `phys_mmio_descriptor` must
+///   describe the driver's registers.
+pub unsafe fn remap_mmio(
+    driver: &dyn interface::DeviceDriver,
+    phys_mmio_descriptor: &MMIODescriptor<Physical>,
+    virt_addr: Address<Virtual>,
+) -> Result<Address<Virtual>, &'static str> {
+    if driver.virt_mmio_start_addr().is_none() {
+        return Err("Driver not initialized yet");
+    }
+
+    let new_addr =
+        memory::mmu::kernel_map_mmio_at(driver.compatible(), phys_mmio_descriptor, virt_addr)?;
+    driver.post_mmio_remap(new_addr.into_usize())?;
+
+    Ok(new_addr)
+}
+
+impl DriverError {
+    /// The human readable reason.
+    pub fn msg(&self) -> &'static str {
+        match self {
+            Self::Transient(x) | Self::InvalidConfig(x) => x,
+        }
+    }
+
+    /// Returns true if the failed operation may be attempted again.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Self::Transient(_))
+    }
+}
+
+/// Plain error strings, e.g. from the memory subsystem, are treated as permanent errors.
+impl From<&'static str> for DriverError {
+    fn from(msg: &'static str) -> Self {
+        Self::InvalidConfig(msg)
+    }
+}
+
+impl fmt::Display for DriverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Transient(x) => write!(f, "{} (transient)", x),
+            Self::InvalidConfig(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+/// Call a driver's `init()` up to `attempts` times, spinning for `backoff` between attempts.
+///
+/// Only [`DriverError::Transient`] errors are retried. At least one attempt is always made. If all
+/// attempts fail, the last error is returned.
+///
+/// # Safety
+///
+/// - Same as for [`interface::DeviceDriver::init()`]. Additionally, the driver's `init()` must be
+///   safe to call again after it failed.
+pub unsafe fn retry_init(
+    driver: &dyn interface::DeviceDriver,
+    attempts: u32,
+    backoff: Duration,
+) -> Result<(), DriverError> {
+    use time::interface::TimeManager;
+
+    let mut attempt = 1;
+    loop {
+        let err = match driver.init() {
+            Ok(()) => return Ok(()),
+            Err(x) => x,
+        };
+
+        if !err.is_transient() || attempt >= attempts {
+            return Err(err);
+        }
+
+        warn!(
+            "{}: init attempt {} of {} failed: {}",
+            driver.compatible(),
+            attempt,
+            attempts,
+            err
+        );
+
+        if backoff > Duration::from_secs(0) {
+            time::time_manager().spin_for(backoff);
+        }
+        attempt += 1;
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+    use test_macros::kernel_test;
+
+    /// A driver whose `init()` fails a number of times before it succeeds.
+    struct FlakyDriver {
+        failures_left: AtomicU32,
+        error: DriverError,
+        calls: AtomicU32,
+    }
+
+    impl FlakyDriver {
+        const fn new(failures: u32, error: DriverError) -> Self {
+            Self {
+                failures_left: AtomicU32::new(failures),
+                error,
+                calls: AtomicU32::new(0),
+            }
+        }
+    }
+
+    impl interface::DeviceDriver for FlakyDriver {
+        fn compatible(&self) -> &'static str {
+            "Flaky test driver"
+        }
+
+        unsafe fn init(&self) -> Result<(), DriverError> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+
+            let left = self.failures_left.load(Ordering::Relaxed);
+            if left == 0 {
+                return Ok(());
+            }
+            self.failures_left.store(left - 1, Ordering::Relaxed);
+
+            Err(self.error)
+        }
+    }
+
+    struct NamedDriver(&'static str);
+
+    /// A driver with a single word of configuration, which `init()` resets.
+    struct ConfigDriver {
+        name: &'static str,
+        config: AtomicU32,
+        inits: AtomicU32,
+    }
+
+    impl ConfigDriver {
+        const fn new(name: &'static str) -> Self {
+            Self {
+                name,
+                config: AtomicU32::new(0),
+                inits: AtomicU32::new(0),
+            }
+        }
+    }
+
+    impl interface::DeviceDriver for ConfigDriver {
+        fn compatible(&self) -> &'static str {
+            self.name
+        }
+
+        unsafe fn init(&self) -> Result<(), DriverError> {
+            self.inits.fetch_add(1, Ordering::Relaxed);
+            self.config.store(0, Ordering::Relaxed);
+
+            Ok(())
+        }
+
+        fn save_config(&self, buf: &mut [u8]) -> usize {
+            buf[..4].copy_from_slice(&self.config.load(Ordering::Relaxed).to_le_bytes());
+
+            4
+        }
+
+        fn restore_config(&self, config: &[u8]) -> Result<(), DriverError> {
+            let mut word = [0; 4];
+            if config.len() != word.len() {
+                return Err(DriverError::InvalidConfig("Bad config"));
+            }
+            word.copy_from_slice(config);
+            self.config
+                .store(u32::from_le_bytes(word), Ordering::Relaxed);
+
+            Ok(())
+        }
+    }
+
+    /// A driver that records the address it was remapped to.
+    struct RemapDriver {
+        mmio_start_addr: AtomicUsize,
+    }
+
+    impl interface::DeviceDriver for RemapDriver {
+        fn compatible(&self) -> &'static str {
+            "Remap test driver"
+        }
+
+        fn virt_mmio_start_addr(&self) -> Option<usize> {
+            match self.mmio_start_addr.load(Ordering::Relaxed) {
+                0 => None,
+                x => Some(x),
+            }
+        }
+
+        unsafe fn post_mmio_remap(&self, new_mmio_start_addr: usize) -> Result<(), &'static str> {
+            self.mmio_start_addr
+                .store(new_mmio_start_addr, Ordering::Relaxed);
+
+            Ok(())
+        }
+    }
+
+    impl interface::DeviceDriver for NamedDriver {
+        fn compatible(&self) -> &'static str {
+            self.0
+        }
+    }
+
+    struct MockDriverManager(&'static [&'static (dyn interface::DeviceDriver + Sync)]);
+
+    impl interface::DriverManager for MockDriverManager {
+        fn all_device_drivers(&self) -> &[&'static (dyn interface::DeviceDriver + Sync)] {
+            self.0
+        }
+
+        fn early_print_device_drivers(&self) -> &[&'static (dyn interface::DeviceDriver + Sync)] {
+            &[]
+        }
+
+        fn non_early_print_device_drivers(
+            &self,
+        ) -> &[&'static (dyn interface::DeviceDriver + Sync)] {
+            self.0
+        }
+
+        fn post_early_print_device_driver_init(&self) {}
+    }
+
+    /// Drivers are yielded in registration order.
+    #[kernel_test]
+    fn drivers_follow_registration_order() {
+        static FIRST: NamedDriver = NamedDriver("first");
+        static SECOND: NamedDriver = NamedDriver("second");
+        static EMPTY: MockDriverManager = MockDriverManager(&[]);
+        static MANAGER: MockDriverManager = MockDriverManager(&[&FIRST, &SECOND]);
+
+        assert!(drivers_of(&EMPTY).next().is_none());
+
+        let mut drivers = drivers_of(&MANAGER).map(|x| x.compatible());
+        assert_eq!(drivers.next(), Some("first"));
+        assert_eq!(drivers.next(), Some("second"));
+        assert_eq!(drivers.next(), None);
+    }
+
+    /// A snapshot records the drivers in order with their configurations, and survives being
+    /// encoded. Restoring it brings each driver up again like during boot, with retries, and
+    /// applies the configurations.
+    #[kernel_test]
+    fn registry_snapshot_round_trips() {
+        static UART: ConfigDriver = ConfigDriver::new("uart");
+        static GPIO: ConfigDriver = ConfigDriver::new("gpio");
+        static FLAKY: FlakyDriver = FlakyDriver::new(1, DriverError::Transient("busy"));
+        static MANAGER: MockDriverManager = MockDriverManager(&[&UART, &GPIO, &FLAKY]);
+        static CHANGED: MockDriverManager = MockDriverManager(&[&GPIO, &UART, &FLAKY]);
+
+        UART.config.store(115_200, Ordering::Relaxed);
+        GPIO.config.store(7, Ordering::Relaxed);
+        let snapshot = snapshot_registry_of(&MANAGER).unwrap();
+        assert_eq!(snapshot.len(), 3);
+
+        let encoded = snapshot.encode();
+        assert_eq!(RegistrySnapshot::decode(&encoded), Some(snapshot));
+        assert_eq!(
+            RegistrySnapshot::decode(&[0; RegistrySnapshot::ENCODED_LEN]),
+            None
+        );
+        assert_eq!(RegistrySnapshot::decode(&encoded[..8]), None);
+
+        UART.config.store(9600, Ordering::Relaxed);
+        let snapshot = RegistrySnapshot::decode(&encoded).unwrap();
+        assert_eq!(unsafe { restore_registry_of(&MANAGER, &snapshot) }, Ok(()));
+        assert_eq!(UART.inits.load(Ordering::Relaxed), 1);
+        assert_eq!(UART.config.load(Ordering::Relaxed), 115_200);
+        assert_eq!(GPIO.config.load(Ordering::Relaxed), 7);
+        assert_eq!(FLAKY.calls.load(Ordering::Relaxed), 2);
+
+        assert!(unsafe { restore_registry_of(&CHANGED, &snapshot) }.is_err());
+        assert_eq!(UART.inits.load(Ordering::Relaxed), 1);
+    }
+
+    /// Transient failures are retried until init succeeds.
+    #[kernel_test]
+    fn retry_init_recovers_from_transient_errors() {
+        let driver = FlakyDriver::new(2, DriverError::Transient("busy"));
+
+        let res = unsafe { retry_init(&driver, 3, Duration::from_micros(10)) };
+        assert_eq!(res, Ok(()));
+        assert_eq!(driver.calls.load(Ordering::Relaxed), 3);
+    }
+
+    /// The last error is returned once all attempts are used up.
+    #[kernel_test]
+    fn retry_init_gives_up_after_attempts() {
+        let driver = FlakyDriver::new(5, DriverError::Transient("busy"));
+
+        let res = unsafe { retry_init(&driver, 2, Duration::from_secs(0)) };
+        assert_eq!(res, Err(DriverError::Transient("busy")));
+        assert_eq!(driver.calls.load(Ordering::Relaxed), 2);
+    }
+
+    /// Permanent errors are not retried.
+    #[kernel_test]
+    fn retry_init_does_not_retry_invalid_config() {
+        let driver = FlakyDriver::new(1, DriverError::InvalidConfig("bad"));
+
+        let res = unsafe { retry_init(&driver, 3, Duration::from_secs(0)) };
+        assert_eq!(res, Err(DriverError::InvalidConfig("bad")));
+        assert_eq!(driver.calls.load(Ordering::Relaxed), 1);
+    }
+
+    /// Remapping maps the registers at the requested address and hands the new start address,
+    /// including the offset into the first page, to the driver.
+    #[kernel_test]
+    fn remap_mmio_switches_driver_to_new_mapping() {
+        use bsp::memory::mmu::KernelGranule;
+        use interface::DeviceDriver;
+        use memory::mmu::interface::TranslationGranule;
+
+        unsafe { memory::mmu::kernel_init_translation_tables() };
+
+        let descriptor = MMIODescriptor::new(Address::new(2 * KernelGranule::SIZE + 0x40), 0x48);
+        let virt_addr = Address::new(8 * KernelGranule::SIZE);
+        let driver = RemapDriver {
+            mmio_start_addr: AtomicUsize::new(0),
+        };
+
+        // Not initialized yet, so nothing is mapped.
+        assert!(unsafe { remap_mmio(&driver, &descriptor, virt_addr) }.is_err());
+        assert_eq!(driver.virt_mmio_start_addr(), None);
+
+        driver
+            .mmio_start_addr
+            .store(2 * KernelGranule::SIZE + 0x40, Ordering::Relaxed);
+        // Would fail if the first attempt had mapped the pages.
+        let new_addr = unsafe { remap_mmio(&driver, &descriptor, virt_addr) }.unwrap();
+        assert!(new_addr == virt_addr + 0x40);
+        assert_eq!(driver.virt_mmio_start_addr(), Some(new_addr.into_usize()));
+
+        // Already mapped.
+        assert!(unsafe { remap_mmio(&driver, &descriptor, virt_addr) }.is_err());
+    }
+}
@@ -22,6 +22,7 @@ pub enum PrivilegeLevel {
     User,
     Kernel,
     Hypervisor,
+    SecureMonitor,
     Unknown,
 }
 
@@ -9,12 +9,32 @@
 mod arch_exception_async;
 pub use arch_exception_async::*;
 
+use crate::{bsp, synchronization, synchronization::InitStateLock, warn};
 use core::{fmt, marker::PhantomData};
 
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The handlers of an [`IRQHandlerChain`], in the order they were added. Free slots are at the
+/// end.
+type ChainedHandlers<const N: usize> = [Option<&'static (dyn interface::IRQHandler + Sync)>; N];
+
 //--------------------------------------------------------------------------------------------------
 // Public Definitions
 //--------------------------------------------------------------------------------------------------
 
+/// Interrupt priority.
+///
+/// If multiple IRQs are pending, those with higher priority are handled first.
+#[allow(missing_docs)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum IRQPriority {
+    Low,
+    Normal,
+    High,
+}
+
 /// Interrupt descriptor.
 #[derive(Copy, Clone)]
 pub struct IRQDescriptor {
@@ -23,8 +43,36 @@ pub struct IRQDescriptor {
 
     /// Reference to handler trait object.
     pub handler: &'static (dyn interface::IRQHandler + Sync),
+
+    /// Priority of the interrupt.
+    pub priority: IRQPriority,
+}
+
+/// Which handlers of an [`IRQHandlerChain`] run.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum IRQChainMode {
+    /// Stop at the first handler that claims the IRQ.
+    FirstClaim,
+
+    /// Run all handlers, e.g. so that one that only observes the device sees every IRQ.
+    All,
 }
 
+/// Several handlers for one IRQ source, called in the order they were added.
+///
+/// IRQ managers take a single handler per source, so the chain is registered as that handler, and
+/// the actual handlers are added to the chain. It holds at most `N` of them. Like the handler
+/// tables of the IRQ managers, it is writable only during kernel init.
+pub struct IRQHandlerChain<const N: usize> {
+    mode: IRQChainMode,
+    handlers: InitStateLock<ChainedHandlers<N>>,
+}
+
+/// Handler for IRQs that fire without a registered handler of their own.
+///
+/// Receives the IRQ number and a function that masks the IRQ in the interrupt controller.
+pub type DefaultIRQHandler = fn(irq_number: usize, mask: &dyn Fn());
+
 /// IRQContext token.
 ///
 /// An instance of this type indicates that the local core is currently executing in IRQ
@@ -44,6 +92,16 @@ pub mod interface {
     pub trait IRQHandler {
         /// Called when the corresponding interrupt is asserted.
         fn handle(&self) -> Result<(), &'static str>;
+
+        /// Called instead of `handle()` as part of an [`super::IRQHandlerChain`].
+        ///
+        /// Returns true if the handler claimed the interrupt. By default, calls `handle()` and
+        /// claims it.
+        fn handle_chained(&self) -> Result<bool, &'static str> {
+            self.handle()?;
+
+            Ok(true)
+        }
     }
 
     /// IRQ management functions.
@@ -61,9 +119,25 @@ pub mod interface {
             descriptor: super::IRQDescriptor,
         ) -> Result<(), &'static str>;
 
+        /// Register the handler for IRQs that have none of their own, replacing the previous one.
+        ///
+        /// Until one is registered, [`super::mask_unhandled_irq()`] is used.
+        fn register_default_handler(&self, handler: super::DefaultIRQHandler);
+
         /// Enable an interrupt in the controller.
         fn enable(&self, irq_number: Self::IRQNumberType);
 
+        /// Disable an interrupt in the controller.
+        fn disable(&self, irq_number: Self::IRQNumberType);
+
+        /// Returns true if the interrupt is enabled in the controller.
+        fn is_enabled(&self, irq_number: Self::IRQNumberType) -> bool;
+
+        /// Change the priority of an interrupt, overriding the one from its descriptor.
+        ///
+        /// Interrupts of equal priority are handled in an implementation-defined order.
+        fn set_priority(&self, irq_number: Self::IRQNumberType, priority: super::IRQPriority);
+
         /// Handle pending interrupts.
         ///
         /// This function is called directly from the CPU's IRQ exception vector. On AArch64,
@@ -89,6 +163,7 @@ pub struct IRQNumber<const MAX_INCLUSIVE: usize>(usize);
 //--------------------------------------------------------------------------------------------------
 // Public Code
 //--------------------------------------------------------------------------------------------------
+use synchronization::interface::ReadWriteEx;
 
 impl<'irq_context> IRQContext<'irq_context> {
     /// Creates an IRQContext token.
@@ -109,10 +184,23 @@ impl<'irq_context> IRQContext<'irq_context> {
 
 impl<const MAX_INCLUSIVE: usize> IRQNumber<{ MAX_INCLUSIVE }> {
     /// Creates a new instance if number <= MAX_INCLUSIVE.
+    ///
+    /// Panics otherwise, which fails the build if used in a constant.
     pub const fn new(number: usize) -> Self {
-        assert!(number <= MAX_INCLUSIVE);
+        match Self::try_new(number) {
+            Ok(x) => x,
+            Err(_) => panic!("IRQ number out of range"),
+        }
+    }
 
-        Self { 0: number }
+    /// Creates a new instance if number <= MAX_INCLUSIVE, for numbers that are only known at
+    /// runtime.
+    pub const fn try_new(number: usize) -> Result<Self, &'static str> {
+        if number > MAX_INCLUSIVE {
+            return Err("IRQ number out of range");
+        }
+
+        Ok(Self { 0: number })
     }
 
     /// Return the wrapped number.
@@ -127,6 +215,68 @@ impl<const MAX_INCLUSIVE: usize> fmt::Display for IRQNumber<{ MAX_INCLUSIVE }> {
     }
 }
 
+impl<const N: usize> IRQHandlerChain<{ N }> {
+    /// Create an empty instance.
+    pub const fn new(mode: IRQChainMode) -> Self {
+        Self {
+            mode,
+            handlers: InitStateLock::new([None; N]),
+        }
+    }
+
+    /// Append `handler` to the chain.
+    pub fn add(
+        &self,
+        handler: &'static (dyn interface::IRQHandler + Sync),
+    ) -> Result<(), &'static str> {
+        self.handlers.write(|handlers| {
+            let slot = handlers
+                .iter_mut()
+                .find(|x| x.is_none())
+                .ok_or("IRQ handler chain is full")?;
+            *slot = Some(handler);
+
+            Ok(())
+        })
+    }
+}
+
+impl<const N: usize> interface::IRQHandler for IRQHandlerChain<{ N }> {
+    /// Fails if no handler claimed the IRQ, since it would keep firing.
+    fn handle(&self) -> Result<(), &'static str> {
+        if !self.handle_chained()? {
+            return Err("No handler in the chain claimed the IRQ");
+        }
+
+        Ok(())
+    }
+
+    /// Claims the IRQ if any of the handlers did, so that chains can be nested. An error stops
+    /// the chain.
+    fn handle_chained(&self) -> Result<bool, &'static str> {
+        self.handlers.read(|handlers| {
+            let mut claimed = false;
+
+            for handler in handlers.iter().flatten() {
+                claimed |= handler.handle_chained()?;
+
+                if claimed && self.mode == IRQChainMode::FirstClaim {
+                    break;
+                }
+            }
+
+            Ok(claimed)
+        })
+    }
+}
+
+/// The default [`DefaultIRQHandler`]. Logs the IRQ number and masks the IRQ, so that a source
+/// nobody handles cannot keep the core busy.
+pub fn mask_unhandled_irq(irq_number: usize, mask: &dyn Fn()) {
+    warn!("No handler registered for IRQ {}, masking it", irq_number);
+    mask();
+}
+
 /// Executes the provided closure while IRQs are masked on the executing core.
 ///
 /// While the function temporarily changes the HW state of the executing core, it restores it to the
@@ -143,3 +293,116 @@ pub fn exec_with_irq_masked<T>(f: impl FnOnce() -> T) -> T {
 
     ret
 }
+
+/// Executes the provided closure while the given IRQ is enabled in the controller.
+///
+/// Unlike [`exec_with_irq_masked()`], this only affects a single interrupt source. If the IRQ was
+/// disabled before, it is disabled again afterwards. If it was already enabled, e.g. by an
+/// enclosing call, it stays enabled.
+pub fn with_irq_unmasked<T>(irq: bsp::device_driver::IRQNumber, f: impl FnOnce() -> T) -> T {
+    exec_with_irq_enabled_on(bsp::exception::asynchronous::irq_manager(), irq, f)
+}
+
+/// Executes the provided closure while `irq` is enabled in the given IRQ manager.
+fn exec_with_irq_enabled_on<M, T>(
+    irq_manager: &M,
+    irq: M::IRQNumberType,
+    f: impl FnOnce() -> T,
+) -> T
+where
+    M: interface::IRQManager + ?Sized,
+    M::IRQNumberType: Copy,
+{
+    let was_enabled = irq_manager.is_enabled(irq);
+    if !was_enabled {
+        irq_manager.enable(irq);
+    }
+
+    let ret = f();
+
+    if !was_enabled {
+        irq_manager.disable(irq);
+    }
+
+    ret
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+    use test_macros::kernel_test;
+
+    /// Tracks the enable state of 64 IRQs.
+    struct MockIRQManager {
+        enabled: Cell<u64>,
+    }
+
+    impl interface::IRQManager for MockIRQManager {
+        type IRQNumberType = usize;
+
+        fn register_handler(&self, _: usize, _: IRQDescriptor) -> Result<(), &'static str> {
+            Ok(())
+        }
+
+        fn register_default_handler(&self, _: DefaultIRQHandler) {}
+
+        fn enable(&self, irq_number: usize) {
+            self.enabled.set(self.enabled.get() | (1 << irq_number));
+        }
+
+        fn disable(&self, irq_number: usize) {
+            self.enabled.set(self.enabled.get() & !(1 << irq_number));
+        }
+
+        fn is_enabled(&self, irq_number: usize) -> bool {
+            self.enabled.get() & (1 << irq_number) != 0
+        }
+
+        fn set_priority(&self, _: usize, _: IRQPriority) {}
+
+        fn handle_pending_irqs<'irq_context>(&'irq_context self, _: &IRQContext<'irq_context>) {}
+
+        fn print_handler(&self) {}
+    }
+
+    /// The range check follows the maximum of the respective controller.
+    #[kernel_test]
+    fn irq_number_try_new_checks_range() {
+        assert_eq!(IRQNumber::<63>::try_new(57).map(IRQNumber::get), Ok(57));
+        assert_eq!(IRQNumber::<63>::try_new(63).map(IRQNumber::get), Ok(63));
+        assert!(IRQNumber::<63>::try_new(64).is_err());
+        assert!(IRQNumber::<63>::try_new(153).is_err());
+        assert_eq!(IRQNumber::<300>::try_new(153).map(IRQNumber::get), Ok(153));
+    }
+
+    /// The IRQ is enabled inside the closure only, nested calls leave restoring to the outermost.
+    #[kernel_test]
+    fn exec_with_irq_enabled_restores_state() {
+        use interface::IRQManager;
+
+        let manager = MockIRQManager {
+            enabled: Cell::new(1 << 2),
+        };
+
+        let ret = exec_with_irq_enabled_on(&manager, 7, || {
+            assert!(manager.is_enabled(7));
+
+            exec_with_irq_enabled_on(&manager, 7, || assert!(manager.is_enabled(7)));
+            assert!(manager.is_enabled(7));
+
+            42
+        });
+
+        assert_eq!(ret, 42);
+        assert_eq!(manager.enabled.get(), 1 << 2);
+
+        // Already enabled sources stay enabled.
+        exec_with_irq_enabled_on(&manager, 2, || ());
+        assert!(manager.is_enabled(2));
+    }
+}
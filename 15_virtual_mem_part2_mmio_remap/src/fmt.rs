@@ -0,0 +1,88 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Formatting helpers.
+
+use core::fmt;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+const UNITS: [&str; 7] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A byte count that is displayed with a binary unit, e.g. `1.5 KiB`.
+///
+/// Created by [`human_bytes()`].
+#[derive(Copy, Clone)]
+pub struct HumanBytes(u64);
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Wrap a byte count for display with a binary unit.
+pub fn human_bytes(n: u64) -> HumanBytes {
+    HumanBytes(n)
+}
+
+impl fmt::Display for HumanBytes {
+    /// Counts below 1 KiB are printed as is, everything else with one decimal, rounded to nearest.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 < 1024 {
+            return write!(f, "{} {}", self.0, UNITS[0]);
+        }
+
+        // In tenths of the unit. u128, so that the scaling cannot overflow.
+        let scaled = |unit_index: usize| {
+            let unit = 1u128 << (10 * unit_index);
+            (u128::from(self.0) * 10 + unit / 2) / unit
+        };
+
+        let mut unit_index = 1;
+        while unit_index < UNITS.len() - 1 && scaled(unit_index) >= 1024 * 10 {
+            unit_index += 1;
+        }
+
+        let tenths = scaled(unit_index);
+        write!(f, "{}.{} {}", tenths / 10, tenths % 10, UNITS[unit_index])
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::FmtBuf;
+    use core::fmt::Write;
+    use test_macros::kernel_test;
+
+    fn format(n: u64) -> FmtBuf<16> {
+        let mut buf = FmtBuf::new();
+        write!(buf, "{}", human_bytes(n)).unwrap();
+
+        buf
+    }
+
+    /// Small counts are exact, larger ones use the biggest unit that keeps the value below 1024.
+    #[kernel_test]
+    fn human_bytes_picks_unit() {
+        assert_eq!(format(0).as_str(), "0 B");
+        assert_eq!(format(1023).as_str(), "1023 B");
+        assert_eq!(format(1024).as_str(), "1.0 KiB");
+        assert_eq!(format(1536).as_str(), "1.5 KiB");
+        assert_eq!(format(3 * 1024 * 1024 + 400 * 1024).as_str(), "3.4 MiB");
+
+        // Rounding up to 1024 KiB switches to the next unit.
+        assert_eq!(format(1024 * 1024 - 1).as_str(), "1.0 MiB");
+        assert_eq!(format(u64::MAX).as_str(), "16.0 EiB");
+    }
+}
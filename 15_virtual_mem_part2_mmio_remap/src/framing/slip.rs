@@ -0,0 +1,179 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! SLIP framing, as in RFC 1055.
+//!
+//! Packets are sent between `END` bytes. `END` and `ESC` within a packet are replaced by `ESC
+//! ESC_END` and `ESC ESC_ESC`, so that `END` can only appear as a delimiter. The leading `END`
+//! flushes whatever noise the receiver picked up since the previous packet.
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Delimits packets.
+pub const END: u8 = 0xc0;
+
+/// Starts an escape sequence.
+pub const ESC: u8 = 0xdb;
+
+/// `END` within a packet, after `ESC`.
+pub const ESC_END: u8 = 0xdc;
+
+/// `ESC` within a packet, after `ESC`.
+pub const ESC_ESC: u8 = 0xdd;
+
+/// Reassembles received packets of up to `N` bytes.
+pub struct Decoder<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    escaped: bool,
+    too_long: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Hand the framed and escaped `packet` to `out`, one byte at a time.
+pub fn encode(packet: &[u8], mut out: impl FnMut(u8)) {
+    out(END);
+
+    for byte in packet {
+        match *byte {
+            END => {
+                out(ESC);
+                out(ESC_END);
+            }
+            ESC => {
+                out(ESC);
+                out(ESC_ESC);
+            }
+            x => out(x),
+        }
+    }
+
+    out(END);
+}
+
+impl<const N: usize> Decoder<{ N }> {
+    /// Create an instance that waits for the first packet.
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+            escaped: false,
+            too_long: false,
+        }
+    }
+
+    /// Process a received byte. Returns the packet it completed, if any.
+    ///
+    /// Empty packets, e.g. between the trailing and the leading `END` of two packets, are
+    /// skipped. An `ESC` followed by anything else than `ESC_END` or `ESC_ESC` is a protocol
+    /// violation; the byte after it is kept as is, as recommended by the RFC. Packets longer than
+    /// `N` bytes are dropped in full, and reported once their `END` arrives.
+    pub fn feed(&mut self, byte: u8) -> Result<Option<&[u8]>, &'static str> {
+        if byte == END {
+            let len = self.len;
+            let too_long = self.too_long;
+            self.len = 0;
+            self.escaped = false;
+            self.too_long = false;
+
+            if too_long {
+                return Err("SLIP packet is too long");
+            }
+
+            return Ok(if len > 0 {
+                Some(&self.buf[..len])
+            } else {
+                None
+            });
+        }
+
+        let byte = match (self.escaped, byte) {
+            (false, ESC) => {
+                self.escaped = true;
+                return Ok(None);
+            }
+            (true, ESC_END) => END,
+            (true, ESC_ESC) => ESC,
+            (_, x) => x,
+        };
+        self.escaped = false;
+
+        if self.len == N {
+            self.too_long = true;
+        } else {
+            self.buf[self.len] = byte;
+            self.len += 1;
+        }
+
+        Ok(None)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_macros::kernel_test;
+
+    /// Encode `packet` into `out`, returning the length.
+    fn encode_into(packet: &[u8], out: &mut [u8; 32]) -> usize {
+        let mut len = 0;
+        encode(packet, |byte| {
+            out[len] = byte;
+            len += 1;
+        });
+
+        len
+    }
+
+    /// Packets with delimiter bytes in them are escaped, and come out of the decoder as they
+    /// went in.
+    #[kernel_test]
+    fn packets_round_trip_with_embedded_delimiters() {
+        let packets: [&[u8]; 3] = [b"abc", &[END, 1, ESC, ESC_END, END], &[ESC, ESC]];
+        let mut decoder: Decoder<8> = Decoder::new();
+        let mut encoded = [0; 32];
+
+        let len = encode_into(packets[1], &mut encoded);
+        assert_eq!(
+            encoded[..len],
+            [END, ESC, ESC_END, 1, ESC, ESC_ESC, ESC_END, ESC, ESC_END, END]
+        );
+
+        for packet in packets.iter() {
+            let len = encode_into(packet, &mut encoded);
+            let (last, rest) = encoded[..len].split_last().unwrap();
+
+            for byte in rest {
+                assert_eq!(decoder.feed(*byte), Ok(None));
+            }
+            assert_eq!(decoder.feed(*last), Ok(Some(*packet)));
+        }
+    }
+
+    /// Overlong packets are dropped without affecting the next one, and a bad escape keeps the
+    /// byte after it.
+    #[kernel_test]
+    fn overlong_packet_is_dropped() {
+        let mut decoder: Decoder<4> = Decoder::new();
+
+        for byte in b"abcde".iter() {
+            assert_eq!(decoder.feed(*byte), Ok(None));
+        }
+        assert_eq!(decoder.feed(END), Err("SLIP packet is too long"));
+
+        for byte in [b'a', ESC, b'b', b'c', b'd'].iter() {
+            assert_eq!(decoder.feed(*byte), Ok(None));
+        }
+        assert_eq!(decoder.feed(END), Ok(Some(&b"abcd"[..])));
+    }
+}
@@ -118,6 +118,7 @@
 #![feature(const_generics)]
 #![feature(const_panic)]
 #![feature(core_intrinsics)]
+#![feature(fmt_as_str)]
 #![feature(format_args_nl)]
 #![feature(global_asm)]
 #![feature(linkage)]
@@ -134,20 +135,29 @@
 // `mod cpu` provides the `_start()` function, the first function to run. `_start()` then calls
 // `runtime_init()`, which jumps to `kernel_init()` (defined in `main.rs`).
 
-mod panic_wait;
 mod runtime_init;
 mod synchronization;
 
+pub mod boot;
 pub mod bsp;
+pub mod chainloader;
+pub mod checksum;
+pub mod collections;
 pub mod common;
 pub mod console;
 pub mod cpu;
 pub mod driver;
 pub mod exception;
+pub mod fmt;
+pub mod framing;
 pub mod memory;
+pub mod panic_wait;
 pub mod print;
+pub mod shell;
 pub mod state;
+pub mod telemetry;
 pub mod time;
+pub mod xmodem;
 
 //--------------------------------------------------------------------------------------------------
 // Testing
@@ -11,7 +11,7 @@
 #![no_main]
 #![no_std]
 
-use libkernel::{bsp, cpu, driver, exception, info, memory, state, time, warn};
+use libkernel::{boot, bsp, cpu, driver, exception, info, memory, state, time, warn};
 
 /// Early init code.
 ///
@@ -29,30 +29,29 @@ unsafe fn kernel_init() -> ! {
 
     exception::handling_init();
 
+    boot::progress("Enabling MMU");
     if let Err(string) = memory::mmu::kernel_map_binary_and_enable_mmu() {
         panic!("Enabling MMU failed: {}", string);
     }
     // Printing will silently fail fail from here on, because the driver's MMIO is not remapped yet.
 
-    // Bring up the drivers needed for printing first.
-    for i in bsp::driver::driver_manager()
-        .early_print_device_drivers()
-        .iter()
-    {
-        // Any encountered errors cannot be printed yet, obviously, so just safely park the CPU.
-        i.init().unwrap_or_else(|_| cpu::wait_forever());
-    }
-    bsp::driver::driver_manager().post_early_print_device_driver_init();
+    // Bring up the drivers needed for printing first. Any encountered errors cannot be printed
+    // yet, obviously, so just safely park the CPU.
+    driver::init_early_print_drivers().unwrap_or_else(|_| cpu::wait_forever());
     // Printing available again from here on.
 
+    // Continue at the serial settings that the kernel before a warm reboot left, if any.
+    if let Err(x) = bsp::console::uart().restore_persisted_config() {
+        warn!("Restoring the UART configuration failed: {}", x);
+    }
+
+    #[cfg(feature = "interactive_gate")]
+    libkernel::print::close_interactive_gate();
+
     // Now bring up the remaining drivers.
-    for i in bsp::driver::driver_manager()
-        .non_early_print_device_drivers()
-        .iter()
-    {
-        if let Err(x) = i.init() {
-            panic!("Error loading driver: {}: {}", i.compatible(), x);
-        }
+    boot::progress("Initializing drivers");
+    if let Err((compatible, x)) = driver::init_non_early_print_drivers() {
+        panic!("Error loading driver: {}: {}", compatible, x);
     }
 
     // Let device drivers register and enable their handlers with the interrupt controller.
@@ -64,6 +63,7 @@ unsafe fn kernel_init() -> ! {
 
     // Unmask interrupts on the boot CPU core.
     exception::asynchronous::local_irq_unmask();
+    boot::progress_done();
 
     // Announce conclusion of the kernel_init() phase.
     state::state_manager().transition_to_single_core_main();
@@ -87,12 +87,17 @@ fn kernel_main() -> ! {
 
     info!("Exception handling state:");
     exception::asynchronous::print_state();
+    exception::print_vector_report();
 
     info!(
         "Architectural timer resolution: {} ns",
         time::time_manager().resolution().as_nanos()
     );
 
+    if let Some(x) = boot::console_ready_time() {
+        info!("Console ready after: {} us", x.as_micros());
+    }
+
     info!("Drivers loaded:");
     for (i, driver) in bsp::driver::driver_manager()
         .all_device_drivers()
@@ -105,6 +110,9 @@ fn kernel_main() -> ! {
     info!("Registered IRQ handlers:");
     bsp::exception::asynchronous::irq_manager().print_handler();
 
+    #[cfg(feature = "mmio_counters")]
+    driver::print_mmio_accesses();
+
     info!("Echoing input now");
     cpu::wait_forever();
 }
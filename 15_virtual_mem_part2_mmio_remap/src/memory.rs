@@ -4,6 +4,8 @@
 
 //! Memory Management.
 
+pub mod cache;
+pub mod dma;
 pub mod mmu;
 
 use core::ops::RangeInclusive;
@@ -0,0 +1,13 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Data cache maintenance.
+//!
+//! Needed for memory that is shared with bus masters which do not snoop the caches, e.g. the
+//! VideoCore or DMA controllers. All addresses are virtual.
+
+#[cfg(target_arch = "aarch64")]
+#[path = "../_arch/aarch64/memory/cache.rs"]
+mod arch_cache;
+pub use arch_cache::*;
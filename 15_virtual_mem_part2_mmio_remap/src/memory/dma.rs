@@ -0,0 +1,144 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Buffers shared with DMA controllers.
+//!
+//! DMA controllers do not snoop the data caches. Before a transfer from memory, the buffer must be
+//! cleaned, or the controller reads stale data. Before and after a transfer to memory, it must be
+//! invalidated, or the core reads stale data, or an evicted line overwrites what was transferred.
+//!
+//! DMA drivers call [`prepare_tx()`], [`prepare_rx()`] and [`complete_rx()`] around their
+//! transfers. Translating the buffer address into a bus address is up to them.
+
+use super::cache;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Required alignment of a DMA buffer's start and length, in bytes.
+///
+/// The BCM DMA controller needs 32 bytes. A cache line of the Cortex-A53 and A72 is 64 bytes, and
+/// invalidating a partial line would discard the data that shares it with the buffer.
+pub const BUFFER_ALIGN: usize = 64;
+
+/// Maximum length of a single transfer of the BCM DMA controller, in bytes.
+///
+/// The largest value of the 30 bit `TXFR_LEN` field. With the alignment, the longest buffer is
+/// 64 bytes shorter.
+pub const MAX_TRANSFER_LEN: usize = (1 << 30) - 1;
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// `check_buffer()` for a buffer at `start` of `len` bytes.
+fn check_region(start: usize, len: usize) -> Result<(), &'static str> {
+    if len == 0 {
+        return Err("DMA buffer is empty");
+    }
+
+    if start % BUFFER_ALIGN != 0 {
+        return Err("DMA buffer start is not 64 byte aligned");
+    }
+
+    if len % BUFFER_ALIGN != 0 {
+        return Err("DMA buffer length is not a multiple of 64 bytes");
+    }
+
+    if len > MAX_TRANSFER_LEN {
+        return Err("DMA buffer is longer than a single transfer");
+    }
+
+    Ok(())
+}
+
+/// `prepare_tx()` with the cache maintenance passed in.
+fn prepare_tx_with(buf: &[u8], clean: impl FnOnce(usize, usize)) -> Result<(), &'static str> {
+    check_buffer(buf)?;
+    clean(buf.as_ptr() as usize, buf.len());
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Check that `buf` satisfies the alignment and length constraints of DMA buffers.
+pub fn check_buffer(buf: &[u8]) -> Result<(), &'static str> {
+    check_region(buf.as_ptr() as usize, buf.len())
+}
+
+/// Get `buf` ready to be read by a DMA controller.
+pub fn prepare_tx(buf: &[u8]) -> Result<(), &'static str> {
+    prepare_tx_with(buf, cache::clean_range)
+}
+
+/// Get `buf` ready to be written by a DMA controller.
+///
+/// Dirty lines are discarded, so that they cannot be evicted on top of the transferred data.
+pub fn prepare_rx(buf: &mut [u8]) -> Result<(), &'static str> {
+    check_buffer(buf)?;
+    cache::invalidate_range(buf.as_ptr() as usize, buf.len());
+
+    Ok(())
+}
+
+/// Make the data that a DMA controller wrote to `buf` visible to the core.
+///
+/// Must be called after the transfer has finished, because the core might have speculatively
+/// fetched lines of the buffer while it was in progress.
+pub fn complete_rx(buf: &mut [u8]) {
+    cache::invalidate_range(buf.as_ptr() as usize, buf.len());
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_macros::kernel_test;
+
+    #[repr(align(64))]
+    struct AlignedBuffer([u8; 2 * BUFFER_ALIGN]);
+
+    /// Buffers that violate the constraints are rejected before the cache is touched. Valid ones
+    /// are cleaned in full.
+    #[kernel_test]
+    fn prepare_tx_checks_and_cleans() {
+        let buf = AlignedBuffer([0; 2 * BUFFER_ALIGN]);
+        let mut cleaned = None;
+
+        let misaligned = &buf.0[1..BUFFER_ALIGN + 1];
+        assert_eq!(
+            prepare_tx_with(misaligned, |start, len| cleaned = Some((start, len))),
+            Err("DMA buffer start is not 64 byte aligned")
+        );
+        assert_eq!(
+            check_buffer(&buf.0[..BUFFER_ALIGN - 1]),
+            Err("DMA buffer length is not a multiple of 64 bytes")
+        );
+        assert_eq!(check_buffer(&buf.0[..0]), Err("DMA buffer is empty"));
+        assert_eq!(cleaned, None);
+
+        assert_eq!(
+            prepare_tx_with(&buf.0, |start, len| cleaned = Some((start, len))),
+            Ok(())
+        );
+        assert_eq!(cleaned, Some((buf.0.as_ptr() as usize, 2 * BUFFER_ALIGN)));
+    }
+
+    /// The length must fit the 30 bit length field of a transfer.
+    #[kernel_test]
+    fn transfer_length_fits_the_length_field() {
+        assert_eq!(check_region(0, (1 << 30) - BUFFER_ALIGN), Ok(()));
+        assert_eq!(
+            check_region(0, 1 << 30),
+            Err("DMA buffer is longer than a single transfer")
+        );
+    }
+}
@@ -12,8 +12,13 @@ pub use arch_mmu::*;
 mod mapping_record;
 mod types;
 
-use crate::{bsp, synchronization, warn};
+use crate::{bsp, bsp::device_driver::common::MMIODerefWrapper, synchronization, warn};
+use core::{
+    mem::size_of,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
+pub use mapping_record::MmioMapping;
 pub use types::*;
 
 //--------------------------------------------------------------------------------------------------
@@ -75,6 +80,12 @@ pub mod interface {
 
         /// Check if a virtual page splice is in the "MMIO region".
         fn is_virt_page_slice_mmio(&self, virt_pages: &PageSliceDescriptor<Virtual>) -> bool;
+
+        /// Walk the tables for `virt_addr`.
+        ///
+        /// Returns `None` if the address is not mapped, including when a table on the way is
+        /// present but the final entry is not.
+        fn translate(&self, virt_addr: Address<Virtual>) -> Option<Translation>;
     }
 
     /// MMU functions.
@@ -89,9 +100,19 @@ pub mod interface {
             &self,
             phys_kernel_table_base_addr: Address<Physical>,
         ) -> Result<(), &'static str>;
+
+        /// Returns true if the MMU is on.
+        fn is_enabled(&self) -> bool;
     }
 }
 
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+/// Set while the kernel's view of memory changes, see [`in_transition()`].
+static IN_TRANSITION: AtomicBool = AtomicBool::new(false);
+
 //--------------------------------------------------------------------------------------------------
 // Private Code
 //--------------------------------------------------------------------------------------------------
@@ -194,26 +215,149 @@ pub unsafe fn kernel_map_mmio(
     Ok(virt_addr + offset_into_start_page)
 }
 
+/// MMIO mapping in the kernel translation tables at a virtual address chosen by the caller.
+///
+/// Useful for identity mapping, where `requested_virt_addr` would be the page-aligned start
+/// address of the descriptor. The returned address points to the start of the MMIO region, so
+/// it includes the descriptor's offset into its first page.
+///
+/// Fails if `requested_virt_addr` is not page-aligned, lies inside the range that is reserved
+/// for `kernel_map_mmio()`, or if any of the target pages is already mapped.
+///
+/// # Safety
+///
+/// - Same as `kernel_map_pages_at()`.
+pub unsafe fn kernel_map_mmio_at(
+    name: &'static str,
+    phys_mmio_descriptor: &MMIODescriptor<Physical>,
+    requested_virt_addr: Address<Virtual>,
+) -> Result<Address<Virtual>, &'static str> {
+    if requested_virt_addr.into_usize() & bsp::memory::mmu::KernelGranule::MASK != 0 {
+        return Err("Requested virtual address is not page-aligned");
+    }
+
+    let phys_pages: PageSliceDescriptor<Physical> = phys_mmio_descriptor.clone().into();
+    let virt_pages = PageSliceDescriptor::from_addr(requested_virt_addr, phys_pages.num_pages());
+    let offset_into_start_page =
+        phys_mmio_descriptor.start_addr().into_usize() & bsp::memory::mmu::KernelGranule::MASK;
+
+    kernel_map_pages_at(
+        name,
+        &phys_pages,
+        &virt_pages,
+        &AttributeFields {
+            mem_attributes: MemAttributes::Device,
+            acc_perms: AccessPermissions::ReadWrite,
+            execute_never: true,
+        },
+    )?;
+
+    Ok(requested_virt_addr + offset_into_start_page)
+}
+
+/// MMIO remapping in the kernel translation tables, returning a typed wrapper for the registers.
+///
+/// Fails if the MMIO aperture is smaller than the register block `T`.
+///
+/// # Safety
+///
+/// - Same as `kernel_map_mmio()`.
+/// - The user must ensure that `T` correctly describes the device's registers.
+pub unsafe fn map_mmio_registers<T>(
+    name: &'static str,
+    phys_mmio_descriptor: &MMIODescriptor<Physical>,
+) -> Result<MMIODerefWrapper<T>, &'static str> {
+    if phys_mmio_descriptor.size() < size_of::<T>() {
+        return Err("MMIO aperture is smaller than the register block");
+    }
+
+    let virt_addr = kernel_map_mmio(name, phys_mmio_descriptor)?;
+
+    Ok(MMIODerefWrapper::new(virt_addr.into_usize()))
+}
+
 /// Map the kernel's binary and enable the MMU.
 ///
 /// # Safety
 ///
 /// - Crucial function during kernel init. Changes the the complete memory view of the processor.
 pub unsafe fn kernel_map_binary_and_enable_mmu() -> Result<(), &'static str> {
-    let phys_base_addr = arch_mmu::kernel_translation_tables().write(|tables| {
-        tables.init();
-        tables.phys_base_address()
-    });
+    // The MMU is still off while the tables are set up, so the physical addresses are reachable.
+    // Once it is on, they are not mapped anymore, so the transition ends before.
+    let phys_base_addr = transition(|| {
+        let phys_base_addr = arch_mmu::kernel_translation_tables().write(|tables| {
+            tables.init();
+            tables.phys_base_address()
+        });
+
+        bsp::memory::mmu::kernel_map_binary().map(|_| phys_base_addr)
+    })?;
 
-    bsp::memory::mmu::kernel_map_binary()?;
     arch_mmu::mmu().enable(phys_base_addr)
 }
 
+/// Run `f`, which changes the kernel's view of memory, with [`in_transition()`] returning true.
+///
+/// # Safety
+///
+/// - Drivers that check `in_transition()` use the physical addresses of their registers while `f`
+///   runs. The caller must ensure that those stay reachable, e.g. by keeping the MMU off or the
+///   MMIO regions identity mapped.
+pub unsafe fn transition<R>(f: impl FnOnce() -> R) -> R {
+    IN_TRANSITION.store(true, Ordering::Release);
+    let ret = f();
+    IN_TRANSITION.store(false, Ordering::Release);
+
+    ret
+}
+
+/// Returns true while the kernel translation tables are being set up, when virtual MMIO addresses
+/// might not be valid yet.
+///
+/// Drivers that print, like the console, use the physical address of their registers meanwhile.
+/// They must check this under the same lock that serializes their register accesses, so that a
+/// single write does not go out partly through either one.
+pub fn in_transition() -> bool {
+    IN_TRANSITION.load(Ordering::Acquire)
+}
+
+/// Returns true if the MMU is on, i.e. addresses are virtual.
+pub fn is_enabled() -> bool {
+    arch_mmu::mmu().is_enabled()
+}
+
+/// Look up where `virt_addr` points to in the kernel translation tables.
+///
+/// Does not depend on the MMU being on, so it can be used to debug translation faults.
+pub fn translate(virt_addr: Address<Virtual>) -> Option<Translation> {
+    arch_mmu::kernel_translation_tables().read(|tables| tables.translate(virt_addr))
+}
+
 /// Human-readable print of all recorded kernel mappings.
 pub fn kernel_print_mappings() {
     mapping_record::kernel_print()
 }
 
+/// Iterate over the MMIO mappings created so far, e.g. by `kernel_map_mmio()`.
+///
+/// The mappings are copied when this is called, so later changes are not reflected by an
+/// iterator that already exists.
+pub fn mmio_mappings() -> impl Iterator<Item = MmioMapping> {
+    mapping_record::kernel_mmio_mappings()
+}
+
+/// Set up the kernel translation tables, but leave the MMU off.
+///
+/// For unit tests of drivers that remap their MMIO.
+///
+/// # Safety
+///
+/// - Only while the MMU is off, as it is during unit tests.
+#[cfg(test)]
+pub unsafe fn kernel_init_translation_tables() {
+    arch_mmu::kernel_translation_tables().write(|tables| tables.init());
+}
+
 //--------------------------------------------------------------------------------------------------
 // Testing
 //--------------------------------------------------------------------------------------------------
@@ -247,4 +391,65 @@ mod tests {
             false
         );
     }
+
+    /// The typed MMIO wrapper points to the remapped virtual address.
+    #[kernel_test]
+    fn map_mmio_registers_targets_virt_base() {
+        // Only the global tables are touched, the MMU stays off during unit tests.
+        unsafe { arch_mmu::kernel_translation_tables().write(|tables| tables.init()) };
+
+        let descriptor = MMIODescriptor::new(Address::new(0x1_0000), 0x48);
+
+        let too_big = unsafe { map_mmio_registers::<[u32; 0x50 / 4]>("test", &descriptor) };
+        assert!(too_big.is_err());
+
+        let regs = unsafe { map_mmio_registers::<[u32; 0x48 / 4]>("test", &descriptor) }.unwrap();
+        let virt_addr = unsafe { kernel_map_mmio("test", &descriptor) }.unwrap();
+
+        assert_eq!(regs.start_addr(), virt_addr.into_usize());
+        assert!(virt_addr != Address::new(0x1_0000));
+    }
+
+    /// MMIO can be mapped at a requested address, as long as it is aligned and still free.
+    #[kernel_test]
+    fn kernel_map_mmio_at_requested_addr() {
+        unsafe { arch_mmu::kernel_translation_tables().write(|tables| tables.init()) };
+
+        let granule = bsp::memory::mmu::KernelGranule::SIZE;
+        let descriptor = MMIODescriptor::new(Address::new(2 * granule), 0x48);
+        let requested = Address::new(4 * granule);
+
+        let virt_addr = unsafe { kernel_map_mmio_at("test", &descriptor, requested) }.unwrap();
+        assert!(virt_addr == requested);
+
+        // Already mapped.
+        assert!(unsafe { kernel_map_mmio_at("test", &descriptor, requested) }.is_err());
+
+        // Not page-aligned.
+        let unaligned = Address::new(5 * granule + 0x1000);
+        assert!(unsafe { kernel_map_mmio_at("test", &descriptor, unaligned) }.is_err());
+    }
+
+    /// A mapped address translates to its physical counterpart with the mapping's attributes.
+    #[kernel_test]
+    fn translate_finds_mapped_mmio() {
+        unsafe { arch_mmu::kernel_translation_tables().write(|tables| tables.init()) };
+
+        let granule = bsp::memory::mmu::KernelGranule::SIZE;
+        let descriptor = MMIODescriptor::new(Address::new(3 * granule), 0x48);
+        let requested = Address::new(8 * granule);
+
+        unsafe { kernel_map_mmio_at("test", &descriptor, requested) }.unwrap();
+
+        let x = translate(requested + 0x40).unwrap();
+        assert!(x.phys_addr == Address::new(3 * granule + 0x40));
+        assert!(x.attributes.mem_attributes == MemAttributes::Device);
+        assert!(x.attributes.execute_never);
+
+        // The table is present, but the page is not mapped.
+        assert!(translate(Address::new(9 * granule)).is_none());
+
+        // Beyond the tables.
+        assert!(translate(Address::new(usize::MAX)).is_none());
+    }
 }
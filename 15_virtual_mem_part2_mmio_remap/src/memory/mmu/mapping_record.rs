@@ -28,6 +28,28 @@ struct MappingRecord {
     inner: [Option<MappingRecordEntry>; 12],
 }
 
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A recorded MMIO mapping, see [`super::mmio_mappings()`].
+///
+/// Mappings cover whole pages, so the start addresses are page-aligned.
+#[derive(Copy, Clone)]
+pub struct MmioMapping {
+    /// The name of the first user. Others that share the mapping are not listed.
+    pub name: &'static str,
+
+    /// Start of the mapped physical pages.
+    pub phys_start_addr: Address<Physical>,
+
+    /// Start of the virtual pages they are mapped to.
+    pub virt_start_addr: Address<Virtual>,
+
+    /// Size of the mapping in bytes.
+    pub size: usize,
+}
+
 //--------------------------------------------------------------------------------------------------
 // Global instances
 //--------------------------------------------------------------------------------------------------
@@ -62,7 +84,13 @@ impl MappingRecordEntry {
         Err("Storage for user info exhausted")
     }
 
+    /// A user that is recorded already, e.g. a driver that is brought up again, is not added a
+    /// second time.
     pub fn add_user(&mut self, user: &'static str) -> Result<(), &'static str> {
+        if self.users.contains(&Some(user)) {
+            return Ok(());
+        }
+
         let x = self.find_next_free_user()?;
         *x = Some(user);
         Ok(())
@@ -107,6 +135,21 @@ impl MappingRecord {
         Ok(())
     }
 
+    /// The MMIO entries, copied so that the record is not locked while they are iterated.
+    fn mmio_mappings(&self) -> impl Iterator<Item = MmioMapping> {
+        let entries = self.inner;
+
+        (0..entries.len())
+            .filter_map(move |i| entries[i])
+            .filter(|x| x.attribute_fields.mem_attributes == MemAttributes::Device)
+            .map(|x| MmioMapping {
+                name: x.users[0].unwrap(),
+                phys_start_addr: x.phys_pages.start_addr(),
+                virt_start_addr: x.virt_start_addr,
+                size: x.phys_pages.size(),
+            })
+    }
+
     pub fn print(&self) {
         const KIB_RSHIFT: u32 = 10; // log2(1024).
         const MIB_RSHIFT: u32 = 20; // log2(1024 * 1024).
@@ -219,3 +262,84 @@ pub fn kernel_find_and_insert_mmio_duplicate(
 pub fn kernel_print() {
     KERNEL_MAPPING_RECORD.read(|mr| mr.print());
 }
+
+/// The recorded MMIO mappings of the kernel, as of the call.
+pub fn kernel_mmio_mappings() -> impl Iterator<Item = MmioMapping> {
+    KERNEL_MAPPING_RECORD.read(|mr| mr.mmio_mappings())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_macros::kernel_test;
+
+    /// Only device mappings are listed, in the order they were added.
+    #[kernel_test]
+    fn mmio_mappings_report_device_entries() {
+        let mut record = MappingRecord::new();
+        let device = AttributeFields {
+            mem_attributes: MemAttributes::Device,
+            acc_perms: AccessPermissions::ReadWrite,
+            execute_never: true,
+        };
+        let dram = AttributeFields {
+            mem_attributes: MemAttributes::CacheableDRAM,
+            ..device
+        };
+
+        let add = |record: &mut MappingRecord, name, phys, virt, num_pages, attr| {
+            record
+                .add(
+                    name,
+                    &PageSliceDescriptor::from_addr(Address::new(phys), num_pages),
+                    &PageSliceDescriptor::from_addr(Address::new(virt), num_pages),
+                    &attr,
+                )
+                .unwrap()
+        };
+        add(&mut record, "UART", 0x3F20_0000, 0x1FFF_0000, 1, device);
+        add(&mut record, "Code", 0x8_0000, 0x8_0000, 4, dram);
+        add(&mut record, "GPIO", 0x3F21_0000, 0x1FFE_0000, 2, device);
+
+        let mut mappings = record.mmio_mappings();
+
+        let uart = mappings.next().unwrap();
+        assert_eq!(uart.name, "UART");
+        assert_eq!(uart.phys_start_addr.into_usize(), 0x3F20_0000);
+        assert_eq!(uart.virt_start_addr.into_usize(), 0x1FFF_0000);
+        assert_eq!(uart.size, 0x1_0000);
+
+        let gpio = mappings.next().unwrap();
+        assert_eq!(gpio.name, "GPIO");
+        assert_eq!(gpio.phys_start_addr.into_usize(), 0x3F21_0000);
+        assert_eq!(gpio.virt_start_addr.into_usize(), 0x1FFE_0000);
+        assert_eq!(gpio.size, 0x2_0000);
+
+        assert!(mappings.next().is_none());
+    }
+
+    /// A user that maps the same region again does not take up another user slot.
+    #[kernel_test]
+    fn repeated_user_is_recorded_once() {
+        let mut entry = MappingRecordEntry::new(
+            "UART",
+            &PageSliceDescriptor::from_addr(Address::new(0x3F20_0000), 1),
+            &PageSliceDescriptor::from_addr(Address::new(0x1FFF_0000), 1),
+            &AttributeFields {
+                mem_attributes: MemAttributes::Device,
+                acc_perms: AccessPermissions::ReadWrite,
+                execute_never: true,
+            },
+        );
+
+        for _ in 0..8 {
+            entry.add_user("UART").unwrap();
+        }
+        entry.add_user("GPIO").unwrap();
+        assert_eq!(entry.users, [Some("UART"), Some("GPIO"), None, None, None]);
+    }
+}
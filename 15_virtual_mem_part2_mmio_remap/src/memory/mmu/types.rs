@@ -69,6 +69,14 @@ pub struct AttributeFields {
     pub execute_never: bool,
 }
 
+/// Where a virtual address points to, as found in the translation tables.
+#[allow(missing_docs)]
+#[derive(Copy, Clone)]
+pub struct Translation {
+    pub phys_addr: Address<Physical>,
+    pub attributes: AttributeFields,
+}
+
 /// An MMIO descriptor for use in device drivers.
 #[derive(Copy, Clone)]
 pub struct MMIODescriptor<ATYPE: AddressType> {
@@ -2,19 +2,154 @@
 //
 // Copyright (c) 2018-2020 Andre Richter <andre.o.richter@gmail.com>
 
-//! A panic handler that infinitely waits.
+//! The panic handler.
+//!
+//! After printing the panic message, the handler follows the [`PanicPolicy`] that was selected at
+//! boot. By default, it halts.
+//!
+//! The message is cut off after a limit, see [`set_panic_output_limit()`], so that a huge one
+//! cannot hold up the policy, e.g. a reboot that a watchdog waits for.
 
-use crate::{bsp, cpu, exception};
-use core::{fmt, panic::PanicInfo};
+use crate::{console, cpu, exception, synchronization, synchronization::InitStateLock, time};
+use core::{
+    fmt,
+    panic::PanicInfo,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Default of the maximum number of bytes printed per panic message.
+const DEFAULT_PANIC_OUTPUT_LIMIT: usize = 1024;
+
+/// Lower bound of the limit. Enough for the start of the message, to tell which panic it was.
+const MIN_PANIC_OUTPUT_LIMIT: usize = 64;
+
+/// Ends a message that was cut off.
+const TRUNCATION_MARKER: &str = "\n[... panic message truncated]\n";
+
+/// Passes up to a limit of bytes on to a writer, then the truncation marker once, and then drops
+/// the rest without reporting an error. So formatting runs to completion either way.
+struct Truncating<W: fmt::Write> {
+    out: W,
+    left: usize,
+    truncated: bool,
+}
+
+/// A single step of the panic handler after the message was printed for the first time.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Step {
+    Wait(Duration),
+    Print,
+    Halt,
+    Reboot,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// What the panic handler does after printing the panic message.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PanicPolicy {
+    /// Park the core.
+    Halt,
+
+    /// Reset the board after `delay`.
+    Reboot {
+        /// Time to give an operator for reading the message.
+        delay: Duration,
+    },
+
+    /// Print the message again and again, e.g. for a logic analyzer to catch it.
+    RepeatPrint {
+        /// Pause between two prints.
+        interval: Duration,
+    },
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static PANIC_POLICY: InitStateLock<PanicPolicy> = InitStateLock::new(PanicPolicy::Halt);
+
+static PANIC_OUTPUT_LIMIT: InitStateLock<usize> = InitStateLock::new(DEFAULT_PANIC_OUTPUT_LIMIT);
+
+/// Set once the panic handler runs, to catch a panic inside the handler itself.
+static PANIC_IN_PROGRESS: AtomicBool = AtomicBool::new(false);
 
 //--------------------------------------------------------------------------------------------------
 // Private Code
 //--------------------------------------------------------------------------------------------------
 
+impl PanicPolicy {
+    /// The `n`th step after the message was printed for the first time.
+    fn step(&self, n: usize) -> Step {
+        match *self {
+            Self::Halt => Step::Halt,
+            Self::Reboot { delay } => {
+                if n == 0 {
+                    Step::Wait(delay)
+                } else {
+                    Step::Reboot
+                }
+            }
+            Self::RepeatPrint { interval } => {
+                if n % 2 == 0 {
+                    Step::Wait(interval)
+                } else {
+                    Step::Print
+                }
+            }
+        }
+    }
+}
+
+impl<W: fmt::Write> Truncating<W> {
+    const fn new(out: W, limit: usize) -> Self {
+        Self {
+            out,
+            left: limit,
+            truncated: false,
+        }
+    }
+}
+
+impl<W: fmt::Write> fmt::Write for Truncating<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.truncated {
+            return Ok(());
+        }
+
+        if s.len() <= self.left {
+            self.left -= s.len();
+            return self.out.write_str(s);
+        }
+
+        // Do not split a character.
+        let mut end = self.left;
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        self.truncated = true;
+        self.out.write_str(&s[..end])?;
+        self.out.write_str(TRUNCATION_MARKER)
+    }
+}
+
 fn _panic_print(args: fmt::Arguments) {
     use fmt::Write;
+    use synchronization::interface::ReadWriteEx;
 
-    unsafe { bsp::console::panic_console_out().write_fmt(args).unwrap() };
+    let limit = PANIC_OUTPUT_LIMIT.read(|x| *x);
+    Truncating::new(console::panic_writer(), limit)
+        .write_fmt(args)
+        .unwrap();
 }
 
 /// The point of exit for the "standard" (non-testing) `libkernel`.
@@ -44,17 +179,62 @@ macro_rules! panic_println {
     })
 }
 
-#[panic_handler]
-fn panic(info: &PanicInfo) -> ! {
-    unsafe { exception::asynchronous::local_irq_mask() };
-
+fn print_panic_message(info: &PanicInfo) {
     if let Some(args) = info.message() {
         panic_println!("\nKernel panic: {}", args);
     } else {
         panic_println!("\nKernel panic!");
     }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    use synchronization::interface::ReadWriteEx;
+    use time::interface::TimeManager;
+
+    unsafe { exception::asynchronous::local_irq_mask() };
+
+    // A panic while panicking, e.g. in the console code, must not recurse.
+    if PANIC_IN_PROGRESS.swap(true, Ordering::Relaxed) {
+        _panic_exit()
+    }
+
+    print_panic_message(info);
+
+    let policy = PANIC_POLICY.read(|policy| *policy);
+    let mut n = 0;
+    loop {
+        match policy.step(n) {
+            Step::Wait(x) => time::time_manager().spin_for(x),
+            Step::Print => print_panic_message(info),
+            Step::Halt => _panic_exit(),
+            Step::Reboot => cpu::reboot(),
+        }
+        n = n.wrapping_add(1);
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Select what the panic handler does after printing the panic message.
+///
+/// Only callable during the kernel init phase.
+pub fn set_panic_policy(policy: PanicPolicy) {
+    use synchronization::interface::ReadWriteEx;
+
+    PANIC_POLICY.write(|x| *x = policy);
+}
+
+/// Limit the panic message to `limit` bytes. Longer ones are cut off with a marker.
+///
+/// The limit is at least 64 bytes, so that the start of the message still tells which panic it
+/// was. Only callable during the kernel init phase.
+pub fn set_panic_output_limit(limit: usize) {
+    use synchronization::interface::ReadWriteEx;
 
-    _panic_exit()
+    PANIC_OUTPUT_LIMIT.write(|x| *x = limit.max(MIN_PANIC_OUTPUT_LIMIT));
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -67,3 +247,54 @@ fn panic(info: &PanicInfo) -> ! {
 fn _panic_exit() -> ! {
     cpu::qemu_exit_failure()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::FmtBuf;
+    use fmt::Write;
+    use test_macros::kernel_test;
+
+    /// Each policy leads to its terminal action.
+    #[kernel_test]
+    fn panic_policy_steps() {
+        let delay = Duration::from_secs(3);
+
+        assert_eq!(PanicPolicy::Halt.step(0), Step::Halt);
+        assert_eq!(PanicPolicy::Halt.step(1), Step::Halt);
+
+        let reboot = PanicPolicy::Reboot { delay };
+        assert_eq!(reboot.step(0), Step::Wait(delay));
+        assert_eq!(reboot.step(1), Step::Reboot);
+
+        let repeat = PanicPolicy::RepeatPrint { interval: delay };
+        for n in 0..4 {
+            let expected = if n % 2 == 0 {
+                Step::Wait(delay)
+            } else {
+                Step::Print
+            };
+            assert_eq!(repeat.step(n), expected);
+        }
+    }
+
+    /// A message beyond the limit is cut off with the marker, and formatting still succeeds, so
+    /// the handler goes on to its policy.
+    #[kernel_test]
+    fn long_panic_message_is_truncated() {
+        let mut out = Truncating::new(FmtBuf::<96>::new(), 16);
+
+        assert!(write!(out, "Kernel panic: {}", "abcdefgh").is_ok());
+        assert!(out.write_str("more").is_ok());
+        assert_eq!(
+            out.out.as_str(),
+            "Kernel panic: ab\n[... panic message truncated]\n"
+        );
+
+        // Exactly at the limit.
+        let mut out = Truncating::new(FmtBuf::<96>::new(), 16);
+        out.write_str("Kernel panic: ab").unwrap();
+        assert!(!out.truncated);
+        assert_eq!(out.out.as_str(), "Kernel panic: ab");
+    }
+}
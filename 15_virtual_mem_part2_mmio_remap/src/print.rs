@@ -4,8 +4,148 @@
 
 //! Printing facilities.
 
-use crate::{bsp, console};
+pub mod log_ring;
+
+use crate::{bsp, console, synchronization, synchronization::IRQSafeNullLock};
 use core::fmt;
+use log_ring::LogRing;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+struct DeferralInner {
+    depth: usize,
+    position: usize,
+
+    /// The console's `chars_read()` when the interactive gate closed, or `None` while it is open.
+    gate: Option<usize>,
+}
+
+/// Keeps output in the log only, while at least one deferral is active.
+struct Deferral {
+    inner: IRQSafeNullLock<DeferralInner>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static DEFERRAL: Deferral = Deferral::new();
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+use synchronization::interface::Mutex;
+
+impl Deferral {
+    const fn new() -> Self {
+        Self {
+            inner: IRQSafeNullLock::new(DeferralInner {
+                depth: 0,
+                position: 0,
+                gate: None,
+            }),
+        }
+    }
+
+    fn print<const N: usize>(
+        &self,
+        log: &LogRing<N>,
+        console: &impl console::interface::Write,
+        args: fmt::Arguments,
+    ) {
+        log.log(args);
+
+        if self.inner.lock(|inner| inner.depth) == 0 {
+            console.write_fmt(args).unwrap();
+        }
+    }
+
+    fn defer<const N: usize>(&self, log: &LogRing<N>) {
+        self.inner.lock(|inner| {
+            if inner.depth == 0 {
+                inner.position = log.num_logged();
+            }
+            inner.depth += 1;
+        })
+    }
+
+    /// Returns whether the outermost deferral ended.
+    fn flush<const N: usize>(
+        &self,
+        log: &LogRing<N>,
+        console: &impl console::interface::Write,
+    ) -> bool {
+        let position = self.inner.lock(|inner| match inner.depth {
+            0 => None,
+            1 => {
+                inner.depth = 0;
+                Some(inner.position)
+            }
+            _ => {
+                inner.depth -= 1;
+                None
+            }
+        });
+
+        match position {
+            Some(x) => {
+                // A failing console leaves nothing to report to.
+                let lost = log.num_logged().wrapping_sub(x).saturating_sub(N);
+                if lost > 0 {
+                    let _ = console.write_fmt(format_args!("[{} bytes dropped]\n", lost));
+                }
+                let _ = log.replay_since_to(x, console);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn close_gate<const N: usize>(
+        &self,
+        log: &LogRing<N>,
+        console: &impl console::interface::Statistics,
+    ) {
+        let chars_read = console.chars_read();
+        let closed = self.inner.lock(|inner| match inner.gate {
+            Some(_) => false,
+            None => {
+                inner.gate = Some(chars_read);
+                true
+            }
+        });
+
+        if closed {
+            self.defer(log);
+        }
+    }
+
+    /// Returns whether the gate opened.
+    fn poll_gate<const N: usize>(
+        &self,
+        log: &LogRing<N>,
+        console: &(impl console::interface::Write + console::interface::Statistics),
+    ) -> bool {
+        // Cheap while the gate is open, which it is nearly all of the time.
+        let closed_at = match self.inner.lock(|inner| inner.gate) {
+            Some(x) => x,
+            None => return false,
+        };
+
+        if console.chars_read() == closed_at {
+            return false;
+        }
+
+        if self.inner.lock(|inner| inner.gate.take()).is_none() {
+            return false;
+        }
+
+        self.flush(log, console);
+        true
+    }
+}
 
 //--------------------------------------------------------------------------------------------------
 // Public Code
@@ -13,9 +153,58 @@ use core::fmt;
 
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
-    use console::interface::Write;
+    DEFERRAL.poll_gate(log_ring::kernel_log_ring(), bsp::console::console());
+    DEFERRAL.print(log_ring::kernel_log_ring(), bsp::console::console(), args);
+
+    #[cfg(test)]
+    console::record_capture(args);
+}
 
-    bsp::console::console().write_fmt(args).unwrap();
+#[doc(hidden)]
+pub fn _early_print(args: fmt::Arguments) {
+    use fmt::Write;
+
+    // Still too early to report a failure anywhere.
+    if let Some(mut out) = unsafe { bsp::console::early_console_out() } {
+        let _ = out.write_fmt(args);
+    }
+}
+
+/// Stop writing printed output to the console. It still goes to the kernel's log ring.
+///
+/// Useful in critical sections that must not wait for the console. Calls nest, and output is held
+/// back until each of them has been matched by a call to [`flush_deferred()`]. Only as much output
+/// as fits into the log ring survives the deferral.
+pub fn defer_output() {
+    DEFERRAL.defer(log_ring::kernel_log_ring());
+}
+
+/// End a deferral started by [`defer_output()`].
+///
+/// When the outermost deferral ends, the output it held back is written to the console. Extra
+/// calls are ignored.
+pub fn flush_deferred() {
+    DEFERRAL.flush(log_ring::kernel_log_ring(), bsp::console::console());
+}
+
+/// Hold all printed output back until the console receives its first byte, e.g. so that a host
+/// tool can synchronize before the boot banner.
+///
+/// Output is kept in the kernel's log ring meanwhile, like with [`defer_output()`]. If there is
+/// more than fits, the oldest is dropped, and a marker that says how much goes out in its place.
+/// The gate opens at the next [`poll_interactive_gate()`] after the byte was received. Calls while
+/// the gate is closed are ignored.
+pub fn close_interactive_gate() {
+    DEFERRAL.close_gate(log_ring::kernel_log_ring(), bsp::console::console());
+}
+
+/// Open the interactive gate and write the held back output, if the console received a byte
+/// since the gate closed. See [`close_interactive_gate()`].
+///
+/// Called with every print, and by the console's IRQ handler. The byte itself is echoed before the
+/// held back output.
+pub fn poll_interactive_gate() {
+    DEFERRAL.poll_gate(log_ring::kernel_log_ring(), bsp::console::console());
 }
 
 /// Prints without a newline.
@@ -37,6 +226,24 @@ macro_rules! println {
     })
 }
 
+/// Prints without a newline, for the earliest boot messages.
+///
+/// Unlike `print!`, it works before the MMU and the console driver are up. The output bypasses the
+/// console and the kernel's log ring, and it is not synchronized with any other output.
+#[macro_export]
+macro_rules! early_print {
+    ($($arg:tt)*) => ($crate::print::_early_print(format_args!($($arg)*)));
+}
+
+/// Prints with a newline, for the earliest boot messages. See `early_print!`.
+#[macro_export]
+macro_rules! early_println {
+    () => ($crate::early_print!("\n"));
+    ($($arg:tt)*) => ({
+        $crate::print::_early_print(format_args_nl!($($arg)*));
+    })
+}
+
 /// Prints an info, with a newline.
 #[macro_export]
 macro_rules! info {
@@ -104,3 +311,69 @@ macro_rules! warn {
         ));
     })
 }
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use console::mock::MockConsole;
+    use test_macros::kernel_test;
+
+    /// Deferred output reaches the console only when the outermost deferral is flushed, and it
+    /// does so exactly once.
+    #[kernel_test]
+    fn deferred_output_appears_after_flush() {
+        let deferral = Deferral::new();
+        let log: LogRing<64> = LogRing::new();
+        let console = MockConsole::new();
+
+        deferral.print(&log, &console, format_args!("a"));
+        assert_eq!(console.written(), 1);
+
+        deferral.defer(&log);
+        deferral.print(&log, &console, format_args!("b{}", 1));
+        deferral.defer(&log);
+        deferral.print(&log, &console, format_args!("c"));
+
+        assert!(!deferral.flush(&log, &console));
+        deferral.print(&log, &console, format_args!("d"));
+        assert_eq!(console.written(), 1);
+
+        assert!(deferral.flush(&log, &console));
+        assert_eq!(&*console.output(), b"ab1cd");
+
+        assert!(!deferral.flush(&log, &console));
+        deferral.print(&log, &console, format_args!("e"));
+        assert_eq!(&*console.output(), b"ab1cde");
+    }
+
+    /// Output is held back until the console received a byte, and what did not fit into the log
+    /// ring is replaced by a marker.
+    #[kernel_test]
+    fn interactive_gate_holds_output_until_input() {
+        let deferral = Deferral::new();
+        let log: LogRing<8> = LogRing::new();
+        let console = MockConsole::new();
+
+        deferral.close_gate(&log, &console);
+        deferral.print(&log, &console, format_args!("ab"));
+        assert!(!deferral.poll_gate(&log, &console));
+        assert_eq!(console.written(), 0);
+
+        console.set_chars_read(1);
+        assert!(deferral.poll_gate(&log, &console));
+        deferral.print(&log, &console, format_args!("c"));
+        assert_eq!(&*console.output(), b"abc");
+        assert!(!deferral.poll_gate(&log, &console));
+
+        console.clear_output();
+        deferral.close_gate(&log, &console);
+        deferral.print(&log, &console, format_args!("0123456789"));
+        console.set_chars_read(2);
+        assert!(deferral.poll_gate(&log, &console));
+        assert_eq!(&*console.output(), b"[2 bytes dropped]\n23456789");
+    }
+}
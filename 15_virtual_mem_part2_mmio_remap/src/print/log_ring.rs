@@ -0,0 +1,221 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Kernel log ring.
+//!
+//! Keeps a copy of the most recent kernel output, so that messages can be recovered after they
+//! scrolled away or were printed before the console was ready.
+
+use crate::{
+    bsp, collections::RingBuffer, console, synchronization, synchronization::IRQSafeNullLock,
+};
+use core::fmt;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Size of the kernel's log ring in bytes.
+const KERNEL_LOG_RING_SIZE: usize = 4096;
+
+/// Formats logged bytes as the text they were logged as.
+///
+/// The bytes stem from whole strings, so they are valid UTF-8, except for a character at the start
+/// that was cut off when the ring wrapped. Its remains are shown as U+FFFD.
+struct Replay<'a>(&'a [u8]);
+
+struct LogRingInner<const N: usize> {
+    ring: RingBuffer<u8, N>,
+    num_logged: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A log that keeps the last `N` bytes written to it.
+pub struct LogRing<const N: usize> {
+    inner: IRQSafeNullLock<LogRingInner<N>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static KERNEL_LOG_RING: LogRing<KERNEL_LOG_RING_SIZE> = LogRing::new();
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl fmt::Display for Replay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use fmt::Write;
+
+        let mut rest = self.0;
+        loop {
+            match core::str::from_utf8(rest) {
+                Ok(x) => return f.write_str(x),
+                Err(e) => {
+                    let (valid, invalid) = rest.split_at(e.valid_up_to());
+                    f.write_str(core::str::from_utf8(valid).unwrap_or_default())?;
+                    f.write_char(core::char::REPLACEMENT_CHARACTER)?;
+
+                    // A sequence cut short at the end has no error length.
+                    rest = &invalid[e.error_len().unwrap_or(invalid.len())..];
+                }
+            }
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+use synchronization::interface::Mutex;
+
+/// Return a reference to the kernel's log ring.
+pub fn kernel_log_ring() -> &'static LogRing<KERNEL_LOG_RING_SIZE> {
+    &KERNEL_LOG_RING
+}
+
+impl<const N: usize> LogRing<{ N }> {
+    /// Create an instance.
+    pub const fn new() -> Self {
+        Self {
+            inner: IRQSafeNullLock::new(LogRingInner {
+                ring: RingBuffer::new(),
+                num_logged: 0,
+            }),
+        }
+    }
+
+    /// Append formatted output. If the ring is full, the oldest bytes are dropped.
+    pub fn log(&self, args: fmt::Arguments) {
+        struct Appender<'a, const M: usize>(&'a mut LogRingInner<M>);
+
+        impl<const M: usize> fmt::Write for Appender<'_, { M }> {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                for byte in s.bytes() {
+                    self.0.ring.push_overwriting(byte);
+                }
+                self.0.num_logged = self.0.num_logged.wrapping_add(s.len());
+
+                Ok(())
+            }
+        }
+
+        self.inner
+            .lock(|inner| fmt::Write::write_fmt(&mut Appender(inner), args))
+            .unwrap();
+    }
+
+    /// The number of bytes logged since creation, wrapping on overflow.
+    ///
+    /// Serves as a position in the log for [`LogRing::replay_since_to()`].
+    pub fn num_logged(&self) -> usize {
+        self.inner.lock(|inner| inner.num_logged)
+    }
+
+    /// Copy the current contents, from oldest to newest, into `buf`.
+    ///
+    /// Returns the number of bytes copied.
+    pub fn snapshot(&self, buf: &mut [u8; N]) -> usize {
+        self.snapshot_with_position(buf).0
+    }
+
+    /// `snapshot()`, additionally returning `num_logged()` at the time of the copy.
+    fn snapshot_with_position(&self, buf: &mut [u8; N]) -> (usize, usize) {
+        self.inner.lock(|inner| {
+            for (dst, src) in buf.iter_mut().zip(inner.ring.iter()) {
+                *dst = *src;
+            }
+
+            (inner.ring.len(), inner.num_logged)
+        })
+    }
+
+    /// Write the whole log to the given console in a single call.
+    ///
+    /// The log is snapshotted before writing, so it is not locked anymore when the console runs.
+    /// This keeps things working if the console logs its output itself.
+    pub fn replay_log_to(&self, console: &impl console::interface::Write) -> fmt::Result {
+        let mut buf = [0; N];
+        let len = self.snapshot(&mut buf);
+
+        console.write_fmt(format_args!("{}", Replay(&buf[..len])))
+    }
+
+    /// Write what was logged after `position` to the given console in a single call.
+    ///
+    /// `position` is a value previously returned by `num_logged()`. If more than `N` bytes were
+    /// logged since, only the last `N` are left to write.
+    pub fn replay_since_to(
+        &self,
+        position: usize,
+        console: &impl console::interface::Write,
+    ) -> fmt::Result {
+        let mut buf = [0; N];
+        let (len, num_logged) = self.snapshot_with_position(&mut buf);
+        let num_new = num_logged.wrapping_sub(position).min(len);
+
+        console.write_fmt(format_args!("{}", Replay(&buf[len - num_new..len])))
+    }
+
+    /// Write the whole log to the kernel's console.
+    pub fn replay_log(&self) {
+        // A failing console leaves nothing to report to.
+        let _ = self.replay_log_to(bsp::console::console());
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use console::mock::MockConsole;
+    use test_macros::kernel_test;
+
+    /// After wrapping, the ring holds the last `N` bytes in chronological order.
+    #[kernel_test]
+    fn log_ring_keeps_latest_in_order() {
+        let log_ring: LogRing<8> = LogRing::new();
+        let mut buf = [0; 8];
+
+        log_ring.log(format_args!("abc"));
+        assert_eq!(log_ring.snapshot(&mut buf), 3);
+        assert_eq!(&buf[..3], b"abc");
+
+        log_ring.log(format_args!("{}{}", "defgh", 12));
+        assert_eq!(log_ring.snapshot(&mut buf), 8);
+        assert_eq!(&buf, b"cdefgh12");
+    }
+
+    /// Replayed output is the text that was logged, also beyond ASCII. A character cut off by the
+    /// wraparound shows as U+FFFD.
+    #[kernel_test]
+    fn replay_keeps_utf8_intact() {
+        let log_ring: LogRing<8> = LogRing::new();
+        let console = MockConsole::new();
+
+        log_ring.log(format_args!("a€ö"));
+        log_ring.replay_log_to(&console).unwrap();
+        assert_eq!(&*console.output(), "a€ö".as_bytes());
+
+        let position = log_ring.num_logged();
+        log_ring.log(format_args!("ü"));
+        console.clear_output();
+        log_ring.replay_since_to(position, &console).unwrap();
+        assert_eq!(&*console.output(), "ü".as_bytes());
+
+        // The ring holds the last 8 of "a€öü€", which starts in the middle of the first '€'.
+        log_ring.log(format_args!("€"));
+        console.clear_output();
+        log_ring.replay_log_to(&console).unwrap();
+        assert_eq!(&*console.output(), "\u{fffd}öü€".as_bytes());
+    }
+}
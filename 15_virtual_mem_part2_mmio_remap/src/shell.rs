@@ -0,0 +1,227 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! A minimal debug shell.
+//!
+//! Lines are split on whitespace. The first word selects the command, the remaining words are
+//! passed to it as arguments. There is no quoting or escaping.
+
+use crate::{console, cpu, print};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Maximum number of arguments a command can be called with.
+const MAX_ARGS: usize = 16;
+
+/// Maximum length of an input line in bytes.
+const LINE_BUFFER_SIZE: usize = 128;
+
+/// Number of input lines that can be recalled with the arrow keys.
+const HISTORY_SIZE: usize = 8;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A command's implementation. Receives the arguments without the command name.
+pub type CommandFn = fn(&[&str]) -> Result<(), &'static str>;
+
+/// A shell with room for `N` commands.
+pub struct Shell<const N: usize> {
+    commands: [Option<(&'static str, CommandFn)>; N],
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+// The signature is dictated by `CommandFn`.
+#[allow(clippy::unnecessary_wraps)]
+fn log(_args: &[&str]) -> Result<(), &'static str> {
+    print::log_ring::kernel_log_ring().replay_log();
+
+    Ok(())
+}
+
+fn hexecho(_args: &[&str]) -> Result<(), &'static str> {
+    console::hex_echo::run(crate::bsp::console::console()).map_err(|_| "Console error")
+}
+
+fn reboot(_args: &[&str]) -> Result<(), &'static str> {
+    cpu::reboot()
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl<const N: usize> Shell<{ N }> {
+    /// Create an instance without any commands.
+    pub const fn new() -> Self {
+        Self {
+            commands: [None; N],
+        }
+    }
+
+    /// Register a command.
+    pub fn register(&mut self, name: &'static str, run: CommandFn) -> Result<(), &'static str> {
+        if self.find(name).is_some() {
+            return Err("Command already registered");
+        }
+
+        let slot = self
+            .commands
+            .iter_mut()
+            .find(|x| x.is_none())
+            .ok_or("No room for another command")?;
+        *slot = Some((name, run));
+
+        Ok(())
+    }
+
+    /// Register the built-in commands `log`, `hexecho` and `reboot`.
+    pub fn register_builtins(&mut self) -> Result<(), &'static str> {
+        self.register("log", log)?;
+        self.register("hexecho", hexecho)?;
+        self.register("reboot", reboot)
+    }
+
+    fn find(&self, name: &str) -> Option<CommandFn> {
+        self.commands
+            .iter()
+            .flatten()
+            .find(|(x, _)| *x == name)
+            .map(|(_, run)| *run)
+    }
+
+    /// Execute a line of input.
+    ///
+    /// For unknown commands, a list of the available ones is written to `out`.
+    pub fn dispatch(
+        &self,
+        line: &str,
+        out: &impl console::interface::Write,
+    ) -> Result<(), &'static str> {
+        let mut words = line.split_whitespace();
+        let name = match words.next() {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+
+        let mut args = [""; MAX_ARGS];
+        let mut num_args = 0;
+        for word in words {
+            if num_args == MAX_ARGS {
+                return Err("Too many arguments");
+            }
+
+            args[num_args] = word;
+            num_args += 1;
+        }
+
+        match self.find(name) {
+            Some(run) => run(&args[..num_args]),
+            None => {
+                out.write_fmt(format_args!(
+                    "Unknown command '{}'. Available commands:",
+                    name
+                ))
+                .map_err(|_| "Console error")?;
+
+                for (name, _) in self.commands.iter().flatten() {
+                    out.write_fmt(format_args!(" {}", name))
+                        .map_err(|_| "Console error")?;
+                }
+                out.write_char('\n');
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Read and execute lines forever.
+    ///
+    /// Earlier lines can be recalled with the up and down arrow keys. The shell echoes input
+    /// itself, so it switches the console's echo off while it reads a line. Commands run with the
+    /// echo mode that the console had before.
+    pub fn run(&self, console: &impl console::interface::All) -> ! {
+        let mut buf = [0; LINE_BUFFER_SIZE];
+        let mut history = console::history::History::<HISTORY_SIZE>::new();
+        let echo_mode = console.set_echo_mode(console::EchoMode::Off);
+
+        loop {
+            console.write_fmt(format_args!("> ")).ok();
+
+            let len =
+                match console::history::read_line_with_history(console, &mut buf, &mut history) {
+                    console::ReadLineStatus::Truncated(_) => {
+                        console
+                            .write_fmt(format_args!("Error: Line too long\n"))
+                            .ok();
+                        continue;
+                    }
+                    x => x.len(),
+                };
+            let line = core::str::from_utf8(&buf[..len]).unwrap_or_default();
+
+            console.set_echo_mode(echo_mode);
+            if let Err(x) = self.dispatch(line, console) {
+                console.write_fmt(format_args!("Error: {}\n", x)).ok();
+            }
+            console.set_echo_mode(console::EchoMode::Off);
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use console::mock::MockConsole;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use test_macros::kernel_test;
+
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    fn copy(args: &[&str]) -> Result<(), &'static str> {
+        if args != ["a.txt", "b.txt"] {
+            return Err("Unexpected arguments");
+        }
+        CALLS.fetch_add(1, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    fn fail(_args: &[&str]) -> Result<(), &'static str> {
+        Err("Failed")
+    }
+
+    /// Lines are dispatched to the registered command with the words after the name as arguments.
+    #[kernel_test]
+    fn shell_dispatches_with_args() {
+        let out = MockConsole::new();
+        let mut shell: Shell<2> = Shell::new();
+
+        shell.register("cp", copy).unwrap();
+        assert!(shell.register("cp", copy).is_err());
+        shell.register("fail", fail).unwrap();
+        assert!(shell.register("other", fail).is_err());
+
+        assert_eq!(shell.dispatch("  cp a.txt\tb.txt ", &out), Ok(()));
+        assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+        assert_eq!(shell.dispatch("fail", &out), Err("Failed"));
+        assert_eq!(shell.dispatch("", &out), Ok(()));
+        assert_eq!(out.written(), 0);
+
+        // Unknown commands produce a hint, but no error.
+        assert_eq!(shell.dispatch("mv a b", &out), Ok(()));
+        assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+        assert!(out.written() > 0);
+    }
+}
@@ -9,7 +9,12 @@
 //!   - https://stackoverflow.com/questions/59428096/understanding-the-send-trait
 //!   - https://doc.rust-lang.org/std/cell/index.html
 
-use core::cell::UnsafeCell;
+use crate::{cpu, time};
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
 
 //--------------------------------------------------------------------------------------------------
 // Public Definitions
@@ -55,9 +60,14 @@ pub struct IRQSafeNullLock<T>
 where
     T: ?Sized,
 {
+    locked: AtomicBool,
     data: UnsafeCell<T>,
 }
 
+/// Returned by [`spin_until()`] if the deadline passed before the condition became true.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TimeoutError;
+
 /// A pseudo-lock that is RW during the single-core kernel init phase and RO afterwards.
 ///
 /// Intended to encapsulate data that is populated during kernel init when no concurrency exists.
@@ -68,10 +78,44 @@ where
     data: UnsafeCell<T>,
 }
 
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// `spin_until()` with the clock passed in.
+fn spin_until_at(
+    mut predicate: impl FnMut() -> bool,
+    deadline: Duration,
+    mut now: impl FnMut() -> Duration,
+) -> Result<(), TimeoutError> {
+    loop {
+        if predicate() {
+            return Ok(());
+        }
+
+        if now() >= deadline {
+            return Err(TimeoutError);
+        }
+
+        cpu::nop();
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Public Code
 //--------------------------------------------------------------------------------------------------
 
+/// Spin until `predicate` returns true, or fail once the uptime reached `deadline`.
+///
+/// The predicate is checked before the deadline, so one that is already true succeeds right away.
+/// Waits with `nop` instead of `wfe` in between: there is no event stream from the timer, so
+/// nothing would guarantee a wake-up before the deadline.
+pub fn spin_until(predicate: impl FnMut() -> bool, deadline: Duration) -> Result<(), TimeoutError> {
+    use time::interface::TimeManager;
+
+    spin_until_at(predicate, deadline, || time::time_manager().uptime())
+}
+
 unsafe impl<T> Send for IRQSafeNullLock<T> where T: ?Sized + Send {}
 unsafe impl<T> Sync for IRQSafeNullLock<T> where T: ?Sized + Send {}
 
@@ -79,11 +123,22 @@ impl<T> IRQSafeNullLock<T> {
     /// Create an instance.
     pub const fn new(data: T) -> Self {
         Self {
+            locked: AtomicBool::new(false),
             data: UnsafeCell::new(data),
         }
     }
 }
 
+impl<T: ?Sized> IRQSafeNullLock<T> {
+    /// Returns true while a closure passed to `lock()` runs.
+    ///
+    /// The lock does not keep anyone out, so code that can run within its own closure, e.g.
+    /// console output from an IRQ handler, checks this instead of locking a second time.
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+}
+
 unsafe impl<T> Send for InitStateLock<T> where T: ?Sized + Send {}
 unsafe impl<T> Sync for InitStateLock<T> where T: ?Sized + Send {}
 
@@ -109,8 +164,14 @@ impl<T> interface::Mutex for IRQSafeNullLock<T> {
         // mutable reference will ever only be given out once at a time.
         let data = unsafe { &mut *self.data.get() };
 
-        // Execute the closure while IRQs are masked.
-        exception::asynchronous::exec_with_irq_masked(|| f(data))
+        // Execute the closure while IRQs are masked. Single core, so Relaxed is enough.
+        exception::asynchronous::exec_with_irq_masked(|| {
+            let was_locked = self.locked.swap(true, Ordering::Relaxed);
+            let ret = f(data);
+            self.locked.store(was_locked, Ordering::Relaxed);
+
+            ret
+        })
     }
 }
 
@@ -138,3 +199,38 @@ impl<T> interface::ReadWriteEx for InitStateLock<T> {
         f(data)
     }
 }
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+    use test_macros::kernel_test;
+
+    /// Spinning ends as soon as the condition holds, or with an error once the deadline passed.
+    #[kernel_test]
+    fn spin_until_condition_or_deadline() {
+        let uptime = Cell::new(Duration::from_millis(0));
+        let tick = || {
+            uptime.set(uptime.get() + Duration::from_millis(1));
+            uptime.get()
+        };
+        let deadline = Duration::from_millis(10);
+
+        assert_eq!(spin_until_at(|| true, Duration::from_secs(0), tick), Ok(()));
+        assert_eq!(uptime.get(), Duration::from_millis(0));
+
+        let ready_at = Duration::from_millis(3);
+        assert_eq!(
+            spin_until_at(|| uptime.get() >= ready_at, deadline, tick),
+            Ok(())
+        );
+        assert_eq!(uptime.get(), ready_at);
+
+        assert_eq!(spin_until_at(|| false, deadline, tick), Err(TimeoutError));
+        assert_eq!(uptime.get(), deadline);
+    }
+}
@@ -0,0 +1,175 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Binary telemetry frames.
+//!
+//! Fixed-format records are sent to the host framed as
+//!
+//! `SYNC0 | SYNC1 | sequence | length | record | CRC16 high | CRC16 low`
+//!
+//! The CRC is CRC-16/XMODEM over the sequence number, the length and the record. The host finds
+//! the start of a frame by the sync bytes, and confirms it by the CRC, since the sync bytes may
+//! also appear within a record.
+//!
+//! # Sequence numbers
+//!
+//! Every frame that is sent gets the next sequence number, so that the host can tell how many
+//! frames were lost in between: `(sequence - previous - 1) mod 256`. The sequence number is a
+//! single byte and wraps from 255 to 0. Handling the wraparound is up to the host. A loss of 256
+//! frames or a multiple thereof cannot be detected.
+
+use crate::checksum;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+const SYNC: [u8; 2] = [0xa5, 0x5a];
+
+/// Sync bytes, sequence number and length.
+const HEADER_LEN: usize = 4;
+
+const CRC_LEN: usize = 2;
+
+/// The longest frame. It fits into the TX FIFO of the PL011, which sends it in one piece.
+const MAX_FRAME_LEN: usize = 16;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Telemetry interfaces.
+pub mod interface {
+    /// A link that sends frames without interleaving them with other output.
+    pub trait Link {
+        /// Send `frame` in one piece.
+        fn write_atomic(&self, frame: &[u8]) -> Result<(), &'static str>;
+    }
+}
+
+/// Maximum length of a record in bytes.
+pub const MAX_RECORD_LEN: usize = MAX_FRAME_LEN - HEADER_LEN - CRC_LEN;
+
+/// Sends telemetry records over a link, numbering the frames.
+pub struct Telemetry<'a, L> {
+    link: &'a L,
+    next_sequence: AtomicU8,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Frame `record` into `buf`, returning the length of the frame.
+fn frame(buf: &mut [u8; MAX_FRAME_LEN], sequence: u8, record: &[u8]) -> usize {
+    let crc_start = HEADER_LEN + record.len();
+
+    buf[..2].copy_from_slice(&SYNC);
+    buf[2] = sequence;
+    buf[3] = record.len() as u8;
+    buf[HEADER_LEN..crc_start].copy_from_slice(record);
+
+    let crc = checksum::crc16_xmodem(&buf[2..crc_start]);
+    buf[crc_start..crc_start + CRC_LEN].copy_from_slice(&crc.to_be_bytes());
+
+    crc_start + CRC_LEN
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl<'a, L: interface::Link> Telemetry<'a, L> {
+    /// Create an instance whose first frame has sequence number 0.
+    pub const fn new(link: &'a L) -> Self {
+        Self {
+            link,
+            next_sequence: AtomicU8::new(0),
+        }
+    }
+
+    /// Frame `record` and send it in one piece.
+    ///
+    /// Records longer than [`MAX_RECORD_LEN`] are rejected without using up a sequence number.
+    pub fn emit(&self, record: &[u8]) -> Result<(), &'static str> {
+        if record.len() > MAX_RECORD_LEN {
+            return Err("Telemetry record is too long");
+        }
+
+        // Wraps from 255 to 0.
+        let sequence = self.next_sequence.fetch_add(1, Ordering::Relaxed);
+
+        let mut buf = [0; MAX_FRAME_LEN];
+        let len = frame(&mut buf, sequence, record);
+
+        self.link.write_atomic(&buf[..len])
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::RefCell;
+    use test_macros::kernel_test;
+
+    /// Keeps the last frame that was sent.
+    struct MockLink {
+        frame: RefCell<([u8; MAX_FRAME_LEN], usize)>,
+    }
+
+    impl interface::Link for MockLink {
+        fn write_atomic(&self, frame: &[u8]) -> Result<(), &'static str> {
+            let mut last = self.frame.borrow_mut();
+            last.0[..frame.len()].copy_from_slice(frame);
+            last.1 = frame.len();
+
+            Ok(())
+        }
+    }
+
+    /// A record is framed with the sync bytes, the next sequence number, its length and a CRC over
+    /// all of it but the sync bytes. Sequence numbers wrap around.
+    #[kernel_test]
+    fn record_is_framed_with_sequence_and_crc() {
+        let link = MockLink {
+            frame: RefCell::new(([0; MAX_FRAME_LEN], 0)),
+        };
+        let telemetry = Telemetry::new(&link);
+
+        telemetry.emit(b"abc").unwrap();
+        {
+            let (frame, len) = *link.frame.borrow();
+            assert_eq!(len, 9);
+            assert_eq!(frame[..7], [0xa5, 0x5a, 0, 3, b'a', b'b', b'c']);
+
+            let crc = checksum::crc16_xmodem(&frame[2..7]);
+            assert_eq!(frame[7..9], crc.to_be_bytes());
+        }
+
+        telemetry.emit(b"").unwrap();
+        assert_eq!(link.frame.borrow().0[2..4], [1, 0]);
+        assert_eq!(link.frame.borrow().1, 6);
+
+        // Overlong records do not use up a sequence number.
+        assert_eq!(
+            telemetry.emit(&[0; MAX_RECORD_LEN + 1]),
+            Err("Telemetry record is too long")
+        );
+        telemetry.emit(&[0; MAX_RECORD_LEN]).unwrap();
+        assert_eq!(link.frame.borrow().0[2], 2);
+        assert_eq!(link.frame.borrow().1, MAX_FRAME_LEN);
+
+        for _ in 3..=255 {
+            telemetry.emit(b"x").unwrap();
+        }
+        assert_eq!(link.frame.borrow().0[2], 255);
+        telemetry.emit(b"x").unwrap();
+        assert_eq!(link.frame.borrow().0[2], 0);
+    }
+}
@@ -3,16 +3,29 @@
 // Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
 
 //! Timer primitives.
+//!
+//! Points in time are given as uptime. Deadlines are computed with [`deadline_after()`], which
+//! saturates at [`NEVER`] instead of overflowing into the past.
 
 #[cfg(target_arch = "aarch64")]
 #[path = "_arch/aarch64/time.rs"]
 mod arch_time;
 pub use arch_time::*;
 
+#[cfg(test)]
+pub mod mock;
+
+use core::time::Duration;
+
 //--------------------------------------------------------------------------------------------------
 // Public Definitions
 //--------------------------------------------------------------------------------------------------
 
+/// A deadline that is never reached.
+///
+/// The uptime reaches it only after more than 500 billion years.
+pub const NEVER: Duration = Duration::from_secs(u64::MAX);
+
 /// Timekeeping interfaces.
 pub mod interface {
     use core::time::Duration;
@@ -24,6 +37,11 @@ pub mod interface {
         /// The timer's resolution.
         fn resolution(&self) -> Duration;
 
+        /// Returns true if the timer is running, i.e. its uptimes are meaningful.
+        fn is_running(&self) -> bool {
+            true
+        }
+
         /// The uptime since power-on of the device.
         ///
         /// This includes time consumed by firmware and bootloaders.
@@ -31,5 +49,55 @@ pub mod interface {
 
         /// Spin for a given duration.
         fn spin_for(&self, duration: Duration);
+
+        /// The uptime at which `timeout` from now will have passed. See
+        /// [`super::deadline_after()`].
+        fn deadline_in(&self, timeout: Duration) -> Duration {
+            super::deadline_after(self.uptime(), timeout)
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// The uptime at which `delay` after `now` will have passed.
+///
+/// A deadline that would overflow saturates to [`NEVER`], so that a huge delay means "no
+/// timeout" instead of a deadline in the past that expires right away.
+pub fn deadline_after(now: Duration, delay: Duration) -> Duration {
+    match now.checked_add(delay) {
+        Some(x) if x < NEVER => x,
+        _ => NEVER,
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_macros::kernel_test;
+
+    /// Deadlines add up normally until they would pass `NEVER`, where they stick.
+    #[kernel_test]
+    fn deadline_saturates_instead_of_wrapping() {
+        let second = Duration::from_secs(1);
+
+        assert_eq!(deadline_after(second, second), Duration::from_secs(2));
+
+        let near_wrap = NEVER - second;
+        assert_eq!(
+            deadline_after(near_wrap, Duration::from_millis(500)),
+            NEVER - second / 2
+        );
+        assert_eq!(deadline_after(near_wrap, second), NEVER);
+        assert_eq!(deadline_after(near_wrap, 2 * second), NEVER);
+
+        assert_eq!(deadline_after(second, NEVER), NEVER);
+        assert_eq!(deadline_after(NEVER, NEVER), NEVER);
     }
 }
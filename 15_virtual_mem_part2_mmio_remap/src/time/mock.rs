@@ -0,0 +1,76 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Mock timer, for unit tests.
+
+use super::interface;
+use core::{
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A timer that stands still at the uptime it was set to. Spinning advances the uptime by the
+/// spun duration right away, and counts the spins.
+pub struct MockTimer {
+    resolution_ns: u64,
+    running: AtomicBool,
+    uptime_ns: AtomicU64,
+    spins: AtomicUsize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl MockTimer {
+    /// Create a running instance with the given resolution, at an uptime of 0.
+    pub const fn new(resolution_ns: u64) -> Self {
+        Self {
+            resolution_ns,
+            running: AtomicBool::new(true),
+            uptime_ns: AtomicU64::new(0),
+            spins: AtomicUsize::new(0),
+        }
+    }
+
+    /// Start or stop the timer. The uptime is not affected.
+    pub fn set_running(&self, running: bool) {
+        self.running.store(running, Ordering::Relaxed);
+    }
+
+    /// Jump to `uptime`.
+    pub fn set_uptime(&self, uptime: Duration) {
+        self.uptime_ns
+            .store(uptime.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// The number of calls of `spin_for()` so far.
+    pub fn spins(&self) -> usize {
+        self.spins.load(Ordering::Relaxed)
+    }
+}
+
+impl interface::TimeManager for MockTimer {
+    fn resolution(&self) -> Duration {
+        Duration::from_nanos(self.resolution_ns)
+    }
+
+    fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    fn uptime(&self) -> Duration {
+        Duration::from_nanos(self.uptime_ns.load(Ordering::Relaxed))
+    }
+
+    fn spin_for(&self, duration: Duration) {
+        self.spins.fetch_add(1, Ordering::Relaxed);
+        self.uptime_ns
+            .fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
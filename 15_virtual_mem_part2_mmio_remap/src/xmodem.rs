@@ -0,0 +1,286 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! XMODEM-CRC receiver.
+//!
+//! Data arrives in blocks of 128 bytes, each one framed as
+//!
+//! `SOH | block number | 255 - block number | data | CRC16 high | CRC16 low`
+//!
+//! and acknowledged by the receiver with `ACK` or, if it was damaged, `NAK`. Block numbers start
+//! at 1 and wrap from 255 to 0. The sender ends the transfer with `EOT`. The last block is padded,
+//! so the received size is always a multiple of 128.
+
+use crate::checksum;
+use core::time::Duration;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+const SOH: u8 = 0x01;
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+
+/// Sent instead of `NAK` until the first block arrived, asking the sender to use CRCs instead of
+/// plain checksums.
+const CRC_REQUEST: u8 = b'C';
+
+/// Number of data bytes in a block.
+const BLOCK_SIZE: usize = 128;
+
+/// How long to wait for the start of a block before asking again.
+const BLOCK_START_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long to wait for each byte within a block.
+const BYTE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Consecutive timeouts or damaged blocks after which the transfer is given up.
+const MAX_ERRORS: usize = 10;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// XMODEM interfaces.
+pub mod interface {
+    use core::time::Duration;
+
+    /// A link that transports raw bytes, without any conversion.
+    pub trait Link {
+        /// Receive a byte, giving up after `timeout`.
+        fn recv(&self, timeout: Duration) -> Option<u8>;
+
+        /// Send a byte.
+        fn send(&self, byte: u8);
+    }
+}
+
+/// Reasons for a failed transfer.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum XmodemError {
+    /// Too many consecutive timeouts or damaged blocks.
+    TooManyErrors,
+
+    /// The sender cancelled the transfer.
+    Cancelled,
+
+    /// A block arrived that is neither the expected one nor a repetition of the previous one.
+    OutOfSequence,
+
+    /// The data does not fit into the destination buffer.
+    BufferTooSmall,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Read the remainder of a block after `SOH`.
+///
+/// Returns the block number and data, or `None` if the block timed out or was damaged.
+fn recv_block(link: &impl interface::Link) -> Option<(u8, [u8; BLOCK_SIZE])> {
+    let number = link.recv(BYTE_TIMEOUT)?;
+    let number_complement = link.recv(BYTE_TIMEOUT)?;
+
+    let mut data = [0; BLOCK_SIZE];
+    for byte in data.iter_mut() {
+        *byte = link.recv(BYTE_TIMEOUT)?;
+    }
+
+    let crc_high = link.recv(BYTE_TIMEOUT)?;
+    let crc_low = link.recv(BYTE_TIMEOUT)?;
+
+    if number != !number_complement
+        || checksum::crc16_xmodem(&data) != u16::from_be_bytes([crc_high, crc_low])
+    {
+        return None;
+    }
+
+    Some((number, data))
+}
+
+/// Discard input until the line has been quiet for a while.
+fn purge(link: &impl interface::Link) {
+    while link.recv(BYTE_TIMEOUT).is_some() {}
+}
+
+fn cancel(link: &impl interface::Link) {
+    link.send(CAN);
+    link.send(CAN);
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Receive a file into `dest`.
+///
+/// Returns the number of bytes received, including the padding of the last block.
+pub fn receive(link: &impl interface::Link, dest: &mut [u8]) -> Result<usize, XmodemError> {
+    let mut expected_number: u8 = 1;
+    let mut received = 0;
+    let mut errors = 0;
+    let mut response = CRC_REQUEST;
+
+    loop {
+        link.send(response);
+
+        let block = match link.recv(BLOCK_START_TIMEOUT) {
+            Some(SOH) => recv_block(link),
+            Some(EOT) => {
+                link.send(ACK);
+                return Ok(received);
+            }
+            Some(CAN) => return Err(XmodemError::Cancelled),
+            // Line noise or a lost `SOH`. Treat it like a damaged block.
+            Some(_) => None,
+            None => None,
+        };
+
+        let (number, data) = match block {
+            Some(x) => x,
+            None => {
+                errors += 1;
+                if errors >= MAX_ERRORS {
+                    cancel(link);
+                    return Err(XmodemError::TooManyErrors);
+                }
+
+                purge(link);
+                if received > 0 {
+                    response = NAK;
+                }
+                continue;
+            }
+        };
+        errors = 0;
+
+        if number == expected_number {
+            let end = received + BLOCK_SIZE;
+            if end > dest.len() {
+                cancel(link);
+                return Err(XmodemError::BufferTooSmall);
+            }
+
+            dest[received..end].copy_from_slice(&data);
+            received = end;
+            expected_number = expected_number.wrapping_add(1);
+        } else if received == 0 || number != expected_number.wrapping_sub(1) {
+            cancel(link);
+            return Err(XmodemError::OutOfSequence);
+        }
+        // Otherwise, it is a repetition because our last ACK got lost. Acknowledge it again.
+
+        response = ACK;
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::{Cell, RefCell};
+    use test_macros::kernel_test;
+
+    /// A sender that plays back one burst of bytes after each byte it receives.
+    struct MockSender<'a> {
+        bursts: &'a [&'a [u8]],
+        num_sent: Cell<usize>,
+        pos: Cell<usize>,
+        responses: RefCell<[u8; 32]>,
+    }
+
+    impl<'a> MockSender<'a> {
+        fn new(bursts: &'a [&'a [u8]]) -> Self {
+            Self {
+                bursts,
+                num_sent: Cell::new(0),
+                pos: Cell::new(0),
+                responses: RefCell::new([0; 32]),
+            }
+        }
+
+        fn responses_match(&self, expected: &[u8]) -> bool {
+            &self.responses.borrow()[..self.num_sent.get()] == expected
+        }
+    }
+
+    impl interface::Link for MockSender<'_> {
+        fn recv(&self, _timeout: Duration) -> Option<u8> {
+            let burst = self.bursts.get(self.num_sent.get().checked_sub(1)?)?;
+            let byte = burst.get(self.pos.get())?;
+            self.pos.set(self.pos.get() + 1);
+
+            Some(*byte)
+        }
+
+        fn send(&self, byte: u8) {
+            self.responses.borrow_mut()[self.num_sent.get()] = byte;
+            self.num_sent.set(self.num_sent.get() + 1);
+            self.pos.set(0);
+        }
+    }
+
+    /// Build a block filled with `fill`.
+    fn block(number: u8, fill: u8) -> [u8; 3 + BLOCK_SIZE + 2] {
+        let mut block = [fill; 3 + BLOCK_SIZE + 2];
+        block[0] = SOH;
+        block[1] = number;
+        block[2] = !number;
+
+        let crc = checksum::crc16_xmodem(&[fill; BLOCK_SIZE]).to_be_bytes();
+        block[3 + BLOCK_SIZE] = crc[0];
+        block[4 + BLOCK_SIZE] = crc[1];
+
+        block
+    }
+
+    /// Blocks are acknowledged and stored, repeated blocks are acknowledged but not stored again.
+    #[kernel_test]
+    fn xmodem_receives_valid_transfer() {
+        let block_1 = block(1, 0xaa);
+        let block_2 = block(2, 0x55);
+        let bursts: [&[u8]; 4] = [&block_1, &block_2, &block_2, &[EOT]];
+
+        let sender = MockSender::new(&bursts);
+        let mut dest = [0; 3 * BLOCK_SIZE];
+
+        assert_eq!(receive(&sender, &mut dest), Ok(2 * BLOCK_SIZE));
+        assert!(sender.responses_match(&[CRC_REQUEST, ACK, ACK, ACK, ACK]));
+        assert!(dest[..BLOCK_SIZE].iter().all(|x| *x == 0xaa));
+        assert!(dest[BLOCK_SIZE..2 * BLOCK_SIZE].iter().all(|x| *x == 0x55));
+        assert!(dest[2 * BLOCK_SIZE..].iter().all(|x| *x == 0));
+    }
+
+    /// A damaged block is NAKed and accepted when sent again. A silent sender ends in an error.
+    #[kernel_test]
+    fn xmodem_naks_corrupted_block() {
+        let block_1 = block(1, 0xaa);
+        let block_2 = block(2, 0x55);
+        let mut corrupted = block_2;
+        corrupted[10] ^= 0x01;
+        let bursts: [&[u8]; 4] = [&block_1, &corrupted, &block_2, &[EOT]];
+
+        let sender = MockSender::new(&bursts);
+        let mut dest = [0; 2 * BLOCK_SIZE];
+
+        assert_eq!(receive(&sender, &mut dest), Ok(2 * BLOCK_SIZE));
+        assert!(sender.responses_match(&[CRC_REQUEST, ACK, NAK, ACK, ACK]));
+        assert!(dest[BLOCK_SIZE..].iter().all(|x| *x == 0x55));
+
+        let silent = MockSender::new(&[]);
+        assert_eq!(receive(&silent, &mut dest), Err(XmodemError::TooManyErrors));
+
+        let mut expected = [CRC_REQUEST; MAX_ERRORS + 2];
+        expected[MAX_ERRORS] = CAN;
+        expected[MAX_ERRORS + 1] = CAN;
+        assert!(silent.responses_match(&expected));
+    }
+}
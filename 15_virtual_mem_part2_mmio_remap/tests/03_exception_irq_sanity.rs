@@ -66,3 +66,16 @@ fn local_irq_mask_save_works() {
     unsafe { exception::asynchronous::local_irq_restore(first) };
     assert!(exception::asynchronous::is_local_irq_masked());
 }
+
+/// Check that `cpu::irqs_masked()` follows the live mask state.
+#[kernel_test]
+fn cpu_irqs_masked_reflects_mask() {
+    // Precondition: IRQs are unmasked.
+    assert!(!cpu::irqs_masked());
+
+    unsafe { exception::asynchronous::local_irq_mask() };
+    assert!(cpu::irqs_masked());
+
+    unsafe { exception::asynchronous::local_irq_unmask() };
+    assert!(!cpu::irqs_masked());
+}